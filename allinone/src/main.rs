@@ -0,0 +1,69 @@
+//! The hub and the panel client in one program, for tiny installations where
+//! both run on the same Pi.
+//!
+//! The `hub` and `displayer` subcommands work just like the separate
+//! programs. The `serve` subcommand runs the hub server and the panel client
+//! together, in one runtime, with the client talking to the hub through a
+//! socket pair inside the process rather than over loopback TCP. The hub
+//! still listens on its usual ports, so that updaters can reach it.
+
+use futures::prelude::*;
+use rc_stickynote_hub::GenericError;
+use structopt::StructOpt;
+use tokio::runtime::Runtime;
+
+// "serve" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct ServeCommand {
+    #[structopt(flatten)]
+    hub: rc_stickynote_hub::ServeCommand,
+}
+
+impl ServeCommand {
+    fn cli(self) -> Result<(), GenericError> {
+        let mut rt = Runtime::new()?;
+
+        rt.block_on(async {
+            let hub = self.hub.cli();
+            let panel = rc_stickynote_displayer::run_with_hub::<rc_stickynote_displayer::Backend>(
+                rc_stickynote_hub::inproc::connect,
+            )
+            .map_err(GenericError::from);
+            future::try_join(hub, panel).await?;
+            Ok(())
+        })
+    }
+}
+
+// CLI root interface
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "rc_stickynote", about = "RC Stickynote hub and panel in one")]
+enum RootCli {
+    #[structopt(name = "displayer")]
+    /// The panel's commands, as in the displayer program
+    Displayer(rc_stickynote_displayer::RootCli),
+
+    #[structopt(name = "hub")]
+    /// The hub's commands, as in the hub program
+    Hub(rc_stickynote_hub::RootCli),
+
+    #[structopt(name = "serve")]
+    /// Launch the hub server and drive the panel from it
+    Serve(ServeCommand),
+}
+
+impl RootCli {
+    fn cli(self) -> Result<(), GenericError> {
+        match self {
+            RootCli::Displayer(opts) => Ok(opts.cli()?),
+            RootCli::Hub(opts) => Runtime::new()?.block_on(opts.cli()),
+            RootCli::Serve(opts) => opts.cli(),
+        }
+    }
+}
+
+fn main() -> Result<(), GenericError> {
+    RootCli::from_args().cli()
+}
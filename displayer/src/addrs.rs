@@ -0,0 +1,98 @@
+//! Figuring out which of the machine's IP addresses to show.
+//!
+//! The address that's useful for reaching the Pi is often not the first one
+//! that the OS reports: it might be on a VPN interface like `tailscale0` or
+//! `wg0`, while container bridges like `docker0` are just noise. So the
+//! interfaces can be filtered, put in order of preference, and given friendly
+//! labels.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, io::Error};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AddressConfiguration {
+    /// Interfaces to list first, in order. Others follow in the order that
+    /// the OS reports them.
+    #[serde(default)]
+    pub prefer: Vec<String>,
+
+    /// Interfaces to leave out. A trailing `*` matches any suffix, so that
+    /// `veth*` covers all of the virtual Ethernet interfaces.
+    #[serde(default)]
+    pub ignore: Vec<String>,
+
+    /// Labels to show instead of interface names, e.g. `tailscale0 = "ts"`.
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+
+    /// The most addresses to show in the panel's footer.
+    #[serde(default = "default_footer_count")]
+    pub footer_count: usize,
+
+    /// Whether to show the hostname in the panel's footer.
+    #[serde(default)]
+    pub footer_hostname: bool,
+
+    /// Whether to show the WiFi network and signal strength in the panel's
+    /// footer.
+    #[serde(default)]
+    pub footer_wifi: bool,
+}
+
+fn default_footer_count() -> usize {
+    2
+}
+
+impl Default for AddressConfiguration {
+    fn default() -> Self {
+        AddressConfiguration {
+            prefer: Vec::new(),
+            ignore: Vec::new(),
+            labels: HashMap::new(),
+            footer_count: default_footer_count(),
+            footer_hostname: false,
+            footer_wifi: false,
+        }
+    }
+}
+
+fn name_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+impl AddressConfiguration {
+    /// Get the interesting IPv4 addresses of this machine, most preferred
+    /// first, each with its label.
+    pub fn labeled_addresses(&self) -> Result<Vec<(String, String)>, Error> {
+        let mut addrs = Vec::new();
+
+        for iface in get_if_addrs::get_if_addrs()? {
+            if iface.is_loopback() || self.ignore.iter().any(|p| name_matches(p, &iface.name)) {
+                continue;
+            }
+
+            if let get_if_addrs::IfAddr::V4(ref addr) = iface.addr {
+                addrs.push((iface.name.clone(), addr.ip.to_string()));
+            }
+        }
+
+        // This sort is stable, so unlisted interfaces stay in the OS's order.
+        addrs.sort_by_key(|(name, _)| {
+            self.prefer
+                .iter()
+                .position(|p| name_matches(p, name))
+                .unwrap_or(self.prefer.len())
+        });
+
+        Ok(addrs
+            .into_iter()
+            .map(|(name, ip)| {
+                let label = self.labels.get(&name).cloned().unwrap_or(name);
+                (label, ip)
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,131 @@
+//! The single-board computer that drives the e-Print Display, and how the
+//! display is wired to it.
+//!
+//! The display's driver needs an SPI device and four GPIO lines: chip select,
+//! busy, data/command, and reset. We get at the lines through the GPIO
+//! character device, which numbers them by their offset on a GPIO chip
+//! rather than by any board-wide numbering, so the same scheme works on any
+//! board that Linux supports. The presets give the SPI device and lines that
+//! the display's signals end up on for the boards that we know about, and any
+//! of them can be overridden in the `[board]` section of the client
+//! configuration, for other boards or other wiring.
+
+use serde::{Deserialize, Serialize};
+
+/// A board that we know how to find the display on.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BoardPreset {
+    /// Any Raspberry Pi with the 40-pin header and the Waveshare HAT. The
+    /// line offsets are BCM GPIO numbers.
+    RaspberryPi,
+
+    /// An Allwinner H3 Orange Pi (PC, One, Lite, and so on) with the
+    /// Waveshare HAT. Their 40-pin headers follow the Raspberry Pi's layout,
+    /// so the HAT fits, but the pins go to different lines.
+    OrangePi,
+
+    /// A BeagleBone Black with the display wired to the P9 header: SPI0 on
+    /// P9_18 and P9_22, chip select on P9_14, busy on P9_23, data/command on
+    /// P9_15, and reset on P9_12. The four GPIOs are all in the GPIO1 bank.
+    Beaglebone,
+}
+
+impl Default for BoardPreset {
+    fn default() -> Self {
+        BoardPreset::RaspberryPi
+    }
+}
+
+/// Where the display's connections are on a particular board.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoardPins {
+    /// The SPI device that the display is on.
+    pub spi_path: String,
+
+    /// The GPIO chip that the lines below belong to.
+    pub gpio_chip_path: String,
+
+    /// The offsets of the lines on the GPIO chip.
+    pub cs: u32,
+    pub busy: u32,
+    pub dc: u32,
+    pub rst: u32,
+}
+
+impl BoardPreset {
+    fn pins(self) -> BoardPins {
+        let (spi_path, gpio_chip_path, cs, busy, dc, rst) = match self {
+            BoardPreset::RaspberryPi => ("/dev/spidev0.0", "/dev/gpiochip0", 8, 24, 25, 17),
+
+            // Header pins 24, 18, 22, and 11 are PC3, PC7, PA2, and PA1.
+            BoardPreset::OrangePi => ("/dev/spidev0.0", "/dev/gpiochip0", 67, 71, 2, 1),
+
+            // GPIO1_18, GPIO1_17, GPIO1_16, and GPIO1_28.
+            BoardPreset::Beaglebone => ("/dev/spidev0.0", "/dev/gpiochip1", 18, 17, 16, 28),
+        };
+
+        BoardPins {
+            spi_path: spi_path.to_owned(),
+            gpio_chip_path: gpio_chip_path.to_owned(),
+            cs,
+            busy,
+            dc,
+            rst,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct BoardConfiguration {
+    /// The board to start from: "raspberry-pi", "orange-pi", or
+    /// "beaglebone".
+    #[serde(default)]
+    preset: BoardPreset,
+
+    /// If set, the SPI device to use instead of the preset's.
+    #[serde(default)]
+    spi_path: Option<String>,
+
+    /// If set, the GPIO chip to use instead of the preset's.
+    #[serde(default)]
+    gpio_chip_path: Option<String>,
+
+    /// If set, the offset of the chip-select line on the GPIO chip.
+    #[serde(default)]
+    cs_line: Option<u32>,
+
+    /// If set, the offset of the busy line on the GPIO chip.
+    #[serde(default)]
+    busy_line: Option<u32>,
+
+    /// If set, the offset of the data/command line on the GPIO chip.
+    #[serde(default)]
+    dc_line: Option<u32>,
+
+    /// If set, the offset of the reset line on the GPIO chip.
+    #[serde(default)]
+    rst_line: Option<u32>,
+}
+
+impl BoardConfiguration {
+    /// Where to find the display: the preset's connections, with any that
+    /// were configured swapped in.
+    pub fn pins(&self) -> BoardPins {
+        let mut pins = self.preset.pins();
+
+        if let Some(ref p) = self.spi_path {
+            pins.spi_path = p.clone();
+        }
+
+        if let Some(ref p) = self.gpio_chip_path {
+            pins.gpio_chip_path = p.clone();
+        }
+
+        pins.cs = self.cs_line.unwrap_or(pins.cs);
+        pins.busy = self.busy_line.unwrap_or(pins.busy);
+        pins.dc = self.dc_line.unwrap_or(pins.dc);
+        pins.rst = self.rst_line.unwrap_or(pins.rst);
+        pins
+    }
+}
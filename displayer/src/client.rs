@@ -1,63 +1,610 @@
 //! The long-running panel driving client.
 
-use chrono::prelude::*;
-use daemonize::Daemonize;
-use embedded_graphics::{
-    coord::Coord,
-    fonts::{Font, Font6x8},
-    primitives::{Line, Rectangle},
-    style::{Style, WithStyle},
-    transform::Transform,
-    Drawing,
+use chrono::{
+    format::{Item, StrftimeItems},
+    prelude::*,
 };
+use chrono_tz::Tz;
 use futures::{prelude::*, select};
 use rc_stickynote_protocol::{
-    is_person_is_valid, ClientHelloMessage, DisplayHelloMessage, DisplayMessage,
-    PersonIsUpdateHelloMessage,
+    is_person_is_valid,
+    mqtt::MqttConfiguration,
+    sealed::{is_sealed, SealingConfiguration},
+    session::{
+        client::{AwaitingHello, DisplaySession},
+        UpdaterHello,
+    },
+    signing::SigningConfiguration,
+    width, Availability, CiStatus, ClientFrame, ClientHelloMessage, Counter, DisplayCapabilities,
+    DisplayHelloMessage, DisplayMessage, DisplaySettings, DoorbellHelloMessage, ErrorFrame,
+    MissedUpdates, OfficeDays, OnCall, PanelCommand, PersonIsUpdateHelloMessage, QueuedStatus,
+    RoomBookingHelloMessage, RoomSchedule, SensorReadingHelloMessage, SystemHealthHelloMessage,
+    VIDEO_CALL_SOURCE,
 };
-use rusttype::FontCollection;
 use serde::{Deserialize, Serialize};
 use std::{
-    fs::File,
-    io::{Error, Read},
-    net::TcpStream as StdTcpStream,
-    path::{Path, PathBuf},
-    sync::mpsc::{channel, Receiver},
+    collections::BTreeMap,
+    convert::TryFrom,
+    io::Error,
+    net::{SocketAddr, TcpStream as StdTcpStream},
+    path::Path,
+    pin::Pin,
+    process::Stdio,
+    sync::{
+        mpsc::{channel, Receiver},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
     thread,
 };
+use timeago::languages::IsolangLanguage;
 use tokio::{
     io::{AsyncRead, AsyncWrite},
-    net::TcpStream,
+    net::{self, TcpStream, UnixStream},
+    process::{Child, ChildStdin, ChildStdout, Command},
     runtime::Runtime,
+    sync::mpsc,
     time::{self, Duration},
 };
-use tokio_serde::{formats::Json, Framed as SerdeFramed};
-use tokio_util::codec::{Framed as CodecFramed, LengthDelimitedCodec};
 
-use super::{Backend, DisplayBackend};
-use crate::text::DrawFontExt;
+use super::DisplayBackend;
+use crate::addrs::AddressConfiguration;
+use crate::board::BoardConfiguration;
+use crate::daemon::{self, DaemonConfiguration};
+use crate::features::FontFeature;
+use crate::fonts::{self, FontFamily};
+use crate::health::{Health, HealthConfiguration};
+use crate::identity;
+use crate::images::ImageConfiguration;
+use crate::logfile::LogConfiguration;
+use crate::meetings::MeetingsConfiguration;
+use crate::netstatus::{self, WifiStatus};
+use crate::scd30::{Measurement, Scd30};
+use crate::schedule::{self, PollingConfiguration, SleepConfiguration};
+use crate::simulation::SimulatorConfiguration;
+use crate::standalone::{self, StandaloneConfiguration};
+use crate::update::{self, UpdateConfiguration, UpdateOutcome};
+use crate::watchdog::{self, Pulse, WatchdogConfiguration};
+use crate::widgets::{self, SharedWidgets, WidgetConfiguration};
+use crate::wifi_setup::WifiSetupConfiguration;
+
+mod crash;
+mod forward;
+mod render;
+mod scenario;
+mod selftest;
+pub mod snapshot;
+
+use crash::FatalError;
+use forward::ForwardConfiguration;
+use render::{DirtyTracker, Refresh, RenderContext};
+
+/// The latest reading from the room sensor, shared between threads.
+type SharedMeasurement = Arc<Mutex<Option<Measurement>>>;
+
+/// The latest check on the Pi's own health, shared between threads.
+type SharedHealth = Arc<Mutex<Health>>;
+
+/// A message to show briefly at the bottom of the panel, and when to stop
+/// showing it, shared between threads.
+type SharedToast = Arc<Mutex<Option<(String, std::time::Instant)>>>;
+
+/// Where to send reports like health checks, if the hub connection can carry
+/// them. The main loop keeps this up to date.
+type TelemetryRoute = Arc<Mutex<Option<mpsc::UnboundedSender<ClientHelloMessage>>>>;
+
+/// How long the doorbell card stays up after a ring that couldn't reach the
+/// hub. This matches the hub.
+const LOCAL_DOORBELL_SECONDS: i64 = 60;
+
+/// How long a toast stays up. The panel might only redraw once a minute, so
+/// this can't be too short.
+const TOAST_DURATION: std::time::Duration = std::time::Duration::from_secs(180);
+
+/// How long to wait for each of the hub's addresses to answer.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Connect to the host at the first of its addresses that works. The name is
+/// looked up afresh every time, so that a connection after the hub's address
+/// changes, as with dynamic DNS, goes to the new one.
+async fn connect_to_any<S, F, Fut>(host: &str, port: u16, connect: F) -> Result<S, Error>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = Result<S, Error>>,
+{
+    let mut last_error = Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("no addresses found for {}", host),
+    );
+
+    for addr in net::lookup_host((host, port)).await? {
+        match connect(addr).await {
+            Ok(s) => {
+                println!("connected to {} at {}", host, addr);
+                return Ok(s);
+            }
+
+            Err(e) => {
+                println!("cannot connect to {} at {}: {}", host, addr, e);
+                last_error = e;
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+/// If the hub rejected something that we sent it, pop up a toast saying why.
+fn toast_rejection(toast: &SharedToast, e: &Error) {
+    if let Some(frame) = ErrorFrame::from_error(e) {
+        let until = std::time::Instant::now() + TOAST_DURATION;
+        *toast.lock().unwrap() = Some((frame.message.clone(), until));
+    }
+}
+
+/// After an outage, pop up a toast saying how much went on while we were
+/// away, since the panel only shows how things ended up.
+fn toast_missed(toast: &SharedToast, missed: &MissedUpdates) {
+    let message = format!(
+        "Missed {} update{} while offline",
+        missed.count,
+        if missed.count == 1 { "" } else { "s" }
+    );
+    println!(
+        "{} (the first at {})",
+        message.to_lowercase(),
+        missed.since.with_timezone(&Local).format("%H:%M")
+    );
+    let until = std::time::Instant::now() + TOAST_DURATION;
+    *toast.lock().unwrap() = Some((message, until));
+}
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 struct ClientConfiguration {
+    /// Which panel this is, so that the hub can tell panels apart and apply
+    /// the settings in its `[displays.<ID>]` section. If unset, an ID is
+    /// generated and saved the first time the client runs.
+    #[serde(default)]
+    display_id: Option<String>,
+
+    /// Where to reach the hub. These can be left out in standalone mode.
+    #[serde(default)]
     hub_host: String,
+    #[serde(default)]
     hub_port: u16,
     ssh: Option<ClientSshConfiguration>,
-    sans_path: String,
-    serif_path: String,
+
+    /// The TTF or OTF font file for most text. If unset, or if it can't be
+    /// loaded, the system's usual sans-serif font is found with fontconfig,
+    /// failing which a built-in copy of DejaVu Sans is used.
+    #[serde(default)]
+    sans_path: Option<String>,
+
+    /// The font file for headings, with the same fallbacks.
+    #[serde(default)]
+    serif_path: Option<String>,
+
+    /// The OpenType features to draw each kind of text with.
+    #[serde(default)]
+    font_features: FontFeaturesConfiguration,
+
+    #[serde(default)]
+    updated_at: ClientUpdatedAtConfiguration,
+
+    /// If set, show the date under the clock.
+    #[serde(default)]
+    date: Option<ClientDateConfiguration>,
+
+    /// Other timezones to show the time in, under the clock. Only the first
+    /// `MAX_WORLD_CLOCKS` are shown.
+    #[serde(default)]
+    world_clocks: Vec<WorldClockConfiguration>,
+
+    /// Which single-board computer drives the display, and how the display
+    /// is wired to it.
+    #[serde(default)]
+    board: BoardConfiguration,
+
+    /// How the SDL2 simulator shows the panel, in builds that have it.
+    #[serde(default)]
+    simulator: SimulatorConfiguration,
+
+    /// If set, the sysfs GPIO number of a doorbell button. The pin should
+    /// read low when the button is pressed.
+    #[serde(default)]
+    doorbell_button_gpio: Option<u64>,
+
+    /// If set, the sysfs GPIO number of a button that books the meeting room
+    /// that the hub is watching, for `room_booking_minutes` from now. The
+    /// pin should read low when the button is pressed.
+    #[serde(default)]
+    room_button_gpio: Option<u64>,
+
+    /// How long a press of the room button books the room for.
+    #[serde(default = "default_room_booking_minutes")]
+    room_booking_minutes: u32,
+
+    /// If set, read room conditions from an SCD30 sensor.
+    #[serde(default)]
+    sensor: Option<ClientSensorConfiguration>,
+
+    /// The access token to present when sending updates to the hub, if it
+    /// requires one.
+    #[serde(default)]
+    hub_token: Option<String>,
+
+    /// If set, talk to the hub through this MQTT broker rather than
+    /// connecting to it directly.
+    #[serde(default)]
+    mqtt: Option<MqttConfiguration>,
+
+    /// If set, run this command and talk to the hub over its standard input
+    /// and output, rather than connecting to it directly. For instance,
+    /// `["ssh", "myhub", "rc_stickynote_hub", "stdio", "config.toml"]`.
+    #[serde(default)]
+    hub_command: Option<Vec<String>>,
+
+    /// If set, listen for updaters, pass what they send along to the hub,
+    /// and hold on to it while the hub is down. The buttons and sensor send
+    /// their updates this way too.
+    #[serde(default)]
+    forward: Option<ForwardConfiguration>,
+
+    /// If set, run without a hub, taking statuses from `set-status` and the
+    /// like over a Unix socket. This takes the place of all of the ways of
+    /// reaching the hub above.
+    #[serde(default)]
+    standalone: Option<StandaloneConfiguration>,
+
+    /// Which of the Pi's IP addresses to show, and how.
+    #[serde(default)]
+    addresses: AddressConfiguration,
+
+    /// If set, the `wifi-setup` command may bring up a hotspot to collect
+    /// WiFi credentials.
+    #[serde(default)]
+    wifi_setup: Option<WifiSetupConfiguration>,
+
+    /// If set, where to get updates of this program.
+    #[serde(default)]
+    update: Option<UpdateConfiguration>,
+
+    /// The commands from the hub that this panel will carry out: any of
+    /// "redraw", "clear", "restart", "layout", and "screensaver".
+    #[serde(default)]
+    allowed_commands: Vec<String>,
+
+    /// Lines of text to show above the footer, each taken from the output of
+    /// a local command or from the hub's extras.
+    #[serde(default)]
+    widgets: Vec<WidgetConfiguration>,
+
+    /// If set, a picture to show while the screensaver is on, rather than a
+    /// blank panel.
+    #[serde(default)]
+    screensaver_image: Option<ImageConfiguration>,
+
+    /// Watching for overheating and under-voltage.
+    #[serde(default)]
+    health: HealthConfiguration,
+
+    /// If set, a daily window when the panel stops redrawing, except for
+    /// the doorbell and the like.
+    #[serde(default)]
+    sleep: Option<SleepConfiguration>,
+
+    /// How often to redraw when nothing's happening.
+    #[serde(default)]
+    polling: PollingConfiguration,
+
+    /// If set, keys for sealing statuses so that the hub can't read them.
+    /// With a secret key, the panel opens sealed statuses from the hub; with
+    /// either key, `set-status` seals the statuses that it sends.
+    #[serde(default)]
+    sealing: Option<SealingConfiguration>,
+
+    /// If set, `set-status` signs the statuses that it sends with this key,
+    /// for hubs that only accept signed updates.
+    #[serde(default)]
+    signing: Option<SigningConfiguration>,
+
+    /// How `watch-meetings` notices video calls.
+    #[serde(default)]
+    meetings: MeetingsConfiguration,
+
+    /// Feeding systemd's or the Pi's watchdog, so that a hung client gets
+    /// the Pi rebooted.
+    #[serde(default)]
+    watchdog: WatchdogConfiguration,
+
+    /// Where the log goes when running as a daemon, and how much of it to
+    /// keep.
+    #[serde(default)]
+    log: LogConfiguration,
+
+    /// Where a daemon keeps its PID file and runs, and the user that it
+    /// switches to once the panel is open.
+    #[serde(default)]
+    daemon: DaemonConfiguration,
+
+    /// A way to reach a hub running in this same process, which takes the
+    /// place of all of the ways of reaching the hub above. Only the all-in-one
+    /// program sets this.
+    #[serde(skip)]
+    in_process_hub: Option<InProcessHub>,
+}
+
+/// Opens a connection to a hub running in the same process.
+pub type InProcessHub = fn() -> Result<std::os::unix::net::UnixStream, Error>;
+
+fn default_room_booking_minutes() -> u32 {
+    30
 }
 
 impl Default for ClientConfiguration {
     fn default() -> Self {
         ClientConfiguration {
+            display_id: None,
             hub_host: "edit-configuration.example.com".to_owned(),
             hub_port: 20200,
             ssh: None,
-            sans_path: "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf".to_owned(),
-            serif_path: "/usr/share/fonts/truetype/freefont/FreeSerif.ttf".to_owned(),
+            sans_path: Some("/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf".to_owned()),
+            serif_path: Some("/usr/share/fonts/truetype/freefont/FreeSerif.ttf".to_owned()),
+            font_features: FontFeaturesConfiguration::default(),
+            updated_at: ClientUpdatedAtConfiguration::default(),
+            date: None,
+            world_clocks: Vec::new(),
+            board: BoardConfiguration::default(),
+            simulator: SimulatorConfiguration::default(),
+            doorbell_button_gpio: None,
+            room_button_gpio: None,
+            room_booking_minutes: default_room_booking_minutes(),
+            sensor: None,
+            hub_token: None,
+            mqtt: None,
+            hub_command: None,
+            forward: None,
+            standalone: None,
+            addresses: AddressConfiguration::default(),
+            wifi_setup: None,
+            update: None,
+            allowed_commands: Vec::new(),
+            widgets: Vec::new(),
+            screensaver_image: None,
+            health: HealthConfiguration::default(),
+            sleep: None,
+            polling: PollingConfiguration::default(),
+            sealing: None,
+            signing: None,
+            meetings: MeetingsConfiguration::default(),
+            watchdog: WatchdogConfiguration::default(),
+            log: LogConfiguration::default(),
+            daemon: DaemonConfiguration::default(),
+            in_process_hub: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct ClientSensorConfiguration {
+    /// The I2C bus device that the sensor is attached to.
+    #[serde(default = "default_i2c_path")]
+    i2c_path: String,
+
+    /// How often to report readings to the hub.
+    #[serde(default = "default_report_minutes")]
+    report_minutes: u64,
+}
+
+fn default_i2c_path() -> String {
+    "/dev/i2c-1".to_owned()
+}
+
+fn default_report_minutes() -> u64 {
+    15
+}
+
+/// The OpenType features, "tabular-numbers" and "small-caps", to turn on for
+/// each kind of text. Fonts that don't have them get imitations.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct FontFeaturesConfiguration {
+    /// The clock and the world clocks. Tabular numbers keep the time from
+    /// shifting sideways, and changing more of the panel than it needs to,
+    /// as the digits change.
+    clock: Vec<FontFeature>,
+
+    /// The headings, in the serif font.
+    heading: Vec<FontFeature>,
+
+    /// The status message.
+    status: Vec<FontFeature>,
+}
+
+impl Default for FontFeaturesConfiguration {
+    fn default() -> Self {
+        FontFeaturesConfiguration {
+            clock: vec![FontFeature::TabularNumbers],
+            heading: Vec::new(),
+            status: Vec::new(),
+        }
+    }
+}
+
+/// A strftime-style format, like "%I:%M %p". Chrono panics when it's asked
+/// to format a time with a format that it can't make sense of, so formats are
+/// checked when the configuration is loaded.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+struct TimeFormat(String);
+
+impl TryFrom<String> for TimeFormat {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        if StrftimeItems::new(&text).any(|i| matches!(i, Item::Error)) {
+            return Err(format!("invalid time format \"{}\"", text));
+        }
+
+        Ok(TimeFormat(text))
+    }
+}
+
+impl From<TimeFormat> for String {
+    fn from(f: TimeFormat) -> String {
+        f.0
+    }
+}
+
+/// Settings for the "updated at ..." line shown below the status message.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct ClientUpdatedAtConfiguration {
+    /// The text to show. The variables `{abs_time}`, `{rel_time}`, and
+    /// `{source}` are replaced with the time of the update, how long ago that
+    /// was, and where the update came from, respectively.
+    template: String,
+
+    /// The strftime-style format used for `{abs_time}`.
+    abs_time_format: TimeFormat,
+
+    /// If the status is younger than this many minutes, don't show the line
+    /// at all. Zero means to always show it.
+    hide_if_fresher_than_minutes: u32,
+
+    /// If true, and the hub knows who set the status, add "set by ..." to the
+    /// line. Useful for shared offices.
+    show_set_by: bool,
+}
+
+impl Default for ClientUpdatedAtConfiguration {
+    fn default() -> Self {
+        ClientUpdatedAtConfiguration {
+            template: "updated at {abs_time} (more than {rel_time})".to_owned(),
+            abs_time_format: TimeFormat("%I:%M %p".to_owned()),
+            hide_if_fresher_than_minutes: 0,
+            show_set_by: false,
+        }
+    }
+}
+
+impl ClientUpdatedAtConfiguration {
+    /// Format the "updated at" text for the given display data, or return
+    /// None if it should be hidden.
+    fn format<L: timeago::Language>(
+        &self,
+        dd: &DisplayData,
+        ago_formatter: &timeago::Formatter<L>,
+    ) -> Option<String> {
+        let set_by = if self.show_set_by && !dd.person_is_set_by.is_empty() {
+            Some(format!("set by {}", dd.person_is_set_by))
+        } else {
+            None
+        };
+
+        // Go by the hub's clock, which set the timestamp. In case our idea of
+        // it is still a bit off, don't let the update be in the future,
+        // which would come out as "in 2 minutes".
+        let now = dd.hub_now();
+        let timestamp = dd.person_is_timestamp.min(now.with_timezone(&Utc));
+        let age = now.signed_duration_since(timestamp);
+
+        if age < chrono::Duration::minutes(self.hide_if_fresher_than_minutes as i64) {
+            return set_by;
+        }
+
+        let abs_time = dd
+            .person_is_timestamp
+            .with_timezone(&dd.now.timezone())
+            .format(&self.abs_time_format.0)
+            .to_string();
+        let rel_time = ago_formatter.convert_chrono(timestamp, now);
+
+        let text = self
+            .template
+            .replace("{abs_time}", &abs_time)
+            .replace("{rel_time}", &rel_time)
+            .replace("{source}", &dd.person_is_source);
+
+        Some(match set_by {
+            Some(set_by) => format!("{}, {}", text, set_by),
+            None => text,
+        })
+    }
+}
+
+/// Settings for the date shown under the clock.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct ClientDateConfiguration {
+    /// The strftime-style format for the date.
+    format: TimeFormat,
+
+    /// The POSIX locale for the names of days and months, such as "fr_FR".
+    locale: String,
+}
+
+impl Default for ClientDateConfiguration {
+    fn default() -> Self {
+        ClientDateConfiguration {
+            format: TimeFormat("%a, %b %-d".to_owned()),
+            locale: "en_US".to_owned(),
         }
     }
 }
 
+impl ClientDateConfiguration {
+    /// Format the date for the given display data. An unknown locale is
+    /// treated as English, rather than leaving the date off.
+    fn format(&self, dd: &DisplayData) -> String {
+        let locale = Locale::try_from(self.locale.as_str()).unwrap_or(Locale::en_US);
+        dd.now.format_localized(&self.format.0, locale).to_string()
+    }
+}
+
+/// There's only room under the clock for this many world clocks.
+const MAX_WORLD_CLOCKS: usize = 3;
+
+/// A timezone, written like "America/New_York" in the configuration file.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+struct TimeZone(Tz);
+
+impl TryFrom<String> for TimeZone {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse().map(TimeZone)
+    }
+}
+
+impl From<TimeZone> for String {
+    fn from(tz: TimeZone) -> String {
+        tz.0.name().to_owned()
+    }
+}
+
+/// The time somewhere else, for teams spread across timezones.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct WorldClockConfiguration {
+    /// What to call the place, such as "NYC".
+    label: String,
+
+    /// Its timezone, such as "America/New_York".
+    timezone: TimeZone,
+}
+
+impl WorldClockConfiguration {
+    /// Format the label and the time there, like "NYC 09:12".
+    fn format(&self, dd: &DisplayData) -> String {
+        format!(
+            "{} {}",
+            self.label,
+            dd.now.with_timezone(&self.timezone.0).format("%H:%M")
+        )
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 struct ClientSshConfiguration {
     private_key_path: String,
@@ -82,185 +629,585 @@ macro_rules! tryssh {
 trait AsyncReadAndWrite: AsyncRead + AsyncWrite + Unpin {}
 
 impl AsyncReadAndWrite for TcpStream {}
+impl AsyncReadAndWrite for UnixStream {}
 impl AsyncReadAndWrite for async_ssh2::Channel {}
+impl AsyncReadAndWrite for CommandTransport {}
+
+/// A transport through the standard input and output of a child process.
+/// The process is killed when the transport is dropped.
+struct CommandTransport {
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+}
+
+impl CommandTransport {
+    fn spawn(argv: &[String]) -> Result<Self, Error> {
+        let (program, args) = argv.split_first().ok_or_else(|| {
+            Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the hub_command setting is empty",
+            )
+        })?;
+
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        // These are always present since we asked for pipes.
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        Ok(CommandTransport {
+            _child: child,
+            stdin,
+            stdout,
+        })
+    }
+}
+
+impl AsyncRead for CommandTransport {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, Error>> {
+        Pin::new(&mut self.stdout).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for CommandTransport {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, Error>> {
+        Pin::new(&mut self.stdin).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.stdin).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.stdin).poll_shutdown(cx)
+    }
+}
 
-/// The type that defines our client/server communication. We use JSON to
-/// encode our messages via Serde, on top of a length-delimited codec because
-/// Serde needs it, on a transport that is abstracted through a Box so that we
-/// can use either an SSH connection or a raw TCP connection (or other
-/// transports if they're added) as needed.
-type HubTransport = SerdeFramed<
-    CodecFramed<Box<dyn AsyncReadAndWrite>, LengthDelimitedCodec>,
-    DisplayMessage,
-    ClientHelloMessage,
-    Json<DisplayMessage, ClientHelloMessage>,
->;
+/// The transport for our client/server communication, abstracted through a
+/// Box so that we can use either an SSH connection or a raw TCP connection
+/// (or other transports if they're added) as needed. The protocol crate's
+/// session types take care of the framing and of what may be sent when.
+type HubTransport = Box<dyn AsyncReadAndWrite>;
 
 impl ClientConfiguration {
-    pub async fn connect(&self) -> Result<HubTransport, Error> {
-        if let Some(sshcfg) = self.ssh.as_ref() {
+    pub async fn connect(&self) -> Result<AwaitingHello<HubTransport>, Error> {
+        if let Some(connect) = self.in_process_hub {
+            Ok(Self::wrap_transport(UnixStream::from_std(connect()?)?))
+        } else if let Some(ref standalone) = self.standalone {
+            Ok(Self::wrap_transport(
+                UnixStream::connect(&standalone.socket_path).await?,
+            ))
+        } else if let Some(argv) = self.hub_command.as_ref() {
+            Ok(Self::wrap_transport(CommandTransport::spawn(argv)?))
+        } else if let Some(sshcfg) = self.ssh.as_ref() {
             let mut sess = tryssh!(async_ssh2::Session::new());
 
-            // NB this is a non-async TcpStream.connect() so it will block the thread!
-            let transport = StdTcpStream::connect((self.hub_host.as_ref(), sshcfg.ssh_port))?;
+            // NB this is a non-async connect so it will block the thread!
+            let transport = connect_to_any(&self.hub_host, sshcfg.ssh_port, |addr| async move {
+                StdTcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+            })
+            .await?;
             tryssh!(sess.set_tcp_stream(transport));
 
             tryssh!(sess.handshake().await);
-            tryssh!(
-                sess.userauth_pubkey_file(
-                    sshcfg.user.as_ref(),
-                    None, // pubkey path; inferred
-                    Path::new(&sshcfg.private_key_path),
-                    None, // passphrase: assume passwordlessness
-                )
-                .await
-            );
+            // A refused login is worth telling apart from other trouble,
+            // since retrying won't fix it.
+            sess.userauth_pubkey_file(
+                sshcfg.user.as_ref(),
+                None, // pubkey path; inferred
+                Path::new(&sshcfg.private_key_path),
+                None, // passphrase: assume passwordlessness
+            )
+            .await
+            .map_err(|e| match e {
+                async_ssh2::Error::SSH2(e2) => {
+                    Error::new(std::io::ErrorKind::PermissionDenied, e2.message())
+                }
+                async_ssh2::Error::Io(e) => e,
+            })?;
 
             Ok(Self::wrap_transport(tryssh!(
                 sess.channel_direct_tcpip("localhost", self.hub_port, None)
                     .await
             )))
         } else {
-            Ok(Self::wrap_transport(
-                TcpStream::connect((self.hub_host.as_ref(), self.hub_port)).await?,
-            ))
+            let transport = connect_to_any(&self.hub_host, self.hub_port, |addr| async move {
+                time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr))
+                    .await
+                    .map_err(|_| Error::new(std::io::ErrorKind::TimedOut, "timed out"))?
+            })
+            .await?;
+
+            Ok(Self::wrap_transport(transport))
         }
     }
 
-    fn wrap_transport<T: AsyncReadAndWrite + 'static>(transport: T) -> HubTransport {
-        let ld = CodecFramed::new(
-            Box::new(transport) as Box<dyn AsyncReadAndWrite>,
-            LengthDelimitedCodec::new(),
-        );
-        SerdeFramed::new(ld, Json::default())
+    fn wrap_transport<T: AsyncReadAndWrite + 'static>(transport: T) -> AwaitingHello<HubTransport> {
+        AwaitingHello::new(Box::new(transport) as HubTransport)
+    }
+
+    /// The MQTT broker to reach the hub through, if that's how we reach it.
+    /// Standalone mode takes precedence.
+    fn mqtt(&self) -> Option<&MqttConfiguration> {
+        match self.standalone {
+            Some(_) => None,
+            None => self.mqtt.as_ref(),
+        }
+    }
+
+    /// Open a sealed status from the hub, if it is one. If we can't, say so on
+    /// the panel rather than showing the gibberish.
+    fn unseal_status(&self, person_is: String) -> String {
+        if !is_sealed(&person_is) {
+            return person_is;
+        }
+
+        let result = match self.sealing {
+            Some(ref s) => s.unseal(&person_is),
+            None => Err(Error::new(
+                std::io::ErrorKind::Other,
+                "the client configuration does not have a [sealing] section",
+            )),
+        };
+
+        match result {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("ERROR: cannot open sealed status: {}", e);
+                "[sealed status]".to_owned()
+            }
+        }
     }
 }
 
-pub fn main_cli(opts: super::ClientCommand) -> Result<(), Error> {
+pub fn main_cli<B: DisplayBackend>(opts: super::ClientCommand) -> Result<(), Error> {
     openssl_probe::init_ssl_cert_env_vars();
 
     // Parse the configuration.
 
-    let config: ClientConfiguration = confy::load("rc-stickynote-client")?;
+    let mut config: ClientConfiguration = confy::load("rc-stickynote-client")?;
+    config.display_id = Some(identity::display_id(config.display_id.as_deref())?);
 
     // If requested, let's get into the background. Do this before any
     // other thread-y operations.
 
     if opts.daemonize {
-        // TODO: files in /var/run, etc? The idea is to lauch this process as
-        // an unprivleged user.
-        let pid_path: PathBuf = ["rc-stickynote-displayer.pid"].iter().collect();
-        let stdio_path: PathBuf = ["rc-stickynote-displayer.log"].iter().collect();
-        let stdio_handle = File::create(&stdio_path)?;
+        daemon::daemonize(&config.daemon, &config.log)?;
+    }
 
-        let dconfig = Daemonize::new()
-            .pid_file(&pid_path)
-            .stdout(stdio_handle.try_clone()?)
-            .stderr(stdio_handle);
+    let mut rt = Runtime::new()?;
+    rt.block_on(run::<B>(config))
+}
 
-        if let Err(e) = dconfig.start() {
-            return Err(Error::new(std::io::ErrorKind::Other, e.to_string()));
-        }
-    }
+/// Drive the panel from a hub running in this same process, rather than one
+/// reached through the configuration's settings. This runs in the caller's
+/// runtime, so that the all-in-one program can run the hub alongside it.
+pub async fn run_with_hub<B: DisplayBackend>(connect: InProcessHub) -> Result<(), Error> {
+    openssl_probe::init_ssl_cert_env_vars();
+
+    let mut config: ClientConfiguration = confy::load("rc-stickynote-client")?;
+    config.display_id = Some(identity::display_id(config.display_id.as_deref())?);
+    config.in_process_hub = Some(connect);
+    config.mqtt = None;
+
+    run::<B>(config).await
+}
 
+/// Run the client: start up the helper threads, then handle events forever.
+async fn run<B: DisplayBackend>(config: ClientConfiguration) -> Result<(), Error> {
     // The actual renderer operates in its own thread since the I/O can be slow
     // and we don't want to block the async runtime.
     let cloned_config = config.clone();
     let (sender, receiver) = channel();
-    thread::spawn(move || renderer_thread(cloned_config, receiver));
+    let room: SharedMeasurement = Arc::new(Mutex::new(None));
+    let cloned_room = room.clone();
+    let widget_text: SharedWidgets = Arc::new(Mutex::new(Vec::new()));
+    widgets::spawn_widget_threads(&config.widgets, widget_text.clone());
+    let health: SharedHealth = Arc::new(Mutex::new(Health::default()));
+    let cloned_health = health.clone();
+    let toast: SharedToast = Arc::new(Mutex::new(None));
+    let cloned_toast = toast.clone();
+    let pulse = Pulse::default();
+    let cloned_pulse = pulse.clone();
+    let (held_sender, mut held_receiver) = mpsc::unbounded_channel();
+
+    if let Some(ref standalone_config) = config.standalone {
+        standalone::start(standalone_config).await?;
+    }
 
-    let mut rt = Runtime::new()?;
+    // We keep a sender of our own, since if nothing is forwarded, a closed
+    // channel would wake up the main loop over and over.
+    forward::start(config.clone(), held_sender.clone()).await?;
+    let telemetry: TelemetryRoute = Arc::new(Mutex::new(None));
+    let (telemetry_sender, mut telemetry_receiver) = mpsc::unbounded_channel();
+    let renderer = thread::spawn(move || {
+        renderer_thread::<B>(
+            cloned_config,
+            receiver,
+            cloned_room,
+            widget_text,
+            cloned_health,
+            cloned_toast,
+            cloned_pulse,
+        )
+    });
+
+    watchdog::spawn(&config.watchdog, pulse.clone())?;
+
+    if config.health.enabled {
+        let cloned_config = config.clone();
+        let telemetry = telemetry.clone();
+        thread::spawn(move || health_thread(cloned_config, health, telemetry));
+    }
+
+    if let Some(ref sensor_config) = config.sensor {
+        let cloned_config = config.clone();
+        let sensor_config = sensor_config.clone();
+        let telemetry = telemetry.clone();
+        thread::spawn(move || sensor_thread(cloned_config, sensor_config, room, telemetry));
+    }
+
+    if let Some(pin_number) = config.doorbell_button_gpio {
+        let cloned_config = config.clone();
+        let toast = toast.clone();
+        thread::spawn(move || {
+            button_thread(cloned_config, pin_number, toast, "doorbell", send_doorbell)
+        });
+    }
+
+    if let Some(pin_number) = config.room_button_gpio {
+        let cloned_config = config.clone();
+        let toast = toast.clone();
+        thread::spawn(move || {
+            button_thread(
+                cloned_config,
+                pin_number,
+                toast,
+                "room booking",
+                send_room_booking,
+            )
+        });
+    }
+
+    if let Some(ref update_config) = config.update {
+        if update_config.auto_check_hours > 0 {
+            let update_config = update_config.clone();
+            thread::spawn(move || update::auto_update_thread(update_config));
+        }
+    }
+
+    // Needed to restart on command; see `update::auto_update_thread()`.
+    let exe = std::env::current_exe()?;
 
     // Ready to start the main event loop
 
-    rt.block_on(async {
-        // How often to wake up this thread if no other events are going
-        // on.
-        let mut wakeup_interval = time::interval(Duration::from_millis(60_000));
+    // Whether we're redrawing often, as during work hours. This
+    // determines the next two durations.
+    let mut active = true;
 
-        // the last time something happened with the hub connection.
-        let mut last_hub_update = time::Instant::now();
+    // How often to wake up this thread if no other events are going
+    // on, and how often to redraw the display even if nothing seems to be
+    // going on. The latter will update the clock, etc. Both are counted
+    // in ticks of the wall clock, so that the panel's clock changes on
+    // the minute.
+    let partial_refresh = B::SUPPORTS_PARTIAL_REFRESH;
+    let (mut wakeup_duration, mut redraw_duration) =
+        config.polling.intervals(active, partial_refresh);
 
-        // if there's a hub problem, wait this long to retry connecting.
-        let hub_retry_duration = Duration::from_millis(180_000);
+    // the last time something happened with the hub connection.
+    let mut last_hub_update = time::Instant::now();
 
-        // How often to redraw the display even if nothing seems to be going on.
-        // This will update the clock, etc.
-        let redraw_duration = Duration::from_millis(600_000);
+    // if there's a hub problem, wait this long to retry connecting.
+    let hub_retry_duration = Duration::from_millis(180_000);
 
-        // the last time we redrew the display (approximately, since that's
-        // done in another thread and takes nontrivial time).
-        let mut last_redraw = time::Instant::now();
+    // the redraw tick in which we last redrew the display.
+    let mut last_redraw_tick = schedule::tick_number(Local::now(), redraw_duration);
 
-        // do we need to redraw even if redraw_duration hasn't elapsed?
-        let mut need_redraw = true;
+    // do we need to redraw even if redraw_duration hasn't elapsed?
+    let mut need_redraw = true;
 
-        let mut display_data = DisplayData::new()?;
-        let mut connection = ServerConnection::default();
+    // do we need to redraw even if we're asleep?
+    let mut need_urgent_redraw = false;
 
-        loop {
-            // `select` on various things that might motivate us to update the
-            // display.
+    // are we inside the configured sleep window?
+    let mut asleep = false;
 
-            select! {
-                // New message from the hub.
-                msg = connection.get_next_message(&config).fuse() => {
-                    last_hub_update = time::Instant::now();
-                    need_redraw = true;
+    let mut display_data = DisplayData::new(&config.addresses)?;
+    let mut connection = ServerConnection::default();
+    let capabilities = render::capabilities::<B>();
 
-                    match msg {
-                        Ok(m) => {
-                            display_data.update_from_message(m);
-                        },
+    // The hub keeps sending the latest command, so we only carry out
+    // ones issued after the last one that we saw. Ones from before we
+    // started are ignored, so that a restart command doesn't loop.
+    let mut last_command = Utc::now();
 
-                        Err(err) => {
-                            // Note that we do *not* instantly reset `connection`,
-                            // because otherwise we just keep on trying to connect
-                            // over and over again. If the hub is just totally
-                            // down, insistently trying isn't going to help.
-                            println!("hub connection failed: {}", err);
-                            display_data.update_for_no_connection();
+    // When the hub sent the last message that we got, so that after an
+    // outage, it can tell us what we missed.
+    let mut last_update = None;
+
+    loop {
+        // `select` on various things that might motivate us to update the
+        // display.
+
+        let mut outgoing = None;
+
+        select! {
+            // New message from the hub.
+            msg = connection.get_next_message(&config, Some(&capabilities), last_update).fuse() => {
+                last_hub_update = time::Instant::now();
+                need_redraw = true;
+
+                match msg {
+                    Ok(mut m) => {
+                        m.person_is = config.unseal_status(m.person_is);
+                        last_update = m.sent_at.or(last_update);
+
+                        if let Some(missed) = m.missed.take() {
+                            toast_missed(&toast, &missed);
+                        }
+
+                        if let Some(ref mut next) = m.next_status {
+                            next.person_is = config.unseal_status(next.person_is.clone());
+                        }
+                        let command = m.command.clone();
+                        let prev_doorbell = display_data.doorbell_until;
+                        display_data.update_from_message(m);
+
+                        if display_data.doorbell_until != prev_doorbell {
+                            need_urgent_redraw = true;
+                        }
+
+                        if let Some(c) = command {
+                            if c.issued > last_command {
+                                last_command = c.issued;
+                                handle_command(&config, &exe, &mut display_data, c.command);
+                                need_urgent_redraw = true;
+                            }
+                        }
+                    },
+
+                    Err(err) => {
+                        // If trying again won't help, say so on the panel
+                        // and give up, once the renderer has drawn it.
+                        if let Some(fatal) = FatalError::from_hub_error(&err) {
+                            eprintln!("ERROR: giving up: {}", fatal.message);
+                            pulse.stop();
+                            display_data.fatal_error = Some(fatal);
+                            let _ = sender.send(display_data);
+                            drop(sender);
+                            let _ = renderer.join();
+                            return Err(err);
                         }
+
+                        // Note that we do *not* instantly reset `connection`,
+                        // because otherwise we just keep on trying to connect
+                        // over and over again. If the hub is just totally
+                        // down, insistently trying isn't going to help.
+                        println!("hub connection failed: {}", err);
+                        display_data.update_for_no_connection();
                     }
                 }
+            }
 
-                // Time has passed since the last wakeup interval tick.
-                _ = wakeup_interval.tick().fuse() => {}
+            // We've reached the next wakeup tick.
+            _ = time::delay_for(schedule::until_next_tick(Local::now(), wakeup_duration)).fuse() => {}
+
+            // The doorbell card should come down.
+            _ = delay_for_maybe(display_data.doorbell_wait()).fuse() => {
+                display_data.doorbell_until = None;
+                need_urgent_redraw = true;
             }
 
-            let now = time::Instant::now();
+            // An update that couldn't reach the hub, to show in the meantime.
+            hello = held_receiver.recv().fuse() => {
+                if let Some(mut hello) = hello {
+                    if let ClientHelloMessage::PersonIsUpdate(ref mut msg) = hello {
+                        msg.person_is = config.unseal_status(msg.person_is.clone());
+                    }
 
-            // Housekeeping: how's the hub connection looking? If the connection is
-            // happy, we're content to just sit and wait -- update messages might
-            // not arrive for *days*. But if the connection has problems, retry if
-            // the time is right.
+                    if display_data.update_from_held(hello) {
+                        need_urgent_redraw = true;
+                    }
+                }
+            }
 
-            if connection.is_failed() && now.duration_since(last_hub_update) > hub_retry_duration {
-                display_data.update_for_no_connection();
-                println!("hub error and delay elapsed; attempting to reconnect ...");
-                connection = ServerConnection::default();
+            // A report to pass along to the hub.
+            hello = telemetry_receiver.recv().fuse() => {
+                outgoing = hello;
             }
+        }
 
-            // Trigger a draw?
+        if let Some(hello) = outgoing {
+            if let Err(e) = connection.send_telemetry(hello).await {
+                println!("failed to pass report along to hub: {}", e);
+            }
+        }
 
-            if need_redraw || now.duration_since(last_redraw) > redraw_duration {
-                if let Err(e) = sender.send(display_data.clone()) {
-                    // Yikes, this is bad. We don't want to exit the program so ...
-                    // just print the error and ignore it. Not much else we can do.
-                    // (We could try sending a message to the hub?)
-                    println!("display thread died?! {}", e);
-                }
+        // Only a hub that's sharing the connection can take reports on it.
+        // Otherwise, they go on connections of their own.
+
+        *telemetry.lock().unwrap() = if connection.is_multiplexed() {
+            Some(telemetry_sender.clone())
+        } else {
+            None
+        };
+
+        let now = time::Instant::now();
+
+        // Time to speed up or slow down?
+
+        let since_status = (Utc::now() + display_data.clock_offset)
+            .signed_duration_since(display_data.person_is_timestamp)
+            .to_std()
+            .unwrap_or_default();
+        let now_active = config.polling.is_active(Local::now().time(), since_status);
+
+        if now_active != active {
+            active = now_active;
+            let (new_wakeup_duration, new_redraw_duration) =
+                config.polling.intervals(active, partial_refresh);
+            println!(
+                "now {}; redrawing every {} minutes",
+                if active { "active" } else { "idle" },
+                new_redraw_duration.as_secs() / 60
+            );
+            wakeup_duration = new_wakeup_duration;
+            redraw_duration = new_redraw_duration;
+            last_redraw_tick = schedule::tick_number(Local::now(), redraw_duration);
+        }
+
+        // Housekeeping: how's the hub connection looking? If the connection is
+        // happy, we're content to just sit and wait -- update messages might
+        // not arrive for *days*. But if the connection has problems, retry if
+        // the time is right.
+
+        if connection.is_failed() && now.duration_since(last_hub_update) > hub_retry_duration {
+            display_data.update_for_no_connection();
+            println!("hub error and delay elapsed; attempting to reconnect ...");
+            connection = ServerConnection::default();
+        }
+
+        // Going to sleep or waking up? Either way, redraw: once to show
+        // that we're asleep, and once to show everything that we slept
+        // through. After a long sleep, clear the panel fully as well.
+
+        let sleep_window = config
+            .sleep
+            .as_ref()
+            .filter(|s| s.window.contains(Local::now().time()));
 
-                need_redraw = false;
-                last_redraw = now;
+        if sleep_window.is_some() != asleep {
+            asleep = sleep_window.is_some();
+            display_data.asleep_until = sleep_window.map(|s| s.window.end.0);
+            need_urgent_redraw = true;
+
+            if asleep {
+                println!("going to sleep");
+            } else {
+                println!("waking up");
+                display_data.clear_first = true;
             }
         }
-    })
+
+        // Trigger a draw?
+
+        let redraw_tick = schedule::tick_number(Local::now(), redraw_duration);
+
+        let redraw = if let Some(s) = sleep_window {
+            need_urgent_redraw || (need_redraw && s.wake_for_status)
+        } else {
+            need_urgent_redraw || need_redraw || redraw_tick != last_redraw_tick
+        };
+
+        if redraw {
+            if let Err(e) = sender.send(display_data.clone()) {
+                // Yikes, this is bad. We don't want to exit the program so ...
+                // just print the error and ignore it. Not much else we can do.
+                // (We could try sending a message to the hub?)
+                println!("display thread died?! {}", e);
+            }
+
+            display_data.clear_first = false;
+            display_data.full_refresh = false;
+            need_redraw = false;
+            need_urgent_redraw = false;
+            last_redraw_tick = redraw_tick;
+        }
+
+        pulse.main_loop_ran(!connection.is_failed());
+    }
+}
+
+/// The layouts that the renderer knows how to draw.
+const LAYOUTS: &[&str] = &["standard", "status", "week", "room"];
+
+/// Carry out a command from the hub, if the configuration allows it.
+fn handle_command(
+    config: &ClientConfiguration,
+    exe: &Path,
+    dd: &mut DisplayData,
+    command: PanelCommand,
+) {
+    if !config.allowed_commands.iter().any(|c| c == command.name()) {
+        println!("ignoring disallowed command from hub: {}", command);
+        return;
+    }
+
+    println!("command from hub: {}", command);
+
+    match command {
+        PanelCommand::Redraw => {
+            dd.screensaver = false;
+            dd.full_refresh = true;
+        }
+
+        PanelCommand::Clear => {
+            dd.screensaver = false;
+            dd.clear_first = true;
+        }
+
+        PanelCommand::Restart => update::restart(exe),
+
+        PanelCommand::Layout(name) => {
+            if LAYOUTS.contains(&name.as_str()) {
+                dd.layout = name;
+            } else {
+                println!("ignoring unknown layout: {}", name);
+            }
+        }
+
+        PanelCommand::Screensaver => {
+            dd.screensaver = true;
+        }
+    }
+}
+
+/// Wait for the specified duration, or forever if it is None.
+async fn delay_for_maybe(duration: Option<Duration>) {
+    match duration {
+        Some(d) => time::delay_for(d).await,
+        None => futures::future::pending().await,
+    }
 }
 
 enum ServerConnection {
     Initializing,
-    Open(HubTransport),
+    Open(DisplaySession<HubTransport>),
+    Mqtt(mpsc::UnboundedReceiver<DisplayMessage>),
     Failed,
 }
 
@@ -278,17 +1225,45 @@ impl ServerConnection {
         }
     }
 
+    fn is_multiplexed(&self) -> bool {
+        match self {
+            ServerConnection::Open(ref hub_comms) => hub_comms.is_multiplexed(),
+            _ => false,
+        }
+    }
+
+    /// Send the hub a report on the display connection.
+    async fn send_telemetry(&mut self, hello: ClientHelloMessage) -> Result<(), Error> {
+        match self {
+            ServerConnection::Open(ref mut hub_comms) => {
+                hub_comms.send(ClientFrame::Telemetry(hello)).await
+            }
+
+            _ => Err(Error::new(
+                std::io::ErrorKind::NotConnected,
+                "the hub connection is down",
+            )),
+        }
+    }
+
     async fn get_next_message(
         &mut self,
         config: &ClientConfiguration,
+        capabilities: Option<&DisplayCapabilities>,
+        last_update: Option<DateTime<Utc>>,
     ) -> Result<DisplayMessage, Error> {
         loop {
             match self {
                 ServerConnection::Initializing => {
+                    if let Some(mqtt_config) = config.mqtt() {
+                        *self = ServerConnection::Mqtt(crate::mqtt::display_messages(mqtt_config));
+                        continue;
+                    }
+
                     // Note: cannot use ?-syntax here since we need to ensure that we set
                     // self to the Failed state is anything goes wrong.
 
-                    let mut hub_comms = match config.connect().await {
+                    let hub_comms = match config.connect().await {
                         Ok(c) => c,
 
                         Err(e) => {
@@ -297,19 +1272,25 @@ impl ServerConnection {
                         }
                     };
 
-                    if let Err(e) = hub_comms
-                        .send(ClientHelloMessage::Display(DisplayHelloMessage {}))
-                        .await
-                    {
-                        *self = ServerConnection::Failed;
-                        return Err(e);
-                    }
+                    let hello = DisplayHelloMessage {
+                        display_id: config.display_id.clone(),
+                        capabilities: capabilities.cloned(),
+                        multiplex: true,
+                        last_update,
+                    };
+
+                    match hub_comms.start_display(hello).await {
+                        Ok(session) => *self = ServerConnection::Open(session),
 
-                    *self = ServerConnection::Open(hub_comms);
+                        Err(e) => {
+                            *self = ServerConnection::Failed;
+                            return Err(e);
+                        }
+                    }
                 }
 
                 ServerConnection::Open(ref mut hub_comms) => {
-                    return match hub_comms.try_next().await {
+                    return match hub_comms.next_message().await {
                         Ok(Some(m)) => {
                             println!("msg: {:?}", m);
                             Ok(m)
@@ -329,6 +1310,20 @@ impl ServerConnection {
                     };
                 }
 
+                ServerConnection::Mqtt(ref mut receiver) => {
+                    return match receiver.recv().await {
+                        Some(m) => {
+                            println!("msg: {:?}", m);
+                            Ok(m)
+                        }
+
+                        None => {
+                            *self = ServerConnection::Failed;
+                            Err(Error::new(std::io::ErrorKind::Other, "MQTT thread died"))
+                        }
+                    };
+                }
+
                 ServerConnection::Failed => {
                     return futures::future::pending().await;
                 }
@@ -337,36 +1332,64 @@ impl ServerConnection {
     }
 }
 
-fn renderer_thread(config: ClientConfiguration, receiver: Receiver<DisplayData>) {
-    if let Err(e) = renderer_thread_inner(config, receiver) {
+fn renderer_thread<B: DisplayBackend>(
+    config: ClientConfiguration,
+    receiver: Receiver<DisplayData>,
+    room: SharedMeasurement,
+    widget_text: SharedWidgets,
+    health: SharedHealth,
+    toast: SharedToast,
+    pulse: Pulse,
+) {
+    if let Err(e) =
+        renderer_thread_inner::<B>(config, receiver, room, widget_text, health, toast, pulse)
+    {
         eprintln!("ERROR: rendererer thread exited with error: {}", e);
     }
 }
 
-fn renderer_thread_inner(
+fn renderer_thread_inner<B: DisplayBackend>(
     config: ClientConfiguration,
     receiver: Receiver<DisplayData>,
+    room: SharedMeasurement,
+    widget_text: SharedWidgets,
+    health: SharedHealth,
+    toast: SharedToast,
+    pulse: Pulse,
 ) -> Result<(), std::io::Error> {
-    // Note that Backend is not Send, so we have to open it up in this thread.
-    let mut backend = Backend::open()?;
-
-    let sans_font = {
-        let mut file = File::open(&config.sans_path)?;
-        let mut font_data = Vec::new();
-        file.read_to_end(&mut font_data)?;
-        let collection = FontCollection::from_bytes(font_data)?;
-        collection.into_font()?
-    };
+    // Note that backends needn't be Send, so we have to open it up in this
+    // thread.
+    let mut backend = B::open()?;
+
+    // Now that the panel is open, we can give up root, if we had it. Carrying
+    // on as root isn't an option if that was the plan.
+    if let Err(e) = daemon::drop_privileges(&config.daemon) {
+        eprintln!("ERROR: cannot switch users: {}", e);
+        std::process::exit(1);
+    }
 
-    let serif_font = {
-        let mut file = File::open(&config.serif_path)?;
-        let mut font_data = Vec::new();
-        file.read_to_end(&mut font_data)?;
-        let collection = FontCollection::from_bytes(font_data)?;
-        collection.into_font()?
+    let sans_font = fonts::load(config.sans_path.as_deref(), FontFamily::Sans);
+    let serif_font = fonts::load(config.serif_path.as_deref(), FontFamily::Serif);
+
+    // Dithering a picture is slow, so do it once, up front. A bad picture
+    // shouldn't keep the panel from working, so it just leaves the
+    // screensaver blank.
+    let screensaver = match config.screensaver_image {
+        Some(ref img) => match render::load_picture::<B>(img) {
+            Ok(p) => Some(p),
+            Err(e) => {
+                eprintln!("ERROR: cannot load screensaver image: {}", e);
+                None
+            }
+        },
+        None => None,
     };
 
-    let ago_formatter = timeago::Formatter::new();
+    // Used to rotate through the news headlines, one per redraw.
+    let mut n_redraws = 0;
+
+    // Used to refresh only the parts of the panel that have changed.
+    let mut tracker = DirtyTracker::default();
 
     loop {
         // Zip through the channel until we find the very latest message.
@@ -391,149 +1414,52 @@ fn renderer_thread_inner(
             };
         }
 
-        // Update the "local" bits.
-
-        dd.update_local()?;
-
-        // Render into the buffer.
-
-        {
-            backend.clear_buffer(Backend::WHITE)?;
-            let buffer = backend.get_buffer_mut();
-
-            fn draw6x8(buf: &mut <Backend as DisplayBackend>::Buffer, s: &str, x: i32, y: i32) {
-                buf.draw(
-                    Font6x8::render_str(s)
-                        .style(Style {
-                            fill_color: Some(Backend::WHITE),
-                            stroke_color: Some(Backend::BLACK),
-                            stroke_width: 0u8, // Has no effect on fonts
-                        })
-                        .translate(Coord::new(x, y))
-                        .into_iter(),
-                );
-            }
-
-            fn draw6x8inverted(
-                buf: &mut <Backend as DisplayBackend>::Buffer,
-                s: &str,
-                x: i32,
-                y: i32,
-            ) {
-                buf.draw(
-                    Font6x8::render_str(s)
-                        .style(Style {
-                            fill_color: Some(Backend::BLACK),
-                            stroke_color: Some(Backend::WHITE),
-                            stroke_width: 0u8, // Has no effect on fonts
-                        })
-                        .translate(Coord::new(x, y))
-                        .into_iter(),
-                );
-            }
-
-            // The clock
-
-            let now = dd.now.format("%I:%M %p").to_string();
-
-            buffer.draw(sans_font.rasterize(&now, 56.0).draw_at(
-                2,
-                0,
-                Backend::BLACK,
-                Backend::WHITE,
-            ));
-
-            let x = 230;
-            let y = 8;
-            let delta = 10;
-
-            draw6x8(buffer, "May be up to 15 minutes", x, y + 0 * delta);
-            draw6x8(buffer, "out of date. If much more", x, y + 1 * delta);
-            draw6x8(buffer, "than that, tell Peter his", x, y + 2 * delta);
-            draw6x8(buffer, "sticky note is broken.", x, y + 3 * delta);
-
-            // hline
-
-            buffer.draw(
-                Line::new(Coord::new(0, 52), Coord::new(383, 52)).style(Style {
-                    fill_color: Some(Backend::BLACK),
-                    stroke_color: Some(Backend::BLACK),
-                    stroke_width: 1u8,
-                }),
-            );
-
-            // "The Innovation Scientist is ..." text
-
-            let x = 8;
-            let y = 54;
-            let delta = 54;
-
-            buffer.draw(serif_font.rasterize("The Innovation", 64.0).draw_at(
-                x,
-                y,
-                Backend::BLACK,
-                Backend::WHITE,
-            ));
-
-            buffer.draw(serif_font.rasterize("Scientist is:", 64.0).draw_at(
-                x + 2,
-                y + delta,
-                Backend::BLACK,
-                Backend::WHITE,
-            ));
-
-            // The actual status message
-
-            let y = y + 2 * delta + 12;
-            let delta = delta;
-
-            buffer.draw(
-                Rectangle::new(Coord::new(0, y), Coord::new(383, y + delta))
-                    .fill(Some(Backend::BLACK)),
-            );
-
-            let layout = sans_font.rasterize(&dd.person_is, 32.0);
-            let x = if layout.width as i32 > 384 {
-                0
-            } else {
-                (384 - layout.width as i32) / 2
-            };
-            let yofs = if layout.height as i32 > delta {
-                0
-            } else {
-                (delta - layout.height as i32) / 2
-            };
+        pulse.render_started();
 
-            buffer.draw(layout.draw_at(x, y + yofs, Backend::WHITE, Backend::BLACK));
+        // Update the "local" bits.
 
-            // "updated at ..." to go with the status message
+        dd.update_local(&config.addresses)?;
 
-            let y = y + delta + 4;
+        if let Some(ref fatal) = dd.fatal_error {
+            return crash::show(&mut backend, &sans_font, fatal, &dd.ip_addr);
+        }
 
-            let msg = format!(
-                "updated at {} (more than {})",
-                dd.person_is_timestamp
-                    .with_timezone(&dd.now.timezone())
-                    .format("%I:%M %p"),
-                ago_formatter.convert_chrono(dd.person_is_timestamp, dd.now)
-            );
-            let x = 382 - 6 * (msg.len() as i32);
-            draw6x8(buffer, &msg, x, y);
+        // Render into the buffer.
 
-            // Footer and IP address
+        let ago_formatter = dd.ago_formatter();
+
+        let ctx = RenderContext {
+            config: &config,
+            dd: &dd,
+            sans_font: &sans_font,
+            serif_font: &serif_font,
+            ago_formatter: &ago_formatter,
+            room: *room.lock().unwrap(),
+            widget_text: widgets::current_text(&config.widgets, &widget_text, &dd.extras),
+            health_warning: health.lock().unwrap().warning(&config.health),
+            toast: toast
+                .lock()
+                .unwrap()
+                .as_ref()
+                .filter(|(_, until)| std::time::Instant::now() < *until)
+                .map(|(message, _)| message.clone()),
+            screensaver: screensaver.as_ref(),
+            n_redraws,
+        };
 
-            let y = 630;
-            let delta = 9;
+        let widgets = render::layout_widgets::<B>(&dd);
+        render::compose(&widgets, &ctx, &mut backend)?;
 
-            buffer.draw(
-                Rectangle::new(Coord::new(0, y), Coord::new(383, y + delta))
-                    .fill(Some(Backend::BLACK)),
-            );
+        // Work out how much of the panel needs refreshing. If nothing has
+        // changed, we can leave the panel asleep.
 
-            draw6x8inverted(buffer, "https://github.com/pkgw/rc-stickynote", 2, y + 1);
+        let layout = format!("{} {}", dd.layout, dd.screensaver);
+        let refresh =
+            tracker.refresh_for(&layout, &widgets, &ctx, dd.clear_first || dd.full_refresh);
 
-            let x = 382 - 6 * (dd.ip_addr.len() as i32);
-            draw6x8inverted(buffer, &dd.ip_addr, x, y + 1);
+        if refresh == Refresh::Nothing {
+            pulse.render_finished();
+            continue;
         }
 
         // https://www.waveshare.com/wiki/E-Paper_Driver_HAT:
@@ -552,10 +1478,25 @@ fn renderer_thread_inner(
         // In principle we could try to be smart and have a timer for sleeping
         // the device to avoid multiple cycles during rapid-fire updates, but
         // that seems like overkill.
+        //
+        // If we're getting rid of ghosting, that has to happen between waking
+        // the device and showing the new frame, since the clear leaves the
+        // buffer alone.
 
         backend.wake_up_device()?;
-        backend.show_buffer()?;
+
+        if dd.clear_first {
+            backend.clear_display()?;
+        }
+
+        match refresh {
+            Refresh::Region(region) => backend.show_region(region)?,
+            _ => backend.show_buffer()?,
+        }
+
         backend.sleep_device()?;
+        n_redraws += 1;
+        pulse.render_finished();
     }
 
     Ok(())
@@ -566,47 +1507,245 @@ struct DisplayData {
     // Digested from DisplayMessage:
     pub person_is: String,
     pub person_is_timestamp: DateTime<Utc>,
+    pub person_is_source: String,
+    pub person_is_set_by: String,
+    pub notes_waiting: usize,
+    pub note_form_url: Option<String>,
+    pub doorbell_until: Option<DateTime<Utc>>,
+    pub headlines: Vec<String>,
+    pub maintenance: bool,
+    pub availability: Option<Availability>,
+    pub ci: Vec<CiStatus>,
+    pub on_call: Vec<OnCall>,
+    pub counters: Vec<Counter>,
+    pub office_days: Vec<OfficeDays>,
+    pub room: Option<RoomSchedule>,
+    pub next_status: Option<QueuedStatus>,
+    pub extras: BTreeMap<String, serde_json::Value>,
+
+    /// How far ahead of ours the hub's clock is.
+    pub clock_offset: chrono::Duration,
 
     // "Local" values determined without the hub:
     pub now: DateTime<Local>,
     pub ip_addr: String,
+    pub hostname: Option<String>,
+    pub wifi: Option<WifiStatus>,
+
+    // Set by commands from the hub:
+    pub layout: String,
+    pub screensaver: bool,
+    pub clear_first: bool,
+    pub full_refresh: bool,
+
+    // Set in the hub's configuration:
+    pub settings: DisplaySettings,
+
+    // Set by the sleep schedule:
+    pub asleep_until: Option<NaiveTime>,
+
+    // Set when the client is giving up:
+    pub fatal_error: Option<FatalError>,
+
+    // Set by a status that couldn't reach the hub, which stays up until the
+    // hub is back:
+    pub status_held: bool,
 }
 
 impl DisplayData {
-    fn new() -> Result<Self, std::io::Error> {
+    fn new(addresses: &AddressConfiguration) -> Result<Self, std::io::Error> {
         let mut dd = DisplayData {
             now: Local::now(),
             person_is: "[connecting to hub...]".to_owned(),
             person_is_timestamp: Utc::now(),
+            person_is_source: String::new(),
+            person_is_set_by: String::new(),
+            notes_waiting: 0,
+            note_form_url: None,
+            doorbell_until: None,
+            headlines: Vec::new(),
+            maintenance: false,
+            availability: None,
+            ci: Vec::new(),
+            on_call: Vec::new(),
+            counters: Vec::new(),
+            office_days: Vec::new(),
+            room: None,
+            next_status: None,
+            extras: BTreeMap::new(),
+            clock_offset: chrono::Duration::zero(),
             ip_addr: "".to_owned(),
+            hostname: None,
+            wifi: None,
+            layout: "standard".to_owned(),
+            screensaver: false,
+            clear_first: false,
+            full_refresh: false,
+            settings: DisplaySettings::default(),
+            asleep_until: None,
+            fatal_error: None,
+            status_held: false,
         };
-        dd.update_local()?;
+        dd.update_local(addresses)?;
         Ok(dd)
     }
 
     fn update_from_message(&mut self, msg: DisplayMessage) {
+        // The hub keeps sending the doorbell time after it has passed, so
+        // ignore it then.
+        let now = Utc::now();
+        let doorbell_until = msg.doorbell_until.filter(|t| *t > now);
+
+        // The screensaver goes away when there's something new to see.
+        if msg.person_is_timestamp != self.person_is_timestamp
+            || (doorbell_until.is_some() && doorbell_until != self.doorbell_until)
+        {
+            self.screensaver = false;
+        }
+
         self.person_is = msg.person_is;
         self.person_is_timestamp = msg.person_is_timestamp;
-    }
+        self.person_is_source = msg.person_is_source;
+        self.person_is_set_by = msg.person_is_set_by;
+        self.status_held = false;
+        self.notes_waiting = msg.notes_waiting;
+        self.note_form_url = msg.note_form_url;
+        self.doorbell_until = doorbell_until;
+        self.headlines = msg.headlines;
+        self.maintenance = msg.maintenance;
+        self.availability = msg.availability;
+        self.ci = msg.ci;
+        self.on_call = msg.on_call;
+        self.counters = msg.counters;
+        self.office_days = msg.office_days;
+        self.room = msg.room;
+        self.next_status = msg.next_status;
+        self.extras = msg.extras;
+
+        // The message spends a moment in transit, so this slightly
+        // underestimates how far ahead the hub's clock is, but that's close
+        // enough for "updated 5 minutes ago".
+        if let Some(sent_at) = msg.sent_at {
+            self.clock_offset = sent_at.signed_duration_since(now);
+        }
 
-    fn update_local(&mut self) -> Result<(), std::io::Error> {
-        self.now = Local::now();
+        // If the hub's configuration picks a layout, switch to it when it
+        // changes, but otherwise leave any layout that an admin picked.
+        let settings = msg.settings.unwrap_or_default();
 
-        self.ip_addr = "???.???.???.???".to_owned();
+        if settings.layout != self.settings.layout {
+            self.layout = match settings.layout {
+                Some(ref l) if LAYOUTS.contains(&l.as_str()) => l.clone(),
 
-        for iface in &get_if_addrs::get_if_addrs()? {
-            if !iface.is_loopback() {
-                if let get_if_addrs::IfAddr::V4(ref addr) = iface.addr {
-                    self.ip_addr = addr.ip.to_string();
-                    break;
+                Some(ref l) => {
+                    println!("ignoring unknown layout from hub settings: {}", l);
+                    "standard".to_owned()
                 }
-            }
+
+                None => "standard".to_owned(),
+            };
         }
 
+        self.settings = settings;
+    }
+
+    /// The current time by the hub's clock, as best we can tell.
+    fn hub_now(&self) -> DateTime<Local> {
+        self.now + self.clock_offset
+    }
+
+    /// The strftime-style format for times on the clock.
+    fn clock_format(&self) -> &str {
+        self.settings.clock_format.as_deref().unwrap_or("%I:%M %p")
+    }
+
+    /// Something to describe how long ago things happened, in the language
+    /// that the hub's configuration asks for.
+    fn ago_formatter(&self) -> timeago::Formatter<Box<dyn timeago::Language>> {
+        let language = self
+            .settings
+            .locale
+            .as_deref()
+            .and_then(IsolangLanguage::from_639_1)
+            .and_then(timeago::languages::from_isolang)
+            .unwrap_or_else(|| timeago::languages::boxup(timeago::English));
+
+        timeago::Formatter::with_language(language)
+    }
+
+    /// How long until the doorbell card should come down, if it's up.
+    fn doorbell_wait(&self) -> Option<Duration> {
+        self.doorbell_until.map(|t| {
+            t.signed_duration_since(Utc::now())
+                .to_std()
+                .unwrap_or_else(|_| Duration::from_millis(0))
+        })
+    }
+
+    fn update_local(&mut self, addresses: &AddressConfiguration) -> Result<(), std::io::Error> {
+        self.now = Local::now();
+
+        let shown: Vec<String> = addresses
+            .labeled_addresses()?
+            .into_iter()
+            .take(addresses.footer_count.max(1))
+            .map(|(label, ip)| format!("{} {}", label, ip))
+            .collect();
+
+        self.ip_addr = if shown.is_empty() {
+            "???.???.???.???".to_owned()
+        } else {
+            shown.join("  ")
+        };
+
+        // Only look these up if they're shown, since asking `iw` means
+        // running a program.
+
+        self.hostname = if addresses.footer_hostname {
+            netstatus::hostname()
+        } else {
+            None
+        };
+
+        self.wifi = if addresses.footer_wifi {
+            netstatus::wifi_status()
+        } else {
+            None
+        };
+
         Ok(())
     }
 
+    /// Show an update that couldn't reach the hub, until the hub has its
+    /// say. Returns whether there was anything to show.
+    fn update_from_held(&mut self, hello: ClientHelloMessage) -> bool {
+        match hello {
+            ClientHelloMessage::PersonIsUpdate(msg) => {
+                self.person_is = msg.person_is;
+                self.person_is_timestamp = msg.timestamp;
+                self.person_is_source = msg.source.unwrap_or_default();
+                self.person_is_set_by = msg.set_by.unwrap_or_default();
+                self.screensaver = false;
+                self.status_held = true;
+                true
+            }
+
+            ClientHelloMessage::Doorbell(msg) => {
+                self.doorbell_until =
+                    Some(msg.timestamp + chrono::Duration::seconds(LOCAL_DOORBELL_SECONDS));
+                self.screensaver = false;
+                true
+            }
+
+            _ => false,
+        }
+    }
+
     fn update_for_no_connection(&mut self) {
+        if self.status_held {
+            return;
+        }
+
         // TODO: should preserve the person_is message since it may
         // have contained useful information.
         self.person_is = "[cannot connect to hub!]".to_owned();
@@ -615,30 +1754,406 @@ impl DisplayData {
 
 /// Send a status update to the hub. This uses the same infrastructure as the
 /// main client but is way simpler.
+/// Watch a GPIO pin connected to a button, like the doorbell, and tell the
+/// hub when it's pressed.
+fn button_thread(
+    config: ClientConfiguration,
+    pin_number: u64,
+    toast: SharedToast,
+    name: &str,
+    send: fn(&ClientConfiguration) -> Result<(), Error>,
+) {
+    if let Err(e) = button_thread_inner(config, pin_number, toast, name, send) {
+        eprintln!("ERROR: {} button thread exited with error: {}", name, e);
+    }
+}
+
+fn button_thread_inner(
+    config: ClientConfiguration,
+    pin_number: u64,
+    toast: SharedToast,
+    name: &str,
+    send: fn(&ClientConfiguration) -> Result<(), Error>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use linux_embedded_hal::sysfs_gpio::{Direction, Pin};
+
+    let pin = Pin::new(pin_number);
+    pin.export()?;
+    pin.set_direction(Direction::In)?;
+
+    // Don't let someone leaning on the button spam the hub.
+    let debounce = std::time::Duration::from_secs(5);
+    let mut was_pressed = false;
+
+    loop {
+        let pressed = pin.get_value()? == 0;
+
+        if pressed && !was_pressed {
+            println!("{} button pressed", name);
+
+            if let Err(e) = send(&config) {
+                println!("failed to send {} to hub: {}", name, e);
+                toast_rejection(&toast, &e);
+            }
+
+            thread::sleep(debounce);
+        }
+
+        was_pressed = pressed;
+        thread::sleep(std::time::Duration::from_millis(50));
+    }
+}
+
+/// Read the room sensor periodically, making the latest reading available
+/// to the renderer and reporting readings to the hub.
+fn sensor_thread(
+    config: ClientConfiguration,
+    sensor_config: ClientSensorConfiguration,
+    room: SharedMeasurement,
+    telemetry: TelemetryRoute,
+) {
+    if let Err(e) = sensor_thread_inner(config, sensor_config, room, telemetry) {
+        eprintln!("ERROR: sensor thread exited with error: {}", e);
+    }
+}
+
+fn sensor_thread_inner(
+    config: ClientConfiguration,
+    sensor_config: ClientSensorConfiguration,
+    room: SharedMeasurement,
+    telemetry: TelemetryRoute,
+) -> Result<(), Error> {
+    let i2c = linux_embedded_hal::I2cdev::new(&sensor_config.i2c_path)
+        .map_err(|e| Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+    let mut sensor = Scd30::new(i2c)?;
+
+    let report_interval = std::time::Duration::from_secs(sensor_config.report_minutes.max(1) * 60);
+    let mut last_report: Option<std::time::Instant> = None;
+
+    loop {
+        // The sensor makes a new measurement every two seconds by default, but
+        // there's no need for us to check nearly that often.
+        thread::sleep(std::time::Duration::from_secs(30));
+
+        let m = match sensor.read() {
+            Ok(Some(m)) => m,
+            Ok(None) => continue,
+            Err(e) => {
+                println!("error reading sensor: {}", e);
+                continue;
+            }
+        };
+
+        *room.lock().unwrap() = Some(m);
+
+        if last_report.map(|t| t.elapsed() >= report_interval) != Some(false) {
+            last_report = Some(std::time::Instant::now());
+
+            let msg = SensorReadingHelloMessage {
+                timestamp: Utc::now(),
+                co2_ppm: m.co2_ppm,
+                temperature_c: m.temperature_c,
+                humidity_percent: m.humidity_percent,
+                display_id: config.display_id.clone(),
+                token: config.hub_token.clone(),
+            };
+
+            if let Err(e) = send_telemetry(&config, &telemetry, msg) {
+                println!("failed to report sensor reading to hub: {}", e);
+            }
+        }
+    }
+}
+
+/// Check on the Pi's health every so often, making the result available to the
+/// renderer and reporting it to the hub.
+fn health_thread(config: ClientConfiguration, shared: SharedHealth, telemetry: TelemetryRoute) {
+    let report_interval = std::time::Duration::from_secs(config.health.report_minutes * 60);
+    let mut last_report: Option<std::time::Instant> = None;
+    let mut last_warning = None;
+
+    loop {
+        let health = Health::read();
+        *shared.lock().unwrap() = health;
+
+        // Only log changes, so that a hot Pi doesn't fill up the log.
+        let warning = health.warning(&config.health);
+
+        if warning != last_warning {
+            match warning {
+                Some(ref w) => println!("health warning: {}", w),
+                None => println!("health warning cleared"),
+            }
+
+            last_warning = warning;
+        }
+
+        if config.health.report_minutes > 0
+            && last_report.map(|t| t.elapsed() >= report_interval) != Some(false)
+        {
+            last_report = Some(std::time::Instant::now());
+
+            let msg = SystemHealthHelloMessage {
+                timestamp: Utc::now(),
+                cpu_temperature_c: health.cpu_temperature_c,
+                throttled_flags: health.throttled_flags,
+                display_id: config.display_id.clone(),
+                token: config.hub_token.clone(),
+            };
+
+            if let Err(e) = send_telemetry(&config, &telemetry, msg) {
+                println!("failed to report health to hub: {}", e);
+            }
+        }
+
+        thread::sleep(std::time::Duration::from_secs(60));
+    }
+}
+
+/// Make a one-off connection to the hub to send it a message. If the client
+/// forwards updates, this goes by way of the forwarder.
+fn send_hello<H: UpdaterHello>(config: &ClientConfiguration, msg: H) -> Result<(), Error> {
+    if let Some(ref forward_config) = config.forward {
+        return Runtime::new()?.block_on(async {
+            let transport = TcpStream::connect(forward_config.local_address()).await?;
+            let hub_comms = ClientConfiguration::wrap_transport(transport);
+            hub_comms.send_update(msg).await
+        });
+    }
+
+    if let Some(mqtt_config) = config.mqtt() {
+        return crate::mqtt::publish_hello(mqtt_config, &msg.into());
+    }
+
+    let mut rt = Runtime::new()?;
+
+    rt.block_on(async {
+        let hub_comms = config.connect().await?;
+        hub_comms.send_update(msg).await
+    })
+}
+
+/// Send the hub a report that doesn't need an answer, on the display
+/// connection if it can take it, so as not to open a new connection each
+/// time.
+fn send_telemetry<H: UpdaterHello + Clone>(
+    config: &ClientConfiguration,
+    route: &TelemetryRoute,
+    msg: H,
+) -> Result<(), Error> {
+    if let Some(ref sender) = *route.lock().unwrap() {
+        if sender.send(msg.clone().into()).is_ok() {
+            return Ok(());
+        }
+    }
+
+    send_hello(config, msg)
+}
+
+/// Tell the hub that someone rang the doorbell.
+fn send_doorbell(config: &ClientConfiguration) -> Result<(), Error> {
+    send_hello(
+        config,
+        DoorbellHelloMessage {
+            timestamp: Utc::now(),
+            token: config.hub_token.clone(),
+        },
+    )
+}
+
+/// Ask the hub to book the meeting room that it's watching.
+fn send_room_booking(config: &ClientConfiguration) -> Result<(), Error> {
+    send_hello(
+        config,
+        RoomBookingHelloMessage {
+            timestamp: Utc::now(),
+            minutes: config.room_booking_minutes,
+            display_id: config.display_id.clone(),
+            token: config.hub_token.clone(),
+        },
+    )
+}
+
+pub fn play_cli<B: DisplayBackend>(opts: super::PlayCommand) -> Result<(), Error> {
+    let config: ClientConfiguration = confy::load("rc-stickynote-client")?;
+    scenario::play::<B>(config, &opts.scenario_path)
+}
+
+pub fn self_test_cli<B: DisplayBackend>(_opts: super::SelfTestCommand) -> Result<(), Error> {
+    openssl_probe::init_ssl_cert_env_vars();
+
+    let mut config: ClientConfiguration = confy::load("rc-stickynote-client")?;
+    config.display_id = Some(identity::display_id(config.display_id.as_deref())?);
+    selftest::run::<B>(config)
+}
+
+pub fn ring_doorbell_cli(_opts: super::RingDoorbellCommand) -> Result<(), Error> {
+    openssl_probe::init_ssl_cert_env_vars();
+
+    let config: ClientConfiguration = confy::load("rc-stickynote-client")?;
+    send_doorbell(&config)
+}
+
+/// Tell the hub about a new status, sealed and signed as the configuration
+/// asks. A status that's already sealed is passed along as it is.
+fn send_status(config: &ClientConfiguration, status: String, source: &str) -> Result<(), Error> {
+    let person_is = match config.sealing {
+        Some(ref s) if !is_sealed(&status) => s.seal(&status)?,
+        _ => status,
+    };
+
+    let mut msg = PersonIsUpdateHelloMessage {
+        person_is,
+        timestamp: Utc::now(),
+        source: Some(source.to_owned()),
+        set_by: None,
+        token: config.hub_token.clone(),
+        signature: None,
+    };
+
+    if let Some(ref s) = config.signing {
+        s.sign(&mut msg)?;
+    }
+
+    send_hello(config, msg)
+}
+
 pub fn set_status_cli(opts: super::SetStatusCommand) -> Result<(), Error> {
     if !is_person_is_valid(&opts.status) {
         return Err(Error::new(
             std::io::ErrorKind::Other,
-            format!("status \"{}\" invalid -- likely too long", &opts.status),
+            format!(
+                "status \"{}\" invalid -- likely too long ({} pixels wide, with room for {})",
+                &opts.status,
+                width::status_width(&opts.status),
+                width::STATUS_BOX_WIDTH
+            ),
         ));
     }
 
     openssl_probe::init_ssl_cert_env_vars();
 
     let config: ClientConfiguration = confy::load("rc-stickynote-client")?;
-    let mut rt = Runtime::new()?;
 
-    rt.block_on(async {
-        let mut hub_comms = config.connect().await?;
-
-        hub_comms
-            .send(ClientHelloMessage::PersonIsUpdate(
-                PersonIsUpdateHelloMessage {
-                    person_is: opts.status,
-                    timestamp: Utc::now(),
-                },
-            ))
-            .await?;
-        Ok(())
+    send_status(&config, opts.status, "command line").map_err(|e| {
+        match ErrorFrame::from_error(&e) {
+            Some(frame) => Error::new(
+                std::io::ErrorKind::Other,
+                format!("the hub rejected the status ({:?}): {}", frame.code, frame),
+            ),
+            None => e,
+        }
     })
 }
+
+/// Find out what the hub is showing, by connecting as a panel just long
+/// enough to get the latest state. We don't say what we can draw, since we
+/// aren't going to draw anything.
+fn peek_display(config: &ClientConfiguration) -> Result<DisplayMessage, Error> {
+    let mut rt = Runtime::new()?;
+    rt.block_on(ServerConnection::default().get_next_message(config, None, None))
+}
+
+pub fn watch_meetings_cli(_opts: super::WatchMeetingsCommand) -> Result<(), Error> {
+    openssl_probe::init_ssl_cert_env_vars();
+
+    let config: ClientConfiguration = confy::load("rc-stickynote-client")?;
+    let meetings = &config.meetings;
+
+    if !is_person_is_valid(&meetings.status) {
+        return Err(Error::new(
+            std::io::ErrorKind::Other,
+            format!("status \"{}\" invalid -- likely too long", &meetings.status),
+        ));
+    }
+
+    // While a call is going on, the status from before it, to put back
+    // afterwards.
+    let mut before: Option<String> = None;
+
+    loop {
+        let in_call = meetings.in_call();
+
+        if in_call && before.is_none() {
+            println!("call started");
+
+            let result = peek_display(&config).and_then(|current| {
+                send_status(&config, meetings.status.clone(), VIDEO_CALL_SOURCE)?;
+                Ok(current.person_is)
+            });
+
+            // If that didn't work, we'll try again next time around.
+            match result {
+                Ok(previous) => before = Some(previous),
+                Err(e) => println!("failed to set the status for the call: {}", e),
+            }
+        } else if !in_call && before.is_some() {
+            println!("call ended");
+
+            // Only put the old status back if nobody has changed it since.
+            let result = peek_display(&config).and_then(|current| {
+                if current.person_is_source == VIDEO_CALL_SOURCE {
+                    send_status(&config, before.clone().unwrap(), VIDEO_CALL_SOURCE)?;
+                }
+
+                Ok(())
+            });
+
+            match result {
+                Ok(()) => before = None,
+                Err(e) => println!("failed to restore the status after the call: {}", e),
+            }
+        }
+
+        thread::sleep(std::time::Duration::from_secs(meetings.poll_seconds.max(1)));
+    }
+}
+
+/// Get the settings for which IP addresses to show, for the `show-ips`
+/// command.
+pub fn address_configuration() -> Result<AddressConfiguration, Error> {
+    let config: ClientConfiguration = confy::load("rc-stickynote-client")?;
+    Ok(config.addresses)
+}
+
+/// Get the settings for where the display is, for the Waveshare backend.
+#[cfg(feature = "waveshare")]
+pub fn board_configuration() -> Result<BoardConfiguration, Error> {
+    let config: ClientConfiguration = confy::load("rc-stickynote-client")?;
+    Ok(config.board)
+}
+
+/// Get the settings for the simulator's window.
+#[cfg(feature = "simulator")]
+pub fn simulator_configuration() -> Result<SimulatorConfiguration, Error> {
+    let config: ClientConfiguration = confy::load("rc-stickynote-client")?;
+    Ok(config.simulator)
+}
+
+/// Get the settings for the `wifi-setup` command.
+pub fn wifi_setup_configuration() -> Result<Option<WifiSetupConfiguration>, Error> {
+    let config: ClientConfiguration = confy::load("rc-stickynote-client")?;
+    Ok(config.wifi_setup)
+}
+
+pub fn self_update_cli(opts: super::SelfUpdateCommand) -> Result<(), Error> {
+    openssl_probe::init_ssl_cert_env_vars();
+
+    let config: ClientConfiguration = confy::load("rc-stickynote-client")?;
+    let update_config = config.update.ok_or_else(|| {
+        Error::new(
+            std::io::ErrorKind::Other,
+            "the client configuration does not have an [update] section",
+        )
+    })?;
+
+    let mut rt = Runtime::new()?;
+
+    match rt.block_on(update::check_and_update(&update_config, opts.check))? {
+        UpdateOutcome::UpToDate => println!("already up to date"),
+        UpdateOutcome::Available(v) => println!("version {} is available", v),
+        UpdateOutcome::Installed(v) => println!("installed version {}", v),
+    }
+
+    Ok(())
+}
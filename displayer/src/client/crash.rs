@@ -0,0 +1,94 @@
+//! The screen shown when the client gives up.
+//!
+//! Some problems can't be fixed by waiting and trying again, like the hub
+//! refusing to let the panel in. Rather than leave the last frame up, with
+//! no sign that anything is wrong, the client draws a screen saying what
+//! happened, along with the panel's IP address, so that someone can log in
+//! and fix it, and the time, so that they know how long it's been broken.
+//! Then it exits. The e-paper keeps showing the screen after that, since it
+//! doesn't need power to hold a picture.
+
+use chrono::prelude::*;
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+use std::io::{Error, ErrorKind};
+
+use crate::drawing::{Alignment, LineStyle, MonoStyle, TtfStyle};
+use crate::fonts::Typeface;
+use crate::DisplayBackend;
+
+/// A problem that the client can't recover from on its own.
+#[derive(Clone, Debug)]
+pub struct FatalError {
+    /// A short code for the kind of problem, to look up or search for.
+    pub code: &'static str,
+
+    /// What went wrong.
+    pub message: String,
+}
+
+impl FatalError {
+    /// The fatal problem behind a failure to talk to the hub, if it is one.
+    /// Most failures, like the network being down, are worth retrying, but
+    /// being refused entry isn't going to get better by itself.
+    pub fn from_hub_error(e: &Error) -> Option<Self> {
+        if e.kind() == ErrorKind::PermissionDenied {
+            Some(FatalError {
+                code: "HUB-AUTH",
+                message: format!("The hub wouldn't let this panel log in: {}", e),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Draw the error screen on the panel.
+pub fn show<B: DisplayBackend>(
+    backend: &mut B,
+    font: &Typeface,
+    error: &FatalError,
+    ip_addr: &str,
+) -> Result<(), Error> {
+    backend.clear_buffer(B::WHITE)?;
+
+    {
+        let buffer = backend.get_buffer_mut();
+        let heading = TtfStyle::new(font, 40.0, B::BLACK, B::WHITE).align(Alignment::Center);
+        let body = TtfStyle::new(font, 24.0, B::BLACK, B::WHITE);
+        let mono = MonoStyle::new(B::BLACK, B::WHITE);
+
+        heading
+            .draw_line("Sticky note", Point::new(192, 40), buffer)
+            .unwrap();
+        heading
+            .draw_line("stopped working", Point::new(192, 88), buffer)
+            .unwrap();
+        heading
+            .draw_line(error.code, Point::new(192, 170), buffer)
+            .unwrap();
+        body.draw_paragraph(
+            &error.message,
+            &Rectangle::new(Point::new(16, 240), Size::new(352, 260)),
+            4,
+            buffer,
+        )
+        .unwrap();
+
+        let lines = [
+            format!("IP: {}", ip_addr),
+            format!("At: {}", Local::now().format("%Y-%m-%d %H:%M")),
+            "Restart the client once it's fixed.".to_owned(),
+        ];
+
+        for (i, line) in lines.iter().enumerate() {
+            mono.draw_line(line, Point::new(16, 540 + 20 * i as i32), buffer)
+                .unwrap();
+        }
+    }
+
+    backend.wake_up_device()?;
+    backend.clear_display()?;
+    backend.show_buffer()?;
+    backend.sleep_device()?;
+    Ok(())
+}
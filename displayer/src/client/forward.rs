@@ -0,0 +1,258 @@
+//! Passing updates along to the hub, and holding on to them while it's down.
+//!
+//! With forwarding turned on, the client listens for updaters itself, on a
+//! port of its own, and the panel's buttons and sensor send their hellos
+//! there too. Each hello is passed straight along to the hub, so while the
+//! hub is up, nothing changes, including the hub turning updates down. But
+//! if the hub can't be reached, the hello is kept, a status or doorbell ring
+//! is shown on the panel in the meantime, and the hellos are passed along in
+//! the order that they arrived once the hub is back.
+//!
+//! While the hub is down, nobody checks tokens, so anyone who can reach the
+//! port can change what the panel shows until the hub returns. That's why
+//! the port is only open to this machine unless configured otherwise.
+
+use futures::{prelude::*, select};
+use rc_stickynote_protocol::{
+    session::hub::{AwaitingHello, Session},
+    ClientHelloMessage, ErrorFrame,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    io::{Error, ErrorKind},
+    net::{Ipv4Addr, SocketAddr},
+    thread,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    runtime::Runtime,
+    sync::{mpsc, oneshot},
+    time::{self, Duration},
+};
+
+use super::ClientConfiguration;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ForwardConfiguration {
+    /// The address to listen on. Only listen on other machines' behalf on a
+    /// network that you trust.
+    pub bind_address: Ipv4Addr,
+
+    /// The port to listen on, which updaters on this machine should use in
+    /// place of the hub's.
+    pub port: u16,
+
+    /// How often to try the hub again while updates are waiting, in seconds.
+    pub retry_seconds: u64,
+
+    /// The most updates to hold while the hub is down. Past this, the oldest
+    /// are dropped.
+    pub max_queued: usize,
+}
+
+impl Default for ForwardConfiguration {
+    fn default() -> Self {
+        ForwardConfiguration {
+            bind_address: Ipv4Addr::LOCALHOST,
+            port: 20201,
+            retry_seconds: 60,
+            max_queued: 100,
+        }
+    }
+}
+
+impl ForwardConfiguration {
+    /// Where updaters on this machine can reach us.
+    pub fn local_address(&self) -> SocketAddr {
+        let host = if self.bind_address.is_unspecified() {
+            Ipv4Addr::LOCALHOST
+        } else {
+            self.bind_address
+        };
+
+        (host, self.port).into()
+    }
+}
+
+/// A hello to pass along, and where to say how it went.
+struct Request {
+    hello: ClientHelloMessage,
+    reply: oneshot::Sender<Result<(), ErrorFrame>>,
+}
+
+/// Start listening for updaters, and passing what they send along to the
+/// hub. Hellos that can't reach the hub are also sent to `local`, so that
+/// the panel can show them in the meantime.
+pub async fn start(
+    config: ClientConfiguration,
+    local: mpsc::UnboundedSender<ClientHelloMessage>,
+) -> Result<(), Error> {
+    let forward_config = match config.forward {
+        Some(ref f) => f.clone(),
+        None => return Ok(()),
+    };
+
+    let addr = SocketAddr::from((forward_config.bind_address, forward_config.port));
+    let mut listener = TcpListener::bind(addr).await?;
+    println!("forwarding updates to the hub from {}", addr);
+
+    // Connections to the hub can't be handed between threads, so the
+    // forwarding happens on a runtime of its own.
+
+    let (requests, receiver) = mpsc::unbounded_channel();
+
+    thread::spawn(move || match Runtime::new() {
+        Ok(mut rt) => rt.block_on(forward_task(config, forward_config, receiver, local)),
+        Err(e) => eprintln!("ERROR: cannot start forwarding updates: {}", e),
+    });
+
+    tokio::spawn(async move {
+        loop {
+            let (socket, peer) = match listener.accept().await {
+                Ok(c) => c,
+                Err(e) => {
+                    println!("error accepting updater connection: {}", e);
+                    continue;
+                }
+            };
+
+            let requests = requests.clone();
+
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(socket, requests).await {
+                    println!("error handling updater {}: {}", peer, e);
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle_connection(
+    socket: TcpStream,
+    requests: mpsc::UnboundedSender<Request>,
+) -> Result<(), Error> {
+    let (hello, reply) = match AwaitingHello::new(socket).receive_hello().await? {
+        Session::Updater(s) => s.into_parts(),
+
+        Session::Display(_) => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "panels have to connect to the hub itself",
+            ));
+        }
+    };
+
+    let (sender, outcome) = oneshot::channel();
+    let request = Request {
+        hello,
+        reply: sender,
+    };
+
+    if requests.send(request).is_err() {
+        return Err(Error::new(
+            ErrorKind::BrokenPipe,
+            "the forwarder has stopped",
+        ));
+    }
+
+    if let Ok(Err(frame)) = outcome.await {
+        // As with the hub, older updaters hang up without waiting to hear.
+        let _ = reply.reject(frame).await;
+    }
+
+    Ok(())
+}
+
+/// Pass a hello along to the hub, directly, or through the MQTT broker if
+/// that's how we talk to it.
+async fn send_to_hub(config: &ClientConfiguration, hello: ClientHelloMessage) -> Result<(), Error> {
+    if let Some(mqtt_config) = config.mqtt() {
+        return crate::mqtt::publish_hello(mqtt_config, &hello);
+    }
+
+    config.connect().await?.forward_update(hello).await
+}
+
+/// Pass the hellos along one at a time, so that they reach the hub in the
+/// order that they arrived, holding them while the hub can't be reached.
+async fn forward_task(
+    config: ClientConfiguration,
+    forward_config: ForwardConfiguration,
+    mut requests: mpsc::UnboundedReceiver<Request>,
+    local: mpsc::UnboundedSender<ClientHelloMessage>,
+) {
+    let retry = Duration::from_secs(forward_config.retry_seconds.max(1));
+    let mut queue = VecDeque::new();
+
+    loop {
+        let mut request = None;
+
+        select! {
+            r = requests.recv().fuse() => match r {
+                Some(r) => request = Some(r),
+                None => return,
+            },
+
+            _ = time::delay_for(retry).fuse() => {},
+        }
+
+        // Anything that's waiting goes first. If the hub turns something
+        // down now, there's nobody left to tell, so just log it.
+
+        while let Some(hello) = queue.front().cloned() {
+            match send_to_hub(&config, hello).await {
+                Ok(()) => {}
+
+                Err(e) => match ErrorFrame::from_error(&e) {
+                    Some(frame) => println!("hub turned down a held update: {}", frame),
+                    None => break,
+                },
+            }
+
+            queue.pop_front();
+
+            if queue.is_empty() {
+                println!("passed all held updates along to the hub");
+            }
+        }
+
+        let Request { hello, reply } = match request {
+            Some(r) => r,
+            None => continue,
+        };
+
+        if queue.is_empty() {
+            match send_to_hub(&config, hello.clone()).await {
+                Ok(()) => {
+                    let _ = reply.send(Ok(()));
+                    continue;
+                }
+
+                Err(e) => {
+                    if let Some(frame) = ErrorFrame::from_error(&e) {
+                        let _ = reply.send(Err(frame.clone()));
+                        continue;
+                    }
+
+                    println!(
+                        "cannot reach the hub; holding updates until it's back: {}",
+                        e
+                    );
+                }
+            }
+        }
+
+        if queue.len() >= forward_config.max_queued {
+            println!("too many updates held; dropping the oldest");
+            queue.pop_front();
+        }
+
+        let _ = local.send(hello.clone());
+        queue.push_back(hello);
+        let _ = reply.send(Ok(()));
+    }
+}
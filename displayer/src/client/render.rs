@@ -0,0 +1,1440 @@
+//! Drawing a frame out of independent widgets.
+//!
+//! Each part of the panel is a `Widget` that draws itself from the
+//! `RenderContext`, which gathers up everything that a frame depends on. A
+//! layout is just a list of widgets, drawn in order onto a blank buffer, so
+//! that later ones can cover up earlier ones.
+//!
+//! Each widget also says where it draws and what its appearance depends on,
+//! so that the `DirtyTracker` can work out which part of the panel actually
+//! needs refreshing.
+
+use chrono::prelude::*;
+use embedded_graphics::{
+    prelude::*,
+    primitives::{Circle, Line, PrimitiveStyle, Rectangle},
+};
+use rc_stickynote_protocol::{Availability, CiStatus, DisplayCapabilities};
+use std::io::Error;
+
+use super::{ClientConfiguration, DisplayData, MAX_WORLD_CLOCKS};
+use crate::drawing::{Alignment, Baseline, LineStyle, MonoStyle, QrImage, TtfStyle};
+use crate::fonts::Typeface;
+use crate::images::{ImageConfiguration, Picture};
+use crate::scd30::Measurement;
+use crate::DisplayBackend;
+
+/// Everything that goes into drawing a frame.
+pub struct RenderContext<'a> {
+    pub config: &'a ClientConfiguration,
+    pub dd: &'a DisplayData,
+    pub sans_font: &'a Typeface,
+    pub serif_font: &'a Typeface,
+    pub ago_formatter: &'a timeago::Formatter<Box<dyn timeago::Language>>,
+
+    /// The latest reading from the room sensor, if there is one.
+    pub room: Option<Measurement>,
+
+    /// The latest output of each command-driven text widget.
+    pub widget_text: Vec<Option<String>>,
+
+    /// A warning about the Pi's health, if it needs one.
+    pub health_warning: Option<String>,
+
+    /// A passing message, like why the hub turned down a doorbell ring.
+    pub toast: Option<String>,
+
+    /// The picture to show while the screensaver is on, if there is one.
+    pub screensaver: Option<&'a Picture>,
+
+    /// How many frames we've drawn before this one.
+    pub n_redraws: usize,
+}
+
+/// A TrueType text style for the backend, antialiased if the panel can show
+/// grays.
+fn ttf<'a, B: DisplayBackend>(
+    font: &'a Typeface,
+    height: f32,
+    fg: B::Color,
+    bg: B::Color,
+) -> TtfStyle<'a, B::Color> {
+    let style = TtfStyle::new(font, height, fg, bg);
+
+    match B::GRAYS {
+        Some((dark, light)) if fg == B::BLACK => style.shades(dark, light),
+        Some((dark, light)) => style.shades(light, dark),
+        None => style,
+    }
+}
+
+/// One part of the panel.
+pub trait Widget<B: DisplayBackend> {
+    /// Draw this widget into the backend's buffer. Drawing can't fail, so if
+    /// there's nothing to show, a widget just draws nothing.
+    fn draw(&self, ctx: &RenderContext, target: &mut B::Buffer);
+
+    /// The part of the panel that this widget might draw on.
+    fn bounds(&self, ctx: &RenderContext) -> Rectangle;
+
+    /// A summary of everything that this widget's appearance depends on. If
+    /// it's the same as last time, the widget needn't be refreshed.
+    fn state(&self, ctx: &RenderContext) -> String;
+}
+
+/// The colors that the backend can show, from black to white.
+fn palette<B: DisplayBackend>() -> Vec<B::Color> {
+    match B::GRAYS {
+        Some((dark, light)) => vec![B::BLACK, dark, light, B::WHITE],
+        None => vec![B::BLACK, B::WHITE],
+    }
+}
+
+/// Load a picture, prepared to fill as much of the panel as it can with the
+/// backend's colors.
+pub fn load_picture<B: DisplayBackend>(config: &ImageConfiguration) -> Result<Picture, Error> {
+    Picture::load(
+        config,
+        PANEL.size.width,
+        PANEL.size.height,
+        palette::<B>().len() as u8,
+    )
+}
+
+/// Get the widgets that make up the current layout, bottom to top.
+pub fn layout_widgets<B: DisplayBackend>(dd: &DisplayData) -> Vec<Box<dyn Widget<B>>> {
+    if dd.screensaver {
+        return vec![Box::new(ScreensaverWidget)];
+    }
+
+    match dd.layout.as_str() {
+        // Just the status, as big as possible.
+        "status" => vec![
+            Box::new(BigStatusWidget),
+            Box::new(MaintenanceWidget),
+            Box::new(ToastWidget),
+        ],
+
+        // Who's in the office when, for hybrid-work boards.
+        "week" => vec![
+            Box::new(WeekWidget),
+            Box::new(MaintenanceWidget),
+            Box::new(ToastWidget),
+        ],
+
+        // A meeting room's bookings, for room displays.
+        "room" => vec![
+            Box::new(MeetingRoomWidget),
+            Box::new(MaintenanceWidget),
+            Box::new(ToastWidget),
+        ],
+
+        _ => vec![
+            Box::new(ClockWidget),
+            Box::new(DateWidget),
+            Box::new(DisclaimerWidget),
+            Box::new(HeadingWidget),
+            Box::new(StatusWidget),
+            Box::new(NotesWidget),
+            Box::new(CiWidget),
+            Box::new(OnCallWidget),
+            Box::new(CountersWidget),
+            Box::new(RoomWidget),
+            Box::new(CommandOutputWidget),
+            Box::new(HealthWidget),
+            Box::new(MaintenanceWidget),
+            Box::new(DoorbellWidget),
+            Box::new(ToastWidget),
+            Box::new(FooterWidget),
+        ],
+    }
+}
+
+/// Draw a whole frame into the backend's buffer.
+pub fn compose<B: DisplayBackend>(
+    widgets: &[Box<dyn Widget<B>>],
+    ctx: &RenderContext,
+    backend: &mut B,
+) -> Result<(), Error> {
+    backend.clear_buffer(B::WHITE)?;
+    let buffer = backend.get_buffer_mut();
+
+    for widget in widgets {
+        widget.draw(ctx, buffer);
+    }
+
+    Ok(())
+}
+
+/// How the panel should be refreshed after drawing a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Refresh {
+    /// Nothing has changed.
+    Nothing,
+
+    /// Only this part of the panel has changed.
+    Region(Rectangle),
+
+    /// Refresh the whole panel.
+    Full,
+}
+
+/// After this many partial refreshes in a row, do a full one, to get rid of
+/// the ghosting that partial refreshes leave behind.
+const MAX_PARTIAL_REFRESHES: usize = 20;
+
+/// If the changed region covers more than this fraction of the panel, just
+/// refresh all of it.
+const MAX_PARTIAL_FRACTION: f32 = 0.5;
+
+/// Keeps track of what each widget looked like in the last frame shown, to
+/// work out what needs to be refreshed in the next one.
+#[derive(Debug, Default)]
+pub struct DirtyTracker {
+    /// The layout of the last frame, and the bounds and state of each of its
+    /// widgets. None if nothing has been shown yet.
+    last: Option<(String, Vec<(Rectangle, String)>)>,
+
+    /// How many partial refreshes we've done since the last full one.
+    n_partial: usize,
+}
+
+impl DirtyTracker {
+    /// Work out how to refresh the panel to show a newly drawn frame, and
+    /// remember the frame for next time. `force_full` asks for a full
+    /// refresh regardless.
+    pub fn refresh_for<B: DisplayBackend>(
+        &mut self,
+        layout: &str,
+        widgets: &[Box<dyn Widget<B>>],
+        ctx: &RenderContext,
+        force_full: bool,
+    ) -> Refresh {
+        let current: Vec<(Rectangle, String)> = widgets
+            .iter()
+            .map(|w| (w.bounds(ctx), w.state(ctx)))
+            .collect();
+
+        let last = self.last.replace((layout.to_owned(), current));
+        let (_, ref current) = self.last.as_ref().unwrap();
+
+        let last = match last {
+            Some((last_layout, last)) if last_layout == layout && last.len() == current.len() => {
+                last
+            }
+
+            _ => {
+                self.n_partial = 0;
+                return Refresh::Full;
+            }
+        };
+
+        let mut region: Option<Rectangle> = None;
+
+        for ((old_bounds, old_state), (new_bounds, new_state)) in last.iter().zip(current) {
+            if old_state != new_state || old_bounds != new_bounds {
+                let changed = union(old_bounds, new_bounds);
+                region = Some(region.map_or(changed, |r| union(&r, &changed)));
+            }
+        }
+
+        let region = match (region, force_full) {
+            (_, true) => None,
+            (Some(r), false) => Some(r),
+            (None, false) => return Refresh::Nothing,
+        };
+
+        match region {
+            Some(r)
+                if B::SUPPORTS_PARTIAL_REFRESH
+                    && self.n_partial < MAX_PARTIAL_REFRESHES
+                    && area(&r) as f32 <= MAX_PARTIAL_FRACTION * area(&PANEL) as f32 =>
+            {
+                self.n_partial += 1;
+                Refresh::Region(r)
+            }
+
+            _ => {
+                self.n_partial = 0;
+                Refresh::Full
+            }
+        }
+    }
+}
+
+fn area(r: &Rectangle) -> u32 {
+    r.size.width * r.size.height
+}
+
+/// The smallest rectangle containing both of these. Empty rectangles don't
+/// count.
+fn union(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    match (a.bottom_right(), b.bottom_right()) {
+        (None, _) => *b,
+        (_, None) => *a,
+        (Some(a_br), Some(b_br)) => Rectangle::with_corners(
+            a.top_left.component_min(b.top_left),
+            a_br.component_max(b_br),
+        ),
+    }
+}
+
+/// The whole panel, in portrait orientation.
+const PANEL: Rectangle = Rectangle::new(Point::zero(), Size::new(384, 640));
+
+/// What our panel can do, to tell the hub.
+pub fn capabilities<B: DisplayBackend>() -> DisplayCapabilities {
+    DisplayCapabilities {
+        width: PANEL.size.width,
+        height: PANEL.size.height,
+        colors: if B::GRAYS.is_some() { 4 } else { 2 },
+        partial_refresh: B::SUPPORTS_PARTIAL_REFRESH,
+        images: true,
+    }
+}
+
+// The standard layout is stacked from the top down. These are the tops of
+// its various pieces.
+
+const HEADING_Y: i32 = 54;
+const HEADING_LINE_HEIGHT: i32 = 54;
+const STATUS_Y: i32 = HEADING_Y + 2 * HEADING_LINE_HEIGHT + 12;
+const UPDATED_AT_Y: i32 = STATUS_Y + HEADING_LINE_HEIGHT + 4;
+const NOTES_Y: i32 = UPDATED_AT_Y + 40;
+
+/// How the space for the clock is shared with the lines that can go under
+/// it: the date, and the time in other timezones. The clock shrinks to make
+/// room for them.
+struct ClockBand {
+    clock_size: f32,
+    line_size: f32,
+    date_y: Option<i32>,
+    world_y: Option<i32>,
+}
+
+impl ClockBand {
+    fn new(ctx: &RenderContext) -> Self {
+        let date = ctx.config.date.is_some();
+        let world = !ctx.config.world_clocks.is_empty();
+
+        let (clock_size, line_size, date_y, world_y) = match (date, world) {
+            (false, false) => (56.0, 0.0, None, None),
+            (true, false) => (40.0, 16.0, Some(36), None),
+            (false, true) => (40.0, 16.0, None, Some(36)),
+            (true, true) => (30.0, 12.0, Some(28), Some(40)),
+        };
+
+        ClockBand {
+            clock_size,
+            line_size,
+            date_y,
+            world_y,
+        }
+    }
+}
+
+/// The times in other timezones, like "NYC 09:12 / London 14:12".
+fn world_clock_text(ctx: &RenderContext) -> String {
+    ctx.config
+        .world_clocks
+        .iter()
+        .take(MAX_WORLD_CLOCKS)
+        .map(|c| c.format(ctx.dd))
+        .collect::<Vec<_>>()
+        .join(" / ")
+}
+
+/// The clock, along with the times in any other timezones.
+pub struct ClockWidget;
+
+impl<B: DisplayBackend> Widget<B> for ClockWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        let band = ClockBand::new(ctx);
+
+        ttf::<B>(ctx.sans_font, band.clock_size, B::BLACK, B::WHITE)
+            .features(&ctx.config.font_features.clock)
+            .draw_line(
+                &ctx.dd.now.format(ctx.dd.clock_format()).to_string(),
+                Point::new(2, 0),
+                buffer,
+            )
+            .unwrap();
+
+        if let Some(y) = band.world_y {
+            ttf::<B>(ctx.sans_font, band.line_size, B::BLACK, B::WHITE)
+                .features(&ctx.config.font_features.clock)
+                .draw_line_ellipsized(&world_clock_text(ctx), Point::new(4, y), 224, buffer)
+                .unwrap();
+        }
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        Rectangle::with_corners(Point::new(0, 0), Point::new(229, 51))
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        format!(
+            "{} {}",
+            ctx.dd.now.format(ctx.dd.clock_format()),
+            world_clock_text(ctx)
+        )
+    }
+}
+
+/// The date, such as "Tue, Mar 5", under the clock.
+pub struct DateWidget;
+
+impl<B: DisplayBackend> Widget<B> for DateWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        let band = ClockBand::new(ctx);
+
+        if let (Some(date), Some(y)) = (&ctx.config.date, band.date_y) {
+            ttf::<B>(ctx.sans_font, band.line_size, B::BLACK, B::WHITE)
+                .draw_line_ellipsized(&date.format(ctx.dd), Point::new(4, y), 224, buffer)
+                .unwrap();
+        }
+    }
+
+    fn bounds(&self, ctx: &RenderContext) -> Rectangle {
+        let y = ClockBand::new(ctx).date_y.unwrap_or(36);
+        Rectangle::with_corners(Point::new(0, y), Point::new(229, 51))
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        ctx.config
+            .date
+            .as_ref()
+            .map(|date| date.format(ctx.dd))
+            .unwrap_or_default()
+    }
+}
+
+/// A note next to the clock about how out-of-date it might be.
+pub struct DisclaimerWidget;
+
+impl<B: DisplayBackend> Widget<B> for DisclaimerWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        let disclaimer = match ctx.dd.asleep_until {
+            Some(t) => format!(
+                "Asleep until {}. The doorbell still works.",
+                t.format(ctx.dd.clock_format())
+            ),
+            None => "May be up to 15 minutes out of date. If much more than that, \
+                     tell Peter his sticky note is broken."
+                .to_owned(),
+        };
+
+        MonoStyle::new(B::BLACK, B::WHITE)
+            .draw_paragraph(
+                &disclaimer,
+                &Rectangle::new(Point::new(230, 8), Size::new(152, 40)),
+                1,
+                buffer,
+            )
+            .unwrap();
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        Rectangle::with_corners(Point::new(230, 0), Point::new(383, 51))
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        format!("{:?}", ctx.dd.asleep_until)
+    }
+}
+
+/// The rule under the clock and "The Innovation Scientist is:".
+pub struct HeadingWidget;
+
+impl<B: DisplayBackend> Widget<B> for HeadingWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        Line::new(Point::new(0, 52), Point::new(383, 52))
+            .into_styled(PrimitiveStyle::with_stroke(B::BLACK, 1))
+            .draw(buffer)
+            .unwrap();
+
+        let x = 8;
+        let heading = ttf::<B>(ctx.serif_font, 64.0, B::BLACK, B::WHITE)
+            .features(&ctx.config.font_features.heading);
+
+        heading
+            .draw_line("The Innovation", Point::new(x, HEADING_Y), buffer)
+            .unwrap();
+        heading
+            .draw_line(
+                "Scientist is:",
+                Point::new(x + 2, HEADING_Y + HEADING_LINE_HEIGHT),
+                buffer,
+            )
+            .unwrap();
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        Rectangle::with_corners(Point::new(0, 52), Point::new(383, STATUS_Y - 1))
+    }
+
+    fn state(&self, _ctx: &RenderContext) -> String {
+        String::new()
+    }
+}
+
+/// The actual status message, with "updated at ..." below it.
+pub struct StatusWidget;
+
+impl<B: DisplayBackend> Widget<B> for StatusWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        let status_box = Rectangle::with_corners(
+            Point::new(0, STATUS_Y),
+            Point::new(383, STATUS_Y + HEADING_LINE_HEIGHT),
+        );
+
+        status_box
+            .into_styled(PrimitiveStyle::with_fill(B::BLACK))
+            .draw(buffer)
+            .unwrap();
+
+        // If there's an availability indicator, it goes at the left end of
+        // the box, and the status is centered in what's left.
+
+        let text_box = match ctx.dd.availability {
+            Some(a) => {
+                draw_availability::<B>(a, Point::new(8, STATUS_Y + 11), buffer);
+                Rectangle::with_corners(
+                    Point::new(48, STATUS_Y),
+                    Point::new(383, STATUS_Y + HEADING_LINE_HEIGHT),
+                )
+            }
+
+            None => status_box,
+        };
+
+        ttf::<B>(ctx.sans_font, 32.0, B::WHITE, B::BLACK)
+            .features(&ctx.config.font_features.status)
+            .align(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .draw_line_ellipsized(
+                &ctx.dd.person_is,
+                text_box.center(),
+                text_box.size.width,
+                buffer,
+            )
+            .unwrap();
+
+        if let Some(msg) = ctx.config.updated_at.format(ctx.dd, ctx.ago_formatter) {
+            MonoStyle::new(B::BLACK, B::WHITE)
+                .align(Alignment::Right)
+                .draw_line_ellipsized(&msg, Point::new(381, UPDATED_AT_Y), 380, buffer)
+                .unwrap();
+        }
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        Rectangle::with_corners(Point::new(0, STATUS_Y), Point::new(383, NOTES_Y - 1))
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        format!(
+            "{} {:?} {:?}",
+            ctx.dd.person_is,
+            ctx.dd.availability,
+            ctx.config.updated_at.format(ctx.dd, ctx.ago_formatter)
+        )
+    }
+}
+
+/// The diameter of the availability indicator.
+const AVAILABILITY_DIAMETER: u32 = 32;
+
+/// Draw a traffic-light style indicator of whether the person is available,
+/// in white on the black status box: filled if they're in office hours, open
+/// if they're not, and striped if they're only partly available.
+fn draw_availability<B: DisplayBackend>(
+    availability: Availability,
+    top_left: Point,
+    buffer: &mut B::Buffer,
+) {
+    let circle = Circle::new(top_left, AVAILABILITY_DIAMETER);
+
+    match availability {
+        Availability::Available => {
+            circle
+                .into_styled(PrimitiveStyle::with_fill(B::WHITE))
+                .draw(buffer)
+                .unwrap();
+        }
+
+        Availability::Limited => {
+            // Horizontal stripes, clipped to the circle by only drawing
+            // the part of each row that's inside it.
+            let r = AVAILABILITY_DIAMETER as i32 / 2;
+            let center = circle.center();
+
+            for dy in (-r..=r).step_by(4) {
+                let half = (((r * r - dy * dy) as f32).sqrt()) as i32;
+
+                Line::new(
+                    Point::new(center.x - half, center.y + dy),
+                    Point::new(center.x + half, center.y + dy),
+                )
+                .into_styled(PrimitiveStyle::with_stroke(B::WHITE, 2))
+                .draw(buffer)
+                .unwrap();
+            }
+
+            circle
+                .into_styled(PrimitiveStyle::with_stroke(B::WHITE, 3))
+                .draw(buffer)
+                .unwrap();
+        }
+
+        Availability::Unavailable => {
+            circle
+                .into_styled(PrimitiveStyle::with_stroke(B::WHITE, 3))
+                .draw(buffer)
+                .unwrap();
+        }
+    }
+}
+
+/// The screensaver's picture, centered on the panel. Without a picture, the
+/// screensaver is blank.
+pub struct ScreensaverWidget;
+
+impl<B: DisplayBackend> Widget<B> for ScreensaverWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        if let Some(picture) = ctx.screensaver {
+            let palette = palette::<B>();
+            let size = picture.size();
+            let top_left = Point::new(
+                (PANEL.size.width - size.width) as i32 / 2,
+                (PANEL.size.height - size.height) as i32 / 2,
+            );
+            picture.at(top_left, &palette).draw(buffer).unwrap();
+        }
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        PANEL
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        ctx.screensaver.is_some().to_string()
+    }
+}
+
+/// The status alone, as big as possible.
+pub struct BigStatusWidget;
+
+impl<B: DisplayBackend> Widget<B> for BigStatusWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        ttf::<B>(ctx.sans_font, 72.0, B::BLACK, B::WHITE)
+            .features(&ctx.config.font_features.status)
+            .align(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .draw_paragraph(
+                &ctx.dd.person_is,
+                &Rectangle::with_corners(Point::new(8, 8), Point::new(375, 631)),
+                8,
+                buffer,
+            )
+            .unwrap();
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        PANEL
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        ctx.dd.person_is.clone()
+    }
+}
+
+/// A grid of who plans to be in the office on which days of this week, one
+/// row per person, with today's column picked out.
+pub struct WeekWidget;
+
+const WEEK_NAME_WIDTH: i32 = 136;
+const WEEK_COLUMN_WIDTH: i32 = 48;
+const WEEK_HEADER_Y: i32 = 100;
+const WEEK_ROW_HEIGHT: i32 = 44;
+
+/// The weekdays in the grid, which skips the weekend.
+const WEEK_DAYS: u32 = 5;
+
+impl WeekWidget {
+    /// The Monday of the current week.
+    fn monday(ctx: &RenderContext) -> NaiveDate {
+        let today = ctx.dd.now.naive_local().date();
+        today - chrono::Duration::days(today.weekday().num_days_from_monday() as i64)
+    }
+
+    /// How many people fit in the grid.
+    fn max_rows() -> usize {
+        ((PANEL.size.height as i32 - WEEK_HEADER_Y - 40) / WEEK_ROW_HEIGHT) as usize
+    }
+}
+
+impl<B: DisplayBackend> Widget<B> for WeekWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        let monday = Self::monday(ctx);
+        let today = ctx.dd.now.naive_local().date();
+
+        ttf::<B>(ctx.serif_font, 48.0, B::BLACK, B::WHITE)
+            .features(&ctx.config.font_features.heading)
+            .draw_line("This week", Point::new(8, 4), buffer)
+            .unwrap();
+
+        // The day headings, with today's in reverse.
+
+        let day_style = ttf::<B>(ctx.sans_font, 20.0, B::BLACK, B::WHITE)
+            .align(Alignment::Center)
+            .baseline(Baseline::Bottom);
+        let today_style = ttf::<B>(ctx.sans_font, 20.0, B::WHITE, B::BLACK)
+            .align(Alignment::Center)
+            .baseline(Baseline::Bottom);
+
+        for i in 0..WEEK_DAYS {
+            let day = monday + chrono::Duration::days(i as i64);
+            let x = WEEK_NAME_WIDTH + i as i32 * WEEK_COLUMN_WIDTH;
+            let center = Point::new(x + WEEK_COLUMN_WIDTH / 2, WEEK_HEADER_Y - 4);
+            let name = day.format("%a").to_string();
+
+            if day == today {
+                Rectangle::new(
+                    Point::new(x + 1, WEEK_HEADER_Y - 32),
+                    Size::new(WEEK_COLUMN_WIDTH as u32 - 2, 32),
+                )
+                .into_styled(PrimitiveStyle::with_fill(B::BLACK))
+                .draw(buffer)
+                .unwrap();
+
+                today_style.draw_line(&name, center, buffer).unwrap();
+            } else {
+                day_style.draw_line(&name, center, buffer).unwrap();
+            }
+        }
+
+        Line::new(Point::new(0, WEEK_HEADER_Y), Point::new(383, WEEK_HEADER_Y))
+            .into_styled(PrimitiveStyle::with_stroke(B::BLACK, 2))
+            .draw(buffer)
+            .unwrap();
+
+        // A row per person, with a filled box for each day in the office.
+
+        let name_style =
+            ttf::<B>(ctx.sans_font, 24.0, B::BLACK, B::WHITE).baseline(Baseline::Middle);
+
+        for (row, person) in ctx.dd.office_days.iter().take(Self::max_rows()).enumerate() {
+            let y = WEEK_HEADER_Y + row as i32 * WEEK_ROW_HEIGHT;
+            let middle = y + WEEK_ROW_HEIGHT / 2;
+
+            name_style
+                .draw_line_ellipsized(
+                    &person.name,
+                    Point::new(8, middle),
+                    (WEEK_NAME_WIDTH - 16) as u32,
+                    buffer,
+                )
+                .unwrap();
+
+            for i in 0..WEEK_DAYS {
+                let day = monday + chrono::Duration::days(i as i64);
+                let x = WEEK_NAME_WIDTH + i as i32 * WEEK_COLUMN_WIDTH;
+                let cell = Rectangle::new(
+                    Point::new(x + 8, y + 8),
+                    Size::new(WEEK_COLUMN_WIDTH as u32 - 16, WEEK_ROW_HEIGHT as u32 - 16),
+                );
+
+                let style = if person.days.contains(&day) {
+                    PrimitiveStyle::with_fill(B::BLACK)
+                } else {
+                    PrimitiveStyle::with_stroke(B::BLACK, 1)
+                };
+
+                cell.into_styled(style).draw(buffer).unwrap();
+            }
+
+            Line::new(
+                Point::new(0, y + WEEK_ROW_HEIGHT),
+                Point::new(383, y + WEEK_ROW_HEIGHT),
+            )
+            .into_styled(PrimitiveStyle::with_stroke(B::BLACK, 1))
+            .draw(buffer)
+            .unwrap();
+        }
+
+        let extra = ctx.dd.office_days.len().saturating_sub(Self::max_rows());
+
+        if ctx.dd.office_days.is_empty() {
+            ttf::<B>(ctx.sans_font, 24.0, B::BLACK, B::WHITE)
+                .draw_line(
+                    "Nobody's plans are known.",
+                    Point::new(8, WEEK_HEADER_Y + 40),
+                    buffer,
+                )
+                .unwrap();
+        } else if extra > 0 {
+            let y = WEEK_HEADER_Y + Self::max_rows() as i32 * WEEK_ROW_HEIGHT + 32;
+            ttf::<B>(ctx.sans_font, 20.0, B::BLACK, B::WHITE)
+                .draw_line(&format!("... and {} more", extra), Point::new(8, y), buffer)
+                .unwrap();
+        }
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        PANEL
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        format!(
+            "{} {} {:?}",
+            Self::monday(ctx),
+            ctx.dd.now.weekday(),
+            ctx.dd.office_days
+        )
+    }
+}
+
+/// A meeting room's bookings: whether it's free now, and until when, and
+/// what's coming up later today.
+pub struct MeetingRoomWidget;
+
+/// How many of the later bookings to list.
+const MAX_LATER_BOOKINGS: usize = 5;
+
+/// The text of a room display, worked out from the bookings and the time.
+#[derive(Debug, Default)]
+struct MeetingRoomText {
+    name: String,
+    busy: bool,
+    until: String,
+    current: String,
+    later: Vec<String>,
+    hint: Option<String>,
+}
+
+impl MeetingRoomText {
+    fn new(ctx: &RenderContext) -> Option<Self> {
+        let room = ctx.dd.room.as_ref()?;
+        let now = ctx.dd.now.with_timezone(&Utc);
+        let time = |t: &DateTime<Utc>| {
+            t.with_timezone(&Local)
+                .format(ctx.dd.clock_format())
+                .to_string()
+        };
+
+        let mut upcoming = room.bookings.iter().filter(|b| b.end > now);
+        let mut text = MeetingRoomText {
+            name: room.name.clone(),
+            ..MeetingRoomText::default()
+        };
+
+        let mut next = upcoming.next();
+
+        match next {
+            Some(b) if b.start <= now => {
+                text.busy = true;
+                text.until = format!("until {}", time(&b.end));
+                text.current = b.summary.clone();
+                next = upcoming.next();
+            }
+
+            Some(b) => text.until = format!("until {}", time(&b.start)),
+
+            None => text.until = "for the rest of the day".to_owned(),
+        }
+
+        text.later = next
+            .into_iter()
+            .chain(upcoming)
+            .take(MAX_LATER_BOOKINGS)
+            .map(|b| format!("{}–{} {}", time(&b.start), time(&b.end), b.summary))
+            .collect();
+
+        if room.can_book && !text.busy && ctx.config.room_button_gpio.is_some() {
+            text.hint = Some(format!(
+                "Press the button to book the next {} minutes.",
+                ctx.config.room_booking_minutes
+            ));
+        }
+
+        Some(text)
+    }
+}
+
+impl<B: DisplayBackend> Widget<B> for MeetingRoomWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        let text = match MeetingRoomText::new(ctx) {
+            Some(t) => t,
+            None => {
+                ttf::<B>(ctx.sans_font, 24.0, B::BLACK, B::WHITE)
+                    .draw_line(
+                        "Waiting for the room's calendar...",
+                        Point::new(8, 8),
+                        buffer,
+                    )
+                    .unwrap();
+                return;
+            }
+        };
+
+        ttf::<B>(ctx.serif_font, 48.0, B::BLACK, B::WHITE)
+            .features(&ctx.config.font_features.heading)
+            .draw_line_ellipsized(&text.name, Point::new(8, 4), 368, buffer)
+            .unwrap();
+
+        // "Free" or "Busy", big, with "Busy" in reverse so that it stands out
+        // from across the hall.
+
+        let (fg, bg) = if text.busy {
+            (B::WHITE, B::BLACK)
+        } else {
+            (B::BLACK, B::WHITE)
+        };
+
+        Rectangle::with_corners(Point::new(0, 64), Point::new(383, 263))
+            .into_styled(PrimitiveStyle::with_fill(bg))
+            .draw(buffer)
+            .unwrap();
+
+        ttf::<B>(ctx.sans_font, 120.0, fg, bg)
+            .align(Alignment::Center)
+            .draw_line(
+                if text.busy { "Busy" } else { "Free" },
+                Point::new(192, 72),
+                buffer,
+            )
+            .unwrap();
+
+        ttf::<B>(ctx.sans_font, 36.0, fg, bg)
+            .align(Alignment::Center)
+            .draw_line(&text.until, Point::new(192, 210), buffer)
+            .unwrap();
+
+        let mut y = 276;
+
+        if !text.current.is_empty() {
+            ttf::<B>(ctx.sans_font, 28.0, B::BLACK, B::WHITE)
+                .draw_paragraph(
+                    &text.current,
+                    &Rectangle::with_corners(Point::new(8, y), Point::new(375, y + 68)),
+                    2,
+                    buffer,
+                )
+                .unwrap();
+            y += 80;
+        }
+
+        if !text.later.is_empty() {
+            ttf::<B>(ctx.sans_font, 24.0, B::BLACK, B::WHITE)
+                .draw_line("Later today:", Point::new(8, y), buffer)
+                .unwrap();
+            y += 32;
+
+            let style = ttf::<B>(ctx.sans_font, 22.0, B::BLACK, B::WHITE);
+
+            for line in &text.later {
+                style
+                    .draw_line_ellipsized(line, Point::new(16, y), 360, buffer)
+                    .unwrap();
+                y += 28;
+            }
+        }
+
+        if let Some(ref hint) = text.hint {
+            ttf::<B>(ctx.sans_font, 22.0, B::BLACK, B::WHITE)
+                .align(Alignment::Center)
+                .baseline(Baseline::Bottom)
+                .draw_paragraph(
+                    hint,
+                    &Rectangle::with_corners(Point::new(8, 572), Point::new(375, 631)),
+                    2,
+                    buffer,
+                )
+                .unwrap();
+        }
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        PANEL
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        format!("{:?}", MeetingRoomText::new(ctx))
+    }
+}
+
+/// Visitor notes: a QR code for leaving one, and how many are waiting.
+pub struct NotesWidget;
+
+impl<B: DisplayBackend> Widget<B> for NotesWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        let y = NOTES_Y;
+        let mut x = 8;
+
+        if let Some(ref url) = ctx.dd.note_form_url {
+            if let Some(qr) = QrImage::new(url, Point::new(x, y), 3, B::BLACK, B::WHITE) {
+                qr.draw(buffer).unwrap();
+                x = qr.bounding_box().bottom_right().map(|p| p.x).unwrap_or(x) + 8;
+            }
+
+            ttf::<B>(ctx.sans_font, 24.0, B::BLACK, B::WHITE)
+                .draw_paragraph(
+                    "Not here? Scan to leave me a note.",
+                    &Rectangle::with_corners(Point::new(x, y + 12), Point::new(381, y + 90)),
+                    2,
+                    buffer,
+                )
+                .unwrap();
+        }
+
+        if ctx.dd.notes_waiting > 0 {
+            let msg = if ctx.dd.notes_waiting == 1 {
+                "1 note waiting".to_owned()
+            } else {
+                format!("{} notes waiting", ctx.dd.notes_waiting)
+            };
+
+            ttf::<B>(ctx.sans_font, 24.0, B::BLACK, B::WHITE)
+                .draw_line(&msg, Point::new(x, y + 100), buffer)
+                .unwrap();
+        }
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        Rectangle::with_corners(Point::new(0, NOTES_Y), Point::new(383, NOTES_Y + 130))
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        format!("{:?} {}", ctx.dd.note_form_url, ctx.dd.notes_waiting)
+    }
+}
+
+/// CI results, one line per branch, under the visitor notes.
+pub struct CiWidget;
+
+const CI_Y: i32 = NOTES_Y + 138;
+const CI_LINE_HEIGHT: i32 = 24;
+
+/// A line like "main: \u{2713} 12m ago".
+fn ci_text(status: &CiStatus, now: DateTime<Utc>) -> String {
+    let mark = if status.passed {
+        '\u{2713}'
+    } else {
+        '\u{2717}'
+    };
+    let minutes = now
+        .signed_duration_since(status.finished)
+        .num_minutes()
+        .max(0);
+
+    let ago = if minutes < 60 {
+        format!("{}m", minutes)
+    } else if minutes < 60 * 24 {
+        format!("{}h", minutes / 60)
+    } else {
+        format!("{}d", minutes / (60 * 24))
+    };
+
+    format!("{}: {} {} ago", status.label, mark, ago)
+}
+
+impl<B: DisplayBackend> Widget<B> for CiWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        let now = ctx.dd.hub_now().with_timezone(&Utc);
+        let mut y = CI_Y;
+
+        for status in &ctx.dd.ci {
+            ttf::<B>(ctx.sans_font, 20.0, B::BLACK, B::WHITE)
+                .draw_line_ellipsized(&ci_text(status, now), Point::new(8, y), 374, buffer)
+                .unwrap();
+            y += CI_LINE_HEIGHT;
+        }
+    }
+
+    fn bounds(&self, ctx: &RenderContext) -> Rectangle {
+        let n = ctx.dd.ci.len() as i32;
+        Rectangle::with_corners(
+            Point::new(0, CI_Y),
+            Point::new(383, CI_Y + CI_LINE_HEIGHT * n),
+        )
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        let now = ctx.dd.hub_now().with_timezone(&Utc);
+        let lines: Vec<String> = ctx.dd.ci.iter().map(|s| ci_text(s, now)).collect();
+        lines.join("\n")
+    }
+}
+
+/// Who's on call, under the CI results. The panel's owner gets a badge for
+/// each schedule that they're on call for; anyone else gets their name shown.
+pub struct OnCallWidget;
+
+fn on_call_y(ctx: &RenderContext) -> i32 {
+    CI_Y + CI_LINE_HEIGHT * ctx.dd.ci.len() as i32
+}
+
+impl<B: DisplayBackend> Widget<B> for OnCallWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        let mut y = on_call_y(ctx);
+
+        for entry in &ctx.dd.on_call {
+            match entry.person {
+                Some(ref person) => {
+                    ttf::<B>(ctx.sans_font, 20.0, B::BLACK, B::WHITE)
+                        .draw_line_ellipsized(
+                            &format!("\u{260e} {}: {}", entry.label, person),
+                            Point::new(8, y),
+                            374,
+                            buffer,
+                        )
+                        .unwrap();
+                }
+
+                None => {
+                    let style = ttf::<B>(ctx.sans_font, 20.0, B::WHITE, B::BLACK);
+                    let text = format!("ON CALL \u{b7} {}", entry.label);
+                    let width = style.text_width(&text).min(366) as i32;
+
+                    Rectangle::with_corners(Point::new(4, y - 1), Point::new(12 + width, y + 21))
+                        .into_styled(PrimitiveStyle::with_fill(B::BLACK))
+                        .draw(buffer)
+                        .unwrap();
+
+                    style
+                        .draw_line_ellipsized(&text, Point::new(8, y), 366, buffer)
+                        .unwrap();
+                }
+            }
+
+            y += CI_LINE_HEIGHT;
+        }
+    }
+
+    fn bounds(&self, ctx: &RenderContext) -> Rectangle {
+        let y = on_call_y(ctx);
+        let n = ctx.dd.on_call.len() as i32;
+        Rectangle::with_corners(
+            Point::new(0, y - 1),
+            Point::new(383, y + CI_LINE_HEIGHT * n),
+        )
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        format!("{} {:?}", on_call_y(ctx), ctx.dd.on_call)
+    }
+}
+
+/// The hub's counters, under who's on call: each value in large type,
+/// followed by its label.
+pub struct CountersWidget;
+
+const COUNTER_LINE_HEIGHT: i32 = 32;
+
+fn counters_y(ctx: &RenderContext) -> i32 {
+    on_call_y(ctx) + CI_LINE_HEIGHT * ctx.dd.on_call.len() as i32
+}
+
+impl<B: DisplayBackend> Widget<B> for CountersWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        let mut y = counters_y(ctx) + COUNTER_LINE_HEIGHT - 4;
+
+        for counter in &ctx.dd.counters {
+            let value = counter.value.to_string();
+            let value_style =
+                ttf::<B>(ctx.sans_font, 28.0, B::BLACK, B::WHITE).baseline(Baseline::Bottom);
+            value_style
+                .draw_line(&value, Point::new(8, y), buffer)
+                .unwrap();
+
+            let x = 8 + value_style.text_width(&value) as i32 + 8;
+            ttf::<B>(ctx.sans_font, 20.0, B::BLACK, B::WHITE)
+                .baseline(Baseline::Bottom)
+                .draw_line_ellipsized(
+                    &counter.label,
+                    Point::new(x, y),
+                    (382 - x).max(0) as u32,
+                    buffer,
+                )
+                .unwrap();
+
+            y += COUNTER_LINE_HEIGHT;
+        }
+    }
+
+    fn bounds(&self, ctx: &RenderContext) -> Rectangle {
+        let y = counters_y(ctx);
+        let n = ctx.dd.counters.len() as i32;
+        Rectangle::with_corners(
+            Point::new(0, y),
+            Point::new(383, y + COUNTER_LINE_HEIGHT * n),
+        )
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        format!("{} {:?}", counters_y(ctx), ctx.dd.counters)
+    }
+}
+
+/// Room conditions, in the corner above the footer.
+pub struct RoomWidget;
+
+fn room_text(m: Measurement) -> String {
+    format!(
+        "{:.0} ppm CO\u{2082}   {:.1}\u{b0}C",
+        m.co2_ppm, m.temperature_c
+    )
+}
+
+impl<B: DisplayBackend> Widget<B> for RoomWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        if let Some(m) = ctx.room {
+            let text = room_text(m);
+
+            ttf::<B>(ctx.sans_font, 20.0, B::BLACK, B::WHITE)
+                .align(Alignment::Right)
+                .baseline(Baseline::Bottom)
+                .draw_line(&text, Point::new(381, 626), buffer)
+                .unwrap();
+        }
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        Rectangle::with_corners(Point::new(0, 602), Point::new(383, 629))
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        ctx.room.map(room_text).unwrap_or_default()
+    }
+}
+
+/// The output of configured commands, stacked upwards from just above the
+/// room conditions.
+pub struct CommandOutputWidget;
+
+impl<B: DisplayBackend> Widget<B> for CommandOutputWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        let mut y = 600;
+
+        for text in ctx.widget_text.iter().rev().flatten() {
+            ttf::<B>(ctx.sans_font, 20.0, B::BLACK, B::WHITE)
+                .align(Alignment::Right)
+                .baseline(Baseline::Bottom)
+                .draw_line(text, Point::new(381, y), buffer)
+                .unwrap();
+            y -= 24;
+        }
+    }
+
+    fn bounds(&self, ctx: &RenderContext) -> Rectangle {
+        let n = ctx.widget_text.iter().flatten().count() as i32;
+        Rectangle::with_corners(Point::new(0, 600 - 24 * n), Point::new(383, 601))
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        format!("{:?}", ctx.widget_text)
+    }
+}
+
+/// A warning if the Pi is struggling, in the corner opposite the room
+/// conditions.
+pub struct HealthWidget;
+
+impl<B: DisplayBackend> Widget<B> for HealthWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        if let Some(ref w) = ctx.health_warning {
+            ttf::<B>(ctx.sans_font, 20.0, B::BLACK, B::WHITE)
+                .baseline(Baseline::Bottom)
+                .draw_line(&format!("\u{26a0} {}", w), Point::new(2, 626), buffer)
+                .unwrap();
+        }
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        Rectangle::with_corners(Point::new(0, 602), Point::new(383, 629))
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        format!("{:?}", ctx.health_warning)
+    }
+}
+
+/// A passing message across the bottom, covering whatever's there.
+pub struct ToastWidget;
+
+const TOAST_BANNER: Rectangle = Rectangle::new(Point::new(0, 596), Size::new(384, 34));
+
+impl<B: DisplayBackend> Widget<B> for ToastWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        if let Some(ref t) = ctx.toast {
+            TOAST_BANNER
+                .into_styled(PrimitiveStyle::with_fill(B::BLACK))
+                .draw(buffer)
+                .unwrap();
+
+            ttf::<B>(ctx.sans_font, 20.0, B::WHITE, B::BLACK)
+                .align(Alignment::Center)
+                .baseline(Baseline::Middle)
+                .draw_line(t, TOAST_BANNER.center(), buffer)
+                .unwrap();
+        }
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        TOAST_BANNER
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        format!("{:?}", ctx.toast)
+    }
+}
+
+/// A banner across the top while the hub is down for maintenance, since
+/// the rest of the panel won't be updated until it's over.
+pub struct MaintenanceWidget;
+
+const MAINTENANCE_BANNER: Rectangle = Rectangle::new(Point::new(0, 0), Size::new(384, 52));
+
+impl<B: DisplayBackend> Widget<B> for MaintenanceWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        if !ctx.dd.maintenance {
+            return;
+        }
+
+        MAINTENANCE_BANNER
+            .into_styled(PrimitiveStyle::with_fill(B::BLACK))
+            .draw(buffer)
+            .unwrap();
+
+        ttf::<B>(ctx.sans_font, 32.0, B::WHITE, B::BLACK)
+            .align(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .draw_line("Down for maintenance", MAINTENANCE_BANNER.center(), buffer)
+            .unwrap();
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        MAINTENANCE_BANNER
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        ctx.dd.maintenance.to_string()
+    }
+}
+
+/// The doorbell card, which should go on top of everything else.
+pub struct DoorbellWidget;
+
+const DOORBELL_CARD: Rectangle = Rectangle::new(Point::new(8, 200), Size::new(368, 241));
+
+fn is_doorbell_ringing(ctx: &RenderContext) -> bool {
+    let now = ctx.dd.now.with_timezone(&Utc);
+    ctx.dd.doorbell_until.map(|u| now < u) == Some(true)
+}
+
+impl<B: DisplayBackend> Widget<B> for DoorbellWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        if !is_doorbell_ringing(ctx) {
+            return;
+        }
+
+        let card = DOORBELL_CARD;
+
+        card.into_styled(PrimitiveStyle::with_fill(B::BLACK))
+            .draw(buffer)
+            .unwrap();
+
+        ttf::<B>(ctx.serif_font, 56.0, B::WHITE, B::BLACK)
+            .features(&ctx.config.font_features.heading)
+            .align(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .draw_paragraph("Someone's at the door!", &card.offset(-16), 4, buffer)
+            .unwrap();
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        DOORBELL_CARD
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        is_doorbell_ringing(ctx).to_string()
+    }
+}
+
+/// The footer, with the network details on the right and the project URL or
+/// a news headline on the left.
+pub struct FooterWidget;
+
+const FOOTER: Rectangle = Rectangle::new(Point::new(0, 630), Size::new(384, 10));
+
+fn network_text(dd: &DisplayData) -> String {
+    let mut text = dd.ip_addr.clone();
+
+    if let Some(ref h) = dd.hostname {
+        text = format!("{}  {}", h, text);
+    }
+
+    if let Some(ref w) = dd.wifi {
+        text = format!("{}  {}", text, w.summary());
+    }
+
+    text
+}
+
+/// If the hub has a status queued up, the footer says what's next. Otherwise,
+/// if we have news headlines, they take the place of the project URL,
+/// rotating one per redraw.
+fn footer_text(ctx: &RenderContext) -> String {
+    let dd = ctx.dd;
+
+    if let Some(ref next) = dd.next_status {
+        let at = next.at.with_timezone(&Local);
+
+        let format = if dd.clock_format().contains("%H") {
+            "%H:%M"
+        } else if at.minute() == 0 {
+            "%-I"
+        } else {
+            "%-I:%M"
+        };
+
+        format!("next: {} at {}", next.person_is, at.format(format))
+    } else if dd.headlines.is_empty() {
+        "https://github.com/pkgw/rc-stickynote".to_owned()
+    } else {
+        dd.headlines[ctx.n_redraws % dd.headlines.len()].clone()
+    }
+}
+
+impl<B: DisplayBackend> Widget<B> for FooterWidget {
+    fn draw(&self, ctx: &RenderContext, buffer: &mut B::Buffer) {
+        let footer = FOOTER;
+        let y = footer.center().y;
+        let mono_inverted = MonoStyle::new(B::WHITE, B::BLACK).baseline(Baseline::Middle);
+
+        footer
+            .into_styled(PrimitiveStyle::with_fill(B::BLACK))
+            .draw(buffer)
+            .unwrap();
+
+        let ip_bbox = mono_inverted
+            .align(Alignment::Right)
+            .draw_line(&network_text(ctx.dd), Point::new(381, y), buffer)
+            .unwrap();
+
+        let max_width = (ip_bbox.top_left.x - 2 - 8).max(0) as u32;
+
+        mono_inverted
+            .draw_line_ellipsized(&footer_text(ctx), Point::new(2, y), max_width, buffer)
+            .unwrap();
+    }
+
+    fn bounds(&self, _ctx: &RenderContext) -> Rectangle {
+        FOOTER
+    }
+
+    fn state(&self, ctx: &RenderContext) -> String {
+        format!("{} {}", network_text(ctx.dd), footer_text(ctx))
+    }
+}
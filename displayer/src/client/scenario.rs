@@ -0,0 +1,240 @@
+//! Scripted sequences of display states, for demos and for checking the
+//! layout.
+//!
+//! A scenario file is TOML with a list of `[[step]]`s. Each step changes some
+//! of what the hub would send, leaving the rest as the previous step had it,
+//! and is shown for `hold_seconds` before the next one. The steps go through
+//! the same renderer as the real client, on whatever backend the client uses. Keep in mind that the real panel takes more than ten seconds to
+//! redraw, so a step that goes by faster than that may never be shown.
+
+use chrono::prelude::*;
+use rc_stickynote_protocol::{DisplayMessage, DisplaySettings};
+use serde::Deserialize;
+use std::{
+    io::{Error, ErrorKind},
+    path::Path,
+    sync::{mpsc::channel, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use super::{
+    renderer_thread, ClientConfiguration, DisplayData, SharedHealth, SharedMeasurement,
+    SharedToast, LAYOUTS,
+};
+use crate::health::Health;
+use crate::scd30::Measurement;
+use crate::watchdog::Pulse;
+use crate::widgets::SharedWidgets;
+use crate::DisplayBackend;
+
+#[derive(Clone, Debug, Deserialize)]
+struct Scenario {
+    /// Whether to start over after the last step, until interrupted.
+    #[serde(default)]
+    repeat: bool,
+
+    #[serde(rename = "step")]
+    steps: Vec<ScenarioStep>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ScenarioStep {
+    /// How long to show this step.
+    #[serde(default = "default_hold_seconds")]
+    hold_seconds: f64,
+
+    /// A new "person is:" status.
+    #[serde(default)]
+    person_is: Option<String>,
+
+    /// How long ago the new status was set, in minutes.
+    #[serde(default)]
+    set_minutes_ago: i64,
+
+    /// Where the new status came from.
+    #[serde(default)]
+    source: Option<String>,
+
+    /// Who set the new status.
+    #[serde(default)]
+    set_by: Option<String>,
+
+    #[serde(default)]
+    notes_waiting: Option<usize>,
+
+    #[serde(default)]
+    note_form_url: Option<String>,
+
+    #[serde(default)]
+    headlines: Option<Vec<String>>,
+
+    /// Whether someone has just rung the doorbell. The card stays up for the
+    /// rest of the step.
+    #[serde(default)]
+    doorbell: bool,
+
+    #[serde(default)]
+    maintenance: Option<bool>,
+
+    /// Settings as if from the hub's configuration, like `locale`.
+    #[serde(default)]
+    settings: Option<DisplaySettings>,
+
+    /// A layout to switch to, as if an admin asked for it.
+    #[serde(default)]
+    layout: Option<String>,
+
+    #[serde(default)]
+    screensaver: Option<bool>,
+
+    /// A reading to show as if from the room sensor.
+    #[serde(default)]
+    room: Option<RoomReading>,
+}
+
+fn default_hold_seconds() -> f64 {
+    5.0
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+struct RoomReading {
+    co2_ppm: f32,
+    temperature_c: f32,
+    humidity_percent: f32,
+}
+
+impl ScenarioStep {
+    /// Update the message from the previous step for this one.
+    fn apply(&self, msg: &mut DisplayMessage) {
+        if let Some(ref p) = self.person_is {
+            msg.person_is = p.clone();
+            msg.person_is_timestamp = Utc::now() - chrono::Duration::minutes(self.set_minutes_ago);
+            msg.person_is_source = self.source.clone().unwrap_or_default();
+            msg.person_is_set_by = self.set_by.clone().unwrap_or_default();
+        }
+
+        if let Some(n) = self.notes_waiting {
+            msg.notes_waiting = n;
+        }
+
+        if let Some(ref u) = self.note_form_url {
+            msg.note_form_url = Some(u.clone());
+        }
+
+        if let Some(ref h) = self.headlines {
+            msg.headlines = h.clone();
+        }
+
+        if let Some(m) = self.maintenance {
+            msg.maintenance = m;
+        }
+
+        if let Some(ref s) = self.settings {
+            msg.settings = Some(s.clone());
+        }
+
+        msg.doorbell_until = if self.doorbell {
+            Some(Utc::now() + chrono::Duration::milliseconds((self.hold_seconds * 1000.) as i64))
+        } else {
+            None
+        };
+    }
+}
+
+/// Play the scenario in the given file.
+pub fn play<B: DisplayBackend>(config: ClientConfiguration, path: &Path) -> Result<(), Error> {
+    let text = std::fs::read_to_string(path)?;
+    let scenario: Scenario = toml::from_str(&text).map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("cannot parse scenario `{}`: {}", path.display(), e),
+        )
+    })?;
+
+    if scenario.steps.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("scenario `{}` has no steps", path.display()),
+        ));
+    }
+
+    // Check the layouts up front, rather than bailing out halfway through.
+
+    for step in &scenario.steps {
+        if let Some(ref l) = step.layout {
+            if !LAYOUTS.contains(&l.as_str()) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown layout `{}` in scenario", l),
+                ));
+            }
+        }
+    }
+
+    let (sender, receiver) = channel();
+    let room: SharedMeasurement = Arc::new(Mutex::new(None));
+    let widget_text: SharedWidgets = Arc::new(Mutex::new(Vec::new()));
+    let health: SharedHealth = Arc::new(Mutex::new(Health::default()));
+    let toast: SharedToast = Arc::new(Mutex::new(None));
+
+    let renderer = {
+        let config = config.clone();
+        let room = room.clone();
+        thread::spawn(move || {
+            renderer_thread::<B>(
+                config,
+                receiver,
+                room,
+                widget_text,
+                health,
+                toast,
+                Pulse::default(),
+            )
+        })
+    };
+
+    let mut dd = DisplayData::new(&config.addresses)?;
+
+    loop {
+        let mut msg = DisplayMessage::default();
+
+        for (i, step) in scenario.steps.iter().enumerate() {
+            step.apply(&mut msg);
+            dd.update_from_message(msg.clone());
+
+            if let Some(ref l) = step.layout {
+                dd.layout = l.clone();
+            }
+
+            if let Some(s) = step.screensaver {
+                dd.screensaver = s;
+            }
+
+            if let Some(r) = step.room {
+                *room.lock().unwrap() = Some(Measurement {
+                    co2_ppm: r.co2_ppm,
+                    temperature_c: r.temperature_c,
+                    humidity_percent: r.humidity_percent,
+                });
+            }
+
+            println!("step {}: {}", i + 1, dd.person_is);
+
+            if sender.send(dd.clone()).is_err() {
+                return Err(Error::new(ErrorKind::Other, "the renderer thread died"));
+            }
+
+            thread::sleep(Duration::from_secs_f64(step.hold_seconds.max(0.)));
+        }
+
+        if !scenario.repeat {
+            break;
+        }
+    }
+
+    // Let the renderer finish drawing the last step.
+    drop(sender);
+    let _ = renderer.join();
+    Ok(())
+}
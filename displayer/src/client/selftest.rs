@@ -0,0 +1,298 @@
+//! A check-up for a newly assembled panel.
+//!
+//! The `self-test` command goes through everything that the client needs in
+//! order to work, one step at a time, and says which steps passed: that the
+//! display can be opened and drawn on, that the buttons' GPIO pins can be
+//! read, that the fonts load, that the hub's host name resolves, and that the
+//! hub answers a hello. Unlike the client, it carries on past failures, so
+//! that one run turns up everything that's wrong.
+
+use embedded_graphics::{
+    prelude::*,
+    primitives::{PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+};
+use rc_stickynote_protocol::DisplayHelloMessage;
+use std::{
+    fmt::Display,
+    io::{Error, ErrorKind},
+    net::ToSocketAddrs,
+};
+use tokio::{
+    runtime::Runtime,
+    time::{self, Duration},
+};
+
+use super::ClientConfiguration;
+use crate::drawing::{Alignment, LineStyle, MonoStyle};
+use crate::fonts::{self, FontFamily};
+use crate::DisplayBackend;
+
+/// How long to wait for the hub to answer the hello.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The results of the steps so far.
+#[derive(Debug, Default)]
+struct Report {
+    passed: usize,
+    failed: usize,
+}
+
+impl Report {
+    /// Print the outcome of a step, with what it found or why it failed.
+    fn record<T: Display, E: Display>(&mut self, step: &str, result: Result<T, E>) {
+        match result {
+            Ok(detail) => {
+                self.passed += 1;
+                println!("PASS  {}: {}", step, detail);
+            }
+
+            Err(e) => {
+                self.failed += 1;
+                println!("FAIL  {}: {}", step, e);
+            }
+        }
+    }
+
+    fn skip(&self, step: &str, why: &str) {
+        println!("SKIP  {}: {}", step, why);
+    }
+}
+
+/// Run all of the steps, printing how each one went. If any of them failed,
+/// so does this.
+pub fn run<B: DisplayBackend>(config: ClientConfiguration) -> Result<(), Error> {
+    let display_id = config.display_id.clone().unwrap_or_default();
+
+    let mut report = Report::default();
+    report.record(
+        "configuration",
+        Ok::<_, Error>(format!("panel {}", display_id)),
+    );
+
+    let pins = config.board.pins();
+    println!(
+        "INFO  board: a Waveshare display would be on {}, lines {}/{}/{}/{} (CS/busy/DC/reset) of {}",
+        pins.spi_path, pins.cs, pins.busy, pins.dc, pins.rst, pins.gpio_chip_path
+    );
+
+    report.record("display", test_pattern::<B>(&display_id));
+
+    for (name, pin) in &[
+        ("doorbell button", config.doorbell_button_gpio),
+        ("room button", config.room_button_gpio),
+    ] {
+        match pin {
+            Some(pin) => report.record(name, read_button(*pin)),
+            None => report.skip(name, "not configured"),
+        }
+    }
+
+    // A font that can't be loaded doesn't stop the client, but it's still
+    // not what was asked for.
+
+    for (name, path, family) in &[
+        ("sans font", &config.sans_path, FontFamily::Sans),
+        ("serif font", &config.serif_path, FontFamily::Serif),
+    ] {
+        let result = match path {
+            Some(path) => fonts::load_file(path)
+                .map(|face| format!("{} ({} glyphs)", path, face.font.glyph_count()))
+                .map_err(|e| {
+                    let (_, substitute) = fonts::fallback(*family);
+                    format!("can't load {}: {}; using {} instead", path, e, substitute)
+                }),
+
+            None => Ok(format!(
+                "not configured; using {}",
+                fonts::fallback(*family).1
+            )),
+        };
+
+        report.record(name, result);
+    }
+
+    if config.mqtt().is_some() {
+        report.skip("hub address", "the hub is reached through MQTT");
+        report.skip("hub hello", "the hub is reached through MQTT");
+    } else {
+        if config.hub_command.is_some() {
+            report.skip("hub address", "the hub is reached through a command");
+        } else {
+            report.record("hub address", resolve_hub(&config));
+        }
+
+        report.record("hub hello", hello(&config));
+    }
+
+    println!();
+    println!(
+        "self-test: {} passed, {} failed",
+        report.passed, report.failed
+    );
+
+    if report.failed > 0 {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("{} self-test step(s) failed", report.failed),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Open the display and draw a test pattern: a border around the edge of the
+/// panel, to check that nothing is cut off, a checkerboard, and bands of
+/// each shade that the panel can show.
+fn test_pattern<B: DisplayBackend>(display_id: &str) -> Result<String, Error> {
+    let mut backend = B::open()?;
+
+    {
+        let buffer = backend.get_buffer_mut();
+        let size = buffer.bounding_box().size;
+
+        buffer.clear(B::WHITE).unwrap();
+
+        Rectangle::new(Point::zero(), size)
+            .into_styled(PrimitiveStyle::with_stroke(B::BLACK, 4))
+            .draw(buffer)
+            .unwrap();
+
+        let square = 32;
+
+        for row in 0..4 {
+            for col in 0..(size.width as i32 - 64) / square {
+                if (row + col) % 2 == 0 {
+                    Rectangle::new(
+                        Point::new(32 + col * square, 120 + row * square),
+                        Size::new(square as u32, square as u32),
+                    )
+                    .into_styled(PrimitiveStyle::with_fill(B::BLACK))
+                    .draw(buffer)
+                    .unwrap();
+                }
+            }
+        }
+
+        let mut shades = vec![B::BLACK];
+
+        if let Some((dark, light)) = B::GRAYS {
+            shades.push(dark);
+            shades.push(light);
+        }
+
+        shades.push(B::WHITE);
+
+        let band_width = (size.width - 64) / shades.len() as u32;
+
+        for (i, shade) in shades.into_iter().enumerate() {
+            Rectangle::new(
+                Point::new(32 + (i as u32 * band_width) as i32, 280),
+                Size::new(band_width, 64),
+            )
+            .into_styled(
+                PrimitiveStyleBuilder::new()
+                    .fill_color(shade)
+                    .stroke_color(B::BLACK)
+                    .stroke_width(1)
+                    .build(),
+            )
+            .draw(buffer)
+            .unwrap();
+        }
+
+        let style = MonoStyle::new(B::BLACK, B::WHITE).align(Alignment::Center);
+        let center = size.width as i32 / 2;
+
+        style
+            .draw_line("rc-stickynote self-test", Point::new(center, 50), buffer)
+            .unwrap();
+        style
+            .draw_line(
+                &format!("panel {}", display_id),
+                Point::new(center, 70),
+                buffer,
+            )
+            .unwrap();
+    }
+
+    backend.show_buffer()?;
+    backend.sleep_device()?;
+    Ok("drew the test pattern; check the panel".to_owned())
+}
+
+/// Set up a button's GPIO pin and read it.
+fn read_button(pin_number: u64) -> Result<String, String> {
+    use linux_embedded_hal::sysfs_gpio::{Direction, Pin};
+
+    let pin = Pin::new(pin_number);
+
+    let value = pin
+        .export()
+        .and_then(|_| pin.set_direction(Direction::In))
+        .and_then(|_| pin.get_value())
+        .map_err(|e| format!("can't read GPIO {}: {}", pin_number, e))?;
+
+    let state = if value == 0 { "pressed" } else { "released" };
+    Ok(format!("GPIO {} reads {}", pin_number, state))
+}
+
+/// Look up the address of the hub, or of its SSH server if that's how we get
+/// to it.
+fn resolve_hub(config: &ClientConfiguration) -> Result<String, Error> {
+    let port = match config.ssh {
+        Some(ref ssh) => ssh.ssh_port,
+        None => config.hub_port,
+    };
+
+    let addrs: Vec<String> = (config.hub_host.as_ref(), port)
+        .to_socket_addrs()?
+        .map(|a| a.ip().to_string())
+        .collect();
+
+    if addrs.is_empty() {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("{} has no addresses", config.hub_host),
+        ));
+    }
+
+    Ok(format!("{} is {}", config.hub_host, addrs.join(", ")))
+}
+
+/// Connect to the hub as a panel and wait for the first display state.
+fn hello(config: &ClientConfiguration) -> Result<String, Error> {
+    let mut rt = Runtime::new()?;
+
+    let msg = rt.block_on(async {
+        let handshake = async {
+            let hub_comms = config.connect().await?;
+            let hello = DisplayHelloMessage {
+                display_id: config.display_id.clone(),
+                capabilities: None,
+                multiplex: false,
+                last_update: None,
+            };
+            let mut session = hub_comms.start_display(hello).await?;
+            session.next_message().await
+        };
+
+        match time::timeout(HELLO_TIMEOUT, handshake).await {
+            Ok(result) => result,
+            Err(_) => Err(Error::new(
+                ErrorKind::TimedOut,
+                "the hub didn't answer in time",
+            )),
+        }
+    })?;
+
+    match msg {
+        Some(msg) => Ok(format!(
+            "the hub says the person is \"{}\"",
+            config.unseal_status(msg.person_is)
+        )),
+        None => Err(Error::new(
+            ErrorKind::Other,
+            "the hub hung up without sending anything",
+        )),
+    }
+}
@@ -0,0 +1,88 @@
+//! Drawing single frames without a panel, for programs that want a picture of
+//! the sign, like the hub's image endpoint.
+//!
+//! A snapshot goes through the same layouts as the real client, but there's
+//! no hub connection, no sensor, and no command-driven widgets, so the parts
+//! of the panel that depend on those are left blank. So is the network
+//! information in the footer, which would describe whatever machine is
+//! drawing the snapshot rather than a panel.
+//!
+//! Frames are drawn for the current time unless told otherwise, which is how
+//! the golden-image tests in `tests/snapshot.rs` get the same frame every
+//! time.
+
+use chrono::{DateTime, Local, Utc};
+use rc_stickynote_protocol::DisplayMessage;
+use std::{
+    io::Error,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use super::{render, ClientConfiguration, DisplayData, RenderContext};
+use crate::fonts::{load_file, Typeface};
+use crate::widgets::{self, SharedWidgets};
+use crate::DisplayBackend;
+
+/// Draws frames for display messages, on demand.
+pub struct FrameRenderer {
+    config: ClientConfiguration,
+    sans_font: Typeface,
+    serif_font: Typeface,
+    now: Option<DateTime<Utc>>,
+}
+
+impl FrameRenderer {
+    /// Set up to draw with the fonts in the given files.
+    pub fn new<P: AsRef<Path>>(sans_path: P, serif_path: P) -> Result<Self, Error> {
+        Ok(FrameRenderer {
+            config: ClientConfiguration::default(),
+            sans_font: load_file(sans_path)?,
+            serif_font: load_file(serif_path)?,
+            now: None,
+        })
+    }
+
+    /// Draw frames as if it were the given time, rather than now.
+    pub fn set_now(&mut self, now: DateTime<Utc>) {
+        self.now = Some(now);
+    }
+
+    /// Draw the frame that a panel would show for the message into the
+    /// backend's buffer. The backend isn't asked to show it.
+    pub fn draw<B: DisplayBackend>(
+        &self,
+        msg: DisplayMessage,
+        backend: &mut B,
+    ) -> Result<(), Error> {
+        let mut dd = DisplayData::new(&self.config.addresses)?;
+        dd.update_from_message(msg);
+        dd.ip_addr = String::new();
+        dd.hostname = None;
+        dd.wifi = None;
+
+        if let Some(now) = self.now {
+            dd.now = now.with_timezone(&Local);
+        }
+
+        let widget_text: SharedWidgets = Arc::new(Mutex::new(Vec::new()));
+        let ago_formatter = dd.ago_formatter();
+
+        let ctx = RenderContext {
+            config: &self.config,
+            dd: &dd,
+            sans_font: &self.sans_font,
+            serif_font: &self.serif_font,
+            ago_formatter: &ago_formatter,
+            room: None,
+            widget_text: widgets::current_text(&self.config.widgets, &widget_text, &dd.extras),
+            health_warning: None,
+            toast: None,
+            screensaver: None,
+            n_redraws: 0,
+        };
+
+        let widgets = render::layout_widgets::<B>(&dd);
+        render::compose(&widgets, &ctx, backend)
+    }
+}
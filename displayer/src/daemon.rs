@@ -0,0 +1,137 @@
+//! Running the client as a background daemon.
+//!
+//! At boot, the client is usually started by root, since it takes root to
+//! open the panel's SPI and GPIO devices on some boards. But nothing else that
+//! the client does needs root, so once the panel is open, it can switch over
+//! to an ordinary user. The user's supplementary groups are kept, so that
+//! membership in groups like `gpio` and `i2c` still gets it at the buttons and
+//! the sensor.
+
+use daemonize::Daemonize;
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::CString,
+    fs::File,
+    io::{Error, ErrorKind},
+    os::unix::io::OwnedFd,
+    path::PathBuf,
+};
+
+use crate::logfile::{self, LogConfiguration, RotatingLog};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DaemonConfiguration {
+    /// Where to write the daemon's process ID.
+    pub pid_file: PathBuf,
+
+    /// The directory to run in. Relative paths for the PID file and the log
+    /// are still taken from the directory that the client was started in.
+    pub working_directory: PathBuf,
+
+    /// If set, the user to switch to once the panel is open.
+    pub user: Option<String>,
+
+    /// If set, the group to switch to once the panel is open. Defaults to
+    /// the user's own group.
+    pub group: Option<String>,
+}
+
+impl Default for DaemonConfiguration {
+    fn default() -> Self {
+        DaemonConfiguration {
+            pid_file: "rc-stickynote-displayer.pid".into(),
+            working_directory: "/".into(),
+            user: None,
+            group: None,
+        }
+    }
+}
+
+/// Detach from the terminal and get into the background, sending our output
+/// to the log. This has to happen before any threads are started.
+pub fn daemonize(config: &DaemonConfiguration, log_config: &LogConfiguration) -> Result<(), Error> {
+    let log = RotatingLog::open(log_config)?;
+    let (pipe_reader, pipe_writer) = std::io::pipe()?;
+    let stdio_handle = File::from(OwnedFd::from(pipe_writer));
+
+    let dconfig = Daemonize::new()
+        .pid_file(&config.pid_file)
+        .working_directory(&config.working_directory)
+        .stdout(stdio_handle.try_clone()?)
+        .stderr(stdio_handle);
+
+    if let Err(e) = dconfig.start() {
+        return Err(Error::new(ErrorKind::Other, e.to_string()));
+    }
+
+    logfile::spawn(log, pipe_reader);
+    Ok(())
+}
+
+fn c_string(s: &str) -> Result<CString, Error> {
+    CString::new(s).map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+}
+
+/// Switch to the configured user and group, if any. This applies to every
+/// thread in the process.
+pub fn drop_privileges(config: &DaemonConfiguration) -> Result<(), Error> {
+    let user = match config.user {
+        Some(ref u) => u,
+        None => {
+            if config.group.is_some() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "a group to switch to needs a user to switch to as well",
+                ));
+            }
+
+            return Ok(());
+        }
+    };
+
+    let c_user = c_string(user)?;
+
+    // Safety: these are the usual libc calls, given valid C strings, and the
+    // structures that they return are only read before the next call.
+    unsafe {
+        let passwd = libc::getpwnam(c_user.as_ptr());
+
+        if passwd.is_null() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("no such user \"{}\"", user),
+            ));
+        }
+
+        let uid = (*passwd).pw_uid;
+        let mut gid = (*passwd).pw_gid;
+
+        if let Some(ref group) = config.group {
+            let c_group = c_string(group)?;
+            let grp = libc::getgrnam(c_group.as_ptr());
+
+            if grp.is_null() {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("no such group \"{}\"", group),
+                ));
+            }
+
+            gid = (*grp).gr_gid;
+        }
+
+        // The groups have to change first, since changing the user gives up
+        // the right to change them.
+
+        if libc::initgroups(c_user.as_ptr(), gid) != 0
+            || libc::setgid(gid) != 0
+            || libc::setuid(uid) != 0
+        {
+            return Err(Error::last_os_error());
+        }
+    }
+
+    println!("now running as {}", user);
+    Ok(())
+}
@@ -0,0 +1,494 @@
+//! Higher-level helpers for putting text on the display.
+//!
+//! The raw text APIs make you work out pixel positions yourself, which led to
+//! a lot of `x = 382 - 6 * len` arithmetic. The styles in this module know how
+//! to measure their text, so they can handle alignment relative to an anchor
+//! point, word-wrapping into a box, and truncating text that doesn't fit.
+//!
+//! Alignment and baseline semantics follow those of embedded-graphics: with
+//! `Alignment::Right`, the anchor is the rightmost column of the text, and with
+//! `Baseline::Alphabetic` the anchor is on the font's baseline.
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X9, MonoTextStyle, MonoTextStyleBuilder},
+    pixelcolor::PixelColor,
+    prelude::*,
+    primitives::Rectangle,
+    text::{renderer::TextRenderer, Text},
+};
+use qrcode::QrCode;
+use rusttype::Scale;
+use std::borrow::Cow;
+
+pub use embedded_graphics::text::{Alignment, Baseline};
+
+use crate::features::FontFeature;
+use crate::fonts::Typeface;
+use crate::text::DrawFontExt;
+
+/// Operations common to the text styles defined in this module.
+pub trait LineStyle {
+    type Color: PixelColor;
+
+    /// The width of the given text when rendered on one line, in pixels.
+    fn text_width(&self, text: &str) -> u32;
+
+    /// The height of a line of text, in pixels.
+    fn line_height(&self) -> u32;
+
+    /// The distance from the top of a line of text to its baseline, in pixels.
+    fn ascent(&self) -> u32;
+
+    /// The string appended to text that has been truncated to fit.
+    fn ellipsis(&self) -> &'static str;
+
+    /// The horizontal alignment of text relative to its anchor.
+    fn alignment(&self) -> Alignment;
+
+    /// The vertical alignment of text relative to its anchor.
+    fn baseline(&self) -> Baseline;
+
+    /// Draw a single line of text with its top left corner at the specified
+    /// point, returning the bounding box of the drawn text.
+    fn draw_top_left<D>(
+        &self,
+        text: &str,
+        top_left: Point,
+        target: &mut D,
+    ) -> Result<Rectangle, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>;
+
+    /// Compute where the top left corner of a line of text goes, given its
+    /// anchor point.
+    fn place(&self, text: &str, anchor: Point) -> Point {
+        let width = self.text_width(text) as i32;
+        let height = self.line_height() as i32;
+
+        let x = match self.alignment() {
+            Alignment::Left => anchor.x,
+            Alignment::Center => anchor.x - (width - 1) / 2,
+            Alignment::Right => anchor.x - width + 1,
+        };
+
+        let y = match self.baseline() {
+            Baseline::Top => anchor.y,
+            Baseline::Middle => anchor.y - (height - 1) / 2,
+            Baseline::Alphabetic => anchor.y - self.ascent() as i32,
+            Baseline::Bottom => anchor.y - height + 1,
+        };
+
+        Point::new(x, y)
+    }
+
+    /// Draw a single line of text relative to the specified anchor point.
+    fn draw_line<D>(&self, text: &str, anchor: Point, target: &mut D) -> Result<Rectangle, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        self.draw_top_left(text, self.place(text, anchor), target)
+    }
+
+    /// Like `draw_line`, but truncating the text with an ellipsis if it
+    /// would be wider than `max_width`.
+    fn draw_line_ellipsized<D>(
+        &self,
+        text: &str,
+        anchor: Point,
+        max_width: u32,
+        target: &mut D,
+    ) -> Result<Rectangle, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let text = self.ellipsize(text, max_width);
+        self.draw_line(&text, anchor, target)
+    }
+
+    /// Truncate the text with an ellipsis so that it is no wider than
+    /// `max_width`. If even the ellipsis doesn't fit, it is returned anyway.
+    fn ellipsize<'t>(&self, text: &'t str, max_width: u32) -> Cow<'t, str> {
+        if self.text_width(text) <= max_width {
+            return Cow::Borrowed(text);
+        }
+
+        let ellipsis = self.ellipsis();
+
+        for (i, _) in text.char_indices().rev() {
+            let candidate = format!("{}{}", text[..i].trim_end(), ellipsis);
+
+            if self.text_width(&candidate) <= max_width {
+                return Cow::Owned(candidate);
+            }
+        }
+
+        Cow::Borrowed(ellipsis)
+    }
+
+    /// Break the text into lines no wider than `max_width`, splitting at
+    /// whitespace. Individual words that are too wide are left as-is.
+    fn wrap(&self, text: &str, max_width: u32) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut current = String::new();
+
+        for word in text.split_whitespace() {
+            if current.is_empty() {
+                current.push_str(word);
+                continue;
+            }
+
+            let candidate = format!("{} {}", current, word);
+
+            if self.text_width(&candidate) <= max_width {
+                current = candidate;
+            } else {
+                lines.push(current);
+                current = word.to_owned();
+            }
+        }
+
+        if !current.is_empty() {
+            lines.push(current);
+        }
+
+        lines
+    }
+
+    /// Draw word-wrapped text inside the specified area, with `leading`
+    /// extra pixels between lines. The alignment determines how lines are
+    /// placed horizontally within the area, and the baseline determines how
+    /// the block of text is placed vertically (`Alphabetic` acts like
+    /// `Top`). If the text has too many lines to fit, the last visible line
+    /// is ellipsized. Returns the bounding box of the area actually used.
+    fn draw_paragraph<D>(
+        &self,
+        text: &str,
+        area: &Rectangle,
+        leading: u32,
+        target: &mut D,
+    ) -> Result<Rectangle, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let width = area.size.width;
+        let pitch = self.line_height() + leading;
+        let max_lines = ((area.size.height + leading) / pitch) as usize;
+
+        let mut lines = self.wrap(text, width);
+
+        if lines.len() > max_lines {
+            let rest = lines.split_off(max_lines).join(" ");
+
+            if let Some(last) = lines.last_mut() {
+                let joined = format!("{} {}", last, rest);
+                *last = self.ellipsize(&joined, width).into_owned();
+            }
+        }
+
+        let block_height = (lines.len() as u32 * pitch).saturating_sub(leading);
+
+        let mut y = match self.baseline() {
+            Baseline::Top | Baseline::Alphabetic => area.top_left.y,
+            Baseline::Middle => {
+                area.top_left.y + (area.size.height as i32 - block_height as i32) / 2
+            }
+            Baseline::Bottom => area.top_left.y + area.size.height as i32 - block_height as i32,
+        };
+
+        let mut used = Rectangle::new(Point::new(area.top_left.x, y), Size::zero());
+
+        for line in &lines {
+            let line = self.ellipsize(line, width);
+            let line_width = self.text_width(&line) as i32;
+
+            let x = match self.alignment() {
+                Alignment::Left => area.top_left.x,
+                Alignment::Center => area.top_left.x + (width as i32 - line_width) / 2,
+                Alignment::Right => area.top_left.x + width as i32 - line_width,
+            };
+
+            let bbox = self.draw_top_left(&line, Point::new(x, y), target)?;
+            used = union(&used, &bbox);
+            y += pitch as i32;
+        }
+
+        Ok(used)
+    }
+}
+
+/// The smallest rectangle containing both inputs, treating zero-sized
+/// rectangles as empty.
+fn union(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    if a.size == Size::zero() {
+        return *b;
+    }
+
+    if b.size == Size::zero() {
+        return *a;
+    }
+
+    let tl = a.top_left.component_min(b.top_left);
+    let br = (a.top_left + a.size).component_max(b.top_left + b.size);
+    Rectangle::with_corners(tl, br - Point::new(1, 1))
+}
+
+/// A style for drawing text with a TrueType font.
+#[derive(Clone, Copy)]
+pub struct TtfStyle<'a, C> {
+    face: &'a Typeface,
+    height: f32,
+    features: &'a [FontFeature],
+    fg: C,
+    bg: C,
+    shades: Option<(C, C)>,
+    alignment: Alignment,
+    baseline: Baseline,
+}
+
+impl<'a, C: PixelColor> TtfStyle<'a, C> {
+    /// Create a new style, left-aligned with the anchor at the top.
+    pub fn new(face: &'a Typeface, height: f32, fg: C, bg: C) -> Self {
+        TtfStyle {
+            face,
+            height,
+            features: &[],
+            fg,
+            bg,
+            shades: None,
+            alignment: Alignment::Left,
+            baseline: Baseline::Top,
+        }
+    }
+
+    /// Antialias the text with two shades between the foreground and
+    /// background colors, for panels that can show grays.
+    pub fn shades(mut self, near_fg: C, near_bg: C) -> Self {
+        self.shades = Some((near_fg, near_bg));
+        self
+    }
+
+    /// Turn on OpenType features, such as tabular numbers.
+    pub fn features(mut self, features: &'a [FontFeature]) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Set the horizontal alignment of the text.
+    pub fn align(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Set the vertical alignment of the text.
+    pub fn baseline(mut self, baseline: Baseline) -> Self {
+        self.baseline = baseline;
+        self
+    }
+}
+
+impl<'a, C: PixelColor> LineStyle for TtfStyle<'a, C> {
+    type Color = C;
+
+    fn text_width(&self, text: &str) -> u32 {
+        self.face
+            .layout_text_with(text, self.height, self.features)
+            .width as u32
+    }
+
+    fn line_height(&self) -> u32 {
+        self.height.ceil() as u32
+    }
+
+    fn ascent(&self) -> u32 {
+        self.face
+            .font
+            .v_metrics(Scale::uniform(self.height))
+            .ascent
+            .round() as u32
+    }
+
+    fn ellipsis(&self) -> &'static str {
+        "\u{2026}"
+    }
+
+    fn alignment(&self) -> Alignment {
+        self.alignment
+    }
+
+    fn baseline(&self) -> Baseline {
+        self.baseline
+    }
+
+    fn draw_top_left<D>(
+        &self,
+        text: &str,
+        top_left: Point,
+        target: &mut D,
+    ) -> Result<Rectangle, D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let layout = self.face.layout_text_with(text, self.height, self.features);
+        let mut positioned = layout.draw_at(top_left.x, top_left.y, self.fg, self.bg);
+
+        if let Some((near_fg, near_bg)) = self.shades {
+            positioned = positioned.with_shades(near_fg, near_bg);
+        }
+
+        positioned.draw(target)?;
+        Ok(positioned.bounding_box())
+    }
+}
+
+/// A style for drawing text with the small built-in monospace font.
+#[derive(Clone, Copy, Debug)]
+pub struct MonoStyle<C> {
+    fg: C,
+    bg: C,
+    alignment: Alignment,
+    baseline: Baseline,
+}
+
+impl<C: PixelColor> MonoStyle<C> {
+    /// Create a new style, left-aligned with the anchor at the top.
+    pub fn new(fg: C, bg: C) -> Self {
+        MonoStyle {
+            fg,
+            bg,
+            alignment: Alignment::Left,
+            baseline: Baseline::Top,
+        }
+    }
+
+    /// Set the horizontal alignment of the text.
+    pub fn align(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Set the vertical alignment of the text.
+    pub fn baseline(mut self, baseline: Baseline) -> Self {
+        self.baseline = baseline;
+        self
+    }
+
+    fn character_style(&self) -> MonoTextStyle<'static, C> {
+        MonoTextStyleBuilder::new()
+            .font(&FONT_6X9)
+            .text_color(self.fg)
+            .background_color(self.bg)
+            .build()
+    }
+}
+
+impl<C: PixelColor> LineStyle for MonoStyle<C> {
+    type Color = C;
+
+    fn text_width(&self, text: &str) -> u32 {
+        self.character_style()
+            .measure_string(text, Point::zero(), Baseline::Top)
+            .bounding_box
+            .size
+            .width
+    }
+
+    fn line_height(&self) -> u32 {
+        FONT_6X9.character_size.height
+    }
+
+    fn ascent(&self) -> u32 {
+        FONT_6X9.baseline
+    }
+
+    fn ellipsis(&self) -> &'static str {
+        "..."
+    }
+
+    fn alignment(&self) -> Alignment {
+        self.alignment
+    }
+
+    fn baseline(&self) -> Baseline {
+        self.baseline
+    }
+
+    fn draw_top_left<D>(
+        &self,
+        text: &str,
+        top_left: Point,
+        target: &mut D,
+    ) -> Result<Rectangle, D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let text = Text::with_baseline(text, top_left, self.character_style(), Baseline::Top);
+        text.draw(target)?;
+        Ok(text.bounding_box())
+    }
+}
+
+/// A QR code placed on the display.
+///
+/// Each module of the code is drawn as a square `scale` pixels on a side,
+/// surrounded by the standard four-module "quiet zone" in the light color.
+pub struct QrImage<C> {
+    code: QrCode,
+    top_left: Point,
+    scale: u32,
+    dark: C,
+    light: C,
+}
+
+/// The width of the margin around a QR code, in modules.
+const QR_QUIET_ZONE: u32 = 4;
+
+impl<C: PixelColor> QrImage<C> {
+    /// Encode the given text, returning None if that isn't possible (e.g.,
+    /// because it's too long).
+    pub fn new(text: &str, top_left: Point, scale: u32, dark: C, light: C) -> Option<Self> {
+        let code = QrCode::new(text.as_bytes()).ok()?;
+
+        Some(QrImage {
+            code,
+            top_left,
+            scale,
+            dark,
+            light,
+        })
+    }
+}
+
+impl<C> Dimensions for QrImage<C> {
+    fn bounding_box(&self) -> Rectangle {
+        let side = (self.code.width() as u32 + 2 * QR_QUIET_ZONE) * self.scale;
+        Rectangle::new(self.top_left, Size::new(side, side))
+    }
+}
+
+impl<C: PixelColor> Drawable for QrImage<C> {
+    type Color = C;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        target.fill_solid(&self.bounding_box(), self.light)?;
+
+        let width = self.code.width();
+        let offset = (QR_QUIET_ZONE * self.scale) as i32;
+        let scale = self.scale as i32;
+        let module_size = Size::new(self.scale, self.scale);
+
+        for (i, color) in self.code.to_colors().into_iter().enumerate() {
+            if color == qrcode::Color::Dark {
+                let x = (i % width) as i32;
+                let y = (i / width) as i32;
+                let corner = self.top_left + Point::new(offset + x * scale, offset + y * scale);
+                target.fill_solid(&Rectangle::new(corner, module_size), self.dark)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,93 +1,110 @@
 //! Display backend for the Waveshare 7.5-inch e-Print Display.
+//!
+//! Where the display is connected depends on the board; see the `board`
+//! module.
 
+use embedded_graphics::pixelcolor::BinaryColor;
 use epd_waveshare::{
     color::Color,
-    epd7in5::{Display7in5, EPD7in5},
-    graphics::Display,
+    epd7in5::{Display7in5, Epd7in5},
     prelude::*,
 };
 use linux_embedded_hal::{
-    spidev::{self, SpidevOptions},
-    sysfs_gpio::Direction,
-    Delay, Pin, Spidev,
+    gpio_cdev::{Chip, LineRequestFlags},
+    spidev::{SpiModeFlags, SpidevOptions},
+    CdevPin, Delay, Spidev,
 };
-use std::{io::Error, thread::sleep, time::Duration};
+use std::io::{Error, ErrorKind};
 
 use super::DisplayBackend;
 
 pub struct EPD7in5Backend {
     spi: Spidev,
-    epd7in5: EPD7in5<Spidev, Pin, Pin, Pin, Pin>,
+    delay: Delay,
+    epd7in5: Epd7in5<Spidev, CdevPin, CdevPin, CdevPin, CdevPin, Delay>,
     display: Display7in5,
 }
 
+/// Claim one of the display's GPIO lines.
+fn request_line(
+    chip: &mut Chip,
+    offset: u32,
+    flags: LineRequestFlags,
+    default: u8,
+    name: &str,
+) -> Result<CdevPin, Error> {
+    chip.get_line(offset)
+        .and_then(|line| line.request(flags, default, "rc-stickynote"))
+        .and_then(CdevPin::new)
+        .map_err(|e| {
+            Error::new(
+                ErrorKind::Other,
+                format!(
+                    "cannot set up the display's {} line ({} on {}): {}",
+                    name,
+                    offset,
+                    chip.path().display(),
+                    e
+                ),
+            )
+        })
+}
+
 impl DisplayBackend for EPD7in5Backend {
-    type Color = Color;
+    type Color = BinaryColor;
     type Buffer = Display7in5;
 
-    const BLACK: Color = Color::Black;
-    const WHITE: Color = Color::White;
+    // The waveshare-epd graphics buffers map `On` to black.
+    const BLACK: BinaryColor = BinaryColor::On;
+    const WHITE: BinaryColor = BinaryColor::Off;
 
     fn open() -> Result<Self, Error> {
-        // This is all copied from the epd-waveshare 7in5 example.
-        // TODO: remove .expect()s
+        // This is adapted from the epd-waveshare 7in5 example, with the
+        // connections coming from the board configuration.
+
+        let pins = crate::client::board_configuration()?.pins();
 
-        let mut spi = Spidev::open("/dev/spidev0.0")?;
+        let mut spi = Spidev::open(&pins.spi_path)?;
         let options = SpidevOptions::new()
             .bits_per_word(8)
             .max_speed_hz(4_000_000)
-            .mode(spidev::SPI_MODE_0)
+            .mode(SpiModeFlags::SPI_MODE_0)
             .build();
-        spi.configure(&options)?;
-
-        let cs = Pin::new(8); // Chip Select pin
-        cs.export().expect("cs export");
-        while !cs.is_exported() {}
-        // See https://github.com/rust-embedded/rust-sysfs-gpio/issues/5 --
-        // after the pin is exported, there is a small window before the
-        // RPi udev system changes permissions on the created device file.
-        // If we try to set the direction before this window elapses, we
-        // fail with EACCES when run as non-root. We're only booting up
-        // infrequently, so just hardcode a delay.
-        sleep(Duration::from_millis(750));
-        cs.set_direction(Direction::Out).expect("CS Direction");
-        cs.set_value(1).expect("CS Value set to 1");
-
-        let busy = Pin::new(24); // Busy pin
-        busy.export().expect("busy export");
-        while !busy.is_exported() {}
-        sleep(Duration::from_millis(750)); // see above
-        busy.set_direction(Direction::In).expect("busy Direction");
-
-        let dc = Pin::new(25);
-        dc.export().expect("dc export");
-        while !dc.is_exported() {}
-        sleep(Duration::from_millis(750)); // see above
-        dc.set_direction(Direction::Out).expect("dc Direction");
-        dc.set_value(1).expect("dc Value set to 1");
-
-        let rst = Pin::new(17);
-        rst.export().expect("rst export");
-        while !rst.is_exported() {}
-        sleep(Duration::from_millis(750)); // see above
-        rst.set_direction(Direction::Out).expect("rst Direction");
-        rst.set_value(1).expect("rst Value set to 1");
+        spi.0.configure(&options)?;
+
+        let mut chip = Chip::new(&pins.gpio_chip_path).map_err(|e| {
+            Error::new(
+                ErrorKind::Other,
+                format!("cannot open GPIO chip {}: {}", pins.gpio_chip_path, e),
+            )
+        })?;
+
+        let cs = request_line(&mut chip, pins.cs, LineRequestFlags::OUTPUT, 1, "CS")?;
+        let busy = request_line(&mut chip, pins.busy, LineRequestFlags::INPUT, 0, "busy")?;
+        let dc = request_line(&mut chip, pins.dc, LineRequestFlags::OUTPUT, 1, "DC")?;
+        let rst = request_line(&mut chip, pins.rst, LineRequestFlags::OUTPUT, 1, "reset")?;
 
         let mut delay = Delay {};
-        let epd7in5 = EPD7in5::new(&mut spi, cs, busy, dc, rst, &mut delay)?;
+        let epd7in5 = Epd7in5::new(&mut spi, cs, busy, dc, rst, &mut delay)?;
         let mut display = Display7in5::default();
 
         display.set_rotation(DisplayRotation::Rotate270);
 
         Ok(EPD7in5Backend {
             spi,
+            delay,
             epd7in5,
             display,
         })
     }
 
     fn clear_buffer(&mut self, color: Self::Color) -> Result<(), Error> {
-        self.display.clear_buffer(color);
+        // Don't use `DrawTarget::clear()` here: it iterates over the
+        // unrotated bounding box, so with our rotation it would miss pixels.
+        self.display.clear_buffer(match color {
+            BinaryColor::On => Color::Black,
+            BinaryColor::Off => Color::White,
+        });
         Ok(())
     }
 
@@ -97,23 +114,22 @@ impl DisplayBackend for EPD7in5Backend {
 
     fn show_buffer(&mut self) -> Result<(), Error> {
         self.epd7in5
-            .update_frame(&mut self.spi, &self.display.buffer())?;
-        self.epd7in5.display_frame(&mut self.spi)?;
+            .update_frame(&mut self.spi, self.display.buffer(), &mut self.delay)?;
+        self.epd7in5.display_frame(&mut self.spi, &mut self.delay)?;
         Ok(())
     }
 
     fn clear_display(&mut self) -> Result<(), Error> {
-        self.epd7in5.clear_frame(&mut self.spi)?;
-        self.epd7in5.display_frame(&mut self.spi)?;
+        self.epd7in5.clear_frame(&mut self.spi, &mut self.delay)?;
+        self.epd7in5.display_frame(&mut self.spi, &mut self.delay)?;
         Ok(())
     }
 
     fn sleep_device(&mut self) -> Result<(), Error> {
-        Ok(self.epd7in5.sleep(&mut self.spi)?)
+        Ok(self.epd7in5.sleep(&mut self.spi, &mut self.delay)?)
     }
 
     fn wake_up_device(&mut self) -> Result<(), Error> {
-        let mut delay = Delay {};
-        Ok(self.epd7in5.wake_up(&mut self.spi, &mut delay)?)
+        Ok(self.epd7in5.wake_up(&mut self.spi, &mut self.delay)?)
     }
 }
@@ -0,0 +1,264 @@
+//! OpenType font features.
+//!
+//! Rusttype draws glyphs but doesn't do any shaping, so the alternate glyphs
+//! that OpenType fonts offer through their GSUB tables are out of its reach.
+//! The ones that we care about, like tabular numerals and small caps, are
+//! simple one-for-one substitutions, so we dig those out of the font file
+//! ourselves. Plenty of fonts, DejaVu among them, don't have them at all, in
+//! which case the text layout fakes them as best it can.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A font feature that a piece of text can ask for.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FontFeature {
+    /// Digits that are all the same width, so that numbers that change, like
+    /// the time, don't shift the text around them.
+    TabularNumbers,
+
+    /// Lowercase letters drawn as small capitals.
+    SmallCaps,
+}
+
+impl FontFeature {
+    /// The OpenType tag of the feature.
+    fn tag(self) -> [u8; 4] {
+        match self {
+            FontFeature::TabularNumbers => *b"tnum",
+            FontFeature::SmallCaps => *b"smcp",
+        }
+    }
+}
+
+/// The single-glyph substitutions that a font offers, by feature.
+#[derive(Clone, Debug, Default)]
+pub struct Substitutions {
+    by_tag: HashMap<[u8; 4], HashMap<u16, u16>>,
+}
+
+impl Substitutions {
+    /// Find the substitutions in the font at the given index of a font file
+    /// or collection. Anything that we don't understand is skipped, so a font
+    /// with no usable GSUB table just has no substitutions.
+    pub fn parse(data: &[u8], index: usize) -> Self {
+        let mut subs = Substitutions::default();
+
+        if let Some(gsub) = find_table(data, index, b"GSUB") {
+            subs.parse_gsub(gsub);
+        }
+
+        subs
+    }
+
+    /// Whether the font has any substitutions for the feature.
+    pub fn has(&self, feature: FontFeature) -> bool {
+        matches!(self.by_tag.get(&feature.tag()), Some(m) if !m.is_empty())
+    }
+
+    /// The glyph to use in place of the given one for the feature, if the
+    /// feature changes it.
+    pub fn get(&self, feature: FontFeature, glyph: u16) -> Option<u16> {
+        self.by_tag
+            .get(&feature.tag())
+            .and_then(|m| m.get(&glyph))
+            .copied()
+    }
+
+    fn parse_gsub(&mut self, gsub: &[u8]) -> Option<()> {
+        let feature_list = gsub.get(u16_at(gsub, 6)? as usize..)?;
+        let lookup_list = gsub.get(u16_at(gsub, 8)? as usize..)?;
+
+        for i in 0..u16_at(feature_list, 0)? as usize {
+            let record = 2 + 6 * i;
+            let tag = [
+                *feature_list.get(record)?,
+                *feature_list.get(record + 1)?,
+                *feature_list.get(record + 2)?,
+                *feature_list.get(record + 3)?,
+            ];
+            let feature = feature_list.get(u16_at(feature_list, record + 4)? as usize..)?;
+
+            for j in 0..u16_at(feature, 2)? as usize {
+                let lookup_index = u16_at(feature, 4 + 2 * j)? as usize;
+                let lookup_offset = u16_at(lookup_list, 2 + 2 * lookup_index)?;
+                let lookup = lookup_list.get(lookup_offset as usize..)?;
+                let map = self.by_tag.entry(tag).or_default();
+
+                for k in 0..u16_at(lookup, 4)? as usize {
+                    let subtable = lookup.get(u16_at(lookup, 6 + 2 * k)? as usize..)?;
+
+                    // Type 7 is an extension, which just points at a subtable
+                    // of another type that's too far away for a 16-bit
+                    // offset.
+
+                    let (kind, subtable) = match u16_at(lookup, 0)? {
+                        7 => (
+                            u16_at(subtable, 2)?,
+                            subtable.get(u32_at(subtable, 4)? as usize..)?,
+                        ),
+                        kind => (kind, subtable),
+                    };
+
+                    if kind == 1 {
+                        parse_single(subtable, map);
+                    }
+                }
+            }
+        }
+
+        Some(())
+    }
+}
+
+/// Read a single substitution subtable into the map. If more than one
+/// subtable covers a glyph, the first one wins.
+fn parse_single(subtable: &[u8], map: &mut HashMap<u16, u16>) -> Option<()> {
+    let coverage = subtable.get(u16_at(subtable, 2)? as usize..)?;
+    let glyphs = coverage_glyphs(coverage)?;
+
+    match u16_at(subtable, 0)? {
+        1 => {
+            let delta = u16_at(subtable, 4)?;
+
+            for g in glyphs {
+                map.entry(g).or_insert_with(|| g.wrapping_add(delta));
+            }
+        }
+
+        2 => {
+            for (i, g) in glyphs.into_iter().enumerate() {
+                let substitute = u16_at(subtable, 6 + 2 * i)?;
+                map.entry(g).or_insert(substitute);
+            }
+        }
+
+        _ => {}
+    }
+
+    Some(())
+}
+
+/// The glyphs listed in a coverage table, in coverage index order.
+fn coverage_glyphs(coverage: &[u8]) -> Option<Vec<u16>> {
+    let count = u16_at(coverage, 2)? as usize;
+    let mut glyphs = Vec::new();
+
+    match u16_at(coverage, 0)? {
+        1 => {
+            for i in 0..count {
+                glyphs.push(u16_at(coverage, 4 + 2 * i)?);
+            }
+        }
+
+        2 => {
+            for i in 0..count {
+                let start = u16_at(coverage, 4 + 6 * i)?;
+                let end = u16_at(coverage, 6 + 6 * i)?;
+                glyphs.extend(start..=end);
+            }
+        }
+
+        _ => return None,
+    }
+
+    Some(glyphs)
+}
+
+/// Find a table in the font at the given index of a font file or
+/// collection.
+fn find_table<'d>(data: &'d [u8], index: usize, tag: &[u8; 4]) -> Option<&'d [u8]> {
+    let start = if data.get(0..4)? == b"ttcf" {
+        u32_at(data, 12 + 4 * index)? as usize
+    } else {
+        0
+    };
+
+    let directory = data.get(start..)?;
+
+    for i in 0..u16_at(directory, 4)? as usize {
+        let record = 12 + 16 * i;
+
+        if directory.get(record..record + 4)? == tag {
+            let offset = u32_at(directory, record + 8)? as usize;
+            let length = u32_at(directory, record + 12)? as usize;
+            return data.get(offset..offset + length);
+        }
+    }
+
+    None
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A GSUB table with a "tnum" feature whose one lookup maps glyphs 10
+    /// and 11 to 20 and 21, and an "smcp" feature with a lookup that adds 100
+    /// to glyphs 30 through 32. The comments give byte offsets.
+    fn gsub() -> Vec<u8> {
+        #[rustfmt::skip]
+        let words: &[u16] = &[
+            // 0: header, with the feature list at 10 and lookups at 36
+            1, 0, 0, 10, 36,
+            // 10: feature list: two features, at 14 and 20 from here
+            2, 0x746e, 0x756d, 14, 0x736d, 0x6370, 20,
+            // 24: "tnum", lookup 0; 30: "smcp", lookup 1
+            0, 1, 0, 0, 1, 1,
+            // 36: lookup list: two lookups, at 6 and 32 from here
+            2, 6, 32,
+            // 42: lookup 0: single substitution, subtable at 8
+            1, 0, 1, 8,
+            // 50: format 2, coverage at 10, two glyphs
+            2, 10, 2, 20, 21,
+            // 60: coverage format 1
+            1, 2, 10, 11,
+            // 68: lookup 1: an extension pointing to a single substitution
+            7, 0, 1, 8,
+            // 76: extension subtable, with the real one 8 bytes on
+            1, 1, 0, 8,
+            // 84: format 1, coverage at 6, delta 100
+            1, 6, 100,
+            // 90: coverage format 2: one range
+            2, 1, 30, 32, 0,
+        ];
+
+        words.iter().flat_map(|w| w.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn single_substitutions() {
+        let mut subs = Substitutions::default();
+        subs.parse_gsub(&gsub()).unwrap();
+
+        assert!(subs.has(FontFeature::TabularNumbers));
+        assert_eq!(subs.get(FontFeature::TabularNumbers, 10), Some(20));
+        assert_eq!(subs.get(FontFeature::TabularNumbers, 11), Some(21));
+        assert_eq!(subs.get(FontFeature::TabularNumbers, 12), None);
+
+        assert!(subs.has(FontFeature::SmallCaps));
+        assert_eq!(subs.get(FontFeature::SmallCaps, 31), Some(131));
+        assert_eq!(subs.get(FontFeature::SmallCaps, 33), None);
+    }
+
+    #[test]
+    fn truncated_tables() {
+        let data = gsub();
+
+        for len in 0..data.len() {
+            let mut subs = Substitutions::default();
+            subs.parse_gsub(&data[..len]);
+        }
+    }
+}
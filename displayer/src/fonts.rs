@@ -0,0 +1,135 @@
+//! Finding the fonts to draw with.
+//!
+//! The client configuration names a sans-serif and a serif font file, but a
+//! path that's wrong, or a font package that's gone missing in an upgrade,
+//! shouldn't leave the panel unable to draw anything. So if a configured
+//! font can't be loaded, or none is configured, we ask fontconfig for the
+//! system's usual font of that kind, and if that doesn't work out either, we
+//! fall back on a copy of DejaVu Sans that's built into the program.
+
+use rusttype::{Font, FontCollection};
+use std::{
+    fs::File,
+    io::{Error, ErrorKind, Read},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::features::Substitutions;
+
+/// DejaVu Sans, which is always available. See `fonts/LICENSE-DejaVu.txt`.
+const BUNDLED_FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+
+/// The kinds of fonts that the layouts use.
+#[derive(Clone, Copy, Debug)]
+pub enum FontFamily {
+    Sans,
+    Serif,
+}
+
+impl FontFamily {
+    /// The name to ask fontconfig for.
+    fn pattern(self) -> &'static str {
+        match self {
+            FontFamily::Sans => "sans-serif",
+            FontFamily::Serif => "serif",
+        }
+    }
+}
+
+/// A font, along with the OpenType substitutions that it offers.
+pub struct Typeface {
+    pub font: Font<'static>,
+    pub substitutions: Substitutions,
+}
+
+impl Typeface {
+    /// Load the font at the given index of a font file or collection.
+    fn from_bytes(font_data: Vec<u8>, index: usize) -> Result<Self, Error> {
+        let substitutions = Substitutions::parse(&font_data, index);
+        let font = FontCollection::from_bytes(font_data)?.font_at(index)?;
+
+        Ok(Typeface {
+            font,
+            substitutions,
+        })
+    }
+}
+
+/// Load a TTF or OTF font file.
+pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Typeface, Error> {
+    let mut file = File::open(path)?;
+    let mut font_data = Vec::new();
+    file.read_to_end(&mut font_data)?;
+    Typeface::from_bytes(font_data, 0)
+}
+
+/// Load the configured font of the given kind, or if that can't be done, the
+/// best substitute that we can find. This can't fail.
+pub fn load(configured: Option<&str>, family: FontFamily) -> Typeface {
+    if let Some(path) = configured {
+        match load_file(path) {
+            Ok(font) => return font,
+            Err(e) => eprintln!(
+                "ERROR: cannot load the {} font {}: {}",
+                family.pattern(),
+                path,
+                e
+            ),
+        }
+    }
+
+    let (font, description) = fallback(family);
+    println!("using {} for the {} font", description, family.pattern());
+    font
+}
+
+/// The font to use when there's no configured one, and what it is.
+pub fn fallback(family: FontFamily) -> (Typeface, String) {
+    match discover(family) {
+        Ok((font, path)) => return (font, path.display().to_string()),
+        Err(e) => eprintln!(
+            "ERROR: cannot find a {} font with fontconfig: {}",
+            family.pattern(),
+            e
+        ),
+    }
+
+    let font = Typeface::from_bytes(BUNDLED_FONT.to_vec(), 0).expect("the bundled font is valid");
+    (font, "the built-in DejaVu Sans".to_owned())
+}
+
+/// Ask fontconfig for the system's font of the given kind.
+fn discover(family: FontFamily) -> Result<(Typeface, PathBuf), Error> {
+    let output = Command::new("fc-match")
+        .args(["--format", "%{file}\n%{index}\n", family.pattern()])
+        .output()?;
+
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("fc-match failed: {}", output.status),
+        ));
+    }
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut lines = text.lines();
+
+    let path = match lines.next() {
+        Some(p) if !p.is_empty() => PathBuf::from(p),
+        _ => return Err(Error::new(ErrorKind::Other, "fc-match found nothing")),
+    };
+
+    let index = lines.next().and_then(|i| i.parse().ok()).unwrap_or(0);
+
+    // Fontconfig is happy to suggest a font collection, so pick the right
+    // font out of it.
+
+    let mut font_data = Vec::new();
+    File::open(&path)?.read_to_end(&mut font_data)?;
+
+    let font = Typeface::from_bytes(font_data, index)
+        .map_err(|e| Error::new(ErrorKind::Other, format!("{}: {}", path.display(), e)))?;
+
+    Ok((font, path))
+}
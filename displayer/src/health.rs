@@ -0,0 +1,116 @@
+//! Keeping an eye on the Pi's own health.
+//!
+//! Raspberry Pis slow themselves down when they get too hot or their power
+//! supply sags, and they don't tell anyone. We read the SoC temperature from
+//! the kernel and the throttling flags from `vcgencmd get_throttled`, so that
+//! the panel can show a warning and the hub can keep a record.
+
+use serde::{Deserialize, Serialize};
+use std::{fs, process::Command};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HealthConfiguration {
+    /// Whether to check on the Pi's health at all.
+    pub enabled: bool,
+
+    /// How often to report the Pi's health to the hub. Zero means never.
+    pub report_minutes: u64,
+
+    /// Show a warning on the panel if the SoC is at least this hot, in
+    /// degrees Celsius.
+    pub warn_temperature_c: f32,
+}
+
+impl Default for HealthConfiguration {
+    fn default() -> Self {
+        HealthConfiguration {
+            enabled: true,
+            report_minutes: 60,
+            warn_temperature_c: 80.0,
+        }
+    }
+}
+
+// The bits of the `vcgencmd get_throttled` value that describe the current
+// state. The same bits shifted up by 16 say whether each has happened since
+// boot.
+
+const UNDER_VOLTAGE_NOW: u32 = 1 << 0;
+const FREQUENCY_CAPPED_NOW: u32 = 1 << 1;
+const THROTTLED_NOW: u32 = 1 << 2;
+const SOFT_TEMPERATURE_LIMIT_NOW: u32 = 1 << 3;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Health {
+    /// The SoC temperature, in degrees Celsius.
+    pub cpu_temperature_c: Option<f32>,
+
+    /// The raw flags reported by `vcgencmd get_throttled`.
+    pub throttled_flags: Option<u32>,
+}
+
+impl Health {
+    /// Check on the Pi's health right now.
+    pub fn read() -> Health {
+        Health {
+            cpu_temperature_c: read_cpu_temperature(),
+            throttled_flags: read_throttled_flags(),
+        }
+    }
+
+    fn flag(&self, mask: u32) -> bool {
+        self.throttled_flags.map(|f| f & mask != 0) == Some(true)
+    }
+
+    /// Whether the power supply is sagging right now.
+    pub fn under_voltage(&self) -> bool {
+        self.flag(UNDER_VOLTAGE_NOW)
+    }
+
+    /// Whether the SoC is being slowed down right now.
+    pub fn throttled(&self) -> bool {
+        self.flag(FREQUENCY_CAPPED_NOW | THROTTLED_NOW | SOFT_TEMPERATURE_LIMIT_NOW)
+    }
+
+    /// A few words about what's wrong, if anything is.
+    pub fn warning(&self, config: &HealthConfiguration) -> Option<String> {
+        if self.under_voltage() {
+            return Some("low voltage".to_owned());
+        }
+
+        let too_hot = self
+            .cpu_temperature_c
+            .filter(|t| *t >= config.warn_temperature_c);
+
+        match (self.throttled(), too_hot) {
+            (true, Some(t)) => Some(format!("throttled, {:.0}\u{b0}C", t)),
+            (true, None) => Some("throttled".to_owned()),
+            (false, Some(t)) => Some(format!("hot, {:.0}\u{b0}C", t)),
+            (false, None) => None,
+        }
+    }
+}
+
+/// The kernel reports the temperature in millidegrees.
+fn read_cpu_temperature() -> Option<f32> {
+    let text = fs::read_to_string("/sys/class/thermal/thermal_zone0/temp").ok()?;
+    let millidegrees: f32 = text.trim().parse().ok()?;
+    Some(millidegrees / 1000.)
+}
+
+/// `vcgencmd` prints something like `throttled=0x50005`.
+fn read_throttled_flags() -> Option<u32> {
+    let output = Command::new("vcgencmd")
+        .arg("get_throttled")
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let value = text.trim().strip_prefix("throttled=")?;
+    u32::from_str_radix(value.trim_start_matches("0x"), 16).ok()
+}
@@ -0,0 +1,76 @@
+//! Giving each panel a name that sticks.
+//!
+//! The hub sees panels come and go by IP address and port, which change every
+//! time a panel reconnects. So each panel has an ID that it sends along with
+//! its hellos. It can be set in the configuration file, like "office-door";
+//! otherwise we make one up the first time the client runs and save it, so
+//! that the panel keeps the same ID across restarts.
+
+use std::{
+    env, fs,
+    io::{Error, ErrorKind, Read},
+    path::PathBuf,
+};
+
+use crate::netstatus;
+
+/// Where the generated ID is saved, next to the client's configuration file.
+fn id_path() -> Option<PathBuf> {
+    let mut path = match env::var_os("XDG_CONFIG_HOME") {
+        Some(d) if !d.is_empty() => PathBuf::from(d),
+        _ => {
+            let mut p = PathBuf::from(env::var_os("HOME")?);
+            p.push(".config");
+            p
+        }
+    };
+
+    path.push("rc-stickynote-client");
+    path.push("display-id");
+    Some(path)
+}
+
+/// Make up a new ID, like "raspberrypi-3f9c2a1b". The hostname makes it
+/// recognizable and the random part makes it unique, since lots of Pis are
+/// called "raspberrypi".
+fn generate_id() -> Result<String, Error> {
+    let mut bytes = [0u8; 4];
+    fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+
+    let host = netstatus::hostname().unwrap_or_else(|| "panel".to_owned());
+    let suffix: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!("{}-{}", host, suffix))
+}
+
+/// Figure out this panel's ID. If the configuration doesn't set one, use the
+/// saved ID, generating and saving a new one if need be.
+pub fn display_id(configured: Option<&str>) -> Result<String, Error> {
+    if let Some(id) = configured {
+        return Ok(id.to_owned());
+    }
+
+    let path = id_path().ok_or_else(|| {
+        Error::new(
+            ErrorKind::Other,
+            "cannot locate configuration directory to save the display ID",
+        )
+    })?;
+
+    if let Ok(text) = fs::read_to_string(&path) {
+        let id = text.trim();
+
+        if !id.is_empty() {
+            return Ok(id.to_owned());
+        }
+    }
+
+    let id = generate_id()?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(&path, format!("{}\n", id))?;
+    println!("generated display ID `{}`, saved in {}", id, path.display());
+    Ok(id)
+}
@@ -0,0 +1,390 @@
+//! Getting pictures onto the panel.
+//!
+//! Photos come in all sizes and in millions of colors, while the panel has a
+//! fixed size and only black and white, or four grays at best. So a picture
+//! is decoded, converted to grayscale, shrunk to fit, and then dithered down
+//! to the levels that the panel can show. Dithering spreads the error from
+//! each pixel onto its neighbors, so that areas of gray come out as patterns
+//! with the right average brightness rather than as blobs.
+//!
+//! This is slow, so it's done once, when a picture is loaded. The result is a
+//! `Picture` that can be drawn as often as needed.
+
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+use image::{imageops, GrayImage};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+/// How a picture is scaled to fit the space that it's shown in.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Filter {
+    /// Take the nearest pixel. Fast and blocky.
+    Nearest,
+
+    /// Interpolate linearly between pixels.
+    Triangle,
+
+    /// A cubic filter, sharper than `Triangle`.
+    CatmullRom,
+
+    /// The sharpest and slowest option.
+    Lanczos3,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Triangle
+    }
+}
+
+impl Filter {
+    fn filter_type(self) -> imageops::FilterType {
+        match self {
+            Filter::Nearest => imageops::FilterType::Nearest,
+            Filter::Triangle => imageops::FilterType::Triangle,
+            Filter::CatmullRom => imageops::FilterType::CatmullRom,
+            Filter::Lanczos3 => imageops::FilterType::Lanczos3,
+        }
+    }
+}
+
+/// How a grayscale picture is reduced to the levels that the panel can show.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dither {
+    /// Round each pixel to the nearest level. Good for line art and text.
+    Threshold,
+
+    /// Floyd–Steinberg error diffusion, which passes on all of the error.
+    /// Good for photos.
+    FloydSteinberg,
+
+    /// Atkinson error diffusion, which passes on only three-quarters of the
+    /// error, giving more contrast and cleaner highlights and shadows.
+    Atkinson,
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Dither::FloydSteinberg
+    }
+}
+
+impl Dither {
+    /// Where the error from each pixel goes: offsets to neighbors that
+    /// haven't been visited yet, and the fraction of the error that each one
+    /// gets.
+    fn kernel(self) -> &'static [(i32, i32, f32)] {
+        match self {
+            Dither::Threshold => &[],
+
+            Dither::FloydSteinberg => &[
+                (1, 0, 7. / 16.),
+                (-1, 1, 3. / 16.),
+                (0, 1, 5. / 16.),
+                (1, 1, 1. / 16.),
+            ],
+
+            Dither::Atkinson => &[
+                (1, 0, 1. / 8.),
+                (2, 0, 1. / 8.),
+                (-1, 1, 1. / 8.),
+                (0, 1, 1. / 8.),
+                (1, 1, 1. / 8.),
+                (0, 2, 1. / 8.),
+            ],
+        }
+    }
+}
+
+/// A picture to load, and how to prepare it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ImageConfiguration {
+    /// The image file, in PNG or JPEG format.
+    pub path: PathBuf,
+
+    /// How to scale the image to fit.
+    #[serde(default)]
+    pub filter: Filter,
+
+    /// How to reduce the image to the panel's levels of gray.
+    #[serde(default)]
+    pub dither: Dither,
+}
+
+/// Decode an image file into grayscale.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<GrayImage, Error> {
+    let path = path.as_ref();
+
+    let img = image::io::Reader::open(path)?
+        .with_guessed_format()?
+        .decode()
+        .map_err(|e| {
+            Error::new(
+                ErrorKind::Other,
+                format!("cannot decode image `{}`: {}", path.display(), e),
+            )
+        })?;
+
+    Ok(img.to_luma8())
+}
+
+/// Scale the image to be as large as possible within the given size, while
+/// keeping its shape. Images are scaled up as well as down.
+pub fn fit(img: &GrayImage, width: u32, height: u32, filter: Filter) -> GrayImage {
+    let (w, h) = img.dimensions();
+
+    if w == 0 || h == 0 {
+        return img.clone();
+    }
+
+    // Compare the aspect ratios to find the side that limits the scaling.
+    let (fit_w, fit_h) = if w as u64 * height as u64 > h as u64 * width as u64 {
+        let fit_h = (h as u64 * width as u64 + w as u64 / 2) / w as u64;
+        (width, fit_h.max(1) as u32)
+    } else {
+        let fit_w = (w as u64 * height as u64 + h as u64 / 2) / h as u64;
+        (fit_w.max(1) as u32, height)
+    };
+
+    if (fit_w, fit_h) == (w, h) {
+        img.clone()
+    } else {
+        imageops::resize(img, fit_w, fit_h, filter.filter_type())
+    }
+}
+
+/// Reduce the image to `n_levels` evenly-spaced levels of gray. The result
+/// has one level per pixel, in rows from the top, from 0 for black to
+/// `n_levels - 1` for white.
+pub fn dither(img: &GrayImage, n_levels: u8, method: Dither) -> Vec<u8> {
+    assert!(n_levels >= 2, "need at least two levels to dither to");
+
+    let (width, height) = (img.width() as i32, img.height() as i32);
+    let step = 255. / (n_levels - 1) as f32;
+    let kernel = method.kernel();
+
+    // The brightness that we want at each pixel, including the error that's
+    // been passed to it so far.
+    let mut values: Vec<f32> = img.pixels().map(|p| p.0[0] as f32).collect();
+    let mut levels = Vec::with_capacity(values.len());
+
+    for y in 0..height {
+        for x in 0..width {
+            let value = values[(y * width + x) as usize];
+            let level = (value / step).round().max(0.).min((n_levels - 1) as f32);
+            let error = value - level * step;
+            levels.push(level as u8);
+
+            for &(dx, dy, weight) in kernel {
+                let (nx, ny) = (x + dx, y + dy);
+
+                if nx >= 0 && nx < width && ny < height {
+                    values[(ny * width + nx) as usize] += error * weight;
+                }
+            }
+        }
+    }
+
+    levels
+}
+
+/// A picture that's ready to draw: scaled, and dithered down to a few
+/// levels of gray.
+#[derive(Clone, Debug)]
+pub struct Picture {
+    width: u32,
+    height: u32,
+    n_levels: u8,
+    levels: Vec<u8>,
+}
+
+impl Picture {
+    /// Load the configured image, and prepare it to be shown within the
+    /// given size with the given number of levels of gray.
+    pub fn load(
+        config: &ImageConfiguration,
+        width: u32,
+        height: u32,
+        n_levels: u8,
+    ) -> Result<Self, Error> {
+        let img = load(&config.path)?;
+        Ok(Self::from_gray(&img, width, height, n_levels, config))
+    }
+
+    /// Prepare an already-decoded image.
+    pub fn from_gray(
+        img: &GrayImage,
+        width: u32,
+        height: u32,
+        n_levels: u8,
+        config: &ImageConfiguration,
+    ) -> Self {
+        let img = fit(img, width, height, config.filter);
+
+        Picture {
+            width: img.width(),
+            height: img.height(),
+            n_levels,
+            levels: dither(&img, n_levels, config.dither),
+        }
+    }
+
+    /// The size of the picture, after scaling.
+    pub fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+
+    /// Place the picture for drawing. The palette gives the color for each
+    /// level, from black to white, and must have as many entries as the
+    /// picture has levels.
+    pub fn at<'a, C: PixelColor>(
+        &'a self,
+        top_left: Point,
+        palette: &'a [C],
+    ) -> PlacedPicture<'a, C> {
+        assert_eq!(palette.len(), self.n_levels as usize);
+
+        PlacedPicture {
+            picture: self,
+            top_left,
+            palette,
+        }
+    }
+}
+
+/// A `Picture` at a particular spot, in particular colors.
+pub struct PlacedPicture<'a, C> {
+    picture: &'a Picture,
+    top_left: Point,
+    palette: &'a [C],
+}
+
+impl<'a, C> Dimensions for PlacedPicture<'a, C> {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.top_left, self.picture.size())
+    }
+}
+
+impl<'a, C: PixelColor> Drawable for PlacedPicture<'a, C> {
+    type Color = C;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let width = self.picture.width as usize;
+
+        target.draw_iter(self.picture.levels.iter().enumerate().map(|(i, &level)| {
+            let p = Point::new((i % width) as i32, (i / width) as i32);
+            Pixel(self.top_left + p, self.palette[level as usize])
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(width: u32, height: u32, pixels: &[u8]) -> GrayImage {
+        GrayImage::from_raw(width, height, pixels.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn threshold_rounds_to_nearest_level() {
+        let img = gray(8, 1, &[0, 42, 43, 100, 127, 128, 200, 255]);
+        assert_eq!(dither(&img, 2, Dither::Threshold), [0, 0, 0, 0, 0, 1, 1, 1]);
+        assert_eq!(dither(&img, 4, Dither::Threshold), [0, 0, 1, 1, 1, 2, 2, 3]);
+    }
+
+    #[test]
+    fn exact_levels_are_kept() {
+        // Grays that the panel can show exactly have no error to spread.
+        let img = gray(4, 2, &[0, 85, 170, 255, 255, 170, 85, 0]);
+
+        for &method in &[Dither::FloydSteinberg, Dither::Atkinson] {
+            assert_eq!(dither(&img, 4, method), [0, 1, 2, 3, 3, 2, 1, 0]);
+        }
+    }
+
+    #[test]
+    fn floyd_steinberg_golden() {
+        let img = gray(4, 4, &[128; 16]);
+        assert_eq!(
+            dither(&img, 2, Dither::FloydSteinberg),
+            [1, 0, 1, 0, 0, 1, 0, 1, 1, 0, 1, 0, 0, 1, 0, 1]
+        );
+
+        let img = gray(8, 2, &[0, 36, 73, 109, 146, 182, 219, 255].repeat(2));
+        assert_eq!(
+            dither(&img, 4, Dither::FloydSteinberg),
+            [0, 0, 1, 1, 2, 2, 3, 3, 0, 1, 1, 1, 2, 2, 2, 3]
+        );
+    }
+
+    #[test]
+    fn atkinson_golden() {
+        let img = gray(4, 4, &[128; 16]);
+        assert_eq!(
+            dither(&img, 2, Dither::Atkinson),
+            [1, 0, 0, 1, 0, 1, 1, 0, 0, 1, 1, 0, 1, 0, 0, 1]
+        );
+
+        let img = gray(8, 2, &[0, 36, 73, 109, 146, 182, 219, 255].repeat(2));
+        assert_eq!(
+            dither(&img, 4, Dither::Atkinson),
+            [0, 0, 1, 1, 2, 2, 3, 3, 0, 0, 1, 1, 2, 2, 3, 3]
+        );
+    }
+
+    #[test]
+    fn fit_keeps_shape() {
+        let wide = gray(40, 20, &[255; 800]);
+        assert_eq!(fit(&wide, 10, 10, Filter::Nearest).dimensions(), (10, 5));
+        assert_eq!(
+            fit(&wide, 100, 100, Filter::Triangle).dimensions(),
+            (100, 50)
+        );
+
+        let tall = gray(3, 7, &[255; 21]);
+        assert_eq!(
+            fit(&tall, 384, 640, Filter::Lanczos3).dimensions(),
+            (274, 640)
+        );
+    }
+
+    #[test]
+    fn nearest_golden() {
+        let img = gray(2, 2, &[0, 255, 255, 0]);
+        let big = fit(&img, 4, 4, Filter::Nearest);
+        assert_eq!(
+            big.into_raw(),
+            [0, 0, 255, 255, 0, 0, 255, 255, 255, 255, 0, 0, 255, 255, 0, 0]
+        );
+    }
+
+    #[test]
+    fn picture_draws_in_palette() {
+        use embedded_graphics::{mock_display::MockDisplay, pixelcolor::BinaryColor};
+
+        let config = ImageConfiguration {
+            path: PathBuf::new(),
+            filter: Filter::Nearest,
+            dither: Dither::Threshold,
+        };
+
+        let picture = Picture::from_gray(&gray(2, 1, &[0, 255]), 2, 1, 2, &config);
+        let palette = [BinaryColor::On, BinaryColor::Off];
+        let mut display = MockDisplay::new();
+        picture
+            .at(Point::new(1, 1), &palette)
+            .draw(&mut display)
+            .unwrap();
+        display.assert_pattern(&["   ", " #."]);
+    }
+}
@@ -0,0 +1,529 @@
+//! The program that renders information to the e-Print Display. (Or a
+//! simulated version thereof.)
+//!
+//! This is a library so that the all-in-one program, which runs the hub and a
+//! panel client in the same process, can use it too. The
+//! `rc_stickynote_displayer` program itself is a thin wrapper around
+//! `RootCli`.
+//!
+//! It's also how to drive something other than our two built-in backends,
+//! like a window in some other toolkit: implement `DisplayBackend` for it,
+//! and run `RootCli::cli_with_backend()` with it. The client loop, the
+//! layouts, and all of the other subcommands work the same for any backend.
+//! Build against this crate with `default-features = false` to leave out the
+//! Waveshare driver.
+
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+use rc_stickynote_protocol::{sealed::SealingConfiguration, signing::SigningConfiguration};
+use rusttype::FontCollection;
+use std::{
+    convert::Infallible,
+    fs::File,
+    io::{Error, Read},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+use structopt::StructOpt;
+
+// The two backends are each other's alternatives, and the Waveshare one
+// needs Linux's SPI and GPIO devices. `cargo xtask build-pi` picks the right
+// features for each target.
+
+#[cfg(all(feature = "waveshare", feature = "simulator"))]
+compile_error!(
+    "the `waveshare` and `simulator` features can't be used together; \
+     build the simulator with `--no-default-features --features simulator`"
+);
+
+#[cfg(all(feature = "waveshare", not(target_os = "linux")))]
+compile_error!(
+    "the `waveshare` backend only works on Linux; build with \
+     `--no-default-features --features simulator` elsewhere"
+);
+
+#[cfg(feature = "waveshare")]
+mod epd7in5;
+#[cfg(feature = "waveshare")]
+pub use epd7in5::EPD7in5Backend as Backend;
+
+#[cfg(feature = "simulator")]
+mod simulator;
+#[cfg(feature = "simulator")]
+pub use simulator::SimulatorBackend as Backend;
+
+mod addrs;
+mod board;
+mod client;
+mod daemon;
+mod drawing;
+mod features;
+mod fonts;
+mod health;
+mod identity;
+pub mod images;
+mod logfile;
+mod meetings;
+pub mod memory;
+mod mqtt;
+mod netstatus;
+mod scd30;
+mod schedule;
+mod simulation;
+mod standalone;
+mod text;
+mod update;
+mod watchdog;
+mod widgets;
+mod wifi_setup;
+pub use client::{run_with_hub, snapshot::FrameRenderer, InProcessHub};
+use drawing::{LineStyle, MonoStyle};
+use text::DrawFontExt;
+
+/// Re-exported so that backends can be written against the same version of
+/// embedded-graphics that we draw with.
+pub use embedded_graphics;
+
+/// Something that frames can be drawn on and shown, like the e-Print
+/// Display. The layouts are drawn for a 384×640 portrait panel.
+pub trait DisplayBackend: Sized {
+    type Color: PixelColor;
+
+    /// The buffer that frames are drawn into. Drawing into it can't fail, so
+    /// the results of `Drawable::draw()` calls can be safely unwrapped.
+    type Buffer: DrawTarget<Color = Self::Color, Error = Infallible>;
+
+    const BLACK: Self::Color;
+    const WHITE: Self::Color;
+
+    /// The dark and light grays of a panel that can show four levels of
+    /// gray. If set, the edges of text are drawn with them.
+    const GRAYS: Option<(Self::Color, Self::Color)> = None;
+
+    /// Whether `show_region()` is any quicker than `show_buffer()`.
+    const SUPPORTS_PARTIAL_REFRESH: bool = false;
+
+    /// Set up the display. This is called in the thread that draws the
+    /// frames, so backends needn't be `Send`.
+    fn open() -> Result<Self, Error>;
+
+    fn get_buffer_mut(&mut self) -> &mut Self::Buffer;
+    fn clear_buffer(&mut self, color: Self::Color) -> Result<(), Error>;
+    fn show_buffer(&mut self) -> Result<(), Error>;
+
+    /// Refresh just the given part of the panel from the buffer. Panels that
+    /// can't do that show the whole buffer instead.
+    fn show_region(&mut self, _region: Rectangle) -> Result<(), Error> {
+        self.show_buffer()
+    }
+
+    fn clear_display(&mut self) -> Result<(), Error>;
+    fn sleep_device(&mut self) -> Result<(), Error>;
+    fn wake_up_device(&mut self) -> Result<(), Error>;
+}
+
+// black-screen subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct BlackScreenCommand {}
+
+impl BlackScreenCommand {
+    fn cli<B: DisplayBackend>(self) -> Result<(), Error> {
+        let mut backend = B::open()?;
+        backend.clear_buffer(B::BLACK)?;
+        backend.show_buffer()?;
+        backend.sleep_device()?;
+        Ok(())
+    }
+}
+
+// clear-and-sleep subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct ClearAndSleepCommand {}
+
+impl ClearAndSleepCommand {
+    fn cli<B: DisplayBackend>(self) -> Result<(), Error> {
+        let mut backend = B::open()?;
+        backend.clear_display()?;
+        backend.sleep_device()?;
+        Ok(())
+    }
+}
+
+// client subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct ClientCommand {
+    #[structopt(
+        long = "daemonize",
+        short = "d",
+        help = "If present, detach from the terminal and run as a background daemon"
+    )]
+    daemonize: bool,
+}
+
+impl ClientCommand {
+    fn cli<B: DisplayBackend>(self) -> Result<(), Error> {
+        client::main_cli::<B>(self)
+    }
+}
+
+// demo-font subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct DemoFontCommand {
+    #[structopt(help = "The path to a TTF or OTF font file.")]
+    font_path: PathBuf,
+}
+
+impl DemoFontCommand {
+    fn cli<B: DisplayBackend>(self) -> Result<(), Error> {
+        let mut file = File::open(&self.font_path)?;
+        let mut font_data = Vec::new();
+        file.read_to_end(&mut font_data)?;
+
+        let collection = FontCollection::from_bytes(font_data)?;
+        let font = collection.into_font()?; // only succeeds if collection consists of one font
+
+        let mut backend = B::open()?;
+
+        {
+            let buffer = backend.get_buffer_mut();
+
+            let lines = [
+                ("The quick brown fox jumps over the lazy dog.", 10.0, 10),
+                ("The quick brown fox jumps over the lazy dog.", 14.0, 30),
+                ("The quick brown fox", 20.0, 58),
+                ("jumps over the lazy dog.", 20.0, 80),
+                ("The quick brown fox", 32.0, 110),
+                ("jumps over the lazy dog.", 32.0, 138),
+                ("The quick brown", 48.0, 184),
+                ("fox jumps over", 48.0, 230),
+                ("the lazy dog.", 48.0, 276),
+            ];
+
+            for (text, height, y) in &lines {
+                let layout = font.layout_text(text, *height);
+                let mut positioned = layout.draw_at(10, *y, B::BLACK, B::WHITE);
+
+                if let Some((dark, light)) = B::GRAYS {
+                    positioned = positioned.with_shades(dark, light);
+                }
+
+                positioned.draw(buffer).unwrap();
+            }
+        }
+
+        backend.show_buffer()?;
+        backend.sleep_device()?;
+        Ok(())
+    }
+}
+
+// play subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct PlayCommand {
+    #[structopt(help = "The path to the scenario file")]
+    scenario_path: PathBuf,
+}
+
+impl PlayCommand {
+    fn cli<B: DisplayBackend>(self) -> Result<(), Error> {
+        client::play_cli::<B>(self)
+    }
+}
+
+// ring-doorbell subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct RingDoorbellCommand {}
+
+impl RingDoorbellCommand {
+    fn cli(self) -> Result<(), Error> {
+        client::ring_doorbell_cli(self)
+    }
+}
+
+// self-test subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct SelfTestCommand {}
+
+impl SelfTestCommand {
+    fn cli<B: DisplayBackend>(self) -> Result<(), Error> {
+        client::self_test_cli::<B>(self)
+    }
+}
+
+// set-status subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct SetStatusCommand {
+    status: String,
+}
+
+impl SetStatusCommand {
+    fn cli(self) -> Result<(), Error> {
+        client::set_status_cli(self)
+    }
+}
+
+// gen-sealing-keys subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct GenSealingKeysCommand {}
+
+impl GenSealingKeysCommand {
+    fn cli(self) -> Result<(), Error> {
+        let (secret, public) = SealingConfiguration::generate_keys();
+        println!("# For the panel's configuration:");
+        println!("[sealing]");
+        println!("secret_key = \"{}\"", secret);
+        println!();
+        println!("# For updaters' configurations:");
+        println!("[sealing]");
+        println!("public_key = \"{}\"", public);
+        Ok(())
+    }
+}
+
+// gen-signing-key subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct GenSigningKeyCommand {
+    #[structopt(help = "The name of the key, as listed in the hub's configuration")]
+    key_name: String,
+}
+
+impl GenSigningKeyCommand {
+    fn cli(self) -> Result<(), Error> {
+        let (secret, public) = SigningConfiguration::generate_key();
+        println!("# For this updater's configuration:");
+        println!("[signing]");
+        println!("key_name = \"{}\"", self.key_name);
+        println!("secret_key = \"{}\"", secret);
+        println!();
+        println!("# For the hub's configuration:");
+        println!("[updater_keys]");
+        println!("{} = \"{}\"", self.key_name, public);
+        Ok(())
+    }
+}
+
+// self-update subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct SelfUpdateCommand {
+    #[structopt(
+        long = "check",
+        help = "Only report whether an update is available; don't install it"
+    )]
+    check: bool,
+}
+
+impl SelfUpdateCommand {
+    fn cli(self) -> Result<(), Error> {
+        client::self_update_cli(self)
+    }
+}
+
+// show-ips subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct ShowIpsCommand {}
+
+impl ShowIpsCommand {
+    fn cli<B: DisplayBackend>(self) -> Result<(), Error> {
+        // This is meant to help out when things aren't working, so don't let
+        // a broken configuration file stop it.
+        let addresses = client::address_configuration().unwrap_or_default();
+        let mut backend = B::open()?;
+
+        {
+            let buffer = backend.get_buffer_mut();
+            let mut got_any = false;
+
+            // If this program is set up to run on boot, the WiFi might not be
+            // fully set up by the time we get here. So, retry several times
+            // if we don't find any interesting IP addresses.
+
+            let style = MonoStyle::new(B::BLACK, B::WHITE);
+            let mut y = 50;
+
+            if let Some(h) = netstatus::hostname() {
+                style
+                    .draw_line(&format!("Hostname: {}", h), Point::new(50, y), buffer)
+                    .unwrap();
+
+                y += 20;
+            }
+
+            style
+                .draw_line("IP addresses:", Point::new(50, y), buffer)
+                .unwrap();
+
+            y += 20;
+
+            for _ in 0..10 {
+                // Note that we don't need to clear the buffer here, since the only
+                // time we loop is when no addresses have been drawn.
+
+                for (label, ip) in addresses.labeled_addresses()? {
+                    let text = format!("{}   {}", label, ip);
+
+                    style.draw_line(&text, Point::new(50, y), buffer).unwrap();
+
+                    y += 10;
+                    got_any = true;
+                }
+
+                if got_any {
+                    break;
+                }
+
+                thread::sleep(Duration::from_millis(10_000));
+            }
+
+            if !got_any {
+                return Err(Error::new(
+                    std::io::ErrorKind::Other,
+                    "never got any useful IP addresses",
+                ));
+            }
+
+            let wifi_text = match netstatus::wifi_status() {
+                Some(w) => format!("WiFi: {} on {}", w.summary(), w.interface),
+                None => "WiFi: not connected".to_owned(),
+            };
+
+            style
+                .draw_line(&wifi_text, Point::new(50, y + 10), buffer)
+                .unwrap();
+        }
+
+        backend.show_buffer()?;
+        backend.sleep_device()?;
+        Ok(())
+    }
+}
+
+// watch-meetings subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct WatchMeetingsCommand {}
+
+impl WatchMeetingsCommand {
+    fn cli(self) -> Result<(), Error> {
+        client::watch_meetings_cli(self)
+    }
+}
+
+// wifi-setup subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct WifiSetupCommand {
+    #[structopt(
+        long = "force",
+        short = "f",
+        help = "Start the setup portal even if there is already a network connection"
+    )]
+    force: bool,
+}
+
+impl WifiSetupCommand {
+    fn cli<B: DisplayBackend>(self) -> Result<(), Error> {
+        wifi_setup::wifi_setup_cli::<B>(self)
+    }
+}
+
+// CLI root interface
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "displayer", about = "e-Ink Displayer tools")]
+pub enum RootCli {
+    #[structopt(name = "black-screen")]
+    /// Set the display to all black
+    BlackScreen(BlackScreenCommand),
+
+    #[structopt(name = "clear-and-sleep")]
+    /// Clear the display and sleep the device
+    ClearAndSleep(ClearAndSleepCommand),
+
+    #[structopt(name = "client")]
+    /// Launch a client that connects to a hub and drives the display.
+    Client(ClientCommand),
+
+    #[structopt(name = "demo-font")]
+    /// Render a TrueType font at various sizes.
+    DemoFont(DemoFontCommand),
+
+    #[structopt(name = "gen-sealing-keys")]
+    /// Generate keys for sealing statuses so that the hub can't read them
+    GenSealingKeys(GenSealingKeysCommand),
+
+    #[structopt(name = "gen-signing-key")]
+    /// Generate a key for signing status updates
+    GenSigningKey(GenSigningKeyCommand),
+
+    #[structopt(name = "play")]
+    /// Show a scripted sequence of display states, for demos and testing
+    Play(PlayCommand),
+
+    #[structopt(name = "ring-doorbell")]
+    /// Tell the hub that someone is at the door
+    RingDoorbell(RingDoorbellCommand),
+
+    #[structopt(name = "self-test")]
+    /// Check that the display, buttons, fonts, and hub connection all work
+    SelfTest(SelfTestCommand),
+
+    #[structopt(name = "self-update")]
+    /// Install the latest release of this program
+    SelfUpdate(SelfUpdateCommand),
+
+    #[structopt(name = "set-status")]
+    /// Set the "scientist is:" satus on the display
+    SetStatus(SetStatusCommand),
+
+    #[structopt(name = "show-ips")]
+    /// Show IP addresses on the display
+    ShowIps(ShowIpsCommand),
+
+    #[structopt(name = "watch-meetings")]
+    /// Set the status automatically during video calls
+    WatchMeetings(WatchMeetingsCommand),
+
+    #[structopt(name = "wifi-setup")]
+    /// If there's no network, collect WiFi credentials through a hotspot
+    WifiSetup(WifiSetupCommand),
+}
+
+impl RootCli {
+    /// Run the command with the backend that this crate was built for.
+    #[cfg(any(feature = "simulator", feature = "waveshare"))]
+    pub fn cli(self) -> Result<(), Error> {
+        self.cli_with_backend::<Backend>()
+    }
+
+    /// Run the command with the given backend.
+    pub fn cli_with_backend<B: DisplayBackend>(self) -> Result<(), Error> {
+        match self {
+            RootCli::BlackScreen(opts) => opts.cli::<B>(),
+            RootCli::ClearAndSleep(opts) => opts.cli::<B>(),
+            RootCli::Client(opts) => opts.cli::<B>(),
+            RootCli::DemoFont(opts) => opts.cli::<B>(),
+            RootCli::GenSealingKeys(opts) => opts.cli(),
+            RootCli::GenSigningKey(opts) => opts.cli(),
+            RootCli::Play(opts) => opts.cli::<B>(),
+            RootCli::RingDoorbell(opts) => opts.cli(),
+            RootCli::SelfTest(opts) => opts.cli::<B>(),
+            RootCli::SelfUpdate(opts) => opts.cli(),
+            RootCli::SetStatus(opts) => opts.cli(),
+            RootCli::ShowIps(opts) => opts.cli::<B>(),
+            RootCli::WatchMeetings(opts) => opts.cli(),
+            RootCli::WifiSetup(opts) => opts.cli::<B>(),
+        }
+    }
+}
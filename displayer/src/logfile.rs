@@ -0,0 +1,161 @@
+//! The log file written by the daemonized client.
+//!
+//! A panel that can't reach its hub prints a line every few minutes, forever,
+//! and an SD card doesn't have room for forever. So when running as a daemon,
+//! the client's output goes through a pipe to a thread that writes it to the
+//! log, starting a new file when the current one gets too big or too old and
+//! keeping only a few of the old ones around.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Error, ErrorKind, PipeReader, Write},
+    path::PathBuf,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LogConfiguration {
+    /// Where to write the log when running as a daemon.
+    pub path: PathBuf,
+
+    /// Start a new log file once the current one reaches this many
+    /// kilobytes. Zero means never.
+    pub max_kilobytes: u64,
+
+    /// Start a new log file once the current one is this many hours old.
+    /// Zero means never.
+    pub max_age_hours: u64,
+
+    /// How many old log files to keep, as `<path>.1`, `<path>.2`, and so on,
+    /// newest first.
+    pub keep: usize,
+}
+
+impl Default for LogConfiguration {
+    fn default() -> Self {
+        LogConfiguration {
+            path: "rc-stickynote-displayer.log".into(),
+            max_kilobytes: 1024,
+            max_age_hours: 0,
+            keep: 3,
+        }
+    }
+}
+
+/// The log file, which starts over now and then.
+#[derive(Debug)]
+pub struct RotatingLog {
+    config: LogConfiguration,
+    file: File,
+    size: u64,
+    opened: SystemTime,
+}
+
+impl RotatingLog {
+    /// Open the log, adding to what's already there.
+    pub fn open(config: &LogConfiguration) -> Result<Self, Error> {
+        // Daemonizing changes the working directory, so a relative path has
+        // to be pinned down first.
+        let mut config = config.clone();
+        config.path = std::env::current_dir()?.join(&config.path);
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+        let meta = file.metadata()?;
+
+        Ok(RotatingLog {
+            config,
+            file,
+            size: meta.len(),
+            opened: meta.created().unwrap_or_else(|_| SystemTime::now()),
+        })
+    }
+
+    fn is_due(&self) -> bool {
+        let too_big =
+            self.config.max_kilobytes > 0 && self.size >= self.config.max_kilobytes * 1024;
+        let max_age = Duration::from_secs(self.config.max_age_hours * 3600);
+        let too_old = self.config.max_age_hours > 0
+            && self.opened.elapsed().map(|a| a >= max_age).unwrap_or(false);
+        too_big || too_old
+    }
+
+    fn old_path(&self, n: usize) -> PathBuf {
+        let mut path = self.config.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    /// Move the current file out of the way, shuffling along the old ones and
+    /// deleting the oldest, and start a new one.
+    fn rotate(&mut self) -> Result<(), Error> {
+        let remove = |path: PathBuf| match fs::remove_file(path) {
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            r => r,
+        };
+
+        if self.config.keep == 0 {
+            remove(self.config.path.clone())?;
+        } else {
+            remove(self.old_path(self.config.keep))?;
+
+            for n in (1..self.config.keep).rev() {
+                let from = self.old_path(n);
+
+                if from.exists() {
+                    fs::rename(from, self.old_path(n + 1))?;
+                }
+            }
+
+            fs::rename(&self.config.path, self.old_path(1))?;
+        }
+
+        self.file = File::create(&self.config.path)?;
+        self.size = 0;
+        self.opened = SystemTime::now();
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> Result<(), Error> {
+        // If we can't start a new file, it's better to keep writing to the old
+        // one than to lose the line.
+        if self.is_due() {
+            let _ = self.rotate();
+        }
+
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// Copy everything that comes through the pipe into the log, in a thread of
+/// its own. This has to be called after daemonizing, since threads don't
+/// survive the fork.
+pub fn spawn(mut log: RotatingLog, pipe: PipeReader) {
+    thread::spawn(move || {
+        let mut pipe = BufReader::new(pipe);
+        let mut line = Vec::new();
+
+        loop {
+            line.clear();
+
+            match pipe.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+
+            // There's nowhere left to complain to, and the rest of the client
+            // shouldn't stop because the SD card is full, so problems just
+            // lose the line.
+            let _ = log.write_line(&line);
+        }
+    });
+}
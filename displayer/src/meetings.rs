@@ -0,0 +1,104 @@
+//! Noticing when we're in a video call.
+//!
+//! The `watch-meetings` command is meant to run on a desktop machine rather
+//! than the Pi. It looks for the processes that video-call programs only run
+//! during a call, like Zoom's `CptHost`, and, on Linux, for anything using the
+//! camera, which catches calls in a web browser. While a call is going on,
+//! the status says so.
+
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, process::Command};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MeetingsConfiguration {
+    /// Names of processes that mean that a call is going on.
+    pub processes: Vec<String>,
+
+    /// Whether the camera being in use means that a call is going on. Only
+    /// checked on Linux. Turn this off if something else keeps the camera
+    /// open.
+    pub camera: bool,
+
+    /// The status to show during a call.
+    pub status: String,
+
+    /// How often to check for a call.
+    pub poll_seconds: u64,
+}
+
+impl Default for MeetingsConfiguration {
+    fn default() -> Self {
+        MeetingsConfiguration {
+            processes: vec!["CptHost".to_owned()],
+            camera: true,
+            status: "In a video call".to_owned(),
+            poll_seconds: 10,
+        }
+    }
+}
+
+impl MeetingsConfiguration {
+    /// Whether a call seems to be going on right now.
+    pub fn in_call(&self) -> bool {
+        if self.camera && camera_in_use() {
+            return true;
+        }
+
+        if self.processes.is_empty() {
+            return false;
+        }
+
+        running_processes()
+            .iter()
+            .any(|p| self.processes.iter().any(|name| name == p))
+    }
+}
+
+/// The names of the running processes. `ps` does the work, since it's
+/// available on Linux and macOS alike.
+fn running_processes() -> Vec<String> {
+    let output = match Command::new("ps").args(["-A", "-o", "comm="]).output() {
+        Ok(o) => o,
+        Err(e) => {
+            println!("cannot list processes: {}", e);
+            return Vec::new();
+        }
+    };
+
+    // On macOS, `comm` is the full path of the executable.
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|l| Path::new(l.trim()).file_name())
+        .map(|n| n.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Whether any process has a video device open, going by the file
+/// descriptors listed in `/proc`. Elsewhere than on Linux, this is always
+/// false.
+fn camera_in_use() -> bool {
+    let procs = match fs::read_dir("/proc") {
+        Ok(d) => d,
+        Err(_) => return false,
+    };
+
+    // We can only see the file descriptors of our own processes, but that's
+    // where the video calls will be.
+    for p in procs.flatten() {
+        let fds = match fs::read_dir(p.path().join("fd")) {
+            Ok(d) => d,
+            Err(_) => continue,
+        };
+
+        for fd in fds.flatten() {
+            if let Ok(target) = fs::read_link(fd.path()) {
+                if target.to_string_lossy().starts_with("/dev/video") {
+                    return true;
+                }
+            }
+        }
+    }
+
+    false
+}
@@ -0,0 +1,147 @@
+//! A backend that keeps frames in memory rather than showing them anywhere,
+//! for programs that want pictures of the sign.
+//!
+//! Since the pictures aren't limited by any panel, frames are drawn in four
+//! levels of gray, so that text comes out antialiased.
+
+use embedded_graphics::{
+    pixelcolor::{Gray2, GrayColor},
+    prelude::*,
+    Pixel,
+};
+use std::{convert::Infallible, io::Error};
+
+use super::DisplayBackend;
+
+/// The size of a frame, which is that of the Waveshare panel, in portrait
+/// orientation.
+pub const WIDTH: u32 = 384;
+pub const HEIGHT: u32 = 640;
+
+/// A frame's worth of 2-bit grayscale pixels.
+pub struct FrameBuffer {
+    pixels: Vec<Gray2>,
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        FrameBuffer {
+            pixels: vec![Gray2::WHITE; (WIDTH * HEIGHT) as usize],
+        }
+    }
+}
+
+impl FrameBuffer {
+    /// The brightness of the pixel at the given position, from 0 for black
+    /// to 3 for white.
+    pub fn luma(&self, x: u32, y: u32) -> u8 {
+        self.pixels[(y * WIDTH + x) as usize].luma()
+    }
+
+    /// Scale the frame to the given size, as 8-bit grayscale pixels in rows
+    /// from the top, with 0 being black. Each output pixel averages the
+    /// pixels of the frame that it covers, so that shrunken text stays
+    /// legible.
+    pub fn to_gray(&self, width: u32, height: u32) -> Vec<u8> {
+        let mut gray = Vec::with_capacity((width * height) as usize);
+
+        for oy in 0..height {
+            let y0 = oy * HEIGHT / height;
+            let y1 = ((oy + 1) * HEIGHT / height).max(y0 + 1);
+
+            for ox in 0..width {
+                let x0 = ox * WIDTH / width;
+                let x1 = ((ox + 1) * WIDTH / width).max(x0 + 1);
+                let mut total = 0;
+
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        total += self.luma(x, y) as u32;
+                    }
+                }
+
+                gray.push((85 * total / ((y1 - y0) * (x1 - x0))) as u8);
+            }
+        }
+
+        gray
+    }
+}
+
+impl OriginDimensions for FrameBuffer {
+    fn size(&self) -> Size {
+        Size::new(WIDTH, HEIGHT)
+    }
+}
+
+impl DrawTarget for FrameBuffer {
+    type Color = Gray2;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Infallible>
+    where
+        I: IntoIterator<Item = Pixel<Gray2>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 && point.x < WIDTH as i32 && point.y < HEIGHT as i32 {
+                self.pixels[(point.y as u32 * WIDTH + point.x as u32) as usize] = color;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Draws into a `FrameBuffer`. Showing the frame does nothing.
+#[derive(Default)]
+pub struct MemoryBackend {
+    buffer: FrameBuffer,
+}
+
+impl MemoryBackend {
+    /// The most recently drawn frame.
+    pub fn frame(&self) -> &FrameBuffer {
+        &self.buffer
+    }
+}
+
+impl DisplayBackend for MemoryBackend {
+    type Color = Gray2;
+    type Buffer = FrameBuffer;
+
+    const BLACK: Gray2 = Gray2::BLACK;
+    const WHITE: Gray2 = Gray2::WHITE;
+    const GRAYS: Option<(Gray2, Gray2)> = Some((Gray2::new(1), Gray2::new(2)));
+
+    fn open() -> Result<Self, Error> {
+        Ok(MemoryBackend::default())
+    }
+
+    fn get_buffer_mut(&mut self) -> &mut Self::Buffer {
+        &mut self.buffer
+    }
+
+    fn clear_buffer(&mut self, color: Self::Color) -> Result<(), Error> {
+        for p in self.buffer.pixels.iter_mut() {
+            *p = color;
+        }
+
+        Ok(())
+    }
+
+    fn show_buffer(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn clear_display(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn sleep_device(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn wake_up_device(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+//! Talking to the hub through an MQTT broker, for installations where the
+//! panel can't connect to the hub directly.
+//!
+//! The MQTT client library is synchronous, so it runs in its own thread.
+
+use rc_stickynote_protocol::{mqtt::MqttConfiguration, ClientHelloMessage, DisplayMessage};
+use rumqttc::{Client, Event, Outgoing, Packet, QoS};
+use std::{io::Error, thread, time::Duration};
+use tokio::sync::mpsc;
+
+fn mqtt_error<E: std::fmt::Display>(e: E) -> Error {
+    Error::new(std::io::ErrorKind::Other, format!("MQTT error: {}", e))
+}
+
+/// Subscribe to the display state that the hub publishes. The returned
+/// channel yields each new state until the MQTT thread dies.
+pub fn display_messages(config: &MqttConfiguration) -> mpsc::UnboundedReceiver<DisplayMessage> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let (mut client, mut connection) = Client::new(config.options("display"), 10);
+    let topic = config.display_topic();
+
+    thread::spawn(move || {
+        for event in connection.iter() {
+            match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    if let Err(e) = client.try_subscribe(topic.as_str(), QoS::AtLeastOnce) {
+                        println!("cannot subscribe to {}: {}", topic, e);
+                    }
+                }
+
+                Ok(Event::Incoming(Packet::Publish(p))) => {
+                    match serde_json::from_slice(&p.payload) {
+                        Ok(msg) => {
+                            if sender.send(msg).is_err() {
+                                return;
+                            }
+                        }
+
+                        Err(e) => println!("ignoring unparseable display message: {}", e),
+                    }
+                }
+
+                Ok(_) => {}
+
+                Err(e) => {
+                    // The connection is retried the next time around the loop.
+                    println!("MQTT connection error: {}", e);
+                    thread::sleep(Duration::from_secs(30));
+                }
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Publish a "hello" message for the hub, waiting until the broker has
+/// accepted it.
+pub fn publish_hello(config: &MqttConfiguration, msg: &ClientHelloMessage) -> Result<(), Error> {
+    let payload = serde_json::to_vec(msg)?;
+    let (mut client, mut connection) = Client::new(config.options("client"), 10);
+    client
+        .publish(config.hello_topic(), QoS::AtLeastOnce, false, payload)
+        .map_err(mqtt_error)?;
+
+    for event in connection.iter() {
+        match event.map_err(mqtt_error)? {
+            Event::Incoming(Packet::PubAck(_)) => {
+                client.disconnect().map_err(mqtt_error)?;
+            }
+
+            Event::Outgoing(Outgoing::Disconnect) => return Ok(()),
+
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,90 @@
+//! Other facts about the network connection that help when debugging a
+//! headless Pi: what it's called, and which WiFi network it's on.
+
+use std::{fs, process::Command};
+
+/// The machine's hostname, if we can find it.
+pub fn hostname() -> Option<String> {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|s| s.trim().to_owned())
+        .filter(|s| !s.is_empty())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WifiStatus {
+    /// The wireless interface.
+    pub interface: String,
+
+    /// The network it's connected to.
+    pub ssid: String,
+
+    /// The signal strength, in dBm.
+    pub signal_dbm: Option<i32>,
+}
+
+impl WifiStatus {
+    /// Describe the connection in a few words.
+    pub fn summary(&self) -> String {
+        match self.signal_dbm {
+            Some(s) => format!("{} ({} dBm)", self.ssid, s),
+            None => self.ssid.clone(),
+        }
+    }
+}
+
+/// Find out which WiFi network we're connected to, if any, by asking `iw`.
+/// If there are several wireless interfaces, the first connected one wins.
+pub fn wifi_status() -> Option<WifiStatus> {
+    let mut names: Vec<String> = fs::read_dir("/sys/class/net")
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().join("wireless").exists())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+
+    names.into_iter().find_map(|iface| {
+        let output = Command::new("iw")
+            .args(["dev", &iface, "link"])
+            .output()
+            .ok()?;
+
+        if !output.status.success() {
+            return None;
+        }
+
+        parse_iw_link(&iface, &String::from_utf8_lossy(&output.stdout))
+    })
+}
+
+/// Parse the output of `iw dev <iface> link`, which looks like:
+///
+/// ```text
+/// Connected to 00:11:22:33:44:55 (on wlan0)
+///         SSID: MyNetwork
+///         freq: 2412
+///         signal: -52 dBm
+/// ```
+///
+/// or just "Not connected."
+fn parse_iw_link(iface: &str, text: &str) -> Option<WifiStatus> {
+    let mut ssid = None;
+    let mut signal_dbm = None;
+
+    for line in text.lines() {
+        let line = line.trim();
+
+        if let Some(s) = line.strip_prefix("SSID:") {
+            ssid = Some(s.trim().to_owned());
+        } else if let Some(s) = line.strip_prefix("signal:") {
+            signal_dbm = s.split_whitespace().next().and_then(|v| v.parse().ok());
+        }
+    }
+
+    Some(WifiStatus {
+        interface: iface.to_owned(),
+        ssid: ssid?,
+        signal_dbm,
+    })
+}
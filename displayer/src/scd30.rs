@@ -0,0 +1,122 @@
+//! A minimal driver for the Sensirion SCD30 CO2, temperature, and humidity
+//! sensor, which talks over I2C.
+//!
+//! We just start continuous measurement with the default settings and read
+//! out the results. See the "Interface Description Sensirion SCD30 Sensor
+//! Module" document for the gory details.
+
+use embedded_hal::blocking::i2c::{Read, Write};
+use std::io::{Error, ErrorKind};
+
+/// The fixed I2C address of the SCD30.
+const ADDRESS: u8 = 0x61;
+
+const CMD_START_CONTINUOUS: u16 = 0x0010;
+const CMD_GET_DATA_READY: u16 = 0x0202;
+const CMD_READ_MEASUREMENT: u16 = 0x0300;
+
+/// One set of measurements.
+#[derive(Clone, Copy, Debug)]
+pub struct Measurement {
+    pub co2_ppm: f32,
+    pub temperature_c: f32,
+    pub humidity_percent: f32,
+}
+
+pub struct Scd30<I2C> {
+    i2c: I2C,
+}
+
+/// The CRC-8 checksum that the SCD30 attaches to each 16-bit word.
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0xFFu8;
+
+    for byte in data {
+        crc ^= byte;
+
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+fn i2c_error<E: std::fmt::Debug>(e: E) -> Error {
+    Error::new(ErrorKind::Other, format!("I2C error: {:?}", e))
+}
+
+impl<I2C, E> Scd30<I2C>
+where
+    I2C: Read<Error = E> + Write<Error = E>,
+    E: std::fmt::Debug,
+{
+    /// Set up the sensor and start continuous measurement.
+    pub fn new(i2c: I2C) -> Result<Self, Error> {
+        let mut sensor = Scd30 { i2c };
+
+        // The argument is the ambient pressure compensation; zero means none.
+        sensor.command_with_arg(CMD_START_CONTINUOUS, 0)?;
+        Ok(sensor)
+    }
+
+    fn command(&mut self, cmd: u16) -> Result<(), Error> {
+        self.i2c
+            .write(ADDRESS, &cmd.to_be_bytes())
+            .map_err(i2c_error)
+    }
+
+    fn command_with_arg(&mut self, cmd: u16, arg: u16) -> Result<(), Error> {
+        let c = cmd.to_be_bytes();
+        let a = arg.to_be_bytes();
+        let buf = [c[0], c[1], a[0], a[1], crc8(&a)];
+        self.i2c.write(ADDRESS, &buf).map_err(i2c_error)
+    }
+
+    /// Read 16-bit words in response to a command, checking their CRCs.
+    fn read_words(&mut self, cmd: u16, words: &mut [u16]) -> Result<(), Error> {
+        self.command(cmd)?;
+
+        // The datasheet asks for a short pause between the command and the
+        // read.
+        std::thread::sleep(std::time::Duration::from_millis(5));
+
+        let mut buf = vec![0u8; words.len() * 3];
+        self.i2c.read(ADDRESS, &mut buf).map_err(i2c_error)?;
+
+        for (i, chunk) in buf.chunks(3).enumerate() {
+            if crc8(&chunk[..2]) != chunk[2] {
+                return Err(Error::new(ErrorKind::InvalidData, "SCD30 CRC mismatch"));
+            }
+
+            words[i] = u16::from_be_bytes([chunk[0], chunk[1]]);
+        }
+
+        Ok(())
+    }
+
+    /// Get the latest measurement, or None if a new one isn't ready yet.
+    pub fn read(&mut self) -> Result<Option<Measurement>, Error> {
+        let mut ready = [0u16];
+        self.read_words(CMD_GET_DATA_READY, &mut ready)?;
+
+        if ready[0] != 1 {
+            return Ok(None);
+        }
+
+        let mut w = [0u16; 6];
+        self.read_words(CMD_READ_MEASUREMENT, &mut w)?;
+
+        let float = |hi: u16, lo: u16| f32::from_bits(((hi as u32) << 16) | lo as u32);
+
+        Ok(Some(Measurement {
+            co2_ppm: float(w[0], w[1]),
+            temperature_c: float(w[2], w[3]),
+            humidity_percent: float(w[4], w[5]),
+        }))
+    }
+}
@@ -0,0 +1,184 @@
+//! Times of day when the panel should behave differently.
+//!
+//! Overnight, nobody's looking at the panel, so there's no point redrawing
+//! it, and on a battery-powered install every refresh counts. The schedule
+//! here says when the panel goes to sleep, only waking up for things that
+//! can't wait, and when it can get away with redrawing less often.
+
+use chrono::{DateTime, Local, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, time::Duration};
+
+/// A time of day, written like "22:30" in the configuration file.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ClockTime(pub NaiveTime);
+
+impl TryFrom<String> for ClockTime {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        NaiveTime::parse_from_str(&text, "%H:%M")
+            .map(ClockTime)
+            .map_err(|_| format!("expected a time like \"22:30\", got \"{}\"", text))
+    }
+}
+
+impl From<ClockTime> for String {
+    fn from(t: ClockTime) -> String {
+        t.0.format("%H:%M").to_string()
+    }
+}
+
+/// A day of the week, written like "mon" or "Monday" in the configuration
+/// file.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Day(pub Weekday);
+
+impl TryFrom<String> for Day {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+            .map(Day)
+            .map_err(|_| format!("expected a day like \"mon\", got \"{}\"", text))
+    }
+}
+
+impl From<Day> for String {
+    fn from(d: Day) -> String {
+        d.0.to_string().to_lowercase()
+    }
+}
+
+/// A daily span of time. If `end` is earlier than `start`, the window runs
+/// overnight.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct TimeWindow {
+    pub start: ClockTime,
+    pub end: ClockTime,
+}
+
+impl TimeWindow {
+    /// Whether the given time of day falls inside the window.
+    pub fn contains(&self, t: NaiveTime) -> bool {
+        let (start, end) = (self.start.0, self.end.0);
+
+        if start <= end {
+            t >= start && t < end
+        } else {
+            t >= start || t < end
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SleepConfiguration {
+    /// When the panel goes to sleep and wakes up again.
+    #[serde(flatten)]
+    pub window: TimeWindow,
+
+    /// If true, status updates wake the panel up too. Otherwise, only the
+    /// doorbell and commands from the hub do.
+    #[serde(default)]
+    pub wake_for_status: bool,
+}
+
+/// How often the client redraws when nothing in particular is happening.
+/// During work hours, or soon after the status changes, it redraws often
+/// enough to keep the clock useful; otherwise it backs off, to spare the
+/// panel and the power supply.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PollingConfiguration {
+    /// When people are likely to be looking at the panel. If unset, it's
+    /// always treated as work hours.
+    pub work_hours: Option<TimeWindow>,
+
+    /// How often to redraw during work hours. Panels that can refresh just
+    /// the part that changed update the clock every minute regardless.
+    pub active_redraw_minutes: u64,
+
+    /// How often to redraw otherwise.
+    pub idle_redraw_minutes: u64,
+
+    /// After the status changes, redraw as in work hours for this long.
+    pub activity_minutes: u64,
+}
+
+impl Default for PollingConfiguration {
+    fn default() -> Self {
+        PollingConfiguration {
+            work_hours: None,
+            active_redraw_minutes: 10,
+            idle_redraw_minutes: 30,
+            activity_minutes: 30,
+        }
+    }
+}
+
+/// How often the main loop wakes up when it's active or idle. Idle wakeups
+/// are no less frequent than hub reconnection attempts.
+const ACTIVE_WAKEUP: Duration = Duration::from_secs(60);
+const IDLE_WAKEUP: Duration = Duration::from_secs(180);
+
+impl PollingConfiguration {
+    /// Whether the client should be keeping the panel up to date, given the
+    /// time of day and how long it's been since the status changed.
+    pub fn is_active(&self, t: NaiveTime, since_activity: Duration) -> bool {
+        if since_activity < Duration::from_secs(self.activity_minutes * 60) {
+            return true;
+        }
+
+        match self.work_hours {
+            Some(ref w) => w.contains(t),
+            None => true,
+        }
+    }
+
+    /// How often to wake up the main loop, and how often to redraw. If the
+    /// panel can do partial refreshes, an active panel redraws every minute,
+    /// since only the clock will need refreshing.
+    pub fn intervals(&self, active: bool, partial_refresh: bool) -> (Duration, Duration) {
+        let (wakeup, minutes) = if active {
+            (ACTIVE_WAKEUP, self.active_redraw_minutes)
+        } else {
+            (IDLE_WAKEUP, self.idle_redraw_minutes)
+        };
+
+        let minutes = if active && partial_refresh {
+            1
+        } else {
+            minutes
+        };
+        let redraw = Duration::from_secs(minutes.max(1) * 60);
+        (wakeup.min(redraw), redraw)
+    }
+}
+
+/// How long after a tick to actually wake up, so that the time read by the
+/// renderer is safely past it.
+const TICK_SLACK: Duration = Duration::from_millis(100);
+
+/// Nanoseconds since the epoch, as it would be if the epoch were in local
+/// time.
+fn local_nanos(t: DateTime<Local>) -> i64 {
+    let secs = t.timestamp() + t.offset().local_minus_utc() as i64;
+    secs * 1_000_000_000 + t.timestamp_subsec_nanos() as i64
+}
+
+/// Which tick of the given period the time falls in. Ticks are counted in
+/// local time, so that they line up with the minutes on the clock.
+pub fn tick_number(t: DateTime<Local>, period: Duration) -> i64 {
+    local_nanos(t).div_euclid(period.as_nanos() as i64)
+}
+
+/// How long from the given time until the next tick of the given period.
+/// Waking up on ticks, rather than at intervals counted from whenever we
+/// started, means that the clock changes on the minute.
+pub fn until_next_tick(t: DateTime<Local>, period: Duration) -> Duration {
+    let period_nanos = period.as_nanos() as i64;
+    let into = local_nanos(t).rem_euclid(period_nanos);
+    Duration::from_nanos((period_nanos - into) as u64) + TICK_SLACK
+}
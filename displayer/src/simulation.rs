@@ -0,0 +1,71 @@
+//! Settings for the SDL2 simulator's window.
+//!
+//! These live outside of the simulator itself so that the client
+//! configuration is the same whichever backend is built in, just as the
+//! `[board]` section is there for builds without the Waveshare driver.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SimulatorConfiguration {
+    /// How many screen pixels wide and tall to draw each of the panel's
+    /// pixels. If 0, the largest scale at which the window fits on the
+    /// screen is used. The `+` and `-` keys zoom the window in and out, and
+    /// `0` puts it back to this scale.
+    pub scale: usize,
+
+    /// Whether to draw with every pixel of a HiDPI screen, where windows are
+    /// sized in units of more than one pixel. Otherwise the window is
+    /// scaled up by the system, which blurs it.
+    pub high_dpi: bool,
+
+    /// How the panel looks.
+    pub theme: SimulatorTheme,
+
+    /// Whether to leave a faint copy of the frame before each one, as the
+    /// panel does when it isn't cleared in between.
+    pub ghosting: bool,
+
+    /// Whether to highlight the pixels that changed in each frame, and the
+    /// region that a partial refresh covered. The `d` key turns this on and
+    /// off.
+    pub show_changes: bool,
+
+    /// If nonzero, take this many milliseconds to show each frame, flashing
+    /// black and white first, as the panel does, rather than showing it at
+    /// once and waiting for Escape to be pressed. The panel takes about ten
+    /// seconds.
+    pub refresh_ms: u64,
+
+    /// How many milliseconds a partial refresh takes, if `refresh_ms` is
+    /// set. Partial refreshes don't flash.
+    pub partial_refresh_ms: u64,
+}
+
+/// The look of the simulated panel.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SimulatorTheme {
+    /// Black on white.
+    #[default]
+    Plain,
+
+    /// Dark gray on off-white, like the e-Print Display, for screenshots
+    /// that look like the real thing.
+    Epd,
+}
+
+impl Default for SimulatorConfiguration {
+    fn default() -> Self {
+        SimulatorConfiguration {
+            scale: 0,
+            high_dpi: true,
+            theme: SimulatorTheme::default(),
+            ghosting: false,
+            show_changes: false,
+            refresh_ms: 0,
+            partial_refresh_ms: 0,
+        }
+    }
+}
@@ -5,48 +5,63 @@
 //! provided with the
 //! [embedded-graphics](https://crates.io/crates/embedded-graphics) crate.
 //!
-//! TODO: the From<u8> behavior of this could be changed to exactly match that
-//! of waveshare-epd, in which only 0 and 1 are valid inputs and (IIRC) 1 is
-//! white.
+//! Like the waveshare-epd displays, we use `BinaryColor`, with `On` meaning
+//! black.
+//!
+//! The window's size and zoom come from the `[simulator]` section of the
+//! client configuration. While the window is open, `+` and `-` zoom it in
+//! and out, and `0` puts it back to the configured size. So does the look of
+//! the window: the EPD theme imitates the panel's gray-on-cream, and can
+//! leave a faint ghost of the last frame, as the panel does. For working on
+//! partial refreshes, the pixels that changed in each frame can be
+//! highlighted, along with the region that was refreshed; `d` turns this on
+//! and off.
 
 // To minimize differences with upstream, we keep in a few features that we
 // don't use, so:
 #![allow(unused)]
 
-use embedded_graphics::{drawable::Pixel, prelude::*, Drawing};
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*, primitives::Rectangle, Pixel};
 use sdl2::{event::Event, keyboard::Keycode, pixels::Color, rect::Rect, render};
-use std::{io::Error, thread, time::Duration};
-
-use super::DisplayBackend;
+use std::{
+    convert::Infallible,
+    io::Error,
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{
+    memory::{HEIGHT, WIDTH},
+    simulation::SimulatorTheme,
+    DisplayBackend,
+};
+
+/// XXX for rc-stickynote: the color that changed pixels are highlighted
+/// with, which stands out against any of the themes.
+const CHANGE_COLOR: Color = Color {
+    r: 230,
+    g: 0,
+    b: 170,
+    a: 0xff,
+};
 
 // Begin stuff that's basically copy/pasted from
 // embedded-graphics/simulator/src/lib.rs
 
-#[derive(Clone, Copy, PartialEq)]
-pub struct SimPixelColor(pub bool);
-
-impl PixelColor for SimPixelColor {}
-
-impl From<u8> for SimPixelColor {
-    fn from(other: u8) -> Self {
-        SimPixelColor(other != 0)
-    }
-}
-
-impl From<u16> for SimPixelColor {
-    fn from(other: u16) -> Self {
-        SimPixelColor(other != 0)
-    }
-}
-
 pub struct Display {
     width: usize,
     height: usize,
     scale: usize,
+    initial_scale: usize,
     pixel_spacing: usize,
     background_color: Color,
     pixel_color: Color,
-    pixels: Box<[SimPixelColor]>,
+    ghost_color: Option<Color>,
+    show_changes: bool,
+    pixels: Box<[BinaryColor]>,
+    shown: Box<[BinaryColor]>,
+    previous: Box<[BinaryColor]>,
+    region: Option<Rectangle>,
     canvas: render::Canvas<sdl2::video::Window>,
     event_pump: sdl2::EventPump,
 }
@@ -54,7 +69,16 @@ pub struct Display {
 impl Display {
     /// XXX modified for rc-stickynote
     pub fn run_once(&mut self) -> bool {
+        let should_exit = self.handle_events();
+        self.draw();
+        should_exit
+    }
+
+    /// XXX split out of `run_once()` for rc-stickynote: returns whether the
+    /// window should close.
+    fn handle_events(&mut self) -> bool {
         let mut should_exit = false;
+        let mut new_scale = None;
 
         // Handle events
         for event in self.event_pump.poll_iter() {
@@ -66,44 +90,228 @@ impl Display {
                 } => {
                     should_exit = true;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Plus),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Equals),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::KpPlus),
+                    ..
+                } => {
+                    new_scale = Some(self.scale + 1);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Minus),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::KpMinus),
+                    ..
+                } => {
+                    new_scale = Some(self.scale.saturating_sub(1).max(1));
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::D),
+                    ..
+                } => {
+                    self.show_changes = !self.show_changes;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Num0),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Kp0),
+                    ..
+                } => {
+                    new_scale = Some(self.initial_scale);
+                }
                 _ => {}
             }
         }
 
+        if let Some(scale) = new_scale {
+            self.set_scale(scale);
+        }
+
+        should_exit
+    }
+
+    /// XXX split out of `run_once()` for rc-stickynote.
+    fn draw(&mut self) {
         self.canvas.set_draw_color(self.background_color);
         self.canvas.clear();
 
+        // XXX for rc-stickynote: with HiDPI, the window's size is in units
+        // of some whole number of the canvas's pixels, which we draw with.
+        let (window_width, _) = self.canvas.window().size();
+        let density = match self.canvas.output_size() {
+            Ok((output_width, _)) => (output_width / window_width.max(1)).max(1) as usize,
+            Err(_) => 1,
+        };
+
         self.canvas.set_draw_color(self.pixel_color);
-        let pitch = self.scale + self.pixel_spacing;
+        let pitch = (self.scale + self.pixel_spacing) * density;
+        let size = (self.scale * density) as u32;
         for (index, value) in self.pixels.iter().enumerate() {
-            if *value == SimPixelColor(true) {
+            if *value == BinaryColor::On {
                 let x = (index % self.width * pitch) as i32;
                 let y = (index / self.width * pitch) as i32;
-                let r = Rect::new(x, y, self.scale as u32, self.scale as u32);
+                let r = Rect::new(x, y, size, size);
                 self.canvas.fill_rect(r).unwrap();
             }
         }
 
+        // XXX for rc-stickynote: what was black in the frame before, but
+        // isn't now, doesn't quite go away.
+        if let Some(ghost_color) = self.ghost_color {
+            self.canvas.set_draw_color(ghost_color);
+
+            for (index, value) in self.previous.iter().enumerate() {
+                if *value == BinaryColor::On && self.pixels[index] == BinaryColor::Off {
+                    let x = (index % self.width * pitch) as i32;
+                    let y = (index / self.width * pitch) as i32;
+                    let r = Rect::new(x, y, size, size);
+                    self.canvas.fill_rect(r).unwrap();
+                }
+            }
+        }
+
+        // XXX for rc-stickynote: what changed in this frame, and the region
+        // that a partial refresh covered.
+        if self.show_changes {
+            self.canvas.set_draw_color(CHANGE_COLOR);
+
+            for (index, value) in self.shown.iter().enumerate() {
+                if *value != self.previous[index] {
+                    let x = (index % self.width * pitch) as i32;
+                    let y = (index / self.width * pitch) as i32;
+                    let r = Rect::new(x, y, size, size);
+                    self.canvas.fill_rect(r).unwrap();
+                }
+            }
+
+            if let Some(region) = self.region {
+                let r = Rect::new(
+                    region.top_left.x * pitch as i32,
+                    region.top_left.y * pitch as i32,
+                    region.size.width * pitch as u32,
+                    region.size.height * pitch as u32,
+                );
+                self.canvas.draw_rect(r).unwrap();
+            }
+        }
+
         self.canvas.present();
-        should_exit
+    }
+
+    /// XXX new method for rc-stickynote: take as long as the panel does to
+    /// show the buffer. A full refresh flashes black and white before the
+    /// frame appears, while the panel shakes its particles loose.
+    pub fn refresh(&mut self, duration: Duration, flash: bool) {
+        let mut phases = Vec::new();
+
+        if flash {
+            for _ in 0..2 {
+                phases.push(Some(self.pixel_color));
+                phases.push(Some(self.background_color));
+            }
+        }
+
+        // The frame appears for the last phase, but like the panel, we're
+        // busy until it's done settling.
+        phases.push(None);
+        let phase_duration = duration / phases.len() as u32;
+
+        for phase in phases {
+            match phase {
+                Some(color) => {
+                    self.canvas.set_draw_color(color);
+                    self.canvas.clear();
+                    self.canvas.present();
+                }
+                None => self.draw(),
+            }
+
+            let until = Instant::now() + phase_duration;
+
+            while Instant::now() < until {
+                // Closing the window would only hide the next frame.
+                self.handle_events();
+                thread::sleep(Duration::from_millis(20).min(phase_duration));
+            }
+        }
+    }
+
+    /// XXX new method for rc-stickynote: keep the window going until it's
+    /// closed.
+    pub fn wait_for_escape(&mut self) {
+        loop {
+            let end = self.run_once();
+
+            if end {
+                break;
+            }
+
+            thread::sleep(Duration::from_millis(200));
+        }
+
+        println!("*** unblocking thread ***");
+    }
+
+    /// XXX new method for rc-stickynote: zoom the window.
+    pub fn set_scale(&mut self, scale: usize) {
+        if scale == self.scale {
+            return;
+        }
+
+        let (width, height) = window_size(self.width, self.height, scale, self.pixel_spacing);
+
+        match self.canvas.window_mut().set_size(width, height) {
+            Ok(_) => {
+                self.scale = scale;
+                println!("*** simulator scale: {} ***", scale);
+            }
+            Err(e) => eprintln!("ERROR: cannot resize the simulator window: {}", e),
+        }
+    }
+
+    /// XXX new method for rc-stickynote: note that the buffer is being
+    /// shown as a new frame, so that the one before can haunt it and what
+    /// changed can be highlighted. A partial refresh gives its region.
+    pub fn new_frame(&mut self, region: Option<Rectangle>) {
+        self.previous.copy_from_slice(&self.shown);
+        self.shown.copy_from_slice(&self.pixels);
+        self.region = region;
     }
 
     /// XXX new method for rc-stickynote:
-    pub fn fill(&mut self, color: SimPixelColor) {
+    pub fn fill(&mut self, color: BinaryColor) {
         for p in self.pixels.iter_mut() {
             *p = color;
         }
     }
 }
 
-impl Drawing<SimPixelColor> for Display {
-    fn draw<T>(&mut self, item_pixels: T)
+impl DrawTarget for Display {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
     where
-        T: IntoIterator<Item = Pixel<SimPixelColor>>,
+        I: IntoIterator<Item = Pixel<BinaryColor>>,
     {
-        for Pixel(coord, color) in item_pixels {
-            let x = coord[0] as usize;
-            let y = coord[1] as usize;
+        for Pixel(coord, color) in pixels {
+            if coord.x < 0 || coord.y < 0 {
+                continue;
+            }
+
+            let x = coord.x as usize;
+            let y = coord.y as usize;
 
             if x >= self.width || y >= self.height {
                 continue;
@@ -111,6 +319,14 @@ impl Drawing<SimPixelColor> for Display {
 
             self.pixels[y * self.width + x] = color;
         }
+
+        Ok(())
+    }
+}
+
+impl OriginDimensions for Display {
+    fn size(&self) -> Size {
+        Size::new(self.width as u32, self.height as u32)
     }
 }
 
@@ -120,15 +336,29 @@ pub enum DisplayTheme {
     LcdBlue,
     OledWhite,
     OledBlue,
+    /// XXX new for rc-stickynote: like the e-Print Display.
+    Epd,
 }
 
 pub struct DisplayBuilder {
     width: usize,
     height: usize,
     scale: usize,
+    scale_to_fit: bool,
+    high_dpi: bool,
     pixel_spacing: usize,
     background_color: Color,
     pixel_color: Color,
+    ghosting: bool,
+    show_changes: bool,
+}
+
+/// XXX for rc-stickynote: the size of the window, in the units that the
+/// system sizes windows in.
+fn window_size(width: usize, height: usize, scale: usize, pixel_spacing: usize) -> (u32, u32) {
+    let window_width = width * scale + (width - 1) * pixel_spacing;
+    let window_height = height * scale + (height - 1) * pixel_spacing;
+    (window_width as u32, window_height as u32)
 }
 
 impl DisplayBuilder {
@@ -137,9 +367,13 @@ impl DisplayBuilder {
             width: 256,
             height: 256,
             scale: 1,
+            scale_to_fit: false,
+            high_dpi: false,
             pixel_spacing: 0,
             background_color: Color::RGB(255, 255, 255),
             pixel_color: Color::RGB(0, 0, 0),
+            ghosting: false,
+            show_changes: false,
         }
     }
 
@@ -160,6 +394,23 @@ impl DisplayBuilder {
         }
 
         self.scale = scale;
+        self.scale_to_fit = false;
+
+        self
+    }
+
+    /// XXX new method for rc-stickynote: use the largest scale at which the
+    /// window fits on the screen.
+    pub fn scale_to_fit(&mut self) -> &mut Self {
+        self.scale_to_fit = true;
+
+        self
+    }
+
+    /// XXX new method for rc-stickynote: draw with all of the pixels of a
+    /// HiDPI screen.
+    pub fn high_dpi(&mut self, high_dpi: bool) -> &mut Self {
+        self.high_dpi = high_dpi;
 
         self
     }
@@ -176,8 +427,31 @@ impl DisplayBuilder {
         self
     }
 
+    /// XXX new method for rc-stickynote: leave a faint copy of the frame
+    /// before each one, as an e-Print Display does.
+    pub fn ghosting(&mut self, ghosting: bool) -> &mut Self {
+        self.ghosting = ghosting;
+
+        self
+    }
+
+    /// XXX new method for rc-stickynote: highlight the pixels that changed
+    /// in each frame.
+    pub fn show_changes(&mut self, show_changes: bool) -> &mut Self {
+        self.show_changes = show_changes;
+
+        self
+    }
+
     pub fn theme(&mut self, theme: DisplayTheme) -> &mut Self {
         match theme {
+            DisplayTheme::Epd => {
+                // The panel's pixels are as big as they are, so this leaves
+                // the scale and spacing alone.
+                self.background_color(232, 228, 216);
+                self.pixel_color(48, 48, 52);
+                return self;
+            }
             DisplayTheme::LcdWhite => {
                 self.background_color(245, 245, 245);
                 self.pixel_color(32, 32, 32);
@@ -216,30 +490,67 @@ impl DisplayBuilder {
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
 
-        let window_width = self.width * self.scale + (self.width - 1) * self.pixel_spacing;
-        let window_height = self.height * self.scale + (self.height - 1) * self.pixel_spacing;
+        // XXX for rc-stickynote: find the scale that fits on the screen,
+        // leaving room for the title bar and the like. If we can't tell how
+        // big the screen is, the scale is left as it is.
+        let mut scale = self.scale;
+
+        if self.scale_to_fit {
+            if let Ok(bounds) = video_subsystem.display_bounds(0) {
+                let fit = |room: u32, n: usize| {
+                    let room = room as usize * 9 / 10;
+                    ((room + self.pixel_spacing) / n).saturating_sub(self.pixel_spacing)
+                };
+
+                scale = fit(bounds.width(), self.width)
+                    .min(fit(bounds.height(), self.height))
+                    .max(1);
+            }
+        }
+
+        let (window_width, window_height) =
+            window_size(self.width, self.height, scale, self.pixel_spacing);
+
+        let mut window = video_subsystem.window("graphics-emulator", window_width, window_height);
+        window.position_centered();
 
-        let window = video_subsystem
-            .window(
-                "graphics-emulator",
-                window_width as u32,
-                window_height as u32,
-            )
-            .position_centered()
-            .build()
-            .unwrap();
+        if self.high_dpi {
+            window.allow_highdpi();
+        }
 
-        let pixels = vec![SimPixelColor(false); self.width * self.height];
+        let window = window.build().unwrap();
+
+        let pixels = vec![BinaryColor::Off; self.width * self.height];
+
+        // XXX for rc-stickynote: a ghost is a tenth of the way from the
+        // background to the pixels.
+        let ghost_color = if self.ghosting {
+            let blend = |bg: u8, fg: u8| ((bg as u32 * 9 + fg as u32) / 10) as u8;
+            let (bg, fg) = (self.background_color, self.pixel_color);
+            Some(Color::RGB(
+                blend(bg.r, fg.r),
+                blend(bg.g, fg.g),
+                blend(bg.b, fg.b),
+            ))
+        } else {
+            None
+        };
         let canvas = window.into_canvas().build().unwrap();
         let event_pump = sdl_context.event_pump().unwrap();
 
         Display {
             width: self.width,
             height: self.height,
-            scale: self.scale,
+            scale,
+            initial_scale: scale,
             pixel_spacing: self.pixel_spacing,
             background_color: self.background_color,
             pixel_color: self.pixel_color,
+            ghost_color,
+            show_changes: self.show_changes,
+            shown: pixels.clone().into_boxed_slice(),
+            previous: pixels.clone().into_boxed_slice(),
+            region: None,
             pixels: pixels.into_boxed_slice(),
             canvas,
             event_pump,
@@ -251,20 +562,57 @@ impl DisplayBuilder {
 
 pub struct SimulatorBackend {
     display: Display,
+
+    /// How long full and partial refreshes take, if we're imitating the
+    /// panel's timing.
+    refresh: Option<(Duration, Duration)>,
 }
 
 impl DisplayBackend for SimulatorBackend {
-    type Color = SimPixelColor;
+    type Color = BinaryColor;
     type Buffer = Display;
 
-    const BLACK: SimPixelColor = SimPixelColor(true);
-    const WHITE: SimPixelColor = SimPixelColor(false);
+    const BLACK: BinaryColor = BinaryColor::On;
+    const WHITE: BinaryColor = BinaryColor::Off;
+
+    // So that the partial-refresh logic can be tried out without a panel
+    // that supports it.
+    const SUPPORTS_PARTIAL_REFRESH: bool = true;
 
     fn open() -> Result<Self, Error> {
-        // Make the size the same as the Waveshare 7in5 that I have.
-        let display = DisplayBuilder::new().size(384, 640).build();
+        let config = crate::client::simulator_configuration()?;
+
+        // Make the size the same as the Waveshare 7in5 that I have, in the
+        // portrait orientation that the layouts are drawn for.
+        let mut builder = DisplayBuilder::new();
+        builder
+            .size(WIDTH as usize, HEIGHT as usize)
+            .high_dpi(config.high_dpi)
+            .ghosting(config.ghosting)
+            .show_changes(config.show_changes);
+
+        if let SimulatorTheme::Epd = config.theme {
+            builder.theme(DisplayTheme::Epd);
+        }
+
+        if config.scale == 0 {
+            builder.scale_to_fit();
+        } else {
+            builder.scale(config.scale);
+        }
+
+        let display = builder.build();
 
-        Ok(SimulatorBackend { display })
+        let refresh = if config.refresh_ms > 0 {
+            Some((
+                Duration::from_millis(config.refresh_ms),
+                Duration::from_millis(config.partial_refresh_ms),
+            ))
+        } else {
+            None
+        };
+
+        Ok(SimulatorBackend { display, refresh })
     }
 
     fn get_buffer_mut(&mut self) -> &mut Self::Buffer {
@@ -277,19 +625,36 @@ impl DisplayBackend for SimulatorBackend {
     }
 
     fn show_buffer(&mut self) -> Result<(), Error> {
+        self.display.new_frame(None);
+
+        // Like the panel, return once the frame is up, rather than waiting
+        // for someone to look at it.
+        if let Some((full, _)) = self.refresh {
+            println!("*** simulator full refresh ***");
+            self.display.refresh(full, true);
+            return Ok(());
+        }
+
         println!("*** hit Escape when you're done looking at this image ***");
+        self.display.wait_for_escape();
+        Ok(())
+    }
 
-        loop {
-            let end = self.display.run_once();
+    fn show_region(&mut self, region: Rectangle) -> Result<(), Error> {
+        println!(
+            "*** simulator partial refresh: {}x{} at ({}, {}) ***",
+            region.size.width, region.size.height, region.top_left.x, region.top_left.y
+        );
 
-            if end {
-                break;
-            }
+        self.display.new_frame(Some(region));
 
-            thread::sleep(Duration::from_millis(200));
+        if let Some((_, partial)) = self.refresh {
+            self.display.refresh(partial, false);
+            return Ok(());
         }
 
-        println!("*** unblocking thread ***");
+        println!("*** hit Escape when you're done looking at this image ***");
+        self.display.wait_for_escape();
         Ok(())
     }
 
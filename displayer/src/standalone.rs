@@ -0,0 +1,332 @@
+//! Running without a hub.
+//!
+//! For one panel on one machine, running a hub as well is overkill. In
+//! standalone mode, the client keeps the display state itself and listens on
+//! a Unix socket, where `set-status`, `ring-doorbell`, and the buttons reach
+//! it just as they would a hub, and where the client's own main loop
+//! connects as a panel. When nobody has set a status, or the one that was
+//! set has gone stale, a default from the configuration is shown, which can
+//! depend on the time of the week, much as with the hub's default statuses.
+//! Things that only a hub can provide, like notes, headlines, and room
+//! bookings, are just left out.
+
+use chrono::{Datelike, Duration as ChronoDuration, Local, Utc};
+use futures::{prelude::*, select};
+use rc_stickynote_protocol::{
+    is_person_is_valid,
+    session::hub::{AwaitingHello, DisplaySession, Session},
+    ClientFrame, ClientHelloMessage, ControlReply, DisplayMessage, ErrorCode, ErrorFrame,
+    UNKNOWN_PERSON_IS,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{Error, ErrorKind},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    net::{UnixListener, UnixStream},
+    sync::watch,
+    time::{self, Duration},
+};
+
+use crate::schedule::{Day, TimeWindow};
+
+/// The source name attached to default statuses.
+const SOURCE: &str = "default";
+
+/// How long the doorbell card stays up. This matches the hub.
+const DOORBELL_SECONDS: i64 = 60;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StandaloneConfiguration {
+    /// The Unix socket to listen on. Anyone who can write to it can set the
+    /// status, so it should be somewhere that only the right users can get
+    /// at.
+    pub socket_path: PathBuf,
+
+    /// The default status outside all of the windows.
+    pub status: String,
+
+    /// The weekly table of default statuses, in local time. If windows
+    /// overlap, the first one listed wins.
+    pub windows: Vec<DefaultStatusWindow>,
+
+    /// If nonzero, a status that hasn't been updated for this many hours is
+    /// replaced by the default.
+    pub stale_hours: u64,
+}
+
+impl Default for StandaloneConfiguration {
+    fn default() -> Self {
+        StandaloneConfiguration {
+            socket_path: "/tmp/rc-stickynote-displayer.sock".into(),
+            status: UNKNOWN_PERSON_IS.to_owned(),
+            windows: Vec::new(),
+            stale_hours: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DefaultStatusWindow {
+    /// The days of the week that the window applies to. If empty, it applies
+    /// every day. For a window that runs overnight, these are the days that
+    /// it starts on.
+    #[serde(default)]
+    pub days: Vec<Day>,
+
+    #[serde(flatten)]
+    pub window: TimeWindow,
+
+    /// The default status during the window.
+    pub status: String,
+}
+
+impl StandaloneConfiguration {
+    /// The default status right now.
+    fn status_now(&self) -> &str {
+        let now = Local::now();
+        let (day, time) = (now.weekday(), now.time());
+
+        self.windows
+            .iter()
+            .find(|w| {
+                let day = if w.window.start.0 <= time {
+                    day
+                } else {
+                    day.pred()
+                };
+
+                (w.days.is_empty() || w.days.iter().any(|d| d.0 == day)) && w.window.contains(time)
+            })
+            .map(|w| &w.status[..])
+            .unwrap_or(&self.status)
+    }
+}
+
+/// The display state, and the means of telling the panel about changes.
+#[derive(Clone)]
+struct Standalone {
+    config: Arc<StandaloneConfiguration>,
+    inner: Arc<Mutex<(DisplayMessage, watch::Sender<DisplayMessage>)>>,
+    receiver: watch::Receiver<DisplayMessage>,
+}
+
+impl Standalone {
+    fn new(config: StandaloneConfiguration) -> Self {
+        let display = DisplayMessage {
+            person_is: config.status_now().to_owned(),
+            person_is_source: SOURCE.to_owned(),
+            ..DisplayMessage::default()
+        };
+
+        let (sender, receiver) = watch::channel(display.clone());
+
+        Standalone {
+            config: Arc::new(config),
+            inner: Arc::new(Mutex::new((display, sender))),
+            receiver,
+        }
+    }
+
+    /// Change the display state and tell the panel.
+    fn update<F: FnOnce(&mut DisplayMessage)>(&self, change: F) {
+        let mut inner = self.inner.lock().unwrap();
+        change(&mut inner.0);
+
+        // We hold a receiver ourselves, so this can't fail.
+        let _ = inner.1.broadcast(inner.0.clone());
+    }
+
+    fn show_default(&self) {
+        let status = self.config.status_now().to_owned();
+
+        self.update(|d| {
+            d.person_is = status;
+            d.person_is_timestamp = Utc::now();
+            d.person_is_source = SOURCE.to_owned();
+            d.person_is_set_by = String::new();
+        });
+    }
+
+    /// Keep the default status in step with the time of the week, and put
+    /// it back up when the status goes stale.
+    async fn keep_default(self) {
+        let mut interval = time::interval(Duration::from_secs(60));
+
+        loop {
+            interval.tick().await;
+
+            let display = self.inner.lock().unwrap().0.clone();
+
+            if display.person_is_source == SOURCE {
+                if display.person_is != self.config.status_now() {
+                    self.show_default();
+                }
+            } else if self.config.stale_hours > 0
+                && Utc::now() - display.person_is_timestamp
+                    >= ChronoDuration::hours(self.config.stale_hours as i64)
+            {
+                println!("status has gone stale; going back to the default");
+                self.show_default();
+            }
+        }
+    }
+
+    /// Act on a hello from an updater, or say why not.
+    fn handle_hello(&self, hello: ClientHelloMessage) -> Result<(), ErrorFrame> {
+        match hello {
+            ClientHelloMessage::PersonIsUpdate(msg) => {
+                if !is_person_is_valid(&msg.person_is) {
+                    return Err(ErrorFrame::new(
+                        ErrorCode::InvalidStatus,
+                        "status didn't validate -- likely too long",
+                    ));
+                }
+
+                self.update(|d| {
+                    d.person_is = msg.person_is;
+                    d.person_is_timestamp = msg.timestamp;
+                    d.person_is_source = msg.source.unwrap_or_default();
+                    d.person_is_set_by = msg.set_by.unwrap_or_default();
+                });
+                Ok(())
+            }
+
+            ClientHelloMessage::Doorbell(msg) => {
+                self.update(|d| {
+                    d.doorbell_until =
+                        Some(msg.timestamp + ChronoDuration::seconds(DOORBELL_SECONDS));
+                });
+                Ok(())
+            }
+
+            // Nobody's listening for these without a hub.
+            ClientHelloMessage::SensorReading(_) | ClientHelloMessage::SystemHealth(_) => Ok(()),
+
+            ClientHelloMessage::BookRoom(_) => Err(ErrorFrame::new(
+                ErrorCode::Unavailable,
+                "rooms can only be booked through a hub",
+            )),
+
+            ClientHelloMessage::Display(_) => Ok(()),
+        }
+    }
+
+    /// Keep a panel up to date, and answer what it sends on a multiplexed
+    /// connection.
+    async fn serve_display(&self, mut session: DisplaySession<UnixStream>) -> Result<(), Error> {
+        let mut updates = self.receiver.clone();
+
+        loop {
+            let mut outgoing = None;
+            let mut incoming = None;
+
+            select! {
+                update = updates.recv().fuse() => match update {
+                    Some(u) => outgoing = Some(u),
+                    None => return Ok(()),
+                },
+
+                frame = session.next_frame().fuse() => {
+                    incoming = Some(frame);
+                },
+            }
+
+            if let Some(mut msg) = outgoing {
+                msg.sent_at = Some(Utc::now());
+                session.send(msg).await?;
+            }
+
+            match incoming {
+                Some(Ok(Some(ClientFrame::Telemetry(hello)))) => {
+                    let _ = self.handle_hello(hello);
+                }
+
+                Some(Ok(Some(ClientFrame::Control(request)))) => {
+                    let error = self.handle_hello(request.hello).err();
+                    session
+                        .reply(ControlReply {
+                            id: request.id,
+                            error,
+                        })
+                        .await?;
+                }
+
+                Some(Ok(None)) => return Ok(()),
+                Some(Err(e)) => return Err(e),
+                Some(Ok(Some(ClientFrame::Unknown))) | None => {}
+            }
+        }
+    }
+
+    async fn handle_connection(self, socket: UnixStream) -> Result<(), Error> {
+        match AwaitingHello::new(socket).receive_hello().await? {
+            Session::Display(s) => self.serve_display(s).await,
+
+            Session::Updater(s) => {
+                let (hello, reply) = s.into_parts();
+
+                if let Err(frame) = self.handle_hello(hello) {
+                    let message = frame.message.clone();
+                    let _ = reply.reject(frame).await;
+                    return Err(Error::new(ErrorKind::InvalidInput, message));
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Start listening on the configured socket, in place of a hub.
+pub async fn start(config: &StandaloneConfiguration) -> Result<(), Error> {
+    // A socket left over from an earlier run would keep us from listening.
+    match fs::remove_file(&config.socket_path) {
+        Err(ref e) if e.kind() != ErrorKind::NotFound => {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "cannot remove old socket {}: {}",
+                    config.socket_path.display(),
+                    e
+                ),
+            ));
+        }
+        _ => {}
+    }
+
+    let mut listener = UnixListener::bind(&config.socket_path)?;
+    println!(
+        "running without a hub; listening on {}",
+        config.socket_path.display()
+    );
+
+    let standalone = Standalone::new(config.clone());
+    tokio::spawn(standalone.clone().keep_default());
+
+    tokio::spawn(async move {
+        loop {
+            let socket = match listener.accept().await {
+                Ok((s, _)) => s,
+                Err(e) => {
+                    println!("error accepting local connection: {}", e);
+                    continue;
+                }
+            };
+
+            let standalone = standalone.clone();
+
+            tokio::spawn(async move {
+                if let Err(e) = standalone.handle_connection(socket).await {
+                    println!("error handling local connection: {}", e);
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
@@ -1,140 +1,277 @@
 //! Rendering text with TTF font support.
 //!
-//! Sadly there is an impedance mismatch between the rusttype and
-//! embedded-graphics APIs: rusttype wants to be given a closure that it will
-//! call with (x, y, value), whereas embedded-graphics wants an iterator of
-//! (x, y, value). So we have to buffer.
-
-use embedded_graphics::{pixelcolor::PixelColor, prelude::*};
-use rusttype::{point, Font, PositionedGlyph, Scale};
-
-/// A convenience extension trait to help with rasterizing a rusttype font
-/// into an embedded-graphics Drawing.
-pub trait DrawFontExt {
-    /// Rasterize the given text at the given height into a layout buffer.
-    fn rasterize(&self, text: &str, height: f32) -> Layout;
+//! There is an impedance mismatch between the rusttype and embedded-graphics
+//! APIs: rusttype wants to be given a closure that it will call with (x, y,
+//! value), whereas embedded-graphics wants an iterator of (x, y, value). We
+//! used to bridge the gap by rasterizing into an intermediate buffer. Now we
+//! just hand pixels to the DrawTarget one at a time from inside the rusttype
+//! closure, through a clipping adapter so that glyphs can't spill outside of
+//! the text's bounding box.
+//!
+//! On a black-and-white panel, every pixel that a glyph touches at all is
+//! painted. If the panel can show two shades of gray as well, the glyph
+//! coverage is quantized to four levels instead, which smooths out the edges
+//! of large text.
+
+use embedded_graphics::{
+    draw_target::DrawTargetExt, pixelcolor::PixelColor, prelude::*, primitives::Rectangle, Pixel,
+};
+use rusttype::{point, Font, GlyphId, PositionedGlyph, Scale};
+use std::iter;
+
+use crate::features::{FontFeature, Substitutions};
+use crate::fonts::Typeface;
+
+/// How big to make fake small caps, relative to the capitals, if we can't
+/// measure the font's x-height.
+const SMALL_CAPS_RATIO: f32 = 0.72;
+
+/// A convenience extension trait to help with rendering a rusttype font
+/// into an embedded-graphics DrawTarget.
+pub trait DrawFontExt<'f> {
+    /// Lay out the given text at the given height, ready to be drawn.
+    fn layout_text(&self, text: &str, height: f32) -> Layout<'f> {
+        self.layout_text_with(text, height, &[])
+    }
+
+    /// Like `layout_text`, but with some OpenType features turned on. If the
+    /// font doesn't have them, they're faked: digits are centered in cells
+    /// as wide as the widest one, and lowercase letters are drawn as shrunken
+    /// capitals.
+    fn layout_text_with(&self, text: &str, height: f32, features: &[FontFeature]) -> Layout<'f>;
 }
 
-impl<'a> DrawFontExt for Font<'a> {
-    fn rasterize(&self, text: &str, float_height: f32) -> Layout {
-        let height = float_height.ceil() as usize;
+impl<'f> DrawFontExt<'f> for Font<'f> {
+    fn layout_text_with(&self, text: &str, height: f32, features: &[FontFeature]) -> Layout<'f> {
+        lay_out(self, &Substitutions::default(), text, height, features)
+    }
+}
 
-        let scale = Scale {
-            x: float_height,
-            y: float_height,
-        };
+impl DrawFontExt<'static> for Typeface {
+    fn layout_text_with(
+        &self,
+        text: &str,
+        height: f32,
+        features: &[FontFeature],
+    ) -> Layout<'static> {
+        lay_out(&self.font, &self.substitutions, text, height, features)
+    }
+}
 
-        // This stuff copied from the rusttype sample.rs file:
-        let v_metrics = self.v_metrics(scale);
-        let offset = point(0.0, v_metrics.ascent);
-        let glyphs: Vec<PositionedGlyph<'_>> = self.layout(text, scale, offset).collect();
-        let width = glyphs
-            .iter()
-            .rev()
-            .map(|g| g.position().x as f32 + g.unpositioned().h_metrics().advance_width)
-            .next()
-            .unwrap_or(0.0)
-            .ceil() as usize;
-
-        let mut buf: Vec<u8> = vec![0u8.into(); width * height];
-
-        for g in glyphs {
-            if let Some(bb) = g.pixel_bounding_box() {
-                g.draw(|x, y, v| {
-                    let x = x as i32 + bb.min.x;
-                    let y = y as i32 + bb.min.y;
-
-                    // There's still a possibility that the glyph clips the boundaries of the bitmap
-                    if x >= 0 && x < width as i32 && y >= 0 && y < height as i32 {
-                        let x = x as usize;
-                        let y = y as usize;
-                        buf[x + y * width] = (v * 255.0) as u8;
-                    }
-                })
+fn lay_out<'f>(
+    font: &Font<'f>,
+    subs: &Substitutions,
+    text: &str,
+    float_height: f32,
+    features: &[FontFeature],
+) -> Layout<'f> {
+    let height = float_height.ceil() as usize;
+    let scale = Scale::uniform(float_height);
+
+    // Work out which features we have to fake.
+
+    let fake = |f| features.contains(&f) && !subs.has(f);
+
+    let tabular_width = if fake(FontFeature::TabularNumbers) {
+        ('0'..='9')
+            .map(|c| font.glyph(c).scaled(scale).h_metrics().advance_width)
+            .fold(None, |w: Option<f32>, a| Some(w.map_or(a, |w| w.max(a))))
+    } else {
+        None
+    };
+
+    let small_caps_scale = if fake(FontFeature::SmallCaps) {
+        Some(Scale::uniform(float_height * small_caps_ratio(font)))
+    } else {
+        None
+    };
+
+    // This is what rusttype's own layout does, but with the substitutions
+    // and fakery, which also means adding up the width ourselves.
+
+    let v_metrics = font.v_metrics(scale);
+    let mut glyphs: Vec<PositionedGlyph<'f>> = Vec::new();
+    let mut caret = 0.0;
+    let mut last = None;
+
+    for c in text.chars() {
+        let mut glyph_scale = scale;
+        let mut id = font.glyph(c).id();
+
+        for &f in features {
+            if let Some(substitute) = subs.get(f, id.0 as u16) {
+                id = GlyphId(substitute as u32);
             }
         }
 
-        Layout { buf, width, height }
+        if let (Some(small_scale), true) = (small_caps_scale, c.is_lowercase()) {
+            if let Some(upper) = c.to_uppercase().next() {
+                id = font.glyph(upper).id();
+                glyph_scale = small_scale;
+            }
+        }
+
+        let glyph = font.glyph(id).scaled(glyph_scale);
+        let advance = glyph.h_metrics().advance_width;
+
+        let x = match (tabular_width, c.is_ascii_digit()) {
+            (Some(cell), true) => {
+                // Kerning would throw the digits out of their cells.
+                let x = caret + (cell - advance) / 2.0;
+                caret += cell;
+                last = None;
+                x
+            }
+
+            _ => {
+                if let Some(last) = last {
+                    caret += font.pair_kerning(scale, last, id);
+                }
+
+                let x = caret;
+                caret += advance;
+                last = Some(id);
+                x
+            }
+        };
+
+        glyphs.push(glyph.positioned(point(x, v_metrics.ascent)));
+    }
+
+    Layout {
+        glyphs,
+        width: caret.ceil() as usize,
+        height,
     }
 }
 
-/// A buffered rasterization of a bit of text.
+/// The ratio of the font's x-height to its cap height, which is how big
+/// small caps should be.
+fn small_caps_ratio(font: &Font) -> f32 {
+    let height = |c| {
+        font.glyph(c)
+            .scaled(Scale::uniform(100.0))
+            .exact_bounding_box()
+            .map(|bb| -bb.min.y)
+    };
+
+    match (height('x'), height('H')) {
+        (Some(x), Some(cap)) if x > 0.0 && cap > 0.0 => x / cap,
+        _ => SMALL_CAPS_RATIO,
+    }
+}
+
+/// A bit of text that has been laid out but not yet rasterized.
 #[derive(Clone, Debug)]
-pub struct Layout {
+pub struct Layout<'f> {
     pub width: usize,
     pub height: usize,
-    buf: Vec<u8>,
+    glyphs: Vec<PositionedGlyph<'f>>,
 }
 
-impl Layout {
-    /// Represent this rasterization as a pixel iterator suitable for
-    /// consumption by `embedded_graphics::Drawing::draw()`.
+impl<'f> Layout<'f> {
+    /// Position this layout on the display, returning an item that can be
+    /// drawn with `embedded_graphics::Drawable::draw()`.
     ///
-    /// If some of the text falls at `x < 0` or `y < 0`, it will be clipped.
-    pub fn draw_at<'a, C: PixelColor>(
-        &'a self,
+    /// If some of the text falls outside of the target, it will be clipped.
+    pub fn draw_at<C: PixelColor>(
+        &self,
         x0: i32,
         y0: i32,
         fg: C,
         bg: C,
-    ) -> LayoutPixelIter<'a, C> {
-        let ix = if x0 < 0 { -x0 } else { 0 } as usize;
-        let iy = if y0 < 0 { -y0 } else { 0 } as usize;
-
-        LayoutPixelIter {
+    ) -> PositionedLayout<'_, 'f, C> {
+        PositionedLayout {
             layout: self,
             x0,
             y0,
-            ix,
-            iy,
             fg,
             bg,
+            shades: None,
         }
     }
 }
 
-/// An iterator over pixels in a Layout.
+/// A Layout that has been placed on the display with specified colors.
 ///
-/// While PixelColor is defined to implement From<u8>, the waveshare-epd
-/// implementation only wants inputs of 0 and 1, and they have different
-/// polarity than what my simulator expects. So we have the iterator carry
-/// around the `fg` and `bg` colors rather than converting the u8 values in
-/// `layout.buf` directly.
+/// The bounding box of the text is filled with the background color, and
+/// pixels with nonzero glyph coverage are set to the foreground color, or one
+/// of the shades between it and the background if there are any. The
+/// bounding box is available through the `Dimensions` trait, so that callers
+/// can figure out which region of the display a piece of text touches.
 #[derive(Debug)]
-pub struct LayoutPixelIter<'a, C> {
-    layout: &'a Layout,
+pub struct PositionedLayout<'a, 'f, C> {
+    layout: &'a Layout<'f>,
     x0: i32,
     y0: i32,
-    ix: usize,
-    iy: usize,
     fg: C,
     bg: C,
+    shades: Option<(C, C)>,
+}
+
+impl<'a, 'f, C> PositionedLayout<'a, 'f, C> {
+    /// Antialias the edges of the glyphs with two intermediate shades: one
+    /// nearer the foreground color, and one nearer the background.
+    pub fn with_shades(mut self, near_fg: C, near_bg: C) -> Self {
+        self.shades = Some((near_fg, near_bg));
+        self
+    }
 }
 
-impl<'a, C: PixelColor> Iterator for LayoutPixelIter<'a, C> {
-    type Item = Pixel<C>;
+impl<'a, 'f, C> Dimensions for PositionedLayout<'a, 'f, C> {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(
+            Point::new(self.x0, self.y0),
+            Size::new(self.layout.width as u32, self.layout.height as u32),
+        )
+    }
+}
 
-    fn next(&mut self) -> Option<Pixel<C>> {
-        if self.iy >= self.layout.height {
-            return None;
-        }
+impl<'a, 'f, C: PixelColor> Drawable for PositionedLayout<'a, 'f, C> {
+    type Color = C;
+    type Output = ();
 
-        let rx = (self.x0 as usize + self.ix) as u32;
-        let ry = (self.y0 as usize + self.iy) as u32;
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let bbox = self.bounding_box();
+        let mut clipped = target.clipped(&bbox);
+        clipped.fill_solid(&bbox, self.bg)?;
 
-        let rc = if self.layout.buf[self.ix + self.iy * self.layout.width] > 0 {
-            self.fg
-        } else {
-            self.bg
-        };
+        // The rusttype drawing closure can't return an error, so we stash the
+        // first one we get and stop drawing after that.
+        let mut result = Ok(());
+
+        for g in &self.layout.glyphs {
+            if let Some(bb) = g.pixel_bounding_box() {
+                g.draw(|x, y, v| {
+                    if result.is_err() {
+                        return;
+                    }
+
+                    let color = match self.shades {
+                        // Only paint pixels that would have survived
+                        // quantization to an 8-bit coverage value.
+                        None if (v * 255.0) as u8 == 0 => return,
+                        None => self.fg,
 
-        self.ix += 1;
+                        Some((near_fg, near_bg)) => match (v * 3.0).round() as u8 {
+                            0 => return,
+                            1 => near_bg,
+                            2 => near_fg,
+                            _ => self.fg,
+                        },
+                    };
 
-        if self.ix >= self.layout.width {
-            self.ix = if self.x0 < 0 { -self.x0 as usize } else { 0 };
-            self.iy += 1;
+                    let p =
+                        Point::new(self.x0 + x as i32 + bb.min.x, self.y0 + y as i32 + bb.min.y);
+
+                    result = clipped.draw_iter(iter::once(Pixel(p, color)));
+                })
+            }
         }
 
-        Some(Pixel(UnsignedCoord(rx, ry), rc))
+        result
     }
 }
@@ -0,0 +1,348 @@
+//! Updating the displayer program from GitHub releases.
+//!
+//! Each release should have the displayer executable as an asset, along with
+//! a signature made by [minisign](https://jedisct1.github.io/minisign/) with
+//! the same name plus `.minisig`. We only install executables whose signature
+//! checks out against the public key in the configuration, so someone who
+//! gets hold of the GitHub repository can't take over every panel.
+//!
+//! The signature's trusted comment, which the signature covers too, has to
+//! name the release and the asset, as in
+//!
+//! ```text
+//! minisign -S -m rc_stickynote_displayer-arm -t "version:v1.2.3 file:rc_stickynote_displayer-arm"
+//! ```
+//!
+//! Otherwise, an old executable, or one built for another kind of machine,
+//! could be passed off under a newer release with its genuine signature.
+//!
+//! The new executable is written next to the old one and renamed into place,
+//! so the directory that it's in has to be writable. If the client switches
+//! to an ordinary user once the panel is open, as set up in `[daemon]`, then
+//! automatic updates only work if that user can write there; otherwise, run
+//! `self-update` as root.
+
+use hyper::{body::Bytes, client::HttpConnector, header, Body, Client, Request};
+use hyper_tls::HttpsConnector;
+use minisign_verify::{PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    io::{Error, ErrorKind},
+    path::Path,
+    thread,
+    time::Duration,
+};
+use tokio::runtime::Runtime;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UpdateConfiguration {
+    /// The GitHub repository whose releases we follow.
+    #[serde(default = "default_repo")]
+    pub repo: String,
+
+    /// The minisign public key that releases are signed with, in the
+    /// base64 form that minisign prints.
+    pub public_key: String,
+
+    /// The name of the release asset to install. It has to end with a dash
+    /// and the architecture that the displayer is built for, like "-arm".
+    #[serde(default = "default_asset")]
+    pub asset: String,
+
+    /// If nonzero, the client checks for updates this often, and restarts
+    /// itself if it installs one.
+    #[serde(default)]
+    pub auto_check_hours: u64,
+}
+
+fn default_repo() -> String {
+    "pkgw/rc-stickynote".to_owned()
+}
+
+fn default_asset() -> String {
+    format!("rc_stickynote_displayer-{}", env::consts::ARCH)
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+    assets: Vec<GithubAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+fn update_error<E: std::fmt::Display>(e: E) -> Error {
+    Error::new(ErrorKind::Other, format!("update error: {}", e))
+}
+
+/// Check that a signature's trusted comment says that it's for the given
+/// release and asset.
+fn check_signed_release(signature: &Signature, tag: &str, asset: &str) -> Result<(), Error> {
+    let comment = signature.trusted_comment();
+    let field = |name: &str| {
+        comment
+            .split_whitespace()
+            .find_map(|f| f.strip_prefix(name)?.strip_prefix(':'))
+    };
+
+    if field("version") != Some(tag) {
+        return Err(update_error(format!(
+            "the signature on {} is for version {}, not {}",
+            asset,
+            field("version").unwrap_or("(unknown)"),
+            tag
+        )));
+    }
+
+    if field("file") != Some(asset) {
+        return Err(update_error(format!(
+            "the signature on {} is for {}",
+            asset,
+            field("file").unwrap_or("(unknown)")
+        )));
+    }
+
+    Ok(())
+}
+
+/// Parse a version like "v1.2.3" into something that sorts correctly.
+fn parse_version(text: &str) -> Vec<u64> {
+    text.trim_start_matches('v')
+        .split(['.', '-'])
+        .map_while(|p| p.parse().ok())
+        .collect()
+}
+
+/// GET a URL, following redirects, since GitHub sends asset downloads
+/// elsewhere.
+async fn fetch(url: &str) -> Result<Bytes, Error> {
+    let client = Client::builder().build::<_, Body>(HttpsConnector::<HttpConnector>::new());
+    let mut url = url.to_owned();
+
+    for _ in 0..5 {
+        let req = Request::get(url.as_str())
+            .header(header::USER_AGENT, "rc-stickynote-displayer")
+            .header(header::ACCEPT, "application/vnd.github.v3+json")
+            .body(Body::empty())
+            .map_err(update_error)?;
+        let resp = client.request(req).await.map_err(update_error)?;
+
+        if resp.status().is_redirection() {
+            url = resp
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| update_error("redirect without a location"))?
+                .to_owned();
+            continue;
+        }
+
+        if !resp.status().is_success() {
+            return Err(update_error(format!(
+                "fetch of {} failed with status {}",
+                url,
+                resp.status()
+            )));
+        }
+
+        return hyper::body::to_bytes(resp.into_body())
+            .await
+            .map_err(update_error);
+    }
+
+    Err(update_error(format!("too many redirects for {}", url)))
+}
+
+/// What happened when we checked for an update.
+pub enum UpdateOutcome {
+    /// We're running the latest version.
+    UpToDate,
+
+    /// A newer version is available, but we were asked not to install it.
+    Available(String),
+
+    /// We installed a newer version.
+    Installed(String),
+}
+
+/// Check for a newer release, and unless `check_only`, install it in place
+/// of the running executable.
+pub async fn check_and_update(
+    config: &UpdateConfiguration,
+    check_only: bool,
+) -> Result<UpdateOutcome, Error> {
+    let public_key = PublicKey::from_base64(&config.public_key).map_err(update_error)?;
+
+    let url = format!(
+        "https://api.github.com/repos/{}/releases/latest",
+        config.repo
+    );
+    let release: GithubRelease = serde_json::from_slice(&fetch(&url).await?)?;
+
+    // The signature has to name this same version, so this also keeps us
+    // from being sent back to an older release.
+    if parse_version(&release.tag_name) <= parse_version(env!("CARGO_PKG_VERSION")) {
+        return Ok(UpdateOutcome::UpToDate);
+    }
+
+    if check_only {
+        return Ok(UpdateOutcome::Available(release.tag_name));
+    }
+
+    if !config.asset.ends_with(&format!("-{}", env::consts::ARCH)) {
+        return Err(update_error(format!(
+            "the asset {} isn't named for this machine's architecture, {}",
+            config.asset,
+            env::consts::ARCH
+        )));
+    }
+
+    // Make sure that we can put the new executable in place before
+    // downloading it.
+
+    let exe = env::current_exe()?;
+    let mut temp = exe.clone().into_os_string();
+    temp.push(".new");
+
+    if let Err(e) = fs::File::create(&temp).and_then(|_| fs::remove_file(&temp)) {
+        return Err(update_error(format!(
+            "cannot write next to {}, as this user: {}",
+            exe.display(),
+            e
+        )));
+    }
+
+    let find_asset = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| a.browser_download_url.clone())
+            .ok_or_else(|| {
+                update_error(format!(
+                    "release {} has no asset named {}",
+                    release.tag_name, name
+                ))
+            })
+    };
+
+    let binary_url = find_asset(&config.asset)?;
+    let signature_url = find_asset(&format!("{}.minisig", config.asset))?;
+
+    let binary = fetch(&binary_url).await?;
+    let signature_text = String::from_utf8_lossy(&fetch(&signature_url).await?).into_owned();
+    let signature = Signature::decode(&signature_text).map_err(update_error)?;
+
+    public_key
+        .verify(&binary, &signature, false)
+        .map_err(|e| update_error(format!("bad signature on {}: {}", config.asset, e)))?;
+    check_signed_release(&signature, &release.tag_name, &config.asset)?;
+
+    // Write the new executable next to the old one, then rename it into
+    // place, so that we never leave a half-written executable behind.
+
+    fs::write(&temp, &binary)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&temp, fs::Permissions::from_mode(0o755))?;
+    }
+
+    fs::rename(&temp, &exe)?;
+    Ok(UpdateOutcome::Installed(release.tag_name))
+}
+
+/// Periodically check for updates, and if one is installed, restart into it.
+pub fn auto_update_thread(config: UpdateConfiguration) {
+    let interval = Duration::from_secs(3600 * config.auto_check_hours);
+
+    // Once the executable has been replaced, Linux reports our own path as
+    // "... (deleted)", so get it now.
+    let exe = match env::current_exe() {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("ERROR: cannot locate this executable for updates: {}", e);
+            return;
+        }
+    };
+
+    loop {
+        thread::sleep(interval);
+
+        let outcome =
+            Runtime::new().and_then(|mut rt| rt.block_on(check_and_update(&config, false)));
+
+        match outcome {
+            Ok(UpdateOutcome::Installed(version)) => {
+                println!("installed version {}; restarting", version);
+                restart(&exe);
+            }
+
+            Ok(_) => {}
+
+            Err(e) => eprintln!("ERROR: checking for updates failed: {}", e),
+        }
+    }
+}
+
+/// Replace this process with the newly installed executable, keeping the
+/// same arguments.
+#[cfg(unix)]
+pub fn restart(exe: &Path) {
+    use std::os::unix::process::CommandExt;
+
+    let err = std::process::Command::new(exe)
+        .args(env::args_os().skip(1))
+        .exec();
+    eprintln!("ERROR: cannot restart after update: {}", err);
+}
+
+#[cfg(not(unix))]
+pub fn restart(_exe: &Path) {
+    eprintln!("ERROR: cannot restart after update on this platform");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A signature with the given trusted comment. Its bytes are all zeros,
+    /// which doesn't matter, since it isn't verified here.
+    fn signed(comment: &str) -> Signature {
+        let text = format!(
+            "untrusted comment: test\n{}\ntrusted comment: {}\n{}\n",
+            "RWQAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+            comment,
+            "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=="
+        );
+
+        Signature::decode(&text).unwrap()
+    }
+
+    #[test]
+    fn signed_release() {
+        let sig = signed("timestamp:1792224000 version:v1.2.3 file:displayer-arm");
+        assert!(check_signed_release(&sig, "v1.2.3", "displayer-arm").is_ok());
+
+        // Another release's executable, or another machine's.
+        assert!(check_signed_release(&sig, "v1.2.4", "displayer-arm").is_err());
+        assert!(check_signed_release(&sig, "v1.2.3", "displayer-x86_64").is_err());
+
+        // The comment that minisign writes by default isn't enough.
+        let sig = signed("timestamp:1792224000 file:displayer-arm");
+        assert!(check_signed_release(&sig, "v1.2.3", "displayer-arm").is_err());
+    }
+
+    #[test]
+    fn versions() {
+        assert!(parse_version("v1.10.0") > parse_version("1.9.2"));
+        assert!(parse_version("v1.2.3-rc1") <= parse_version("1.2.3"));
+        assert!(parse_version("nightly") <= parse_version("0.1.0"));
+    }
+}
@@ -0,0 +1,243 @@
+//! Feeding a watchdog, so that a hung client gets the Pi rebooted.
+//!
+//! If the client wedges -- a driver call that never returns, a deadlock --
+//! the panel just keeps showing its last frame, which can go unnoticed for a
+//! whole weekend. A watchdog reboots the Pi unless it's told regularly that
+//! all is well. We can feed systemd's watchdog, for a service with
+//! `WatchdogSec=` set, and the kernel's hardware watchdog device, but we only
+//! do so while the main loop is turning over, the renderer isn't stuck, and
+//! the hub connection is up, so that silence means something is wrong.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io::{Error, Write},
+    os::unix::net::UnixDatagram,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WatchdogConfiguration {
+    /// Whether to feed systemd's watchdog. This only does anything when the
+    /// client is run by a service with `WatchdogSec=` set, which should be
+    /// at least a few times `interval_seconds`.
+    pub systemd: bool,
+
+    /// If set, a hardware watchdog device to feed, like "/dev/watchdog".
+    /// Once it's opened, the Pi will reboot if the feeding stops, so only
+    /// set this on a panel that's meant to run unattended.
+    pub device: Option<PathBuf>,
+
+    /// How often to feed the watchdogs, in seconds.
+    pub interval_seconds: u64,
+
+    /// Stop feeding if the main loop hasn't come around, or a redraw has
+    /// been going on, for this many minutes.
+    pub stale_minutes: u64,
+
+    /// Stop feeding if the hub connection has been down for this many
+    /// minutes. Zero means to keep feeding however long the hub is gone.
+    pub hub_stale_minutes: u64,
+}
+
+impl Default for WatchdogConfiguration {
+    fn default() -> Self {
+        WatchdogConfiguration {
+            systemd: true,
+            device: None,
+            interval_seconds: 10,
+            stale_minutes: 10,
+            hub_stale_minutes: 60,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Signs {
+    /// When the main loop last came around.
+    main_loop: Instant,
+
+    /// When the hub connection was last seen to be up.
+    hub: Instant,
+
+    /// When the redraw going on now started, if there is one.
+    rendering_since: Option<Instant>,
+
+    /// The hardware watchdog, once it's been opened.
+    device: Option<File>,
+
+    /// Set when the client is exiting on purpose.
+    stopped: bool,
+}
+
+/// Signs of life from the client's threads, shared between them.
+#[derive(Clone, Debug)]
+pub struct Pulse {
+    signs: Arc<Mutex<Signs>>,
+}
+
+impl Default for Pulse {
+    fn default() -> Self {
+        let now = Instant::now();
+
+        Pulse {
+            signs: Arc::new(Mutex::new(Signs {
+                main_loop: now,
+                hub: now,
+                rendering_since: None,
+                device: None,
+                stopped: false,
+            })),
+        }
+    }
+}
+
+impl Pulse {
+    /// Note that the main loop has come around, and whether the hub
+    /// connection is up.
+    pub fn main_loop_ran(&self, hub_ok: bool) {
+        let mut signs = self.signs.lock().unwrap();
+        signs.main_loop = Instant::now();
+
+        if hub_ok {
+            signs.hub = signs.main_loop;
+        }
+    }
+
+    /// Note that the renderer has started a redraw.
+    pub fn render_started(&self) {
+        self.signs.lock().unwrap().rendering_since = Some(Instant::now());
+    }
+
+    /// Note that the renderer has finished a redraw. A redraw that fails
+    /// never finishes, so the feeding stops after a while.
+    pub fn render_finished(&self) {
+        self.signs.lock().unwrap().rendering_since = None;
+    }
+
+    /// Stop feeding and disarm the hardware watchdog, for when the client
+    /// is exiting on purpose and shouldn't get the Pi rebooted.
+    pub fn stop(&self) {
+        let mut signs = self.signs.lock().unwrap();
+        signs.stopped = true;
+
+        // Writing "V" before closing the device is the "magic close" that
+        // tells the kernel that we meant to stop.
+        if let Some(mut device) = signs.device.take() {
+            if let Err(e) = device.write_all(b"V") {
+                eprintln!("ERROR: cannot disarm the hardware watchdog: {}", e);
+            }
+        }
+    }
+
+    /// Whether everything seems to be going all right, or if not, why not.
+    fn check(&self, config: &WatchdogConfiguration) -> Result<(), &'static str> {
+        let signs = self.signs.lock().unwrap();
+        let stale = Duration::from_secs(config.stale_minutes * 60);
+
+        if signs.main_loop.elapsed() > stale {
+            return Err("the main loop is stuck");
+        }
+
+        if let Some(since) = signs.rendering_since {
+            if since.elapsed() > stale {
+                return Err("the renderer is stuck");
+            }
+        }
+
+        if config.hub_stale_minutes > 0
+            && signs.hub.elapsed() > Duration::from_secs(config.hub_stale_minutes * 60)
+        {
+            return Err("the hub connection has been down too long");
+        }
+
+        Ok(())
+    }
+}
+
+/// Start feeding the configured watchdogs in a thread of their own.
+pub fn spawn(config: &WatchdogConfiguration, pulse: Pulse) -> Result<(), Error> {
+    let systemd = if config.systemd {
+        systemd_socket()?
+    } else {
+        None
+    };
+
+    if let Some(ref path) = config.device {
+        let device = OpenOptions::new().write(true).open(path)?;
+        println!("feeding the hardware watchdog {}", path.display());
+        pulse.signs.lock().unwrap().device = Some(device);
+    }
+
+    if systemd.is_none() && config.device.is_none() {
+        return Ok(());
+    }
+
+    let config = config.clone();
+    thread::spawn(move || feed_thread(config, pulse, systemd));
+    Ok(())
+}
+
+/// The socket for talking to systemd, if we were started by a service that
+/// wants watchdog notifications.
+fn systemd_socket() -> Result<Option<(UnixDatagram, PathBuf)>, Error> {
+    if env::var_os("WATCHDOG_USEC").is_none() {
+        return Ok(None);
+    }
+
+    let path = match env::var_os("NOTIFY_SOCKET") {
+        Some(p) => PathBuf::from(p),
+        None => return Ok(None),
+    };
+
+    println!("feeding the systemd watchdog");
+    Ok(Some((UnixDatagram::unbound()?, path)))
+}
+
+fn feed_thread(
+    config: WatchdogConfiguration,
+    pulse: Pulse,
+    systemd: Option<(UnixDatagram, PathBuf)>,
+) {
+    let interval = Duration::from_secs(config.interval_seconds.max(1));
+    let mut starving = false;
+
+    loop {
+        thread::sleep(interval);
+
+        if pulse.signs.lock().unwrap().stopped {
+            return;
+        }
+
+        // Once we stop feeding, don't start up again if things recover,
+        // since a reboot is on its way.
+
+        if !starving {
+            if let Err(why) = pulse.check(&config) {
+                eprintln!("ERROR: no longer feeding the watchdog: {}", why);
+                starving = true;
+            }
+        }
+
+        if starving {
+            continue;
+        }
+
+        if let Some((ref socket, ref path)) = systemd {
+            if let Err(e) = socket.send_to(b"WATCHDOG=1", path) {
+                eprintln!("ERROR: cannot feed the systemd watchdog: {}", e);
+            }
+        }
+
+        if let Some(ref mut device) = pulse.signs.lock().unwrap().device {
+            if let Err(e) = device.write_all(b"\0") {
+                eprintln!("ERROR: cannot feed the hardware watchdog: {}", e);
+            }
+        }
+    }
+}
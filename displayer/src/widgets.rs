@@ -0,0 +1,140 @@
+//! Text widgets whose contents come from running local commands.
+//!
+//! Each widget runs its command every so often and shows the first line of
+//! its output, so that the panel can display things like the SoC temperature
+//! (`vcgencmd measure_temp`) without the client having to know about them.
+//!
+//! A widget can instead be bound to a key in the hub's extras, like
+//! "weather.temperature", in which case it shows whatever the hub has for that
+//! key, and nothing if it has nothing.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    process::Command,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// The latest text of each widget, in configuration order, shared between
+/// threads. `None` means that the widget hasn't produced anything yet.
+pub type SharedWidgets = Arc<Mutex<Vec<Option<String>>>>;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WidgetConfiguration {
+    /// The command to run, along with its arguments.
+    #[serde(default)]
+    pub command: Vec<String>,
+
+    /// If set, show the hub's value for this key in its extras instead of
+    /// running a command.
+    #[serde(default)]
+    pub key: Option<String>,
+
+    /// How often to rerun the command.
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+
+    /// If set, shown before the command's output, e.g. "SoC".
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+fn default_interval_seconds() -> u64 {
+    300
+}
+
+impl WidgetConfiguration {
+    /// Run the command and get the text to show.
+    fn run(&self) -> String {
+        let output = match self.command.split_first() {
+            Some((program, args)) => Command::new(program).args(args).output(),
+            None => return self.labeled("(no command)"),
+        };
+
+        let text = match output {
+            Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout)
+                .lines()
+                .map(|l| l.trim())
+                .find(|l| !l.is_empty())
+                .unwrap_or("")
+                .to_owned(),
+
+            Ok(o) => format!("failed ({})", o.status),
+
+            Err(e) => {
+                println!("error running widget command {:?}: {}", self.command, e);
+                "failed".to_owned()
+            }
+        };
+
+        self.labeled(&text)
+    }
+
+    fn labeled(&self, text: &str) -> String {
+        match self.label {
+            Some(ref l) => format!("{}: {}", l, text),
+            None => text.to_owned(),
+        }
+    }
+}
+
+/// Start a thread for each command widget, keeping its entry in `shared` up
+/// to date. Each one gets its own thread so that a command that hangs doesn't
+/// hold up the others.
+pub fn spawn_widget_threads(configs: &[WidgetConfiguration], shared: SharedWidgets) {
+    *shared.lock().unwrap() = vec![None; configs.len()];
+
+    for (index, config) in configs.iter().enumerate() {
+        if config.key.is_some() {
+            continue;
+        }
+
+        let config = config.clone();
+        let shared = shared.clone();
+
+        thread::spawn(move || {
+            let interval = Duration::from_secs(config.interval_seconds.max(1));
+
+            loop {
+                let text = config.run();
+                shared.lock().unwrap()[index] = Some(text);
+                thread::sleep(interval);
+            }
+        });
+    }
+}
+
+/// How to show a value from the hub's extras. Strings are shown as they are,
+/// without quotes.
+fn value_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        v => Some(v.to_string()),
+    }
+}
+
+/// The text of each widget, in configuration order: the latest output of its
+/// command, or the hub's value for its key.
+pub fn current_text(
+    configs: &[WidgetConfiguration],
+    shared: &SharedWidgets,
+    extras: &BTreeMap<String, Value>,
+) -> Vec<Option<String>> {
+    let from_commands = shared.lock().unwrap().clone();
+
+    configs
+        .iter()
+        .zip(from_commands)
+        .map(|(config, text)| match config.key {
+            Some(ref key) => extras
+                .get(key)
+                .and_then(value_text)
+                .map(|t| config.labeled(&t)),
+            None => text,
+        })
+        .collect()
+}
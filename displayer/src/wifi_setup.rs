@@ -0,0 +1,459 @@
+//! Setting up WiFi through a captive portal.
+//!
+//! When the Pi is taken somewhere new, it has no way to learn the local WiFi
+//! credentials without a keyboard. So if there's no network, we use
+//! NetworkManager to bring up a hotspot, show how to join it on the panel,
+//! and serve a little web form that collects the network name and password.
+//! Then we take the hotspot down and try to connect, starting over if that
+//! fails.
+//!
+//! This needs NetworkManager, and permission to manage it through `nmcli`.
+//! The form is served on port 80 by default, which needs privileges too.
+
+use embedded_graphics::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{BufRead, BufReader, Error, ErrorKind, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    process::Command,
+    thread,
+    time::Duration,
+};
+
+use super::DisplayBackend;
+use crate::drawing::{LineStyle, MonoStyle, QrImage};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WifiSetupConfiguration {
+    /// The wireless interface to use.
+    #[serde(default = "default_interface")]
+    pub interface: String,
+
+    /// The name of the hotspot network.
+    #[serde(default = "default_hotspot_ssid")]
+    pub hotspot_ssid: String,
+
+    /// The hotspot's password. If unset, NetworkManager makes one up, and
+    /// it's shown on the panel.
+    #[serde(default)]
+    pub hotspot_password: Option<String>,
+
+    /// The port to serve the setup form on.
+    #[serde(default = "default_port")]
+    pub port: u16,
+
+    /// How long to wait for a network connection before bringing up the
+    /// hotspot.
+    #[serde(default = "default_wait_seconds")]
+    pub wait_seconds: u64,
+}
+
+fn default_interface() -> String {
+    "wlan0".to_owned()
+}
+
+fn default_hotspot_ssid() -> String {
+    "stickynote-setup".to_owned()
+}
+
+fn default_port() -> u16 {
+    80
+}
+
+fn default_wait_seconds() -> u64 {
+    60
+}
+
+/// The name of the NetworkManager connection for the hotspot.
+const HOTSPOT_CONNECTION: &str = "stickynote-setup";
+
+/// The address that NetworkManager gives the Pi on its hotspot network.
+const HOTSPOT_ADDRESS: &str = "10.42.0.1";
+
+fn has_network() -> Result<bool, Error> {
+    Ok(get_if_addrs::get_if_addrs()?
+        .iter()
+        .any(|iface| !iface.is_loopback() && matches!(iface.addr, get_if_addrs::IfAddr::V4(_))))
+}
+
+/// Run `nmcli` with the given arguments, returning its output.
+fn nmcli(args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("nmcli").args(args).output()?;
+
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!(
+                "nmcli failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// List the names of nearby networks, to offer in the form. This has to be
+/// done before the hotspot is up, since the interface can't scan then.
+fn scan_networks(config: &WifiSetupConfiguration) -> Vec<String> {
+    let mut ssids: Vec<String> = nmcli(&[
+        "-t",
+        "-f",
+        "SSID",
+        "device",
+        "wifi",
+        "list",
+        "ifname",
+        &config.interface,
+    ])
+    .unwrap_or_default()
+    .lines()
+    .map(|l| l.trim().to_owned())
+    .filter(|l| !l.is_empty())
+    .collect();
+
+    ssids.sort();
+    ssids.dedup();
+    ssids
+}
+
+/// Bring up the hotspot, returning its password.
+fn start_hotspot(config: &WifiSetupConfiguration) -> Result<String, Error> {
+    let mut args = vec![
+        "device",
+        "wifi",
+        "hotspot",
+        "ifname",
+        &config.interface,
+        "con-name",
+        HOTSPOT_CONNECTION,
+        "ssid",
+        &config.hotspot_ssid,
+    ];
+
+    if let Some(ref pw) = config.hotspot_password {
+        args.push("password");
+        args.push(pw);
+    }
+
+    nmcli(&args)?;
+
+    if let Some(ref pw) = config.hotspot_password {
+        return Ok(pw.clone());
+    }
+
+    let shown = nmcli(&[
+        "device",
+        "wifi",
+        "show-password",
+        "ifname",
+        &config.interface,
+    ])?;
+
+    shown
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("Password:"))
+        .map(|pw| pw.trim().to_owned())
+        .ok_or_else(|| Error::new(ErrorKind::Other, "cannot determine the hotspot password"))
+}
+
+fn stop_hotspot() {
+    // This fails if the hotspot is already down, which is fine.
+    let _ignored = nmcli(&["connection", "down", HOTSPOT_CONNECTION]);
+}
+
+fn connect(config: &WifiSetupConfiguration, ssid: &str, password: &str) -> Result<(), Error> {
+    let mut args = vec![
+        "device",
+        "wifi",
+        "connect",
+        ssid,
+        "ifname",
+        &config.interface,
+    ];
+
+    if !password.is_empty() {
+        args.push("password");
+        args.push(password);
+    }
+
+    nmcli(&args)?;
+    Ok(())
+}
+
+/// Show some lines of text on the panel, with an optional QR code below.
+fn show_message<B: DisplayBackend>(
+    backend: &mut B,
+    lines: &[String],
+    qr: Option<&str>,
+) -> Result<(), Error> {
+    backend.clear_buffer(B::WHITE)?;
+
+    {
+        let buffer = backend.get_buffer_mut();
+        let style = MonoStyle::new(B::BLACK, B::WHITE);
+        let mut y = 50;
+
+        for line in lines {
+            style.draw_line(line, Point::new(50, y), buffer).unwrap();
+            y += 20;
+        }
+
+        if let Some(text) = qr {
+            if let Some(q) = QrImage::new(text, Point::new(50, y), 4, B::BLACK, B::WHITE) {
+                q.draw(buffer).unwrap();
+            }
+        }
+    }
+
+    backend.wake_up_device()?;
+    backend.show_buffer()?;
+    backend.sleep_device()?;
+    Ok(())
+}
+
+/// Quote a value for a WiFi QR code.
+fn qr_escape(text: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in text.chars() {
+        if "\\;,\":".contains(c) {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Decode an `application/x-www-form-urlencoded` value.
+fn form_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => {
+                        decoded.push(b);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+
+            b => decoded.push(b),
+        }
+
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn form_field(body: &str, name: &str) -> Option<String> {
+    body.split('&').find_map(|pair| {
+        let mut pieces = pair.splitn(2, '=');
+        if pieces.next()? == name {
+            Some(form_decode(pieces.next().unwrap_or("")))
+        } else {
+            None
+        }
+    })
+}
+
+fn form_page(networks: &[String], message: &str) -> String {
+    let options: String = networks
+        .iter()
+        .map(|n| format!("<option value=\"{}\">", html_escape(n)))
+        .collect();
+
+    format!(
+        "<!doctype html>
+<html>
+<head>
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>Sticky note WiFi setup</title>
+</head>
+<body>
+<h1>WiFi setup</h1>
+<p>{}</p>
+<form method=\"post\" action=\"/connect\">
+<p><label>Network: <input name=\"ssid\" list=\"networks\" required></label></p>
+<datalist id=\"networks\">{}</datalist>
+<p><label>Password: <input name=\"password\" type=\"password\"></label></p>
+<p><input type=\"submit\" value=\"Connect\"></p>
+</form>
+</body>
+</html>
+",
+        html_escape(message),
+        options
+    )
+}
+
+/// Handle one HTTP request. Every GET gets the form, so that phones' captive
+/// portal checks land on it. Returns the credentials if they were submitted.
+fn handle_request(
+    stream: TcpStream,
+    networks: &[String],
+    message: &str,
+) -> Result<Option<(String, String)>, Error> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+
+    loop {
+        let mut header = String::new();
+
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut result = None;
+
+    let page = if request_line.starts_with("POST /connect ") {
+        let mut body = vec![0; content_length.min(4096)];
+        reader.read_exact(&mut body)?;
+        let body = String::from_utf8_lossy(&body);
+
+        match form_field(&body, "ssid").filter(|s| !s.is_empty()) {
+            Some(ssid) => {
+                let password = form_field(&body, "password").unwrap_or_default();
+                let page = format!(
+                    "<!doctype html><html><body><p>Connecting to {}. Check the sticky note to \
+                     see how it goes.</p></body></html>",
+                    html_escape(&ssid)
+                );
+                result = Some((ssid, password));
+                page
+            }
+
+            None => form_page(networks, "Please enter a network name."),
+        }
+    } else {
+        form_page(networks, message)
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        page.len(),
+        page
+    )?;
+    stream.flush()?;
+    Ok(result)
+}
+
+/// Serve the form until someone submits credentials.
+fn collect_credentials(
+    listener: &TcpListener,
+    networks: &[String],
+    message: &str,
+) -> Result<(String, String), Error> {
+    for stream in listener.incoming() {
+        match handle_request(stream?, networks, message) {
+            Ok(Some(creds)) => return Ok(creds),
+            Ok(None) => {}
+            Err(e) => eprintln!("error handling setup request: {}", e),
+        }
+    }
+
+    Err(Error::new(ErrorKind::Other, "the setup server stopped"))
+}
+
+pub fn wifi_setup_cli<B: DisplayBackend>(opts: super::WifiSetupCommand) -> Result<(), Error> {
+    let config = crate::client::wifi_setup_configuration()?.ok_or_else(|| {
+        Error::new(
+            ErrorKind::Other,
+            "the client configuration does not have a [wifi_setup] section",
+        )
+    })?;
+
+    if !opts.force {
+        for _ in 0..(config.wait_seconds / 5).max(1) {
+            if has_network()? {
+                return Ok(());
+            }
+
+            thread::sleep(Duration::from_secs(5));
+        }
+    }
+
+    let mut backend = B::open()?;
+    let networks = scan_networks(&config);
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, config.port))?;
+    let mut message = "Choose the network that the sticky note should use.".to_owned();
+
+    loop {
+        let password = start_hotspot(&config)?;
+        let url = if config.port == 80 {
+            format!("http://{}/", HOTSPOT_ADDRESS)
+        } else {
+            format!("http://{}:{}/", HOTSPOT_ADDRESS, config.port)
+        };
+
+        show_message(
+            &mut backend,
+            &[
+                "WiFi setup".to_owned(),
+                message.clone(),
+                format!("Join the network \"{}\"", config.hotspot_ssid),
+                format!("with the password \"{}\",", password),
+                format!("then visit {}", url),
+            ],
+            Some(&format!(
+                "WIFI:T:WPA;S:{};P:{};;",
+                qr_escape(&config.hotspot_ssid),
+                qr_escape(&password)
+            )),
+        )?;
+
+        let (ssid, wifi_password) = collect_credentials(&listener, &networks, &message)?;
+
+        show_message(
+            &mut backend,
+            &[format!("Connecting to \"{}\" ...", ssid)],
+            None,
+        )?;
+
+        stop_hotspot();
+
+        match connect(&config, &ssid, &wifi_password) {
+            Ok(_) => {
+                show_message(&mut backend, &[format!("Connected to \"{}\"!", ssid)], None)?;
+                return Ok(());
+            }
+
+            Err(e) => {
+                message = format!("Could not connect to \"{}\": {}", ssid, e);
+                eprintln!("{}", message);
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Golden-image tests of the layouts.
+//!
+//! Each case draws a frame for a display message at a fixed time and
+//! compares it with a reference image in `snapshots/`. A few stray pixels
+//! are allowed, since font rasterization can shift with dependency updates,
+//! but a widget that moves or changes size fails. When a case fails, the
+//! frame that was drawn and a picture of the differences are saved next to
+//! the test binary's temporary files, and the paths are in the message.
+//!
+//! Run these with `cargo test -p rc_stickynote_displayer --features
+//! snapshot`. After a change that's meant to alter the layouts, set
+//! `UPDATE_SNAPSHOTS=1` to rewrite the references, and check them over
+//! before committing them.
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use image::{GrayImage, Luma};
+use rc_stickynote_displayer::{
+    memory::{MemoryBackend, HEIGHT, WIDTH},
+    DisplayBackend, FrameRenderer,
+};
+use rc_stickynote_protocol::{DisplayMessage, DisplaySettings};
+use std::{env, path::PathBuf};
+
+/// How far apart two pixels' brightnesses can be before they count as
+/// different. Each of the four levels of gray is 85 apart.
+const PIXEL_TOLERANCE: u8 = 42;
+
+/// How many pixels may differ before a frame fails to match.
+const MAX_DIFFERING_PIXELS: usize = 200;
+
+/// When every frame is drawn, in UTC, which the tests run in.
+fn now() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2021, 3, 4, 15, 30, 0).unwrap()
+}
+
+fn message(layout: &str) -> DisplayMessage {
+    DisplayMessage {
+        person_is: "at the whiteboard".to_owned(),
+        person_is_timestamp: now() - Duration::minutes(47),
+        person_is_source: "slack".to_owned(),
+        settings: Some(DisplaySettings {
+            layout: Some(layout.to_owned()),
+            ..DisplaySettings::default()
+        }),
+        ..DisplayMessage::default()
+    }
+}
+
+fn draw(msg: DisplayMessage) -> GrayImage {
+    // The clock is drawn in local time.
+    env::set_var("TZ", "UTC");
+
+    let font = concat!(env!("CARGO_MANIFEST_DIR"), "/fonts/DejaVuSans.ttf");
+    let mut renderer = FrameRenderer::new(font, font).unwrap();
+    renderer.set_now(now());
+
+    let mut backend = MemoryBackend::open().unwrap();
+    renderer.draw(msg, &mut backend).unwrap();
+    GrayImage::from_raw(WIDTH, HEIGHT, backend.frame().to_gray(WIDTH, HEIGHT)).unwrap()
+}
+
+fn check(name: &str, actual: GrayImage) {
+    let reference_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(format!("{}.png", name));
+
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        actual.save(&reference_path).unwrap();
+        return;
+    }
+
+    let expected = image::open(&reference_path)
+        .unwrap_or_else(|e| {
+            panic!(
+                "cannot open {}: {}; run with UPDATE_SNAPSHOTS=1 to create it",
+                reference_path.display(),
+                e
+            )
+        })
+        .into_luma8();
+
+    assert_eq!(expected.dimensions(), actual.dimensions());
+
+    // Changed pixels are black in the picture of the differences, and the
+    // rest are a faint copy of the reference.
+    let mut diff = GrayImage::new(WIDTH, HEIGHT);
+    let mut n_differing = 0;
+
+    for (x, y, Luma([e])) in expected.enumerate_pixels() {
+        let Luma([a]) = actual.get_pixel(x, y);
+
+        if e.abs_diff(*a) > PIXEL_TOLERANCE {
+            n_differing += 1;
+            diff.put_pixel(x, y, Luma([0]));
+        } else {
+            diff.put_pixel(x, y, Luma([192 + e / 4]));
+        }
+    }
+
+    if n_differing > MAX_DIFFERING_PIXELS {
+        let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+        let actual_path = dir.join(format!("{}.png", name));
+        let diff_path = dir.join(format!("{}-diff.png", name));
+        actual.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+
+        panic!(
+            "{} pixels of the `{}` frame differ from the reference; see {} and {}",
+            n_differing,
+            name,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+#[test]
+fn standard() {
+    check("standard", draw(message("standard")));
+}
+
+#[test]
+fn standard_busy() {
+    let msg = DisplayMessage {
+        notes_waiting: 3,
+        headlines: vec![
+            "Pairing session at 4".to_owned(),
+            "Lunch is on the roof today".to_owned(),
+        ],
+        ..message("standard")
+    };
+
+    check("standard-busy", draw(msg));
+}
+
+#[test]
+fn status() {
+    check("status", draw(message("status")));
+}
+
+#[test]
+fn long_status() {
+    let msg = DisplayMessage {
+        person_is: "working from home until Thursday; ping me on chat".to_owned(),
+        ..message("standard")
+    };
+
+    check("long-status", draw(msg));
+}
@@ -0,0 +1,164 @@
+//! An updater for desktop machines: global hotkeys that set the status.
+//!
+//! Each hotkey in the configuration is bound to a preset status, so that
+//! changing the sign on the door is a single keypress while sitting at the
+//! desk. This is its own small program, rather than a subcommand of the
+//! displayer, so that it builds on the machines that people actually sit at,
+//! which are less likely to have SPI buses than Raspberry Pis.
+
+use chrono::Utc;
+use livesplit_hotkey::{Hook, Hotkey};
+use rc_stickynote_protocol::{
+    is_person_is_valid, sealed::SealingConfiguration, session::client::AwaitingHello,
+    signing::SigningConfiguration, PersonIsUpdateHelloMessage, HOTKEY_SOURCE,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{Error, ErrorKind},
+    sync::mpsc::channel,
+};
+use structopt::StructOpt;
+use tokio::{net::TcpStream, runtime::Runtime};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct HotkeysConfiguration {
+    hub_host: String,
+    hub_port: u16,
+
+    /// The access token to present when sending updates to the hub, if it
+    /// requires one.
+    #[serde(default)]
+    hub_token: Option<String>,
+
+    /// If set, seal the statuses that we send so that the hub can't read
+    /// them.
+    #[serde(default)]
+    sealing: Option<SealingConfiguration>,
+
+    /// If set, sign the statuses that we send, for hubs that only accept
+    /// signed updates.
+    #[serde(default)]
+    signing: Option<SigningConfiguration>,
+
+    /// The hotkeys and the statuses that they set.
+    #[serde(default)]
+    hotkeys: Vec<HotkeyBinding>,
+}
+
+impl Default for HotkeysConfiguration {
+    fn default() -> Self {
+        HotkeysConfiguration {
+            hub_host: "edit-configuration.example.com".to_owned(),
+            hub_port: 20200,
+            hub_token: None,
+            sealing: None,
+            signing: None,
+            hotkeys: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct HotkeyBinding {
+    /// The key combination, like "Ctrl + Alt + KeyM".
+    keys: Hotkey,
+
+    /// The status that it sets.
+    status: String,
+}
+
+impl HotkeysConfiguration {
+    /// Send the hub a new status.
+    fn send_status(&self, rt: &mut Runtime, status: &str) -> Result<(), Error> {
+        let person_is = match self.sealing {
+            Some(ref s) => s.seal(status)?,
+            None => status.to_owned(),
+        };
+
+        let mut msg = PersonIsUpdateHelloMessage {
+            person_is,
+            timestamp: Utc::now(),
+            source: Some(HOTKEY_SOURCE.to_owned()),
+            set_by: None,
+            token: self.hub_token.clone(),
+            signature: None,
+        };
+
+        if let Some(ref s) = self.signing {
+            s.sign(&mut msg)?;
+        }
+
+        rt.block_on(async {
+            let conn = TcpStream::connect((self.hub_host.as_ref(), self.hub_port)).await?;
+            AwaitingHello::new(conn).send_update(msg).await
+        })
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "rc_stickynote_hotkeys",
+    about = "Set the sticky note status with global hotkeys"
+)]
+struct HotkeysCli {}
+
+impl HotkeysCli {
+    fn cli(self) -> Result<(), Error> {
+        let config: HotkeysConfiguration = confy::load("rc-stickynote-hotkeys")?;
+
+        if config.hotkeys.is_empty() {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "no hotkeys are set up in the configuration file",
+            ));
+        }
+
+        for binding in &config.hotkeys {
+            if !is_person_is_valid(&binding.status) {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("status \"{}\" invalid -- likely too long", binding.status),
+                ));
+            }
+        }
+
+        // The hotkey callbacks run on the hook's own thread, so they just
+        // pass along which binding was pressed, and we talk to the hub here.
+
+        let hook = Hook::new().map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
+        let (sender, receiver) = channel();
+
+        for (i, binding) in config.hotkeys.iter().enumerate() {
+            let sender = sender.clone();
+
+            hook.register(binding.keys, move || {
+                let _ = sender.send(i);
+            })
+            .map_err(|e| {
+                Error::new(
+                    ErrorKind::Other,
+                    format!("cannot register hotkey {}: {}", binding.keys, e),
+                )
+            })?;
+
+            println!("{}: \"{}\"", binding.keys, binding.status);
+        }
+
+        let mut rt = Runtime::new()?;
+
+        for i in receiver {
+            let status = &config.hotkeys[i].status;
+
+            match config.send_status(&mut rt, status) {
+                Ok(()) => println!("set status: \"{}\"", status),
+                Err(e) => println!("failed to set status \"{}\": {}", status, e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn main() -> Result<(), Error> {
+    HotkeysCli::from_args().cli()
+}
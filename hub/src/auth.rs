@@ -0,0 +1,164 @@
+//! Access control based on tokens and roles.
+//!
+//! The server configuration can list tokens, each of which is granted a role.
+//! Roles are ordered: admins can do everything that updaters can, and
+//! updaters can do everything that observers can.
+//!
+//! Tokens can also be issued and revoked from the command line; see the
+//! `tokens` module.
+//!
+//! If the configuration lists no tokens and doesn't say where to keep issued
+//! ones, access control is disabled and everything is allowed, which is how
+//! the hub behaved before roles existed. Whether it's enabled depends only
+//! on the configuration, so revoking the last issued token, or a tokens file
+//! that can't be read, doesn't open everything up.
+
+use hyper::{header, Body, Request};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+use crate::tokens::{hash_token, IssuedToken};
+
+/// What a token holder is allowed to do.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Can look at things, like the stats page.
+    Observer,
+
+    /// Can also set the status and ring the doorbell.
+    Updater,
+
+    /// Can also change locks and manage visitor notes.
+    Admin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Observer => write!(f, "observer"),
+            Role::Updater => write!(f, "updater"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "observer" => Ok(Role::Observer),
+            "updater" => Ok(Role::Updater),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!(
+                "unknown role `{}`; expected observer, updater, or admin",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerTokenConfiguration {
+    /// A name for the token holder, for logging.
+    pub name: String,
+
+    /// The secret token itself.
+    pub token: String,
+
+    pub role: Role,
+
+    /// The sources that the holder may say their updates come from over the
+    /// stickynote protocol, like "hotkey" or "video call". Admins may claim
+    /// any source.
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+/// Someone who presented a valid token.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Holder {
+    pub name: String,
+    pub role: Role,
+    pub sources: Vec<String>,
+}
+
+/// The outcome of checking a request's credentials.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Access {
+    /// The request may proceed. The name identifies the token holder, if
+    /// there was one.
+    Granted(Option<String>),
+
+    /// The request lacks a sufficiently privileged token.
+    Denied,
+}
+
+/// Check whether the given token grants at least the given role, when access
+/// control is enabled. It may be one of the tokens from the configuration file
+/// or one issued from the command line.
+pub fn check_token(
+    tokens: &[ServerTokenConfiguration],
+    issued: &[IssuedToken],
+    token: Option<&str>,
+    needed: Role,
+) -> Access {
+    match token.and_then(|t| find_holder(tokens, issued, t)) {
+        Some(holder) if holder.role >= needed => Access::Granted(Some(holder.name)),
+        _ => Access::Denied,
+    }
+}
+
+/// Find who holds the given token, if it's one of the tokens from the
+/// configuration file or one issued from the command line.
+pub fn find_holder(
+    tokens: &[ServerTokenConfiguration],
+    issued: &[IssuedToken],
+    token: &str,
+) -> Option<Holder> {
+    if let Some(t) = tokens.iter().find(|t| tokens_match(&t.token, token)) {
+        return Some(Holder {
+            name: t.name.clone(),
+            role: t.role,
+            sources: t.sources.clone(),
+        });
+    }
+
+    let hash = hash_token(token);
+
+    issued
+        .iter()
+        .find(|t| tokens_match(&t.hash, &hash))
+        .map(|t| Holder {
+            name: t.name.clone(),
+            role: t.role,
+            sources: t.sources.clone(),
+        })
+}
+
+/// Compare a secret with what someone presented, taking as long whichever
+/// byte they differ in, so that the secret can't be guessed a byte at a time.
+pub fn tokens_match(secret: &str, presented: &str) -> bool {
+    let (a, b) = (secret.as_bytes(), presented.as_bytes());
+
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Get the token from an HTTP request. It can be given as a bearer token in
+/// the `Authorization` header or as a `token` query parameter.
+pub fn request_token(req: &Request<Body>) -> Option<String> {
+    if let Some(value) = req.headers().get(header::AUTHORIZATION) {
+        if let Ok(value) = value.to_str() {
+            if let Some(token) = value.strip_prefix("Bearer ") {
+                return Some(token.trim().to_owned());
+            }
+        }
+    }
+
+    let query = req.uri().query().unwrap_or("");
+
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(name, _)| name == "token")
+        .map(|(_, value)| value.into_owned())
+}
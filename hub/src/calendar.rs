@@ -0,0 +1,525 @@
+//! Calendar integration: automatic vacation mode, and who's in the office.
+//!
+//! We periodically fetch an iCalendar feed and look for all-day "out of
+//! office" events. While one is happening, the hub shows a vacation status
+//! and locks out lower-priority update sources. When it ends, the status
+//! from before the vacation is restored, and the lock is released, if it's
+//! still the calendar's. If the hub is restarted during a vacation, the
+//! status to restore is found in the history.
+//!
+//! Separately, for teams that use the panel as a hybrid-work board, we can
+//! fetch teammates' feeds and look for all-day "in office" events, so that
+//! the panels' "week" layout can show who plans to be in on which days.
+//!
+//! The iCalendar parsing here is deliberately minimal: we only care about
+//! the start, end, and summary of all-day events.
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone, Utc};
+use futures::{prelude::*, select};
+use rc_stickynote_protocol::{is_person_is_valid, OfficeDays, PersonIsUpdateHelloMessage};
+use serde::Deserialize;
+use tokio::time::{self, Duration as TokioDuration};
+
+use crate::{
+    history::{History, HistoryEvent},
+    http_client,
+    updates::UpdateHub,
+    DisplayStateMutation, GenericError, SourceLock,
+};
+
+/// The source name attached to updates made by the calendar integration.
+pub const SOURCE: &str = "calendar";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerCalendarConfiguration {
+    /// The URL of an iCalendar (.ics) feed to monitor.
+    ics_url: String,
+
+    /// How often to check the feed.
+    #[serde(default = "default_poll_minutes")]
+    poll_minutes: u64,
+
+    /// All-day events whose summary contains this text (case-insensitively)
+    /// trigger vacation mode.
+    #[serde(default = "default_ooo_summary")]
+    ooo_summary: String,
+
+    /// The status to show during vacation mode. `{date}` is replaced with the
+    /// last day of the vacation. If the result is too long to be a valid
+    /// status, "On vacation" is used instead.
+    #[serde(default = "default_vacation_template")]
+    vacation_template: String,
+}
+
+fn default_poll_minutes() -> u64 {
+    15
+}
+
+fn default_ooo_summary() -> String {
+    "out of office".to_owned()
+}
+
+fn default_vacation_template() -> String {
+    "Vacation until {date}".to_owned()
+}
+
+/// An all-day calendar event. `end` is exclusive, as in iCalendar.
+#[derive(Clone, Debug)]
+struct AllDayEvent {
+    start: NaiveDate,
+    end: NaiveDate,
+    summary: String,
+}
+
+/// Undo iCalendar line folding: lines beginning with whitespace are
+/// continuations of the previous line.
+fn unfold_lines(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in ics.lines() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(&line[1..]);
+            }
+        } else {
+            lines.push(line.to_owned());
+        }
+    }
+
+    lines
+}
+
+/// Extract the all-day events from iCalendar text.
+fn parse_all_day_events(ics: &str) -> Vec<AllDayEvent> {
+    let lines = unfold_lines(ics);
+    let mut events = Vec::new();
+    let mut in_event = false;
+    let mut start = None;
+    let mut end = None;
+    let mut summary = String::new();
+
+    for line in &lines {
+        let (name, value) = match line.find(':') {
+            Some(i) => (&line[..i], &line[i + 1..]),
+            None => continue,
+        };
+
+        // Property parameters, like `;VALUE=DATE`, come before the colon.
+        let mut params = name.split(';');
+        let prop = params.next().unwrap_or("").to_uppercase();
+        let is_date = params.any(|p| p.eq_ignore_ascii_case("VALUE=DATE")) || value.len() == 8;
+
+        match (prop.as_str(), value) {
+            ("BEGIN", "VEVENT") => {
+                in_event = true;
+                start = None;
+                end = None;
+                summary.clear();
+            }
+
+            ("END", "VEVENT") => {
+                in_event = false;
+
+                if let Some(start) = start {
+                    events.push(AllDayEvent {
+                        start,
+                        end: end.unwrap_or_else(|| start + Duration::days(1)),
+                        summary: summary.clone(),
+                    });
+                }
+            }
+
+            ("DTSTART", v) if in_event && is_date => {
+                start = NaiveDate::parse_from_str(v.trim(), "%Y%m%d").ok();
+            }
+
+            ("DTEND", v) if in_event && is_date => {
+                end = NaiveDate::parse_from_str(v.trim(), "%Y%m%d").ok();
+            }
+
+            ("SUMMARY", v) if in_event => {
+                summary = v.replace("\\,", ",").replace("\\;", ";");
+            }
+
+            _ => {}
+        }
+    }
+
+    events
+}
+
+/// An event with start and end times, like a meeting.
+#[derive(Clone, Debug)]
+pub struct TimedEvent {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub summary: String,
+}
+
+/// Parse an iCalendar date-time. Times in UTC end with "Z"; others are taken
+/// to be in the hub's timezone, even if they name another one with a `TZID`
+/// parameter, which is right for rooms in the same building as the hub.
+fn parse_date_time(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+
+    match value.strip_suffix('Z') {
+        Some(utc) => chrono::NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|t| Utc.from_utc_datetime(&t)),
+
+        None => chrono::NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+            .ok()
+            .and_then(|t| Local.from_local_datetime(&t).earliest())
+            .map(|t| t.with_timezone(&Utc)),
+    }
+}
+
+/// Extract the events with start and end times from iCalendar text, skipping
+/// cancelled ones. The summaries of private events are left empty.
+pub fn parse_timed_events(ics: &str) -> Vec<TimedEvent> {
+    let mut events = Vec::new();
+    let mut in_event = false;
+    let mut start = None;
+    let mut end = None;
+    let mut summary = String::new();
+    let mut private = false;
+    let mut cancelled = false;
+
+    for line in &unfold_lines(ics) {
+        let (name, value) = match line.find(':') {
+            Some(i) => (&line[..i], &line[i + 1..]),
+            None => continue,
+        };
+
+        let prop = name.split(';').next().unwrap_or("").to_uppercase();
+
+        match (prop.as_str(), value) {
+            ("BEGIN", "VEVENT") => {
+                in_event = true;
+                start = None;
+                end = None;
+                summary.clear();
+                private = false;
+                cancelled = false;
+            }
+
+            ("END", "VEVENT") => {
+                in_event = false;
+
+                if let (Some(start), Some(end), false) = (start, end, cancelled) {
+                    events.push(TimedEvent {
+                        start,
+                        end,
+                        summary: if private {
+                            String::new()
+                        } else {
+                            summary.clone()
+                        },
+                    });
+                }
+            }
+
+            ("DTSTART", v) if in_event => start = parse_date_time(v),
+
+            ("DTEND", v) if in_event => end = parse_date_time(v),
+
+            ("SUMMARY", v) if in_event => {
+                summary = v.replace("\\,", ",").replace("\\;", ";");
+            }
+
+            ("CLASS", v) if in_event => {
+                private = !v.trim().eq_ignore_ascii_case("PUBLIC");
+            }
+
+            ("STATUS", v) if in_event => {
+                cancelled = v.trim().eq_ignore_ascii_case("CANCELLED");
+            }
+
+            _ => {}
+        }
+    }
+
+    events
+}
+
+impl ServerCalendarConfiguration {
+    async fn fetch_events(&self) -> Result<Vec<AllDayEvent>, GenericError> {
+        let text = http_client::fetch_text(&self.ics_url).await?;
+        Ok(parse_all_day_events(&text))
+    }
+
+    /// If the given day falls within an out-of-office event, return the
+    /// last day of that event.
+    fn vacation_last_day(&self, events: &[AllDayEvent], today: NaiveDate) -> Option<NaiveDate> {
+        let pattern = self.ooo_summary.to_lowercase();
+
+        events
+            .iter()
+            .filter(|e| e.summary.to_lowercase().contains(&pattern))
+            .filter(|e| e.start <= today && today < e.end)
+            .map(|e| e.end - Duration::days(1))
+            .max()
+    }
+
+    fn vacation_status(&self, last_day: NaiveDate) -> String {
+        let status = self
+            .vacation_template
+            .replace("{date}", &last_day.format("%b %-d").to_string());
+
+        if is_person_is_valid(&status) {
+            status
+        } else {
+            "On vacation".to_owned()
+        }
+    }
+}
+
+/// The moment that a vacation ending on the given (local) day is over.
+fn end_of_day(last_day: NaiveDate) -> DateTime<Utc> {
+    let midnight = (last_day + Duration::days(1))
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time");
+
+    match Local.from_local_datetime(&midnight).earliest() {
+        Some(t) => t.with_timezone(&Utc),
+        None => Utc.from_utc_datetime(&midnight),
+    }
+}
+
+/// If the latest status in the history is the calendar's, the status from
+/// before it, which is the one to restore once the vacation is over.
+fn status_before_vacation(history: &History) -> Option<PersonIsUpdateHelloMessage> {
+    let events = match history.load() {
+        Ok(e) => e,
+        Err(e) => {
+            log!("calendar: error reading the history: {}", e);
+            return None;
+        }
+    };
+
+    let mut before = None;
+    let mut latest_is_ours = false;
+
+    for event in events {
+        if let HistoryEvent::StatusUpdate {
+            timestamp,
+            person_is,
+            source,
+            set_by,
+            ..
+        } = event
+        {
+            latest_is_ours = source.as_deref() == Some(SOURCE);
+
+            if !latest_is_ours {
+                before = Some(PersonIsUpdateHelloMessage {
+                    person_is,
+                    timestamp,
+                    source,
+                    set_by,
+                    token: None,
+                    signature: None,
+                });
+            }
+        }
+    }
+
+    before.filter(|_| latest_is_ours)
+}
+
+/// Monitor the calendar forever, entering and leaving vacation mode as
+/// needed.
+pub async fn run(config: ServerCalendarConfiguration, send_updates: UpdateHub, history: History) {
+    let mut receive_updates = send_updates.subscribe();
+    let mut state = send_updates.current();
+    let mut interval = time::interval(TokioDuration::from_secs(config.poll_minutes.max(1) * 60));
+
+    // While on vacation: the last day, and the status to restore afterwards.
+    let mut vacation: Option<(NaiveDate, PersonIsUpdateHelloMessage)> = None;
+
+    loop {
+        select! {
+            _ = interval.tick().fuse() => {},
+
+            maybe_update = receive_updates.next().fuse() => {
+                match maybe_update {
+                    Some(s) => {
+                        state = s;
+                    },
+
+                    None => {
+                        log!("calendar receive_updates ran out??");
+                    },
+                }
+
+                continue;
+            },
+        }
+
+        let events = match config.fetch_events().await {
+            Ok(e) => e,
+            Err(e) => {
+                log!("error fetching calendar: {}", e);
+                continue;
+            }
+        };
+
+        let today = Local::now().naive_local().date();
+        let last_day = config.vacation_last_day(&events, today);
+        let mut mutations = Vec::new();
+
+        match (last_day, vacation.take()) {
+            (Some(last_day), prior) => {
+                let already = prior.as_ref().map(|p| p.0) == Some(last_day);
+
+                // If we don't know what came before, we might have just been
+                // restarted partway through the vacation, in which case the
+                // history says.
+                let prior = prior
+                    .map(|p| p.1)
+                    .or_else(|| status_before_vacation(&history))
+                    .unwrap_or_else(|| PersonIsUpdateHelloMessage {
+                        person_is: state.display.person_is.clone(),
+                        timestamp: state.display.person_is_timestamp,
+                        source: Some(state.display.person_is_source.clone())
+                            .filter(|s| !s.is_empty()),
+                        set_by: Some(state.display.person_is_set_by.clone())
+                            .filter(|s| !s.is_empty()),
+                        token: None,
+                        signature: None,
+                    });
+
+                if !already {
+                    log!("calendar: on vacation through {}", last_day);
+
+                    mutations.push(DisplayStateMutation::SetPersonIs(
+                        PersonIsUpdateHelloMessage {
+                            person_is: config.vacation_status(last_day),
+                            timestamp: Utc::now(),
+                            source: Some(SOURCE.to_owned()),
+                            set_by: None,
+                            token: None,
+                            signature: None,
+                        },
+                    ));
+
+                    mutations.push(DisplayStateMutation::SetLock(Some(SourceLock {
+                        source: SOURCE.to_owned(),
+                        until: end_of_day(last_day),
+                    })));
+                }
+
+                vacation = Some((last_day, prior));
+            }
+
+            (None, Some((_, prior))) => {
+                log!("calendar: vacation is over");
+
+                // Only release the lock if it's still ours, and only restore
+                // the prior status if nobody has overridden the vacation
+                // status in the meantime.
+                if state.lock.as_ref().map(|l| l.source.as_str()) == Some(SOURCE) {
+                    mutations.push(DisplayStateMutation::SetLock(None));
+                }
+
+                if state.display.person_is_source == SOURCE {
+                    mutations.push(DisplayStateMutation::SetPersonIs(prior));
+                }
+            }
+
+            (None, None) => {}
+        }
+
+        for mutation in mutations {
+            send_updates.send(mutation);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerTeamConfiguration {
+    /// How often to check the feeds.
+    #[serde(default = "default_poll_minutes")]
+    poll_minutes: u64,
+
+    /// All-day events whose summary contains this text (case-insensitively)
+    /// mark days in the office.
+    #[serde(default = "default_office_summary")]
+    office_summary: String,
+
+    /// The people to show, in order.
+    members: Vec<TeamMemberConfiguration>,
+}
+
+fn default_office_summary() -> String {
+    "in office".to_owned()
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct TeamMemberConfiguration {
+    /// The name to show on the panel.
+    name: String,
+
+    /// The URL of the person's iCalendar (.ics) feed.
+    ics_url: String,
+}
+
+impl ServerTeamConfiguration {
+    /// The days of the week starting on `monday` that fall within an
+    /// in-office event.
+    fn office_days(&self, events: &[AllDayEvent], monday: NaiveDate) -> Vec<NaiveDate> {
+        let pattern = self.office_summary.to_lowercase();
+
+        (0..7)
+            .map(|i| monday + Duration::days(i))
+            .filter(|day| {
+                events.iter().any(|e| {
+                    e.summary.to_lowercase().contains(&pattern) && e.start <= *day && *day < e.end
+                })
+            })
+            .collect()
+    }
+}
+
+/// Poll the team's calendars forever, sending the hub who's in the office
+/// on which days of the current week. If a feed can't be fetched, what we
+/// last knew about that person stays up, unless the week has changed since.
+pub async fn run_team(config: ServerTeamConfiguration, send_updates: UpdateHub) {
+    let mut interval = time::interval(TokioDuration::from_secs(config.poll_minutes.max(1) * 60));
+    let mut days: Vec<Vec<NaiveDate>> = vec![Vec::new(); config.members.len()];
+    let mut last_office_days = None;
+
+    loop {
+        interval.tick().await;
+
+        let today = Local::now().naive_local().date();
+        let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+
+        for (i, member) in config.members.iter().enumerate() {
+            match http_client::fetch_text(&member.ics_url).await {
+                Ok(text) => days[i] = config.office_days(&parse_all_day_events(&text), monday),
+
+                Err(e) => {
+                    log!("error fetching calendar for {}: {}", member.name, e);
+                    days[i].retain(|d| *d >= monday);
+                }
+            }
+        }
+
+        let office_days: Vec<OfficeDays> = config
+            .members
+            .iter()
+            .zip(&days)
+            .map(|(member, days)| OfficeDays {
+                name: member.name.clone(),
+                days: days.clone(),
+            })
+            .collect();
+
+        if last_office_days.as_ref() != Some(&office_days) {
+            last_office_days = Some(office_days.clone());
+
+            send_updates.send(DisplayStateMutation::SetOfficeDays(office_days));
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! The commands that the chat integrations understand.
+//!
+//! Slack and Twitter direct messages take the same little language, so that
+//! it only has to be learned once, and so that a new integration only has to
+//! get the text in and the reply out. Anything that isn't a command is taken
+//! to be a new status, since that's what's wanted most of the time. That
+//! includes a status that starts with a command word but doesn't go on like
+//! the command, such as "lock the door"; one that's more ambiguous, like "lock
+//! for good", can be set with "status lock for good".
+
+use chrono::{DateTime, Local, Utc};
+
+use crate::phrases;
+
+/// What the commands are, for `help`.
+pub const HELP: &str = "\
+You can say:
+• `status in the lab`, or just `in the lab`: set the status. If it ends with \
+how long it lasts, like `lunch, 45 min` or `back at 3`, the previous status \
+goes back up after that.
+• `what does it say?`: show the status.
+• `undo`: put the previous status back.
+• `lock for 2h`: ignore less important updates, like the calendar's, for a while.
+• `unlock`: stop ignoring them.
+• `schedule 9am 'in the lab'`: put up a status later on.
+• `notes`: show the notes that visitors have left.
+• `clear notes`: throw them away.
+• `help`: show this list.";
+
+/// What a chat message asks for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChatCommand {
+    /// Say what the status is.
+    Show,
+
+    /// Say what the commands are.
+    Help,
+
+    /// Put the previous status back.
+    Undo,
+
+    /// Set a new status.
+    Set(String),
+
+    /// Hold off updates from less important sources for this many minutes.
+    Lock(i64),
+
+    /// End a lock.
+    Unlock,
+
+    /// Queue up a status to go up later.
+    Schedule { at: DateTime<Utc>, status: String },
+
+    /// List the notes that visitors have left.
+    Notes,
+
+    /// Throw away the visitors' notes.
+    ClearNotes,
+}
+
+/// The ways of asking what the status is. Punctuation at the end is ignored.
+const SHOW_PHRASES: &[&str] = &[
+    "",
+    "show",
+    "get",
+    "status",
+    "what does it say",
+    "what does the sign say",
+    "what's it say",
+    "whats it say",
+];
+
+impl ChatCommand {
+    /// Work out what a message asks for. Times are taken relative to `now`.
+    /// If the message looks like a command but doesn't make sense, the error
+    /// says why, in a form that can be sent back as the reply.
+    pub fn parse(text: &str, now: DateTime<Local>) -> Result<Self, String> {
+        let text = text.trim();
+        let normalized = text
+            .trim_end_matches(['?', '!', '.'])
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+
+        if SHOW_PHRASES.contains(&normalized.as_str()) {
+            return Ok(ChatCommand::Show);
+        }
+
+        match normalized.as_str() {
+            "help" | "?" => return Ok(ChatCommand::Help),
+            "undo" => return Ok(ChatCommand::Undo),
+            "unlock" => return Ok(ChatCommand::Unlock),
+            "notes" | "show notes" => return Ok(ChatCommand::Notes),
+            "clear notes" | "notes clear" => return Ok(ChatCommand::ClearNotes),
+            _ => {}
+        }
+
+        let (keyword, rest) = match text.split_once(char::is_whitespace) {
+            Some((k, r)) => (k.to_lowercase(), r.trim()),
+            None => (text.to_lowercase(), ""),
+        };
+
+        match keyword.as_str() {
+            "set" if rest.is_empty() => Err("Say what to set the status to.".to_owned()),
+            "status" | "set" => Ok(ChatCommand::Set(unquote(rest).to_owned())),
+            "lock" => parse_lock(rest).or_else(|e| {
+                if rest.starts_with(|c: char| c.is_ascii_digit()) || first_word_is(rest, "for") {
+                    Err(e)
+                } else {
+                    Ok(ChatCommand::Set(text.to_owned()))
+                }
+            }),
+            "schedule" => parse_schedule(rest, now).or_else(|e| {
+                if rest.starts_with(|c: char| c.is_ascii_digit()) {
+                    Err(e)
+                } else {
+                    Ok(ChatCommand::Set(text.to_owned()))
+                }
+            }),
+            _ => Ok(ChatCommand::Set(text.to_owned())),
+        }
+    }
+}
+
+fn first_word_is(text: &str, word: &str) -> bool {
+    text.split_whitespace()
+        .next()
+        .map(|w| w.eq_ignore_ascii_case(word))
+        .unwrap_or(false)
+}
+
+/// "lock for 2h", "lock 90 minutes", and so on.
+fn parse_lock(rest: &str) -> Result<ChatCommand, String> {
+    let length = match rest.split_once(char::is_whitespace) {
+        Some((f, length)) if f.eq_ignore_ascii_case("for") => length,
+        _ => rest,
+    };
+
+    phrases::duration_minutes(length)
+        .map(ChatCommand::Lock)
+        .ok_or_else(|| {
+            "Say how long to lock for, like `lock for 2h`. To set a status that starts with \
+             \"lock\", put `status` in front."
+                .to_owned()
+        })
+}
+
+/// "schedule 9am 'in the lab'", "schedule 2:30 pm seminar", and so on.
+fn parse_schedule(rest: &str, now: DateTime<Local>) -> Result<ChatCommand, String> {
+    let problem = || {
+        "Say when and what, like `schedule 9am 'in the lab'`. To set a status that starts \
+         with \"schedule\", put `status` in front."
+            .to_owned()
+    };
+
+    let words: Vec<&str> = rest.split_whitespace().collect();
+
+    // The time can be split in two, like "9 am".
+    for n in &[2, 1] {
+        if words.len() <= *n {
+            continue;
+        }
+
+        if let Some(at) = phrases::next_time(&words[..*n].concat(), now) {
+            let status = unquote(&words[*n..].join(" ")).to_owned();
+
+            if status.is_empty() {
+                return Err(problem());
+            }
+
+            return Ok(ChatCommand::Schedule {
+                at: at.with_timezone(&Utc),
+                status,
+            });
+        }
+    }
+
+    Err(problem())
+}
+
+/// Take the quotes off of a status, if it has them. Chat apps like to turn
+/// straight quotes into curly ones.
+fn unquote(text: &str) -> &str {
+    for (open, close) in &[('\'', '\''), ('"', '"'), ('‘', '’'), ('“', '”')] {
+        if let Some(inner) = text
+            .strip_prefix(*open)
+            .and_then(|t| t.strip_suffix(*close))
+        {
+            return inner.trim();
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Monday, 12 October 2026, at 10:00.
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 10, 12, 10, 0, 0).unwrap()
+    }
+
+    fn parse(text: &str) -> Result<ChatCommand, String> {
+        ChatCommand::parse(text, now())
+    }
+
+    fn set(status: &str) -> Result<ChatCommand, String> {
+        Ok(ChatCommand::Set(status.to_owned()))
+    }
+
+    #[test]
+    fn simple_commands() {
+        assert_eq!(parse("What does it say?"), Ok(ChatCommand::Show));
+        assert_eq!(parse("  "), Ok(ChatCommand::Show));
+        assert_eq!(parse("HELP"), Ok(ChatCommand::Help));
+        assert_eq!(parse("undo."), Ok(ChatCommand::Undo));
+        assert_eq!(parse("unlock"), Ok(ChatCommand::Unlock));
+        assert_eq!(parse("Notes?"), Ok(ChatCommand::Notes));
+        assert_eq!(parse("clear  notes"), Ok(ChatCommand::ClearNotes));
+        assert_eq!(parse("notes on the door"), set("notes on the door"));
+    }
+
+    #[test]
+    fn statuses() {
+        assert_eq!(parse("in the lab"), set("in the lab"));
+        assert_eq!(parse("status 'in the lab'"), set("in the lab"));
+        assert_eq!(parse("set “in the lab”"), set("in the lab"));
+        assert!(parse("set").is_err());
+
+        // A command word that isn't followed by what the command takes
+        // starts a status, but one that looks like a botched command doesn't.
+        assert_eq!(parse("lock the door"), set("lock the door"));
+        assert_eq!(parse("schedule a meeting"), set("schedule a meeting"));
+        assert!(parse("lock for good").is_err());
+        assert_eq!(parse("status lock for good"), set("lock for good"));
+    }
+
+    #[test]
+    fn lock() {
+        assert_eq!(parse("lock for 2h"), Ok(ChatCommand::Lock(120)));
+        assert_eq!(parse("Lock 90 minutes"), Ok(ChatCommand::Lock(90)));
+        assert!(parse("lock for 99999999999h").is_err());
+    }
+
+    #[test]
+    fn schedule() {
+        let at = |hour, minute| {
+            Local
+                .with_ymd_and_hms(2026, 10, 12, hour, minute, 0)
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+
+        assert_eq!(
+            parse("schedule 2:30 pm ‘seminar’"),
+            Ok(ChatCommand::Schedule {
+                at: at(14, 30),
+                status: "seminar".to_owned(),
+            })
+        );
+
+        assert_eq!(
+            parse("schedule 11am in the lab"),
+            Ok(ChatCommand::Schedule {
+                at: at(11, 0),
+                status: "in the lab".to_owned(),
+            })
+        );
+
+        assert!(parse("schedule 9am").is_err());
+        assert!(parse("schedule 25:00 'in the lab'").is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! CI results from GitHub and GitLab.
+//!
+//! The panel hangs next to the build machine, so it might as well say whether
+//! the build is passing. GitHub and GitLab can both be set up to POST an
+//! event to `/webhooks/ci` when a run finishes. If the run is on one of the
+//! branches in the configuration, the panels are told whether it passed.
+//!
+//! GitHub signs its requests with the webhook secret, in an
+//! `X-Hub-Signature-256` header of the form `sha256=<hex>`, while GitLab just
+//! sends the secret itself in an `X-Gitlab-Token` header. We accept GitHub's
+//! `workflow_run` and `check_suite` events, and GitLab's pipeline events.
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use hyper::HeaderMap;
+use rc_stickynote_protocol::CiStatus;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::Sha256;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerCiConfiguration {
+    /// The secret that the webhooks are set up with.
+    secret: String,
+
+    /// The branches whose results should be shown.
+    branches: Vec<CiBranch>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct CiBranch {
+    /// The repository, like "pkgw/rc-stickynote".
+    repo: String,
+
+    /// The branch, like "main".
+    branch: String,
+
+    /// What to call the branch on the panel. Defaults to the branch name.
+    #[serde(default)]
+    label: Option<String>,
+}
+
+/// A finished run, as described by a webhook event.
+struct CiRun<'a> {
+    repo: &'a str,
+    branch: &'a str,
+    passed: bool,
+}
+
+impl ServerCiConfiguration {
+    /// Get the result that a webhook request tells us about. Returns an error
+    /// if the request didn't come from GitHub or GitLab, and `None` if it's
+    /// about something that we don't show.
+    pub fn status_from_webhook(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<Option<CiStatus>, String> {
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+
+        let event: Value =
+            serde_json::from_slice(body).map_err(|e| format!("unparseable event: {}", e))?;
+
+        let run = if let Some(kind) = header("x-github-event") {
+            let signature = header("x-hub-signature-256").ok_or("no signature header")?;
+            self.verify_github_signature(signature, body)?;
+            github_run(kind, &event)
+        } else if let Some(kind) = header("x-gitlab-event") {
+            if header("x-gitlab-token") != Some(self.secret.as_str()) {
+                return Err("token mismatch".to_owned());
+            }
+
+            gitlab_run(kind, &event)
+        } else {
+            return Err("not a GitHub or GitLab event".to_owned());
+        };
+
+        let run = match run {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+
+        // The event arrives as soon as the run finishes, so the time that we
+        // get it is a fine stand-in for when that happened, and saves us from
+        // parsing two different date formats.
+        Ok(self
+            .branches
+            .iter()
+            .find(|b| b.repo == run.repo && b.branch == run.branch)
+            .map(|b| CiStatus {
+                label: b.label.clone().unwrap_or_else(|| b.branch.clone()),
+                passed: run.passed,
+                finished: Utc::now(),
+            }))
+    }
+
+    fn verify_github_signature(&self, signature: &str, body: &[u8]) -> Result<(), String> {
+        let code = signature
+            .strip_prefix("sha256=")
+            .and_then(decode_hex)
+            .ok_or("malformed signature")?;
+
+        let mut mac = Hmac::<Sha256>::new_varkey(self.secret.as_bytes()).expect("uhoh");
+        mac.input(body);
+        mac.verify(&code)
+            .map_err(|_| "signature mismatch".to_owned())
+    }
+}
+
+pub(crate) fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [hi, lo] => u8::from_str_radix(std::str::from_utf8(&[*hi, *lo]).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Runs that end some other way, like being cancelled, don't tell us
+/// anything about the branch, so they're left out.
+fn github_run<'a>(kind: &str, event: &'a Value) -> Option<CiRun<'a>> {
+    if kind != "workflow_run" && kind != "check_suite" {
+        return None;
+    }
+
+    if event["action"] != "completed" {
+        return None;
+    }
+
+    let run = &event[kind];
+
+    let passed = match run["conclusion"].as_str()? {
+        "success" => true,
+        "failure" | "timed_out" => false,
+        _ => return None,
+    };
+
+    Some(CiRun {
+        repo: event["repository"]["full_name"].as_str()?,
+        branch: run["head_branch"].as_str()?,
+        passed,
+    })
+}
+
+fn gitlab_run<'a>(kind: &str, event: &'a Value) -> Option<CiRun<'a>> {
+    if kind != "Pipeline Hook" {
+        return None;
+    }
+
+    let pipeline = &event["object_attributes"];
+
+    let passed = match pipeline["status"].as_str()? {
+        "success" => true,
+        "failed" => false,
+        _ => return None,
+    };
+
+    Some(CiRun {
+        repo: event["project"]["path_with_namespace"].as_str()?,
+        branch: pipeline["ref"].as_str()?,
+        passed,
+    })
+}
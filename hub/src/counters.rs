@@ -0,0 +1,118 @@
+//! Counters, for signs like "12 days since the last incident".
+//!
+//! Each counter has a short name, used to refer to it in the API and on the
+//! command line, and a label to show on the panel next to its value.
+//! Counters are created, incremented, and reset through the running hub, so
+//! that the panels hear about the change right away, and every change goes
+//! into the history log. The counters themselves are kept in a file, so that
+//! they survive a restart of the hub. Nothing increments a counter on its
+//! own, so a "days since" sign needs something like a daily cron job that
+//! calls the increment API.
+
+use rc_stickynote_protocol::Counter;
+use serde::Deserialize;
+use std::{
+    io::{Error, ErrorKind},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+/// The longest counter label that we'll accept, in characters.
+pub const MAX_LABEL_LENGTH: usize = 40;
+
+/// Held while changing the counters, so that simultaneous changes don't
+/// overwrite each other.
+static CHANGING: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerCountersConfiguration {
+    /// Where to store the counters.
+    pub path: PathBuf,
+}
+
+/// A handle to the stored counters.
+#[derive(Clone, Debug)]
+pub struct CounterStore {
+    path: PathBuf,
+}
+
+impl CounterStore {
+    pub fn new(config: &ServerCountersConfiguration) -> Self {
+        CounterStore {
+            path: config.path.clone(),
+        }
+    }
+
+    /// Read all of the counters, in the order that they were created.
+    pub fn load(&self) -> Result<Vec<Counter>, Error> {
+        match std::fs::read(&self.path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&self, counters: &[Counter]) -> Result<(), Error> {
+        std::fs::write(&self.path, serde_json::to_string_pretty(counters)?)
+    }
+
+    /// Change the named counter and save the result, returning the counter's
+    /// new state.
+    fn modify<F: FnOnce(&mut Counter)>(&self, name: &str, f: F) -> Result<Counter, Error> {
+        let _guard = CHANGING.lock().unwrap();
+        let mut counters = self.load()?;
+
+        let counter = counters
+            .iter_mut()
+            .find(|c| c.name == name)
+            .ok_or_else(|| {
+                Error::new(ErrorKind::NotFound, format!("no counter named `{}`", name))
+            })?;
+
+        f(counter);
+        let counter = counter.clone();
+        self.save(&counters)?;
+        Ok(counter)
+    }
+
+    /// Add a new counter, starting at zero.
+    pub fn create(&self, name: &str, label: &str) -> Result<Counter, Error> {
+        if name.is_empty() {
+            return Err(Error::new(ErrorKind::Other, "counter name is empty"));
+        }
+
+        if label.chars().count() > MAX_LABEL_LENGTH {
+            return Err(Error::new(ErrorKind::Other, "counter label is too long"));
+        }
+
+        let _guard = CHANGING.lock().unwrap();
+        let mut counters = self.load()?;
+
+        if counters.iter().any(|c| c.name == name) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("there is already a counter named `{}`", name),
+            ));
+        }
+
+        let counter = Counter {
+            name: name.to_owned(),
+            label: label.to_owned(),
+            value: 0,
+        };
+
+        counters.push(counter.clone());
+        self.save(&counters)?;
+        Ok(counter)
+    }
+
+    /// Add to the named counter.
+    pub fn increment(&self, name: &str, by: i64) -> Result<Counter, Error> {
+        self.modify(name, |c| c.value = c.value.saturating_add(by))
+    }
+
+    /// Set the named counter back to zero.
+    pub fn reset(&self, name: &str) -> Result<Counter, Error> {
+        self.modify(name, |c| c.value = 0)
+    }
+}
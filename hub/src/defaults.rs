@@ -0,0 +1,149 @@
+//! Default statuses.
+//!
+//! When nobody has said where the person is, because the hub has just
+//! started or a temporary status has run out, the panels show a default.
+//! Out of the box that's "whereabouts unknown", but a better guess usually
+//! depends on the time: "somewhere in the building" during working hours,
+//! say, and "probably home" outside them. The configuration can give a
+//! weekly table of such guesses, and the hub keeps a default status in step
+//! with it as the windows come and go. Optionally, a status that hasn't been
+//! updated for long enough is considered stale and replaced by the default
+//! too.
+
+use chrono::{DateTime, Datelike, Duration, Local, Utc};
+use rc_stickynote_protocol::{is_person_is_valid, PersonIsUpdateHelloMessage, UNKNOWN_PERSON_IS};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use tokio::time::{self, Duration as TokioDuration};
+
+use crate::{
+    hours::{ClockTime, Day},
+    updates::UpdateHub,
+    DisplayStateMutation,
+};
+
+/// The source name attached to default statuses.
+pub const SOURCE: &str = "default";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerDefaultStatusConfiguration {
+    /// The default outside all of the windows.
+    #[serde(default = "default_status")]
+    status: Status,
+
+    /// The weekly table of defaults, in the hub's local time. If windows
+    /// overlap, the first one listed wins.
+    #[serde(default)]
+    windows: Vec<DefaultStatusWindow>,
+
+    /// If set, a status that hasn't been updated for this many hours is
+    /// replaced by the default.
+    #[serde(default)]
+    stale_hours: Option<u32>,
+}
+
+fn default_status() -> Status {
+    Status(UNKNOWN_PERSON_IS.to_owned())
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct DefaultStatusWindow {
+    /// The days of the week that the window applies to. If empty, it applies
+    /// every day.
+    #[serde(default)]
+    days: Vec<Day>,
+
+    /// When the window starts.
+    start: ClockTime,
+
+    /// When the window ends, which must be later in the day than the start.
+    end: ClockTime,
+
+    /// The default status during the window.
+    status: Status,
+}
+
+/// A status message, checked to be short enough to show.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "String")]
+struct Status(String);
+
+impl TryFrom<String> for Status {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        if is_person_is_valid(&text) {
+            Ok(Status(text))
+        } else {
+            Err(format!("status \"{}\" invalid -- likely too long", text))
+        }
+    }
+}
+
+impl ServerDefaultStatusConfiguration {
+    /// The default status at the given time.
+    pub fn status_at(&self, t: DateTime<Local>) -> &str {
+        let (day, time) = (t.weekday(), t.time());
+
+        self.windows
+            .iter()
+            .find(|w| {
+                (w.days.is_empty() || w.days.iter().any(|d| d.0 == day))
+                    && w.start.0 <= time
+                    && time < w.end.0
+            })
+            .map(|w| &w.status.0)
+            .unwrap_or(&self.status.0)
+    }
+}
+
+/// The status update that puts up the default for the given time. With no
+/// configuration, that's "whereabouts unknown".
+pub fn update_at(
+    config: Option<&ServerDefaultStatusConfiguration>,
+    t: DateTime<Utc>,
+) -> PersonIsUpdateHelloMessage {
+    let person_is = match config {
+        Some(c) => c.status_at(t.with_timezone(&Local)).to_owned(),
+        None => UNKNOWN_PERSON_IS.to_owned(),
+    };
+
+    PersonIsUpdateHelloMessage {
+        person_is,
+        timestamp: t,
+        source: Some(SOURCE.to_owned()),
+        set_by: None,
+        token: None,
+        signature: None,
+    }
+}
+
+/// Keep the default status in step with the time of day, and put it back up
+/// when the status goes stale.
+pub async fn run(config: ServerDefaultStatusConfiguration, send_updates: UpdateHub) {
+    let mut interval = time::interval(TokioDuration::from_secs(60));
+
+    loop {
+        interval.tick().await;
+
+        let now = Utc::now();
+        let display = send_updates.current().display;
+
+        if display.person_is_source == SOURCE {
+            if display.person_is != config.status_at(now.with_timezone(&Local)) {
+                send_updates.send(DisplayStateMutation::SetPersonIs(update_at(
+                    Some(&config),
+                    now,
+                )));
+            }
+        } else if let Some(hours) = config.stale_hours {
+            if now - display.person_is_timestamp >= Duration::hours(hours as i64) {
+                log!("status has gone stale; going back to the default");
+                send_updates.send(DisplayStateMutation::SetPersonIs(update_at(
+                    Some(&config),
+                    now,
+                )));
+            }
+        }
+    }
+}
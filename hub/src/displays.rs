@@ -0,0 +1,124 @@
+//! Settings for particular display panels.
+//!
+//! Each panel can identify itself with an ID in its hello, and the hub
+//! configuration can have a `[displays.<ID>]` section for it, so that the
+//! settings for a whole fleet of panels can live in one place rather than in
+//! each Pi's configuration file. Some settings are applied here, by changing
+//! what we send; others are passed along for the panel to apply itself.
+
+use chrono::{DateTime, Utc};
+use rc_stickynote_protocol::{
+    DisplayCapabilities, DisplayMessage, DisplaySettings, PersonIsUpdateHelloMessage,
+    UNKNOWN_PERSON_IS,
+};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerDisplayConfiguration {
+    /// What the panel shows before anybody has set the status.
+    #[serde(default)]
+    default_message: Option<String>,
+
+    /// Whether to send news headlines to the panel.
+    #[serde(default = "default_true")]
+    headlines: bool,
+
+    /// Whether to invite visitors to leave notes on the panel.
+    #[serde(default = "default_true")]
+    notes: bool,
+
+    /// Whether this is a test panel, which shows the draft status from the
+    /// preview page instead of the real one.
+    #[serde(default)]
+    preview: bool,
+
+    /// Settings for the panel to apply itself.
+    #[serde(flatten)]
+    settings: DisplaySettings,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl ServerDisplayConfiguration {
+    /// Adjust a message on its way to this panel.
+    pub fn apply(&self, msg: &mut DisplayMessage) {
+        if let Some(ref m) = self.default_message {
+            if msg.person_is == UNKNOWN_PERSON_IS {
+                msg.person_is = m.clone();
+            }
+        }
+
+        if !self.headlines {
+            msg.headlines.clear();
+        }
+
+        if !self.notes {
+            msg.note_form_url = None;
+            msg.notes_waiting = 0;
+        }
+
+        msg.settings = Some(self.settings.clone());
+    }
+
+    /// If this is a test panel, show it the draft status, if there is one.
+    pub fn show_preview(
+        &self,
+        msg: &mut DisplayMessage,
+        draft: Option<&PersonIsUpdateHelloMessage>,
+    ) {
+        if let (true, Some(d)) = (self.preview, draft) {
+            msg.person_is = d.person_is.clone();
+            msg.person_is_timestamp = d.timestamp;
+            msg.person_is_source = d.source.clone().unwrap_or_default();
+            msg.person_is_set_by = d.set_by.clone().unwrap_or_default();
+        }
+    }
+}
+
+/// A panel that's currently connected to the hub.
+#[derive(Clone, Debug)]
+pub struct ConnectedDisplay {
+    pub peer: String,
+    pub display_id: Option<String>,
+    pub connected_at: DateTime<Utc>,
+    pub capabilities: Option<DisplayCapabilities>,
+}
+
+/// The panels that are currently connected, keyed by their network
+/// addresses, shared between the connection tasks and whoever wants to know.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectedDisplays {
+    inner: Arc<Mutex<HashMap<String, ConnectedDisplay>>>,
+}
+
+impl ConnectedDisplays {
+    pub fn add(&self, display: ConnectedDisplay) {
+        self.inner
+            .lock()
+            .unwrap()
+            .insert(display.peer.clone(), display);
+    }
+
+    pub fn remove(&self, peer: &str) {
+        self.inner.lock().unwrap().remove(peer);
+    }
+
+    /// The connected panels, in order of connection.
+    pub fn list(&self) -> Vec<ConnectedDisplay> {
+        let mut displays = self
+            .inner
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        displays.sort_by_key(|d| d.connected_at);
+        displays
+    }
+}
@@ -0,0 +1,199 @@
+//! Running the hub inside another program.
+//!
+//! A bigger daemon, like something that automates a whole office, might
+//! want to host the stickynote hub itself rather than talk to a separate
+//! one. `HubBuilder` sets one up from the usual configuration, and lets the
+//! host register its own update sources: async tasks that are handed a
+//! `HubHandle` for reading and changing the display state. They're
+//! supervised just like the hub's built-in sources, such as the calendar
+//! monitor, and are restarted if they exit or panic.
+//!
+//! ```ignore
+//! let hub = HubBuilder::new()
+//!     .config_path("hub.toml")
+//!     .source("badge reader", |hub| async move {
+//!         while let Some(event) = badge_events().next().await {
+//!             hub.set_status(&event.status, "badge reader");
+//!         }
+//!     })
+//!     .build()?;
+//!
+//! hub.serve().await?;
+//! ```
+//!
+//! Changes made through a handle skip the content filter and moderation
+//! queue, since they come from code that the hub's owner is running, but they
+//! still respect source locks.
+
+use futures::{future::BoxFuture, prelude::*};
+use rc_stickynote_protocol::{
+    is_person_is_valid, Availability, DisplayMessage, DoorbellHelloMessage, PanelCommand,
+    PanelCommandMessage, PersonIsUpdateHelloMessage,
+};
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::{
+    history::History, initial_state, prepare, supervisor, updates::UpdateHub, DisplayStateMutation,
+    GenericError, ServerConfiguration,
+};
+
+type SourceStarter = Box<dyn FnMut(HubHandle) -> BoxFuture<'static, ()> + Send>;
+
+/// Sets up a hub to run inside another program.
+#[derive(Default)]
+pub struct HubBuilder {
+    config_path: Option<PathBuf>,
+    sources: Vec<(String, SourceStarter)>,
+}
+
+impl HubBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the configuration from the given file. If this isn't called,
+    /// the configuration comes from `STICKYNOTE_HUB_*` environment variables
+    /// alone.
+    pub fn config_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Add a custom update source. Once the hub is serving, `start` is
+    /// called to start the source's task, and called again to restart it if
+    /// the task ever exits or panics.
+    pub fn source<S, F>(mut self, name: &str, mut start: S) -> Self
+    where
+        S: FnMut(HubHandle) -> F + Send + 'static,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.sources
+            .push((name.to_owned(), Box::new(move |hub| start(hub).boxed())));
+        self
+    }
+
+    /// Load the configuration and set up the hub's state. Nothing runs
+    /// until `Hub::serve` is called.
+    pub fn build(self) -> Result<Hub, GenericError> {
+        let config = ServerConfiguration::load_layered(self.config_path.as_deref())?;
+        let mut hub = Hub::new(config)?;
+        hub.sources = self.sources;
+        Ok(hub)
+    }
+}
+
+/// A hub that's ready to serve.
+pub struct Hub {
+    config: ServerConfiguration,
+    history: History,
+    send_updates: UpdateHub,
+    sources: Vec<(String, SourceStarter)>,
+}
+
+impl Hub {
+    pub(crate) fn new(config: ServerConfiguration) -> Result<Self, GenericError> {
+        prepare(&config);
+        let state = initial_state(&config)?;
+        let history = History::new(config.history_path.clone());
+        let send_updates = UpdateHub::new(state, &config, history.clone());
+
+        Ok(Hub {
+            config,
+            history,
+            send_updates,
+            sources: Vec::new(),
+        })
+    }
+
+    /// Get a handle on the display state. Handles can be used before the hub
+    /// starts serving, and keep working after it stops.
+    pub fn handle(&self) -> HubHandle {
+        HubHandle {
+            send_updates: self.send_updates.clone(),
+        }
+    }
+
+    /// Start the custom update sources, then run the hub's servers and
+    /// background tasks, forever.
+    pub async fn serve(self) -> Result<(), GenericError> {
+        for (name, mut start) in self.sources {
+            let hub = HubHandle {
+                send_updates: self.send_updates.clone(),
+            };
+            supervisor::spawn_restarting(&name, move || start(hub.clone()));
+        }
+
+        crate::serve(self.config, self.history, self.send_updates).await
+    }
+}
+
+/// A handle for reading and changing the display state of an embedded hub.
+#[derive(Clone)]
+pub struct HubHandle {
+    send_updates: UpdateHub,
+}
+
+impl HubHandle {
+    /// What the panels are being told to show right now.
+    pub fn current(&self) -> DisplayMessage {
+        self.send_updates.current().display
+    }
+
+    /// Watch the display state. The stream yields the current state right
+    /// away, and then the latest state whenever the hub's state changes,
+    /// which can mean repeats if something the panels don't see changed.
+    pub fn subscribe(&self) -> impl Stream<Item = DisplayMessage> {
+        self.send_updates.subscribe().map(|s| s.display)
+    }
+
+    /// Set the "person is" status, attributed to the given source. Returns
+    /// false if the status isn't valid, or if a lock is keeping the source
+    /// out.
+    pub fn set_status(&self, person_is: &str, source: &str) -> bool {
+        if !is_person_is_valid(person_is) {
+            return false;
+        }
+
+        self.send_updates.send(DisplayStateMutation::SetPersonIs(
+            PersonIsUpdateHelloMessage {
+                person_is: person_is.to_owned(),
+                timestamp: chrono::Utc::now(),
+                source: Some(source.to_owned()),
+                set_by: None,
+                token: None,
+                signature: None,
+            },
+        ))
+    }
+
+    /// Ring the doorbell.
+    pub fn ring_doorbell(&self) {
+        self.send_updates
+            .send(DisplayStateMutation::RingDoorbell(DoorbellHelloMessage {
+                timestamp: chrono::Utc::now(),
+                token: None,
+            }));
+    }
+
+    /// Set whether the person is around to be visited.
+    pub fn set_availability(&self, availability: Availability) {
+        self.send_updates
+            .send(DisplayStateMutation::SetAvailability(availability));
+    }
+
+    /// Merge values into the display state's extras. A null value removes
+    /// its key.
+    pub fn merge_extras(&self, patch: BTreeMap<String, serde_json::Value>) {
+        self.send_updates
+            .send(DisplayStateMutation::MergeExtras(patch));
+    }
+
+    /// Send a command to the connected panels.
+    pub fn send_command(&self, command: PanelCommand) {
+        self.send_updates
+            .send(DisplayStateMutation::SendCommand(PanelCommandMessage {
+                command,
+                issued: chrono::Utc::now(),
+            }));
+    }
+}
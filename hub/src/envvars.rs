@@ -0,0 +1,72 @@
+//! Overriding configuration settings with environment variables.
+//!
+//! This makes it possible to configure the hub without a configuration file,
+//! which is handy when running it in a container. A variable named
+//! `STICKYNOTE_HUB_<KEY>` sets the top-level setting `<key>`, and double
+//! underscores separate the names of nested sections, so that
+//! `STICKYNOTE_HUB_NOTES__FORM_URL` sets `form_url` in the `[notes]`
+//! section. Names are lowercased.
+//!
+//! Values are parsed as TOML if possible, so that numbers, booleans, arrays,
+//! and inline tables work as expected; otherwise they're taken as strings.
+//! To force a string that looks like a number, quote it: `'"1234"'`.
+
+/// The prefix of the environment variables that we look at.
+pub const PREFIX: &str = "STICKYNOTE_HUB_";
+
+fn parse_value(raw: &str) -> toml::Value {
+    match format!("v = {}", raw).parse::<toml::Value>() {
+        Ok(toml::Value::Table(mut t)) => t
+            .remove("v")
+            .unwrap_or_else(|| toml::Value::String(raw.to_owned())),
+        _ => toml::Value::String(raw.to_owned()),
+    }
+}
+
+fn set_path(config: &mut toml::Value, keys: &[String], value: toml::Value) {
+    let (last, parents) = match keys.split_last() {
+        Some(x) => x,
+        None => return,
+    };
+
+    let mut table = config;
+
+    for key in parents {
+        if !table.is_table() {
+            *table = toml::Value::Table(Default::default());
+        }
+
+        table = table
+            .as_table_mut()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| toml::Value::Table(Default::default()));
+    }
+
+    if !table.is_table() {
+        *table = toml::Value::Table(Default::default());
+    }
+
+    table.as_table_mut().unwrap().insert(last.clone(), value);
+}
+
+/// Apply overrides from the given environment variables to a configuration.
+pub fn apply_overrides<I: IntoIterator<Item = (String, String)>>(
+    config: &mut toml::Value,
+    vars: I,
+) {
+    for (name, raw) in vars {
+        let path = match name.strip_prefix(PREFIX) {
+            Some(p) => p,
+            None => continue,
+        };
+
+        let keys: Vec<String> = path.split("__").map(|k| k.to_lowercase()).collect();
+
+        if keys.iter().any(|k| k.is_empty()) {
+            continue;
+        }
+
+        set_path(config, &keys, parse_value(&raw));
+    }
+}
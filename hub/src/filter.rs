@@ -0,0 +1,129 @@
+//! Filtering the content of status updates.
+//!
+//! For panels in public places, it can be prudent to screen updates for
+//! objectionable words, shouting, and links before they're shown. Each check
+//! either censors the offending text or rejects the whole update, depending
+//! on the configured policy.
+
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterPolicy {
+    /// Throw away updates that trip the filter.
+    Reject,
+
+    /// Clean up the offending parts of updates and let them through.
+    Censor,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerContentFilterConfiguration {
+    /// What to do with updates that trip the filter.
+    #[serde(default = "default_policy")]
+    policy: FilterPolicy,
+
+    /// Words that aren't allowed, matched case-insensitively.
+    #[serde(default)]
+    words: Vec<String>,
+
+    /// The most capital letters allowed in a row. Zero means no limit.
+    #[serde(default)]
+    max_consecutive_caps: usize,
+
+    /// Whether to filter out things that look like URLs.
+    #[serde(default)]
+    strip_urls: bool,
+
+    /// The ways of reaching the hub whose updates are filtered, as for
+    /// moderation. If this and `tokens` are empty, all updates are.
+    #[serde(default)]
+    sources: Vec<String>,
+
+    /// The holders of tokens whose updates are filtered, by name.
+    #[serde(default)]
+    tokens: Vec<String>,
+}
+
+fn default_policy() -> FilterPolicy {
+    FilterPolicy::Reject
+}
+
+fn looks_like_url(word: &str) -> bool {
+    let word = word.to_lowercase();
+    word.contains("://") || word.starts_with("www.")
+}
+
+impl ServerContentFilterConfiguration {
+    /// Whether an update that came in by way of `via`, with a token held by
+    /// `set_by`, if any, is subject to the filter.
+    pub fn applies_to(&self, via: &str, set_by: Option<&str>) -> bool {
+        (self.sources.is_empty() && self.tokens.is_empty())
+            || self.sources.iter().any(|f| f == via)
+            || matches!(set_by, Some(h) if self.tokens.iter().any(|f| f == h))
+    }
+
+    fn is_banned(&self, word: &str) -> bool {
+        let word = word
+            .trim_matches(|c: char| !c.is_alphanumeric())
+            .to_lowercase();
+        self.words.iter().any(|w| w.to_lowercase() == word)
+    }
+
+    /// Run the text through the filter. Returns the text to show, which may
+    /// have been censored, or an explanation of why it was rejected.
+    pub fn apply(&self, text: &str) -> Result<String, String> {
+        let censor = self.policy == FilterPolicy::Censor;
+        let mut words = Vec::new();
+
+        for word in text.split_whitespace() {
+            if self.strip_urls && looks_like_url(word) {
+                if !censor {
+                    return Err("links aren't allowed".to_owned());
+                }
+            } else if self.is_banned(word) {
+                if !censor {
+                    return Err(format!("\"{}\" isn't allowed", word));
+                }
+
+                words.push(
+                    word.chars()
+                        .map(|c| if c.is_alphanumeric() { '*' } else { c })
+                        .collect(),
+                );
+            } else {
+                words.push(word.to_owned());
+            }
+        }
+
+        let mut result = words.join(" ");
+
+        if self.max_consecutive_caps > 0 {
+            let mut run = 0;
+            let mut too_long = false;
+
+            for c in result.chars() {
+                if c.is_uppercase() {
+                    run += 1;
+                    too_long = too_long || run > self.max_consecutive_caps;
+                } else {
+                    run = 0;
+                }
+            }
+
+            if too_long {
+                if !censor {
+                    return Err("too many capital letters".to_owned());
+                }
+
+                result = result.to_lowercase();
+            }
+        }
+
+        if result.is_empty() {
+            return Err("nothing was left after filtering".to_owned());
+        }
+
+        Ok(result)
+    }
+}
@@ -0,0 +1,255 @@
+//! A GraphQL endpoint for dashboards.
+//!
+//! The HTML pages and the form-based API are fine for people and scripts, but
+//! a dashboard wants to ask for exactly the pieces it needs and to hear about
+//! changes as they happen. So, if enabled, `/graphql` answers queries about
+//! the current status, the history of updates, and the connected panels, and
+//! offers a subscription to status changes. Queries are POSTed as JSON in the
+//! usual way. Subscriptions are delivered as server-sent events, if the
+//! request accepts `text/event-stream`, so that no websocket support is
+//! needed; they can also be made with a GET request with the query in the
+//! `query` parameter, for the sake of the browser's `EventSource`.
+
+use async_graphql::{
+    Context, EmptyMutation, Object, Request as GraphqlRequest, Schema, SimpleObject, Subscription,
+};
+use chrono::{DateTime, Utc};
+use futures::{future, prelude::*};
+use hyper::{header, Body, Method, Request, Response};
+use serde::Deserialize;
+
+use crate::{
+    displays::ConnectedDisplays,
+    history::{History, HistoryEvent},
+    updates::UpdateHub,
+    GenericError, HubDisplayState,
+};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerGraphqlConfiguration {
+    /// A token that grants access to the endpoint, in addition to the
+    /// tokens of observers and above.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// The "person is:" status and what goes with it.
+#[derive(Clone, Debug, PartialEq, SimpleObject)]
+struct Status {
+    person_is: String,
+    timestamp: DateTime<Utc>,
+    source: Option<String>,
+    set_by: Option<String>,
+
+    /// If set, updates from lower-priority sources are ignored until then.
+    locked_until: Option<DateTime<Utc>>,
+
+    notes_waiting: usize,
+
+    /// Whether the hub is in maintenance mode, so that the panels aren't
+    /// being updated.
+    maintenance: bool,
+}
+
+impl Status {
+    fn from_state(state: &HubDisplayState) -> Self {
+        let display = &state.display;
+
+        Status {
+            person_is: display.person_is.clone(),
+            timestamp: display.person_is_timestamp,
+            source: non_empty(&display.person_is_source),
+            set_by: non_empty(&display.person_is_set_by),
+            locked_until: state.lock.as_ref().map(|l| l.until),
+            notes_waiting: display.notes_waiting,
+            maintenance: state.maintenance,
+        }
+    }
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_owned())
+    }
+}
+
+/// A past status update, from the history log.
+#[derive(Clone, Debug, SimpleObject)]
+struct StatusUpdate {
+    timestamp: DateTime<Utc>,
+    person_is: String,
+    source: Option<String>,
+    set_by: Option<String>,
+    signed_by: Option<String>,
+}
+
+/// A panel that's connected to the hub.
+#[derive(Clone, Debug, SimpleObject)]
+struct Display {
+    /// The panel's network address.
+    peer: String,
+    display_id: Option<String>,
+    connected_at: DateTime<Utc>,
+
+    /// What the panel says it can show, like "800x480, 2 colors".
+    capabilities: Option<String>,
+}
+
+/// What the resolvers need from the rest of the hub.
+struct HubData {
+    send_updates: UpdateHub,
+    history: History,
+    displays: ConnectedDisplays,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// The current status.
+    async fn status(&self, ctx: &Context<'_>) -> Status {
+        let hub = ctx.data_unchecked::<HubData>();
+        Status::from_state(&hub.send_updates.current())
+    }
+
+    /// Past status updates, most recent first.
+    async fn history(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 20)] limit: usize,
+    ) -> async_graphql::Result<Vec<StatusUpdate>> {
+        let hub = ctx.data_unchecked::<HubData>();
+
+        Ok(hub
+            .history
+            .load()?
+            .into_iter()
+            .rev()
+            .filter_map(|ev| match ev {
+                HistoryEvent::StatusUpdate {
+                    timestamp,
+                    person_is,
+                    source,
+                    set_by,
+                    signed_by,
+                } => Some(StatusUpdate {
+                    timestamp,
+                    person_is,
+                    source,
+                    set_by,
+                    signed_by,
+                }),
+                _ => None,
+            })
+            .take(limit)
+            .collect())
+    }
+
+    /// The panels that are connected right now.
+    async fn displays(&self, ctx: &Context<'_>) -> Vec<Display> {
+        let hub = ctx.data_unchecked::<HubData>();
+
+        hub.displays
+            .list()
+            .into_iter()
+            .map(|d| Display {
+                peer: d.peer,
+                display_id: d.display_id,
+                connected_at: d.connected_at,
+                capabilities: d.capabilities.map(|c| c.to_string()),
+            })
+            .collect()
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// The status, every time that it changes.
+    async fn status(&self, ctx: &Context<'_>) -> impl Stream<Item = Status> {
+        let hub = ctx.data_unchecked::<HubData>();
+
+        let mut previous = Status::from_state(&hub.send_updates.current());
+
+        hub.send_updates.subscribe().filter_map(move |state| {
+            let status = Status::from_state(&state);
+
+            future::ready(if status != previous {
+                previous = status.clone();
+                Some(status)
+            } else {
+                None
+            })
+        })
+    }
+}
+
+pub type HubSchema = Schema<QueryRoot, EmptyMutation, SubscriptionRoot>;
+
+/// Set up the schema.
+pub fn build_schema(
+    send_updates: UpdateHub,
+    history: History,
+    displays: ConnectedDisplays,
+) -> HubSchema {
+    Schema::build(QueryRoot, EmptyMutation, SubscriptionRoot)
+        .data(HubData {
+            send_updates,
+            history,
+            displays,
+        })
+        .finish()
+}
+
+/// Handle a request to the GraphQL endpoint. The caller has already checked
+/// the token.
+pub async fn handle_request(
+    req: Request<Body>,
+    schema: &HubSchema,
+) -> Result<Response<Body>, GenericError> {
+    let wants_stream = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.contains("text/event-stream"))
+        .unwrap_or(false);
+
+    let gql_req = if req.method() == Method::GET {
+        let query = req.uri().query().unwrap_or("");
+
+        match url::form_urlencoded::parse(query.as_bytes()).find(|(name, _)| name == "query") {
+            Some((_, q)) => GraphqlRequest::new(q.into_owned()),
+            None => return crate::bad_request("missing `query` parameter"),
+        }
+    } else {
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+
+        match serde_json::from_slice::<GraphqlRequest>(&body) {
+            Ok(r) => r,
+            Err(e) => return crate::bad_request(&format!("invalid GraphQL request: {}", e)),
+        }
+    };
+
+    if wants_stream {
+        let events = schema.execute_stream(gql_req).map(|resp| {
+            let json = serde_json::to_string(&resp).unwrap_or_default();
+            Ok::<_, GenericError>(format!("data: {}\n\n", json))
+        });
+
+        return Ok(Response::builder()
+            .status(hyper::StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(Body::wrap_stream(events))?);
+    }
+
+    let resp = schema.execute(gql_req).await;
+
+    Ok(Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&resp)?))?)
+}
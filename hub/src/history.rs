@@ -0,0 +1,294 @@
+//! A persistent log of things that happen on the hub.
+//!
+//! The log is a text file with one JSON record per line. We only ever append
+//! to it, so it's easy to inspect with standard tools, and a half-written
+//! line left behind by a crash just gets skipped when the log is read back.
+//!
+//! Panels report sensor readings and their health every few minutes, so left
+//! alone the log would grow without limit, and everything that reads it back
+//! would get slower and slower. Once the log passes [`MAX_BYTES`], it's moved
+//! aside to a file with `.1` added to its name, replacing any older one, and
+//! a fresh log is started. Reading the log back reads both files, so the
+//! recent past is always available but the oldest events eventually go.
+
+use chrono::{DateTime, Utc};
+use rc_stickynote_protocol::DisplayCapabilities;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Error, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// How big the log can get before it's rotated.
+pub const MAX_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Held while writing to any history log, so that two writers can't both
+/// rotate the same log.
+static WRITING: Mutex<()> = Mutex::new(());
+
+/// An event recorded in the history log.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum HistoryEvent {
+    /// The "person is:" status was updated.
+    StatusUpdate {
+        timestamp: DateTime<Utc>,
+        person_is: String,
+        #[serde(default)]
+        source: Option<String>,
+        #[serde(default)]
+        set_by: Option<String>,
+        #[serde(default)]
+        signed_by: Option<String>,
+    },
+
+    /// A status that was too long was shortened to fit on the panels.
+    AutoShortened {
+        timestamp: DateTime<Utc>,
+        original: String,
+        person_is: String,
+        #[serde(default)]
+        source: Option<String>,
+    },
+
+    /// The latest status was undone, putting the one before it back up. The
+    /// restored status is recorded as a status update with its original
+    /// timestamp.
+    Undo {
+        timestamp: DateTime<Utc>,
+        undone: String,
+        #[serde(default)]
+        set_by: Option<String>,
+    },
+
+    /// A display panel connected to the hub.
+    DisplayConnected {
+        timestamp: DateTime<Utc>,
+        peer: String,
+        #[serde(default)]
+        capabilities: Option<DisplayCapabilities>,
+        #[serde(default)]
+        display_id: Option<String>,
+    },
+
+    /// A display panel's connection to the hub went away.
+    DisplayDisconnected {
+        timestamp: DateTime<Utc>,
+        peer: String,
+        connected_at: DateTime<Utc>,
+        #[serde(default)]
+        display_id: Option<String>,
+    },
+
+    /// A panel reported the room conditions.
+    SensorReading {
+        timestamp: DateTime<Utc>,
+        co2_ppm: f32,
+        temperature_c: f32,
+        humidity_percent: f32,
+        #[serde(default)]
+        display_id: Option<String>,
+    },
+
+    /// A counter was created, incremented, or reset.
+    CounterChange {
+        timestamp: DateTime<Utc>,
+        name: String,
+        value: i64,
+        #[serde(default)]
+        set_by: Option<String>,
+    },
+
+    /// A panel reported on its own health.
+    SystemHealth {
+        timestamp: DateTime<Utc>,
+        #[serde(default)]
+        cpu_temperature_c: Option<f32>,
+        #[serde(default)]
+        throttled_flags: Option<u32>,
+        #[serde(default)]
+        display_id: Option<String>,
+    },
+}
+
+/// A status update, in the form used when exporting the history.
+#[derive(Clone, Debug, Serialize)]
+pub struct StatusRecord {
+    pub timestamp: DateTime<Utc>,
+    pub person_is: String,
+    pub source: Option<String>,
+    pub set_by: Option<String>,
+}
+
+/// Write status records as CSV, with a header row.
+pub fn write_csv<W: Write>(dest: &mut W, records: &[StatusRecord]) -> Result<(), Error> {
+    writeln!(dest, "timestamp,person_is,source,set_by")?;
+
+    for rec in records {
+        writeln!(
+            dest,
+            "{},{},{},{}",
+            rec.timestamp.to_rfc3339(),
+            csv_field(&rec.person_is),
+            csv_field(rec.source.as_deref().unwrap_or("")),
+            csv_field(rec.set_by.as_deref().unwrap_or("")),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Quote a CSV field if needed.
+fn csv_field(text: &str) -> String {
+    if text.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+/// A handle to the history log.
+///
+/// If no log path has been configured, recording events is a no-op and the
+/// log always reads back as empty.
+#[derive(Clone, Debug)]
+pub struct History {
+    path: Option<PathBuf>,
+}
+
+impl History {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        History { path }
+    }
+
+    /// Append an event to the log.
+    ///
+    /// Failures are reported but otherwise ignored, since losing a history
+    /// entry isn't a good reason to interrupt the flow of updates.
+    pub fn record(&self, event: HistoryEvent) {
+        if let Err(e) = self.try_record(&event) {
+            log!("error writing to history log: {}", e);
+        }
+    }
+
+    fn try_record(&self, event: &HistoryEvent) -> Result<(), Error> {
+        let path = match self.path {
+            Some(ref p) => p,
+            None => return Ok(()),
+        };
+
+        let mut line = serde_json::to_string(event)?;
+        line.push('\n');
+
+        let _writing = WRITING.lock().unwrap_or_else(|e| e.into_inner());
+
+        match std::fs::metadata(path) {
+            Ok(md) if md.len() + line.len() as u64 > MAX_BYTES => {
+                std::fs::rename(path, rotated_path(path))?;
+                log!("rotated the history log `{}`", path.display());
+            }
+
+            Ok(_) => {}
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+        f.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// The status that's up now and the one to go back to if it's undone,
+    /// going by the log. Each undo takes a status off of the stack of past
+    /// statuses, and putting back the one below doesn't add it again, since
+    /// it keeps its original timestamp. So undoing repeatedly goes further
+    /// back in time, rather than flipping between the last two statuses.
+    pub fn undo_target(&self) -> Result<Option<(StatusRecord, StatusRecord)>, Error> {
+        let mut stack: Vec<StatusRecord> = Vec::new();
+
+        for event in self.load()? {
+            match event {
+                HistoryEvent::StatusUpdate {
+                    timestamp,
+                    person_is,
+                    source,
+                    set_by,
+                    ..
+                } => {
+                    let restored = matches!(
+                        stack.last(),
+                        Some(r) if r.timestamp == timestamp && r.person_is == person_is
+                    );
+
+                    if !restored {
+                        stack.push(StatusRecord {
+                            timestamp,
+                            person_is,
+                            source,
+                            set_by,
+                        });
+                    }
+                }
+
+                HistoryEvent::Undo { .. } => {
+                    stack.pop();
+                }
+
+                _ => {}
+            }
+        }
+
+        let previous = stack.len().checked_sub(2).map(|i| stack[i].clone());
+        Ok(previous.and_then(|p| stack.pop().map(|c| (c, p))))
+    }
+
+    /// Read back all of the events in the log, oldest first, including the
+    /// ones in the last rotated-out log.
+    pub fn load(&self) -> Result<Vec<HistoryEvent>, Error> {
+        let path = match self.path {
+            Some(ref p) => p,
+            None => return Ok(Vec::new()),
+        };
+
+        // This doesn't hold `WRITING`, so that reading a big log doesn't hold
+        // up updates. A rotation partway through just skews this one read.
+
+        let mut events = Vec::new();
+        load_file(&rotated_path(path), &mut events)?;
+        load_file(path, &mut events)?;
+        Ok(events)
+    }
+}
+
+/// Where a log goes when it's rotated out.
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".1");
+    PathBuf::from(name)
+}
+
+/// Read the events in one log file, if it exists.
+fn load_file(path: &Path, events: &mut Vec<HistoryEvent>) -> Result<(), Error> {
+    let f = match File::open(path) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    for line in BufReader::new(f).lines() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str(&line) {
+            Ok(event) => events.push(event),
+            Err(e) => log!("skipping unparseable history log line: {}", e),
+        }
+    }
+
+    Ok(())
+}
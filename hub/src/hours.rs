@@ -0,0 +1,101 @@
+//! Office hours.
+//!
+//! The status says where the person is right now, but it can go stale, and it
+//! doesn't say when they're usually around. The configuration can include a
+//! weekly table of office hours, and the hub keeps the panels told whether
+//! it's inside them, so that visitors get an at-a-glance answer next to the
+//! status.
+
+use chrono::{DateTime, Datelike, Local, NaiveTime, Weekday};
+use rc_stickynote_protocol::Availability;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use tokio::time::{self, Duration};
+
+use crate::{updates::UpdateHub, DisplayStateMutation};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerOfficeHoursConfiguration {
+    /// The weekly table, in the hub's local time. Outside all of these
+    /// slots, the person is unavailable. If slots overlap, the first one
+    /// listed wins.
+    slots: Vec<OfficeHoursSlot>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct OfficeHoursSlot {
+    /// The days of the week that the slot applies to.
+    days: Vec<Day>,
+
+    /// When the slot starts.
+    start: ClockTime,
+
+    /// When the slot ends, which must be later in the day than the start.
+    end: ClockTime,
+
+    /// What the slot means: "available", "limited", or "unavailable".
+    #[serde(default = "default_availability")]
+    availability: Availability,
+}
+
+fn default_availability() -> Availability {
+    Availability::Available
+}
+
+/// A day of the week, written like "mon" or "Monday".
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub(crate) struct Day(pub Weekday);
+
+impl TryFrom<String> for Day {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+            .map(Day)
+            .map_err(|_| format!("expected a day like \"mon\", got \"{}\"", text))
+    }
+}
+
+/// A time of day, written like "09:30".
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub(crate) struct ClockTime(pub NaiveTime);
+
+impl TryFrom<String> for ClockTime {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        NaiveTime::parse_from_str(&text, "%H:%M")
+            .map(ClockTime)
+            .map_err(|_| format!("expected a time like \"09:30\", got \"{}\"", text))
+    }
+}
+
+impl ServerOfficeHoursConfiguration {
+    /// Whether the person is available at the given time.
+    pub fn availability_at(&self, t: DateTime<Local>) -> Availability {
+        let (day, time) = (t.weekday(), t.time());
+
+        self.slots
+            .iter()
+            .find(|s| s.days.iter().any(|d| d.0 == day) && s.start.0 <= time && time < s.end.0)
+            .map(|s| s.availability)
+            .unwrap_or(Availability::Unavailable)
+    }
+}
+
+/// Keep the panels up to date as office hours begin and end.
+pub async fn run(config: ServerOfficeHoursConfiguration, send_updates: UpdateHub) {
+    let mut interval = time::interval(Duration::from_secs(60));
+
+    loop {
+        interval.tick().await;
+
+        let availability = config.availability_at(Local::now());
+
+        if send_updates.current().display.availability != Some(availability) {
+            send_updates.send(DisplayStateMutation::SetAvailability(availability));
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Helpers for the hub's little HTML pages.
+
+use std::sync::RwLock;
+
+/// The path prefix that the pages are served under, if any; see the `proxy`
+/// module. The pages' links are relative to it.
+static BASE_PATH: RwLock<String> = RwLock::new(String::new());
+
+/// Set the path prefix that the pages are served under.
+pub fn set_base_path(path: &str) {
+    *BASE_PATH.write().unwrap() = path.to_owned();
+}
+
+/// Escape text for inclusion in HTML.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Wrap some body HTML into a complete page.
+pub fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n\
+         <html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <base href=\"{}/\">\n\
+         <title>{}</title>\n\
+         <style>\n\
+         body {{ font-family: sans-serif; margin: 2em; }}\n\
+         td {{ padding: 0.1em 0.5em; }}\n\
+         td.n {{ text-align: right; }}\n\
+         div.bar {{ background: #444; height: 0.8em; }}\n\
+         </style>\n</head>\n<body>\n\
+         <h1>{}</h1>\n{}</body>\n</html>\n",
+        escape_html(&BASE_PATH.read().unwrap()),
+        escape_html(title),
+        escape_html(title),
+        body
+    )
+}
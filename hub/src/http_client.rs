@@ -0,0 +1,80 @@
+//! Making outgoing HTTP(S) requests.
+
+use hyper::{client::HttpConnector, Body, Client, Method, Request, Response};
+use hyper_tls::HttpsConnector;
+use tokio::time::{self, Duration};
+
+use crate::GenericError;
+
+/// How long to wait for a response to an outgoing request before giving up
+/// on it. This is shorter than the outbox's first retry wait, so that a
+/// delivery's first attempt is over before it could be tried again.
+const TIMEOUT_SECONDS: u64 = 20;
+
+/// Create a client that can talk to both HTTP and HTTPS URLs.
+pub fn https_client() -> Client<HttpsConnector<HttpConnector>, Body> {
+    Client::builder().build::<_, Body>(HttpsConnector::new())
+}
+
+/// Send a request to another server, giving up if it doesn't respond in
+/// time.
+pub async fn send(req: Request<Body>) -> Result<Response<Body>, GenericError> {
+    let uri = req.uri().clone();
+
+    match time::timeout(
+        Duration::from_secs(TIMEOUT_SECONDS),
+        https_client().request(req),
+    )
+    .await
+    {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(format!("request to {} timed out", uri).into()),
+    }
+}
+
+/// GET the specified URL and return the response body as text.
+pub async fn fetch_text(url: &str) -> Result<String, GenericError> {
+    fetch_text_with_headers(url, &[]).await
+}
+
+/// Like `fetch_text`, but with extra request headers, such as API keys.
+pub async fn fetch_text_with_headers(
+    url: &str,
+    headers: &[(&str, &str)],
+) -> Result<String, GenericError> {
+    let mut builder = Request::builder().method(Method::GET).uri(url);
+
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+
+    let resp = send(builder.body(Body::empty())?).await?;
+
+    if !resp.status().is_success() {
+        return Err(format!("fetch of {} failed with status {}", url, resp.status()).into());
+    }
+
+    let body = hyper::body::to_bytes(resp.into_body()).await?;
+    Ok(String::from_utf8(body.to_vec())?)
+}
+
+/// PUT the given text to the specified URL, with extra request headers.
+pub async fn put_text_with_headers(
+    url: &str,
+    headers: &[(&str, &str)],
+    body: String,
+) -> Result<(), GenericError> {
+    let mut builder = Request::builder().method(Method::PUT).uri(url);
+
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+
+    let resp = send(builder.body(Body::from(body))?).await?;
+
+    if !resp.status().is_success() {
+        return Err(format!("PUT to {} failed with status {}", url, resp.status()).into());
+    }
+
+    Ok(())
+}
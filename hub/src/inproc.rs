@@ -0,0 +1,55 @@
+//! Connections from panel clients running in the same process as the hub.
+//!
+//! In the all-in-one program, the hub and the panel client share a process,
+//! so there's no sense in the client reaching the hub through a loopback TCP
+//! port. Instead, it asks for one end of a socket pair, and the other end is
+//! handed to the hub, which treats it like any other stickyproto connection.
+//!
+//! The ends are passed around as standard-library sockets, so that each side
+//! can register its own end with whichever runtime it happens to be in.
+//! Connections made before the hub starts serving wait in the channel until
+//! it does.
+
+use std::{
+    io::{Error, ErrorKind},
+    os::unix::net::UnixStream,
+    sync::Mutex,
+};
+use tokio::sync::mpsc;
+
+struct Channel {
+    sender: mpsc::UnboundedSender<UnixStream>,
+    receiver: Option<mpsc::UnboundedReceiver<UnixStream>>,
+}
+
+static CHANNEL: Mutex<Option<Channel>> = Mutex::new(None);
+
+fn with_channel<R, F: FnOnce(&mut Channel) -> R>(f: F) -> R {
+    let mut guard = CHANNEL.lock().unwrap();
+
+    let channel = guard.get_or_insert_with(|| {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Channel {
+            sender,
+            receiver: Some(receiver),
+        }
+    });
+
+    f(channel)
+}
+
+/// Open a connection to the hub running in this process.
+pub fn connect() -> Result<UnixStream, Error> {
+    let (ours, theirs) = UnixStream::pair()?;
+
+    with_channel(|c| c.sender.send(theirs))
+        .map_err(|_| Error::new(ErrorKind::NotConnected, "the in-process hub has stopped"))?;
+
+    Ok(ours)
+}
+
+/// Take the receiving end of the channel, to accept connections from. Only
+/// one hub per process can do this.
+pub(crate) fn incoming() -> Option<mpsc::UnboundedReceiver<UnixStream>> {
+    with_channel(|c| c.receiver.take())
+}
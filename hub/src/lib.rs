@@ -0,0 +1,5454 @@
+//! The hub that brokers events between clients and the displayer panel.
+//!
+//! This is a library so that the all-in-one program, which runs the hub and a
+//! panel client in the same process, can use it too. The `rc_stickynote_hub`
+//! program itself is a thin wrapper around `RootCli`. Other programs can
+//! embed the hub and feed it their own updates with `embed::HubBuilder`.
+
+#![recursion_limit = "256"]
+
+use chrono::offset::TimeZone;
+use futures::{prelude::*, select};
+use hmac::{Hmac, Mac};
+use hyper::{
+    header,
+    server::conn::AddrStream,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server,
+};
+use rc_stickynote_protocol::{
+    session::hub::{AwaitingHello, Session},
+    *,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{stdin, stdout, Error, Read, Write},
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use structopt::StructOpt;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, UnixStream},
+    time::{self, Duration},
+};
+
+#[macro_use]
+mod logging;
+
+mod auth;
+mod calendar;
+mod chat;
+mod ci;
+mod counters;
+mod defaults;
+mod displays;
+pub mod embed;
+mod envvars;
+mod filter;
+mod graphql;
+mod history;
+mod hours;
+mod html;
+mod http_client;
+pub mod inproc;
+mod listen;
+mod maintenance;
+mod moderation;
+mod mqtt;
+mod news;
+mod notes;
+mod notifications;
+mod oncall;
+mod outbox;
+mod panels;
+mod phrases;
+mod preview;
+mod proxy;
+mod queue;
+mod recording;
+mod relay;
+mod render;
+mod rooms;
+mod service;
+mod shorten;
+mod slack;
+mod stats;
+mod supervisor;
+mod tokens;
+mod updates;
+mod webhooks;
+use auth::{Access, Role};
+use counters::CounterStore;
+use history::{History, HistoryEvent};
+use moderation::PendingQueue;
+use notes::{Note, NoteBox};
+use preview::PreviewDraft;
+use queue::StatusQueue;
+use tokens::{IssuedToken, TokenStore};
+use updates::UpdateHub;
+
+// Configuration and state for the hub program
+
+pub type GenericError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Clone, Debug, Deserialize)]
+struct ServerConfiguration {
+    stickyproto_port: u16,
+    http_port: u16,
+    twitter: ServerTwitterConfiguration,
+
+    /// Where to log status updates and display connections. If unset, no
+    /// history is kept and the stats page will be empty. The log is rotated
+    /// once it gets big, keeping one older file alongside it.
+    #[serde(default)]
+    history_path: Option<PathBuf>,
+
+    /// If set, monitor a calendar for out-of-office events.
+    #[serde(default)]
+    calendar: Option<calendar::ServerCalendarConfiguration>,
+
+    /// If set, watch teammates' calendars for the days that they plan to be
+    /// in the office, for the panels' "week" layout.
+    #[serde(default)]
+    team: Option<calendar::ServerTeamConfiguration>,
+
+    /// If set, watch a meeting room's calendar, for panels used as room
+    /// displays.
+    #[serde(default)]
+    room: Option<rooms::ServerRoomConfiguration>,
+
+    /// If set, what to show when nobody has said where the person is, which
+    /// can depend on the time of day.
+    #[serde(default)]
+    default_status: Option<defaults::ServerDefaultStatusConfiguration>,
+
+    /// If set, accept CI webhook events and show the results on the panel.
+    #[serde(default)]
+    ci: Option<ci::ServerCiConfiguration>,
+
+    /// If set, accept Slack slash commands for setting the status.
+    #[serde(default)]
+    slack: Option<slack::ServerSlackConfiguration>,
+
+    /// If set, let visitors leave notes.
+    #[serde(default)]
+    notes: Option<notes::ServerNotesConfiguration>,
+
+    /// How to notify the owner about things like doorbell rings.
+    #[serde(default)]
+    notifications: notifications::ServerNotificationsConfiguration,
+
+    /// If set, allow the doorbell to be rung over HTTP.
+    #[serde(default)]
+    doorbell: Option<ServerDoorbellConfiguration>,
+
+    /// If set, poll news feeds for headlines to show on the panel.
+    #[serde(default)]
+    news: Option<news::ServerNewsConfiguration>,
+
+    /// If set, a weekly table of office hours, so that the panels can show
+    /// whether the person is usually around.
+    #[serde(default)]
+    office_hours: Option<hours::ServerOfficeHoursConfiguration>,
+
+    /// If set, poll PagerDuty or Opsgenie schedules to show who's on call.
+    #[serde(default)]
+    on_call: Option<oncall::ServerOnCallConfiguration>,
+
+    /// If set, serve a GraphQL endpoint at `/graphql`.
+    #[serde(default)]
+    graphql: Option<graphql::ServerGraphqlConfiguration>,
+
+    /// If set, serve pictures of the sign at `/render/current.png`.
+    #[serde(default)]
+    render: Option<render::ServerRenderConfiguration>,
+
+    /// URLs to notify whenever the display state changes.
+    #[serde(default)]
+    webhooks: Vec<webhooks::ServerWebhookConfiguration>,
+
+    /// Access tokens and their roles. If empty, and `tokens_path` isn't set,
+    /// access control is disabled.
+    #[serde(default)]
+    tokens: Vec<auth::ServerTokenConfiguration>,
+
+    /// How the HTTP server fits in behind a reverse proxy, and which other
+    /// origins may call its API.
+    #[serde(default)]
+    http: proxy::ServerHttpConfiguration,
+
+    /// If set, where to keep the tokens issued with the `token` commands.
+    /// Setting it enables access control, even before any are issued.
+    #[serde(default)]
+    tokens_path: Option<PathBuf>,
+
+    /// If set, hold updates from untrusted sources until an admin approves
+    /// them.
+    #[serde(default)]
+    moderation: Option<moderation::ServerModerationConfiguration>,
+
+    /// If set, let draft statuses be tried out on test panels before they go
+    /// live.
+    #[serde(default)]
+    preview: Option<preview::ServerPreviewConfiguration>,
+
+    /// If set, keep counters that can be shown on the panels.
+    #[serde(default)]
+    counters: Option<counters::ServerCountersConfiguration>,
+
+    /// If set, let statuses be queued up to go up at later times.
+    #[serde(default)]
+    status_queue: Option<queue::ServerStatusQueueConfiguration>,
+
+    /// If set, screen updates for objectionable content.
+    #[serde(default)]
+    content_filter: Option<filter::ServerContentFilterConfiguration>,
+
+    /// If set, statuses that are a little too long to fit on the panels are
+    /// shortened rather than rejected.
+    #[serde(default)]
+    shortening: Option<shorten::ServerShorteningConfiguration>,
+
+    /// If set, mirror the status to and from another hub.
+    #[serde(default)]
+    relay: Option<relay::ServerRelayConfiguration>,
+
+    /// If set, also talk to panels and clients through an MQTT broker.
+    #[serde(default)]
+    mqtt: Option<mqtt::MqttConfiguration>,
+
+    /// Settings for particular panels, keyed by the IDs that they send.
+    #[serde(default)]
+    displays: HashMap<String, displays::ServerDisplayConfiguration>,
+
+    /// Ed25519 public keys of updaters, in base64, keyed by name. If any are
+    /// listed, status updates sent by clients must be signed by one of them.
+    /// Updates arriving through the web, Twitter, and so on aren't affected.
+    #[serde(default)]
+    updater_keys: HashMap<String, String>,
+
+    /// The address that the servers listen on. Inside a container, this
+    /// usually needs to be 0.0.0.0.
+    #[serde(default = "default_bind_address")]
+    bind_address: Ipv4Addr,
+
+    /// How many of the latest changes to the display state to remember, so
+    /// that panels that reconnect after an outage can be told how many they
+    /// missed.
+    #[serde(default = "default_recent_frames")]
+    recent_frames: usize,
+
+    /// Whether to log as plain text or as JSON.
+    #[serde(default = "logging::default_log_format")]
+    log_format: logging::LogFormat,
+
+    /// If set, write the log to a file rather than standard output, starting
+    /// a new file as it grows.
+    #[serde(default)]
+    log: Option<logging::ServerLogConfiguration>,
+
+    /// If set, relative paths to the history, notes, and moderation files are
+    /// taken relative to this directory. Point it at a writable volume if the
+    /// rest of the filesystem is read-only.
+    #[serde(default)]
+    state_dir: Option<PathBuf>,
+}
+
+fn default_bind_address() -> Ipv4Addr {
+    Ipv4Addr::new(127, 0, 0, 1)
+}
+
+fn default_recent_frames() -> usize {
+    20
+}
+
+impl ServerConfiguration {
+    fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::load_layered(Some(path.as_ref()))
+    }
+
+    /// Load the configuration from an optional file, with settings overridden
+    /// by environment variables as described in the `envvars` module.
+    fn load_layered(path: Option<&Path>) -> Result<Self, Error> {
+        let mut value = match path {
+            Some(p) => {
+                let mut f = File::open(p)?;
+                let mut buf = Vec::new();
+                f.read_to_end(&mut buf)?;
+                toml::from_slice(&buf[..])?
+            }
+
+            None => toml::Value::Table(Default::default()),
+        };
+
+        envvars::apply_overrides(&mut value, std::env::vars());
+        let mut config: Self = value.try_into()?;
+
+        if let Some(ref dir) = config.state_dir {
+            if let Some(ref mut p) = config.history_path {
+                *p = dir.join(&p);
+            }
+
+            if let Some(ref mut n) = config.notes {
+                n.path = dir.join(&n.path);
+            }
+
+            if let Some(ref mut m) = config.moderation {
+                m.path = dir.join(&m.path);
+            }
+
+            if let Some(ref mut p) = config.preview {
+                p.path = dir.join(&p.path);
+            }
+
+            if let Some(ref mut c) = config.counters {
+                c.path = dir.join(&c.path);
+            }
+
+            if let Some(ref mut q) = config.status_queue {
+                q.path = dir.join(&q.path);
+            }
+
+            if let Some(ref mut o) = config.notifications.outbox {
+                o.path = dir.join(&o.path);
+            }
+
+            if let Some(ref mut p) = config.tokens_path {
+                *p = dir.join(&p);
+            }
+
+            if let Some(ref mut l) = config.log {
+                l.path = dir.join(&l.path);
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Decide whether someone presenting the given token may do something
+    /// that requires the given role.
+    ///
+    /// Some features have their own tokens that predate roles. If such a
+    /// token is specified, it's also honored, and the feature isn't opened up
+    /// to everyone just because no role tokens are configured.
+    fn authorize(&self, token: Option<&str>, needed: Role, feature_token: Option<&str>) -> Access {
+        if let Some(ft) = feature_token {
+            if token.map(|t| auth::tokens_match(ft, t)).unwrap_or(false) {
+                return Access::Granted(None);
+            }
+
+            if !self.has_tokens() {
+                return Access::Denied;
+            }
+        }
+
+        if !self.has_tokens() {
+            return Access::Granted(None);
+        }
+
+        // If we can't tell which tokens have been issued, nobody gets in on
+        // one.
+        let issued = match self.issued_tokens() {
+            Ok(i) => i,
+            Err(e) => {
+                log!("error loading issued tokens: {}", e);
+                return Access::Denied;
+            }
+        };
+
+        auth::check_token(&self.tokens, &issued, token, needed)
+    }
+
+    fn token_store(&self) -> Result<TokenStore, GenericError> {
+        match self.tokens_path {
+            Some(ref p) => Ok(TokenStore::new(p.clone())),
+            None => Err("the server configuration does not specify a tokens_path".into()),
+        }
+    }
+
+    /// The tokens issued from the command line. We reread them every time so
+    /// that revocations take effect immediately.
+    fn issued_tokens(&self) -> Result<Vec<IssuedToken>, Error> {
+        match self.tokens_path {
+            Some(ref p) => TokenStore::new(p.clone()).load(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Whether access control is enabled, which it is if the configuration
+    /// lists any tokens or says where to keep issued ones, however many of
+    /// those there are right now.
+    fn has_tokens(&self) -> bool {
+        !self.tokens.is_empty() || self.tokens_path.is_some()
+    }
+
+    /// The source to record for an update sent over the stickynote protocol
+    /// with the given token, given the source that the client claimed.
+    /// Clients like the hotkey daemon and the video call watcher say what
+    /// they are, but since those sources take precedence over others, the
+    /// token has to grant them, unless it's an admin's. Other names, like
+    /// that of a script, are taken as they are.
+    ///
+    /// Without access control, anyone can claim anything but the hub's own
+    /// sources, just as anyone can do anything else.
+    fn stickyproto_source(&self, claimed: Option<String>, token: Option<&str>) -> String {
+        let claimed = match claimed {
+            Some(s) => s,
+            None => return STICKYPROTO_SOURCE.to_owned(),
+        };
+
+        let is_hub_source = HUB_SOURCES.contains(&claimed.as_str());
+
+        if !is_hub_source && source_priority(Some(&claimed)) == 0 {
+            return claimed;
+        }
+
+        if !self.has_tokens() {
+            return if is_hub_source {
+                STICKYPROTO_SOURCE.to_owned()
+            } else {
+                claimed
+            };
+        }
+
+        let issued = match self.issued_tokens() {
+            Ok(i) => i,
+            Err(e) => {
+                log!("error loading issued tokens: {}", e);
+                return STICKYPROTO_SOURCE.to_owned();
+            }
+        };
+
+        match token.and_then(|t| auth::find_holder(&self.tokens, &issued, t)) {
+            Some(h) if h.role == Role::Admin || h.sources.contains(&claimed) => claimed,
+            _ => STICKYPROTO_SOURCE.to_owned(),
+        }
+    }
+
+    /// Check the signature on a status update sent by a client, if updater
+    /// keys are configured. Signatures older than a few minutes are refused,
+    /// so that old updates can't be replayed.
+    fn check_signature(&self, msg: &PersonIsUpdateHelloMessage) -> Result<(), String> {
+        if self.updater_keys.is_empty() {
+            return Ok(());
+        }
+
+        let sig = match msg.signature {
+            Some(ref s) => s,
+            None => return Err("update is not signed".to_owned()),
+        };
+
+        let key = match self.updater_keys.get(&sig.key_name) {
+            Some(k) => k,
+            None => return Err(format!("unknown updater key `{}`", sig.key_name)),
+        };
+
+        msg.verify_signature(key).map_err(|e| e.to_string())?;
+
+        let age = chrono::Utc::now().signed_duration_since(msg.timestamp);
+
+        if age.num_minutes().abs() > MAX_SIGNATURE_AGE_MINUTES {
+            return Err(format!(
+                "signed update is stale (timestamp {})",
+                msg.timestamp
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn note_box(&self) -> Result<NoteBox, GenericError> {
+        match self.notes {
+            Some(ref n) => Ok(NoteBox::new(n)),
+            None => Err("the server configuration does not have a [notes] section".into()),
+        }
+    }
+
+    fn pending_queue(&self) -> Result<PendingQueue, GenericError> {
+        match self.moderation {
+            Some(ref m) => Ok(PendingQueue::new(m)),
+            None => Err("the server configuration does not have a [moderation] section".into()),
+        }
+    }
+
+    fn preview_draft(&self) -> Result<PreviewDraft, GenericError> {
+        match self.preview {
+            Some(ref p) => Ok(PreviewDraft::new(p)),
+            None => Err("the server configuration does not have a [preview] section".into()),
+        }
+    }
+
+    fn counter_store(&self) -> Result<CounterStore, GenericError> {
+        match self.counters {
+            Some(ref c) => Ok(CounterStore::new(c)),
+            None => Err("the server configuration does not have a [counters] section".into()),
+        }
+    }
+
+    fn outbox(&self) -> Result<outbox::Outbox, GenericError> {
+        match self.notifications.outbox {
+            Some(ref o) => Ok(outbox::Outbox::new(o)),
+            None => Err(
+                "the server configuration does not have a [notifications.outbox] section".into(),
+            ),
+        }
+    }
+
+    fn status_queue(&self) -> Result<StatusQueue, GenericError> {
+        match self.status_queue {
+            Some(ref q) => Ok(StatusQueue::new(q)),
+            None => Err("the server configuration does not have a [status_queue] section".into()),
+        }
+    }
+
+    /// Get a status ready to be shown: as it is, if it's valid, or shortened
+    /// to fit, if it's a bit too long and shortening is configured. Returns
+    /// None if the status isn't usable. Once the status has been accepted,
+    /// call `record_shortening`.
+    fn fit_status(&self, person_is: &str) -> Option<String> {
+        if is_person_is_valid(person_is) {
+            return Some(person_is.to_owned());
+        }
+
+        if sealed::is_sealed(person_is) {
+            return None;
+        }
+
+        let shortened = self.shortening.as_ref()?.shorten(person_is)?;
+        log!("auto-shortened \"{}\" to \"{}\"", person_is, shortened);
+        Some(shortened)
+    }
+
+    /// Note in the history that `fit_status` shortened a status, if it did.
+    /// This waits until the status has been accepted, so that the history
+    /// doesn't mention shortened statuses that never went anywhere.
+    fn record_shortening(&self, original: &str, person_is: &str, source: Option<&str>) {
+        if original == person_is {
+            return;
+        }
+
+        History::new(self.history_path.clone()).record(HistoryEvent::AutoShortened {
+            timestamp: chrono::Utc::now(),
+            original: original.to_owned(),
+            person_is: person_is.to_owned(),
+            source: source.map(str::to_owned),
+        });
+    }
+
+    /// Send a status update along to the displays. It's first run through
+    /// the content filter, if there is one; and if it's moderated, it's
+    /// queued up for approval instead. Both go by `via`, the way that the
+    /// update reached the hub, and the holder of its token, rather than by
+    /// the source that it claims.
+    async fn submit_update(
+        &self,
+        mut msg: PersonIsUpdateHelloMessage,
+        via: &str,
+        send_updates: &UpdateHub,
+    ) -> Result<Submission, GenericError> {
+        // The filter can't see inside sealed statuses, so they pass through.
+        if let Some(ref f) = self.content_filter {
+            if f.applies_to(via, msg.set_by.as_deref()) && !sealed::is_sealed(&msg.person_is) {
+                match f.apply(&msg.person_is) {
+                    Ok(text) => msg.person_is = text,
+                    Err(reason) => {
+                        log!("content filter rejected \"{}\": {}", msg.person_is, reason);
+                        return Ok(Submission::Rejected(reason));
+                    }
+                }
+            }
+        }
+
+        if let Some(ref m) = self.moderation {
+            if m.is_moderated(via, msg.set_by.as_deref()) {
+                let desc = format!(
+                    "\"{}\" from {}",
+                    msg.person_is,
+                    msg.source.as_deref().unwrap_or("unknown")
+                );
+                let id = PendingQueue::new(m).add(msg)?;
+                log!("holding update #{} for approval: {}", id, desc);
+                self.notifications
+                    .notify("Status update awaiting approval", &desc);
+                return Ok(Submission::Queued);
+            }
+        }
+
+        if send_updates
+            .submit(DisplayStateMutation::SetPersonIs(msg))
+            .await?
+        {
+            return Ok(Submission::Sent);
+        }
+
+        let holder = send_updates
+            .current()
+            .lock
+            .map(|l| l.source)
+            .unwrap_or_else(|| "someone".to_owned());
+        Ok(Submission::Locked(holder))
+    }
+}
+
+/// What became of a status update submitted to the hub.
+#[derive(Clone, Debug)]
+enum Submission {
+    /// It was sent along to the displays.
+    Sent,
+
+    /// It's awaiting approval.
+    Queued,
+
+    /// The content filter rejected it, for the given reason.
+    Rejected(String),
+
+    /// Updates from its source are locked out by the given source.
+    Locked(String),
+}
+
+impl Submission {
+    /// Whether the update was taken in, rather than turned away.
+    fn is_accepted(&self) -> bool {
+        matches!(self, Submission::Sent | Submission::Queued)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ServerTwitterConfiguration {
+    env_name: String,
+    webhook_url: String,
+    allowed_sender_id: String,
+    consumer_api_key: String,
+    consumer_api_secret_key: String,
+    access_token: String,
+    access_token_secret: String,
+}
+
+impl ServerTwitterConfiguration {
+    /// The token for acting as the account that the hub is set up with, as
+    /// when replying to direct messages.
+    fn token(&self) -> egg_mode::Token {
+        egg_mode::Token::Access {
+            consumer: egg_mode::KeyPair::new(
+                self.consumer_api_key.clone(),
+                self.consumer_api_secret_key.clone(),
+            ),
+            access: egg_mode::KeyPair::new(
+                self.access_token.clone(),
+                self.access_token_secret.clone(),
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ServerDoorbellConfiguration {
+    /// A secret that must be supplied to ring the doorbell over HTTP.
+    token: String,
+}
+
+/// How long the panel shows the doorbell card after a ring.
+const DOORBELL_DISPLAY_SECONDS: i64 = 60;
+
+/// How far a signed update's timestamp may be from the hub's clock.
+const MAX_SIGNATURE_AGE_MINUTES: i64 = 10;
+
+/// The most keys that the display state's extras may have, so that they
+/// can't grow without bound.
+const MAX_EXTRAS: usize = 100;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct ServerState {
+    twitter: ServerTwitterState,
+}
+
+impl Default for ServerState {
+    fn default() -> Self {
+        ServerState {
+            twitter: ServerTwitterState::default(),
+        }
+    }
+}
+
+impl ServerState {
+    fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut f = File::open(path)?;
+        let mut buf = Vec::new();
+        f.read_to_end(&mut buf)?;
+        Ok(toml::from_slice(&buf[..])?)
+    }
+
+    fn try_load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        use std::io::ErrorKind::NotFound;
+
+        match File::open(path) {
+            Ok(mut f) => {
+                let mut buf = Vec::new();
+                f.read_to_end(&mut buf)?;
+                Ok(toml::from_slice(&buf[..])?)
+            }
+
+            Err(e) => {
+                if e.kind() == NotFound {
+                    Ok(ServerState::default())
+                } else {
+                    Err(e.into())
+                }
+            }
+        }
+    }
+
+    fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), GenericError> {
+        let mut f = File::create(path)?;
+        let data = toml::to_string(self)?;
+        f.write_all(data.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct ServerTwitterState {
+    access_token: String,
+    access_token_secret: String,
+}
+
+impl Default for ServerTwitterState {
+    fn default() -> Self {
+        ServerTwitterState {
+            access_token: "invalid".to_owned(),
+            access_token_secret: "invalid".to_owned(),
+        }
+    }
+}
+
+impl ServerTwitterState {
+    fn get_token(&self, config: &ServerConfiguration) -> egg_mode::Token {
+        let con_token = egg_mode::KeyPair::new(
+            config.twitter.consumer_api_key.clone(),
+            config.twitter.consumer_api_secret_key.clone(),
+        );
+
+        let access_token =
+            egg_mode::KeyPair::new(self.access_token.clone(), self.access_token_secret.clone());
+
+        egg_mode::Token::Access {
+            consumer: con_token,
+            access: access_token,
+        }
+    }
+}
+
+// "counter" subcommands
+
+/// Ask the running hub to change a counter, so that the panels hear about it.
+/// Returns the counter's new value.
+async fn send_counter_action(
+    config: &ServerConfiguration,
+    action: &str,
+    fields: &[(&str, &str)],
+) -> Result<String, GenericError> {
+    let mut form = url::form_urlencoded::Serializer::new(String::new());
+
+    for (name, value) in fields {
+        form.append_pair(name, value);
+    }
+
+    if let Some(t) = config.tokens.iter().find(|t| t.role >= Role::Updater) {
+        form.append_pair("token", &t.token);
+    }
+
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri(format!(
+            "http://127.0.0.1:{}/api/counters/{}",
+            config.http_port, action
+        ))
+        .header(
+            hyper::header::CONTENT_TYPE,
+            "application/x-www-form-urlencoded",
+        )
+        .body(Body::from(form.finish()))?;
+
+    let resp = http_client::https_client().request(req).await?;
+    let status = resp.status();
+    let body = hyper::body::to_bytes(resp.into_body()).await?;
+    let body = String::from_utf8_lossy(&body).into_owned();
+
+    if !status.is_success() {
+        return Err(format!("the hub refused the change: {}: {}", status, body).into());
+    }
+
+    Ok(body)
+}
+
+#[derive(Debug, StructOpt)]
+pub struct CounterCreateCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(help = "The name of the new counter")]
+    name: String,
+
+    #[structopt(help = "What to show next to the counter, like \"days since the last incident\"")]
+    label: String,
+}
+
+impl CounterCreateCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        send_counter_action(
+            &config,
+            "create",
+            &[("name", &self.name), ("label", &self.label)],
+        )
+        .await?;
+        println!("created counter `{}`", self.name);
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct CounterIncrementCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(help = "The name of the counter")]
+    name: String,
+
+    #[structopt(
+        long = "by",
+        default_value = "1",
+        help = "How much to add to the counter"
+    )]
+    by: i64,
+}
+
+impl CounterIncrementCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        let value = send_counter_action(
+            &config,
+            "increment",
+            &[("name", &self.name), ("by", &self.by.to_string())],
+        )
+        .await?;
+        println!("counter `{}` is now {}", self.name, value);
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct CounterResetCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(help = "The name of the counter")]
+    name: String,
+}
+
+impl CounterResetCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        send_counter_action(&config, "reset", &[("name", &self.name)]).await?;
+        println!("counter `{}` is now 0", self.name);
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct CounterListCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+}
+
+impl CounterListCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        let counters = config.counter_store()?.load()?;
+
+        if counters.is_empty() {
+            println!("No counters.");
+        }
+
+        for c in &counters {
+            println!("{}: {} {}", c.name, c.value, c.label);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum CounterCommand {
+    #[structopt(name = "create")]
+    /// Add a new counter, starting at zero
+    Create(CounterCreateCommand),
+
+    #[structopt(name = "increment")]
+    /// Add to a counter
+    Increment(CounterIncrementCommand),
+
+    #[structopt(name = "list")]
+    /// Print the counters and their values
+    List(CounterListCommand),
+
+    #[structopt(name = "reset")]
+    /// Set a counter back to zero
+    Reset(CounterResetCommand),
+}
+
+impl CounterCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        match self {
+            CounterCommand::Create(opts) => opts.cli().await,
+            CounterCommand::Increment(opts) => opts.cli().await,
+            CounterCommand::List(opts) => opts.cli().await,
+            CounterCommand::Reset(opts) => opts.cli().await,
+        }
+    }
+}
+
+// "history export" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct HistoryExportCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(
+        long = "since",
+        help = "Only export updates made on or after this date (YYYY-MM-DD, local time)"
+    )]
+    since: Option<chrono::NaiveDate>,
+
+    #[structopt(long = "source", help = "Only export updates from this source")]
+    source: Option<String>,
+
+    #[structopt(
+        long = "set-by",
+        help = "Only export updates set by the holder of this named token"
+    )]
+    set_by: Option<String>,
+
+    #[structopt(
+        long = "status-contains",
+        help = "Only export updates whose status contains this text"
+    )]
+    status_contains: Option<String>,
+
+    #[structopt(
+        long = "format",
+        default_value = "csv",
+        possible_values = &["csv", "json"],
+        help = "The output format"
+    )]
+    format: String,
+}
+
+impl HistoryExportCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+
+        if config.history_path.is_none() {
+            return Err("the server configuration does not specify a history_path".into());
+        }
+
+        let history = History::new(config.history_path);
+        let mut records = Vec::new();
+
+        for event in history.load()? {
+            if let HistoryEvent::StatusUpdate {
+                timestamp,
+                person_is,
+                source,
+                set_by,
+                ..
+            } = event
+            {
+                if let Some(since) = self.since {
+                    if timestamp.with_timezone(&chrono::Local).naive_local().date() < since {
+                        continue;
+                    }
+                }
+
+                if let Some(ref want) = self.source {
+                    if source.as_ref() != Some(want) {
+                        continue;
+                    }
+                }
+
+                if let Some(ref want) = self.set_by {
+                    if set_by.as_ref() != Some(want) {
+                        continue;
+                    }
+                }
+
+                if let Some(ref text) = self.status_contains {
+                    if !person_is.contains(text.as_str()) {
+                        continue;
+                    }
+                }
+
+                records.push(history::StatusRecord {
+                    timestamp,
+                    person_is,
+                    source,
+                    set_by,
+                });
+            }
+        }
+
+        let stdout = stdout();
+        let mut out = stdout.lock();
+
+        if self.format == "json" {
+            serde_json::to_writer_pretty(&mut out, &records)?;
+            writeln!(out)?;
+        } else {
+            history::write_csv(&mut out, &records)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum HistoryCommand {
+    #[structopt(name = "export")]
+    /// Dump the status update history as CSV or JSON
+    Export(HistoryExportCommand),
+}
+
+impl HistoryCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        match self {
+            HistoryCommand::Export(opts) => opts.cli().await,
+        }
+    }
+}
+
+// "notes list" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct NotesListCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+}
+
+impl NotesListCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        let notebox = config.note_box()?;
+        let notes = notebox.load()?;
+
+        if notes.is_empty() {
+            println!("No notes waiting.");
+        }
+
+        for note in &notes {
+            println!(
+                "{} from {}:\n    {}",
+                note.timestamp
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M"),
+                note.from,
+                note.text
+            );
+        }
+
+        Ok(())
+    }
+}
+
+// "notes clear" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct NotesClearCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+}
+
+impl NotesClearCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        config.note_box()?.clear()?;
+        println!("cleared all notes");
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum NotesCommand {
+    #[structopt(name = "list")]
+    /// Print the notes that visitors have left
+    List(NotesListCommand),
+
+    #[structopt(name = "clear")]
+    /// Throw away all of the notes that visitors have left
+    Clear(NotesClearCommand),
+}
+
+impl NotesCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        match self {
+            NotesCommand::List(opts) => opts.cli().await,
+            NotesCommand::Clear(opts) => opts.cli().await,
+        }
+    }
+}
+
+// "outbox list" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct OutboxListCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+}
+
+impl OutboxListCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        let entries = config.outbox()?.load()?;
+
+        if entries.is_empty() {
+            println!("Nothing is waiting to be tried again.");
+        }
+
+        for e in &entries {
+            println!(
+                "#{} {}, first tried at {}:\n    failed {} times, next try at {}; last error: {}",
+                e.id,
+                e.delivery.describe(),
+                e.created
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M"),
+                e.attempts,
+                e.next_attempt
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M"),
+                e.last_error
+            );
+        }
+
+        Ok(())
+    }
+}
+
+// "outbox purge" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct OutboxPurgeCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(help = "The ID of the delivery to throw away; if omitted, all of them are")]
+    id: Option<u64>,
+}
+
+impl OutboxPurgeCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        config.outbox()?;
+
+        // Only the running hub changes the outbox, so that it can't lose
+        // track of deliveries that it's in the middle of trying.
+        let mut form = url::form_urlencoded::Serializer::new(String::new());
+
+        if let Some(id) = self.id {
+            form.append_pair("id", &id.to_string());
+        }
+
+        if let Some(t) = config.tokens.iter().find(|t| t.role == Role::Admin) {
+            form.append_pair("token", &t.token);
+        }
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(format!(
+                "http://127.0.0.1:{}/api/outbox/purge",
+                config.http_port
+            ))
+            .header(
+                hyper::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(Body::from(form.finish()))?;
+
+        let resp = http_client::https_client().request(req).await?;
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body()).await?;
+        let body = String::from_utf8_lossy(&body).into_owned();
+
+        if !status.is_success() {
+            return Err(format!("the hub refused the purge: {}: {}", status, body).into());
+        }
+
+        let n: usize = body.trim().parse()?;
+
+        match self.id {
+            Some(id) if n == 0 => return Err(format!("no delivery with ID {}", id).into()),
+            Some(id) => println!("threw away delivery #{}", id),
+            None => println!("threw away {} deliveries", n),
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum OutboxCommand {
+    #[structopt(name = "list")]
+    /// Print the notifications and webhook calls waiting to be tried again
+    List(OutboxListCommand),
+
+    #[structopt(name = "purge")]
+    /// Ask the running hub to throw away deliveries waiting to be tried again
+    Purge(OutboxPurgeCommand),
+}
+
+impl OutboxCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        match self {
+            OutboxCommand::List(opts) => opts.cli().await,
+            OutboxCommand::Purge(opts) => opts.cli().await,
+        }
+    }
+}
+
+// "panel-command" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct PanelCommandCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(
+        help = "The command: redraw, clear, restart, screensaver, or layout:<name>",
+        parse(try_from_str)
+    )]
+    command: PanelCommand,
+}
+
+impl PanelCommandCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+
+        // Like approvals, commands go through the running hub.
+        let mut form = url::form_urlencoded::Serializer::new(String::new());
+        form.append_pair("command", &self.command.to_string());
+
+        if let Some(t) = config.tokens.iter().find(|t| t.role == Role::Admin) {
+            form.append_pair("token", &t.token);
+        }
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://127.0.0.1:{}/api/command", config.http_port))
+            .header(
+                hyper::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(Body::from(form.finish()))?;
+
+        let resp = http_client::https_client().request(req).await?;
+
+        if !resp.status().is_success() {
+            return Err(format!("the hub refused the command: {}", resp.status()).into());
+        }
+
+        println!("sent command to the panels");
+        Ok(())
+    }
+}
+
+// "pending list" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct PendingListCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+}
+
+impl PendingListCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        let pending = config.pending_queue()?.load()?;
+
+        if pending.is_empty() {
+            println!("No updates awaiting approval.");
+        }
+
+        for p in &pending {
+            println!(
+                "#{} at {} from {}{}:\n    {}",
+                p.id,
+                p.received
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M"),
+                p.update.source.as_deref().unwrap_or("unknown"),
+                p.update
+                    .set_by
+                    .as_ref()
+                    .map(|s| format!(" ({})", s))
+                    .unwrap_or_default(),
+                p.update.person_is
+            );
+        }
+
+        Ok(())
+    }
+}
+
+// "pending approve" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct PendingApproveCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(help = "The ID of the update to approve")]
+    id: u64,
+}
+
+impl PendingApproveCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+
+        // The update has to get to the displays, so we ask the running hub to
+        // approve it, with an admin token if one is needed.
+        let mut form = url::form_urlencoded::Serializer::new(String::new());
+        form.append_pair("id", &self.id.to_string());
+
+        if let Some(t) = config.tokens.iter().find(|t| t.role == Role::Admin) {
+            form.append_pair("token", &t.token);
+        }
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(format!(
+                "http://127.0.0.1:{}/pending/approve",
+                config.http_port
+            ))
+            .header(
+                hyper::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(Body::from(form.finish()))?;
+
+        let resp = http_client::https_client().request(req).await?;
+
+        if !resp.status().is_success() {
+            return Err(format!("the hub refused the approval: {}", resp.status()).into());
+        }
+
+        println!("approved update #{}", self.id);
+        Ok(())
+    }
+}
+
+// "pending reject" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct PendingRejectCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(help = "The ID of the update to reject")]
+    id: u64,
+}
+
+impl PendingRejectCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+
+        match config.pending_queue()?.take(self.id)? {
+            Some(_) => println!("rejected update #{}", self.id),
+            None => return Err(format!("no pending update with ID {}", self.id).into()),
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum PendingCommand {
+    #[structopt(name = "list")]
+    /// Print the updates awaiting approval
+    List(PendingListCommand),
+
+    #[structopt(name = "approve")]
+    /// Approve an update, sending it to the displays
+    Approve(PendingApproveCommand),
+
+    #[structopt(name = "reject")]
+    /// Throw away an update without showing it
+    Reject(PendingRejectCommand),
+}
+
+impl PendingCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        match self {
+            PendingCommand::List(opts) => opts.cli().await,
+            PendingCommand::Approve(opts) => opts.cli().await,
+            PendingCommand::Reject(opts) => opts.cli().await,
+        }
+    }
+}
+
+// "replay" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct ReplayCommand {
+    #[structopt(help = "The path to the configuration file of the hub to replay into")]
+    config_path: PathBuf,
+
+    #[structopt(help = "The path to the recording made with `serve --record`")]
+    recording_path: PathBuf,
+
+    #[structopt(
+        long = "speed",
+        default_value = "1",
+        help = "How many times faster than the original traffic to go"
+    )]
+    speed: f64,
+}
+
+impl ReplayCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+
+        if self.speed <= 0. {
+            return Err("the replay speed must be positive".into());
+        }
+
+        // If the server listens on all interfaces, loopback will do.
+        let host = if config.bind_address.is_unspecified() {
+            Ipv4Addr::LOCALHOST
+        } else {
+            config.bind_address
+        };
+
+        // The recording doesn't include tokens, so use one of the test hub's.
+        let token = config
+            .tokens
+            .iter()
+            .find(|t| t.role >= Role::Updater)
+            .map(|t| t.token.clone());
+
+        let records = recording::load(&self.recording_path)?;
+        recording::replay(records, host, config.stickyproto_port, token, self.speed).await
+    }
+}
+
+// "serve" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct ServeCommand {
+    #[structopt(
+        help = "The path to the server configuration file; if omitted, settings come from STICKYNOTE_HUB_* environment variables"
+    )]
+    config_path: Option<PathBuf>,
+
+    #[structopt(
+        long = "record",
+        help = "Record the hellos received and frames sent to this file, for the `replay` command"
+    )]
+    record: Option<PathBuf>,
+
+    #[structopt(long = "pid-file", help = "Write the server's process ID to this file")]
+    pid_file: Option<PathBuf>,
+}
+
+/// The hub's view of the display state. The authoritative copy lives in the
+/// `UpdateHub`, which applies `DisplayStateMutation`s to it and hands out
+/// copies of the latest version to the tasks that care.
+#[derive(Clone, Debug, Default)]
+struct HubDisplayState {
+    /// What gets sent to the displays.
+    display: DisplayMessage,
+
+    /// If set, updates from sources of lower priority than the lock holder
+    /// are ignored.
+    lock: Option<SourceLock>,
+
+    /// If set, the displays aren't sent updates until maintenance is over.
+    maintenance: bool,
+
+    /// A draft status to show on test panels.
+    preview: Option<PersonIsUpdateHelloMessage>,
+
+    /// The status update that was most recently accepted, as it came in.
+    last_update: Option<PersonIsUpdateHelloMessage>,
+}
+
+#[derive(Clone, Debug)]
+struct SourceLock {
+    /// The source holding the lock.
+    source: String,
+
+    /// When the lock expires, in case whoever took it never releases it.
+    until: chrono::DateTime<chrono::Utc>,
+}
+
+/// How much precedence updates from different sources take. Explicit
+/// updates from the command line, hotkeys, or chat, and video calls noticed
+/// on the desktop, beat the automatic calendar integration, which beats
+/// everything else.
+fn source_priority(source: Option<&str>) -> u8 {
+    match source {
+        Some(ADMIN_SOURCE) => 3,
+        Some("command line")
+        | Some(HOTKEY_SOURCE)
+        | Some(VIDEO_CALL_SOURCE)
+        | Some(slack::SOURCE)
+        | Some(TWITTER_SOURCE) => 2,
+        Some(calendar::SOURCE) => 1,
+        _ => 0,
+    }
+}
+
+/// The source name attached to updates and locks made by admins over the
+/// HTTP API.
+const ADMIN_SOURCE: &str = "admin";
+
+/// The source name attached to other updates made over the HTTP API.
+const HTTP_API_SOURCE: &str = "HTTP API";
+
+/// The source name attached to updates sent as Twitter direct messages.
+const TWITTER_SOURCE: &str = "Twitter";
+
+/// The source name attached to updates made over the stickynote protocol
+/// that don't say where they're from, or that claim to be from somewhere
+/// they can't be.
+const STICKYPROTO_SOURCE: &str = "stickynote protocol";
+
+/// The sources that the hub attaches to updates itself. A client can't claim
+/// that its updates come from one of these, or from any other source that
+/// takes precedence, unless its token grants it, since that would get them
+/// past locks, filters, and moderation that are meant to hold them back.
+const HUB_SOURCES: &[&str] = &[
+    ADMIN_SOURCE,
+    HTTP_API_SOURCE,
+    TWITTER_SOURCE,
+    STICKYPROTO_SOURCE,
+    slack::SOURCE,
+    calendar::SOURCE,
+    defaults::SOURCE,
+];
+
+#[derive(Clone, Debug)]
+enum DisplayStateMutation {
+    SetPersonIs(PersonIsUpdateHelloMessage),
+    SetLock(Option<SourceLock>),
+    SetNotesWaiting(usize),
+    RingDoorbell(DoorbellHelloMessage),
+    SetHeadlines(Vec<String>),
+    SendCommand(PanelCommandMessage),
+    SetMaintenance(bool),
+    SetPreview(Option<PersonIsUpdateHelloMessage>),
+    SetAvailability(Availability),
+    SetCiStatus(CiStatus),
+    SetOnCall(Vec<OnCall>),
+    SetOfficeDays(Vec<OfficeDays>),
+    SetRoom(RoomSchedule),
+    AddRoomBooking(Booking),
+    RemoveRoomBooking(Booking),
+    SetCounter(Counter, Option<String>),
+    MergeExtras(BTreeMap<String, serde_json::Value>),
+    SetNextStatus(Option<QueuedStatus>),
+}
+
+/// Merge new values into the display state's extras. A null value removes
+/// its key.
+fn merge_extras(
+    extras: &mut BTreeMap<String, serde_json::Value>,
+    patch: BTreeMap<String, serde_json::Value>,
+) {
+    for (key, value) in patch {
+        if value.is_null() {
+            extras.remove(&key);
+        } else {
+            extras.insert(key, value);
+        }
+    }
+}
+
+impl DisplayStateMutation {
+    /// Apply the mutation defined by this value to the specified state
+    /// object, consuming this value in the process. Returns false if the
+    /// mutation was rejected because of a lock.
+    pub fn consume_into(self, state: &mut HubDisplayState) -> bool {
+        match self {
+            DisplayStateMutation::SetPersonIs(msg) => {
+                if let Some(ref lock) = state.lock {
+                    if chrono::Utc::now() < lock.until
+                        && source_priority(msg.source.as_deref())
+                            < source_priority(Some(&lock.source))
+                    {
+                        return false;
+                    }
+                }
+
+                state.last_update = Some(msg.clone());
+                state.display.person_is = msg.person_is;
+                state.display.person_is_timestamp = msg.timestamp;
+                state.display.person_is_source = msg.source.unwrap_or_default();
+                state.display.person_is_set_by = msg.set_by.unwrap_or_default();
+            }
+
+            DisplayStateMutation::SetLock(lock) => {
+                state.lock = lock;
+            }
+
+            DisplayStateMutation::SetNotesWaiting(n) => {
+                state.display.notes_waiting = n;
+            }
+
+            DisplayStateMutation::RingDoorbell(msg) => {
+                state.display.doorbell_until =
+                    Some(msg.timestamp + chrono::Duration::seconds(DOORBELL_DISPLAY_SECONDS));
+            }
+
+            DisplayStateMutation::SetHeadlines(headlines) => {
+                state.display.headlines = headlines;
+            }
+
+            DisplayStateMutation::SendCommand(msg) => {
+                state.display.command = Some(msg);
+            }
+
+            DisplayStateMutation::SetMaintenance(on) => {
+                state.maintenance = on;
+            }
+
+            DisplayStateMutation::SetPreview(draft) => {
+                state.preview = draft;
+            }
+
+            DisplayStateMutation::SetAvailability(availability) => {
+                state.display.availability = Some(availability);
+            }
+
+            DisplayStateMutation::SetCiStatus(status) => {
+                let ci = &mut state.display.ci;
+
+                match ci.iter_mut().find(|s| s.label == status.label) {
+                    Some(s) => *s = status,
+                    None => ci.push(status),
+                }
+            }
+
+            DisplayStateMutation::SetOnCall(on_call) => {
+                state.display.on_call = on_call;
+            }
+
+            DisplayStateMutation::SetOfficeDays(office_days) => {
+                state.display.office_days = office_days;
+            }
+
+            DisplayStateMutation::SetRoom(schedule) => {
+                state.display.room = Some(schedule);
+            }
+
+            DisplayStateMutation::AddRoomBooking(booking) => {
+                if let Some(ref mut room) = state.display.room {
+                    let i = room.bookings.partition_point(|b| b.start <= booking.start);
+                    room.bookings.insert(i, booking);
+                }
+            }
+
+            DisplayStateMutation::RemoveRoomBooking(booking) => {
+                if let Some(ref mut room) = state.display.room {
+                    room.bookings.retain(|b| *b != booking);
+                }
+            }
+
+            DisplayStateMutation::SetCounter(counter, _) => {
+                let counters = &mut state.display.counters;
+
+                match counters.iter_mut().find(|c| c.name == counter.name) {
+                    Some(c) => *c = counter,
+                    None => counters.push(counter),
+                }
+            }
+
+            DisplayStateMutation::MergeExtras(patch) => {
+                merge_extras(&mut state.display.extras, patch);
+            }
+
+            DisplayStateMutation::SetNextStatus(next) => {
+                state.display.next_status = next;
+            }
+        }
+
+        true
+    }
+
+    /// Describe this mutation for the history log, if it's something worth
+    /// logging.
+    pub fn to_history_event(&self) -> Option<HistoryEvent> {
+        match self {
+            DisplayStateMutation::SetPersonIs(msg) => Some(HistoryEvent::StatusUpdate {
+                timestamp: msg.timestamp,
+                person_is: msg.person_is.clone(),
+                source: msg.source.clone(),
+                set_by: msg.set_by.clone(),
+                signed_by: msg.signature.as_ref().map(|s| s.key_name.clone()),
+            }),
+
+            DisplayStateMutation::SetLock(_) => None,
+            DisplayStateMutation::SetNotesWaiting(_) => None,
+            DisplayStateMutation::RingDoorbell(_) => None,
+            DisplayStateMutation::SetHeadlines(_) => None,
+            DisplayStateMutation::SendCommand(_) => None,
+            DisplayStateMutation::SetMaintenance(_) => None,
+            DisplayStateMutation::SetPreview(_) => None,
+            DisplayStateMutation::SetAvailability(_) => None,
+            DisplayStateMutation::SetCiStatus(_) => None,
+            DisplayStateMutation::SetOnCall(_) => None,
+            DisplayStateMutation::SetOfficeDays(_) => None,
+            DisplayStateMutation::SetRoom(_) => None,
+            DisplayStateMutation::AddRoomBooking(_) => None,
+            DisplayStateMutation::RemoveRoomBooking(_) => None,
+            DisplayStateMutation::MergeExtras(_) => None,
+            DisplayStateMutation::SetNextStatus(_) => None,
+
+            DisplayStateMutation::SetCounter(counter, set_by) => {
+                Some(HistoryEvent::CounterChange {
+                    timestamp: chrono::Utc::now(),
+                    name: counter.name.clone(),
+                    value: counter.value,
+                    set_by: set_by.clone(),
+                })
+            }
+        }
+    }
+}
+
+impl ServeCommand {
+    pub async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load_layered(self.config_path.as_deref())?;
+        let hub = embed::Hub::new(config)?;
+
+        if let Some(ref path) = self.record {
+            recording::start(path)?;
+            log!("recording traffic to `{}`", path.display());
+        }
+
+        if let Some(ref path) = self.pid_file {
+            std::fs::write(path, format!("{}\n", std::process::id()))?;
+        }
+
+        let result = hub.serve().await;
+
+        if let Some(ref path) = self.pid_file {
+            let _ = std::fs::remove_file(path);
+        }
+
+        result
+    }
+}
+
+/// Get ready to serve: set up logging, and check that the state directory
+/// is usable.
+fn prepare(config: &ServerConfiguration) {
+    logging::set_format(config.log_format);
+    html::set_base_path(&config.http.base_path());
+
+    if let Some(ref l) = config.log {
+        if let Err(e) = logging::set_file(l) {
+            log!(
+                "warning: cannot write the log to `{}`: {}",
+                l.path.display(),
+                e
+            );
+        }
+    }
+
+    // If the state directory isn't writable, things will fail piecemeal
+    // later on, so give a heads-up now. Not fatal, since perhaps no state
+    // needs saving.
+
+    if config.render.is_some() && !render::AVAILABLE {
+        log!("warning: `[render]` is configured, but this hub was built without the `render` feature");
+    }
+
+    if let Some(ref dir) = config.state_dir {
+        let probe = dir.join(".stickynote-probe");
+
+        if let Err(e) = File::create(&probe).and_then(|_| std::fs::remove_file(&probe)) {
+            log!(
+                "warning: state directory `{}` is not writable: {}",
+                dir.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Work out the display state to start with, from what's been saved and
+/// what's configured.
+fn initial_state(config: &ServerConfiguration) -> Result<HubDisplayState, GenericError> {
+    let mut display_state = HubDisplayState::default();
+
+    // If visitors can leave notes, we need to check for notes left over
+    // from before.
+
+    if let Some(ref n) = config.notes {
+        display_state.display.note_form_url = Some(n.form_url.clone());
+    }
+
+    if let Ok(nb) = config.note_box() {
+        display_state.display.notes_waiting = nb.load()?.len();
+    }
+
+    // Likewise any draft status being previewed.
+
+    if let Ok(pd) = config.preview_draft() {
+        display_state.preview = pd.load()?;
+    }
+
+    // And the counters.
+
+    if let Ok(cs) = config.counter_store() {
+        display_state.display.counters = cs.load()?;
+    }
+
+    // And the status queue.
+
+    if let Ok(sq) = config.status_queue() {
+        display_state.display.next_status = queue::next_status(&sq.load()?);
+    }
+
+    // Start from the default status for the time of day.
+
+    if let Some(ref defaults_config) = config.default_status {
+        let msg = defaults::update_at(Some(defaults_config), chrono::Utc::now());
+        display_state.display.person_is = msg.person_is;
+        display_state.display.person_is_timestamp = msg.timestamp;
+        display_state.display.person_is_source = defaults::SOURCE.to_owned();
+    }
+
+    // And whether we're in office hours.
+
+    if let Some(ref hours) = config.office_hours {
+        display_state.display.availability = Some(hours.availability_at(chrono::Local::now()));
+    }
+
+    Ok(display_state)
+}
+
+/// Run the hub's servers and background tasks, forever.
+async fn serve(
+    config: ServerConfiguration,
+    history: History,
+    send_updates: UpdateHub,
+) -> Result<(), GenericError> {
+    // We periodically recheck the number of notes, since they might be
+    // cleared from the command line.
+
+    let notebox = config.note_box().ok();
+
+    // We also keep track of whether any panels are connected, so that we
+    // can send a notification if they all go away for too long.
+
+    let n_displays = Arc::new(AtomicUsize::new(0));
+    let connected_displays = displays::ConnectedDisplays::default();
+    let mut displays_gone_since = Some(time::Instant::now());
+    let mut offline_notified = false;
+
+    let mut housekeeping_interval = time::interval(Duration::from_secs(60));
+
+    // Start applying the changes that come in from outside. Until this is
+    // running, they wait in the queue.
+
+    {
+        let send_updates = send_updates.clone();
+        supervisor::spawn_restarting("state loop", move || send_updates.clone().run());
+    }
+
+    // Start the calendar monitor, if configured.
+
+    if let Some(ref cal_config) = config.calendar {
+        let cal_config = cal_config.clone();
+        let send_updates = send_updates.clone();
+        let history = history.clone();
+        supervisor::spawn_restarting("calendar monitor", move || {
+            calendar::run(cal_config.clone(), send_updates.clone(), history.clone())
+        });
+    }
+
+    // And the team calendars.
+
+    if let Some(ref team_config) = config.team {
+        let team_config = team_config.clone();
+        let send_updates = send_updates.clone();
+        supervisor::spawn_restarting("team calendars", move || {
+            calendar::run_team(team_config.clone(), send_updates.clone())
+        });
+    }
+
+    // And the meeting room's calendar.
+
+    if let Some(ref room_config) = config.room {
+        let room_config = room_config.clone();
+        let send_updates = send_updates.clone();
+        supervisor::spawn_restarting("room calendar", move || {
+            rooms::run(room_config.clone(), send_updates.clone())
+        });
+    }
+
+    // And the default-status clock.
+
+    if let Some(ref defaults_config) = config.default_status {
+        let defaults_config = defaults_config.clone();
+        let send_updates = send_updates.clone();
+        supervisor::spawn_restarting("default status", move || {
+            defaults::run(defaults_config.clone(), send_updates.clone())
+        });
+    }
+
+    // Likewise the office-hours clock.
+
+    if let Some(ref hours_config) = config.office_hours {
+        let hours_config = hours_config.clone();
+        let send_updates = send_updates.clone();
+        supervisor::spawn_restarting("office hours", move || {
+            hours::run(hours_config.clone(), send_updates.clone())
+        });
+    }
+
+    // And the on-call poller.
+
+    if let Some(ref on_call_config) = config.on_call {
+        let on_call_config = on_call_config.clone();
+        let send_updates = send_updates.clone();
+        supervisor::spawn_restarting("on-call poller", move || {
+            oncall::run(on_call_config.clone(), send_updates.clone())
+        });
+    }
+
+    // And the news poller.
+
+    if let Some(ref news_config) = config.news {
+        let news_config = news_config.clone();
+        let send_updates = send_updates.clone();
+        supervisor::spawn_restarting("news poller", move || {
+            news::run(news_config.clone(), send_updates.clone())
+        });
+    }
+
+    // And the status queue.
+
+    if let Ok(status_queue) = config.status_queue() {
+        let config = config.clone();
+        let send_updates = send_updates.clone();
+        supervisor::spawn_restarting("status queue", move || {
+            queue::run(status_queue.clone(), config.clone(), send_updates.clone())
+        });
+    }
+
+    // And the retrying of failed notifications.
+
+    if let Ok(outbox) = config.outbox() {
+        let config = config.clone();
+        supervisor::spawn_restarting("outbox", move || {
+            outbox::run(outbox.clone(), config.clone())
+        });
+    }
+
+    // And the relay to another hub.
+
+    if let Some(ref relay_config) = config.relay {
+        let relay_config = relay_config.clone();
+        let send_updates = send_updates.clone();
+        supervisor::spawn_restarting("hub relay", move || {
+            relay::run(relay_config.clone(), send_updates.clone())
+        });
+    }
+
+    // And the MQTT bridge.
+
+    if let Some(ref mqtt_config) = config.mqtt {
+        let mqtt_config = mqtt_config.clone();
+        let config = config.clone();
+        let send_updates = send_updates.clone();
+        let history = history.clone();
+        supervisor::spawn_restarting("MQTT bridge", move || {
+            mqtt::run(
+                mqtt_config.clone(),
+                config.clone(),
+                send_updates.clone(),
+                history.clone(),
+            )
+        });
+    }
+
+    // Set up the stickynote protocol server
+
+    let sp_host = config.bind_address;
+    let mut sp_listener = TcpListener::from_std(
+        listen::listen("stickyproto", 0, sp_host, config.stickyproto_port).await?,
+    )?;
+    let mut sp_incoming = sp_listener.incoming();
+    log!(
+        "Stickynote protocol server running on {}:{}",
+        sp_host,
+        config.stickyproto_port
+    );
+
+    // Panel clients in the same process, if there are any, connect
+    // without going through the network.
+
+    let mut local_incoming = inproc::incoming();
+
+    // Set up the GraphQL schema, if wanted.
+
+    let schema = config.graphql.as_ref().map(|_| {
+        graphql::build_schema(
+            send_updates.clone(),
+            history.clone(),
+            connected_displays.clone(),
+        )
+    });
+
+    // Set up the HTTP server
+
+    let http_host = sp_host;
+    let http_config = config.clone();
+    let http_send_updates = send_updates.clone();
+    let http_history = history.clone();
+
+    // The server is started through the supervisor so that it comes back
+    // if it ever dies; each time, it gets a new handle to the listening
+    // socket.
+
+    let http_listener = listen::listen("http", 1, http_host, config.http_port).await?;
+    log!("HTTP server running on {}:{}", http_host, config.http_port);
+    service::notify("READY=1");
+    let feed_watchdog = service::watchdog_enabled();
+
+    supervisor::spawn_restarting("HTTP server", move || {
+        let listener = http_listener.try_clone();
+        let http_config = http_config.clone();
+        let http_send_updates = http_send_updates.clone();
+        let http_history = http_history.clone();
+        let schema = schema.clone();
+
+        let http_service = make_service_fn(move |conn: &AddrStream| {
+            let http_config = http_config.clone();
+            let send_updates = http_send_updates.clone();
+            let history = http_history.clone();
+            let schema = schema.clone();
+            let peer = conn.remote_addr();
+
+            async move {
+                Ok::<_, GenericError>(service_fn(move |req| {
+                    handle_http_request(
+                        req,
+                        peer,
+                        http_config.clone(),
+                        send_updates.clone(),
+                        history.clone(),
+                        schema.clone(),
+                    )
+                }))
+            }
+        });
+
+        async move {
+            Server::from_tcp(listener?)?.serve(http_service).await?;
+            Ok::<_, GenericError>(())
+        }
+    });
+
+    // Stickynote event loop
+
+    loop {
+        select! {
+            maybe_socket = sp_incoming.next().fuse() => {
+                match maybe_socket {
+                    Some(Ok(sock)) => {
+                        let peer = match sock.peer_addr() {
+                            Ok(addr) => addr.to_string(),
+                            Err(_) => "unknown".to_owned(),
+                        };
+
+                        match handle_new_stickyproto_connection(sock, peer, send_updates.clone(), history.clone(), n_displays.clone(), connected_displays.clone(), config.clone()) {
+                            Ok(_) => {}
+                            Err(e) => {
+                                log!("error while setting up new connection: {:?}", e);
+                            }
+                        }
+                    },
+
+                    Some(Err(err)) => {
+                        // Handle error by printing to STDOUT.
+                        log!("accept error = {:?}", err);
+                    },
+
+                    None => {
+                        log!("socket ran out??");
+                    },
+                }
+            },
+
+            maybe_local = next_local_connection(&mut local_incoming).fuse() => {
+                let result = UnixStream::from_std(maybe_local).and_then(|sock| {
+                    handle_new_stickyproto_connection(sock, "this process".to_owned(), send_updates.clone(), history.clone(), n_displays.clone(), connected_displays.clone(), config.clone())
+                });
+
+                if let Err(e) = result {
+                    log!("error while setting up new in-process connection: {:?}", e);
+                }
+            },
+
+            _ = housekeeping_interval.tick().fuse() => {
+                if feed_watchdog {
+                    service::notify("WATCHDOG=1");
+                }
+
+                if n_displays.load(Ordering::SeqCst) > 0 {
+                    if offline_notified {
+                        config.notifications.notify("Panel back online", "A panel has reconnected to the hub.");
+                    }
+
+                    displays_gone_since = None;
+                    offline_notified = false;
+                } else {
+                    let since = *displays_gone_since.get_or_insert_with(time::Instant::now);
+                    let limit = config.notifications.panel_offline_minutes;
+
+                    if limit > 0 && !offline_notified && since.elapsed() > Duration::from_secs(limit * 60) {
+                        config.notifications.notify(
+                            "Panel offline",
+                            &format!("No panel has been connected to the hub for {} minutes.", limit),
+                        );
+                        offline_notified = true;
+                    }
+                }
+
+                if let Some(ref nb) = notebox {
+                    match nb.load() {
+                        Ok(notes) => {
+                            if notes.len() != send_updates.current().display.notes_waiting {
+                                send_updates.send(DisplayStateMutation::SetNotesWaiting(notes.len()));
+                            }
+                        },
+
+                        Err(e) => {
+                            log!("error checking notes: {}", e);
+                        },
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// Wait for the next connection from a panel client in this process. If
+/// there can't be any, this waits forever.
+async fn next_local_connection(
+    incoming: &mut Option<tokio::sync::mpsc::UnboundedReceiver<std::os::unix::net::UnixStream>>,
+) -> std::os::unix::net::UnixStream {
+    if let Some(rx) = incoming {
+        if let Some(sock) = rx.recv().await {
+            return sock;
+        }
+    }
+
+    *incoming = None;
+    futures::future::pending().await
+}
+
+fn handle_new_stickyproto_connection<S>(
+    socket: S,
+    peer: String,
+    send_updates: UpdateHub,
+    history: History,
+    n_displays: Arc<AtomicUsize>,
+    connected_displays: displays::ConnectedDisplays,
+    config: ServerConfiguration,
+) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    log!("Accepted stickyproto connection from {}", peer);
+
+    supervisor::spawn(
+        format!("stickyproto connection from {}", peer),
+        async move {
+            // Receive the initial "hello" message from the client.
+
+            let mut session = match AwaitingHello::new(socket).receive_hello().await? {
+                Session::Display(s) => s,
+
+                Session::Updater(s) => {
+                    recording::inbound(&peer, s.hello());
+                    let (hello, reply) = s.into_parts();
+
+                    return match handle_oneshot_hello(hello, &config, &send_updates, &history).await
+                    {
+                        Ok(()) => Ok(()),
+
+                        Err(frame) => {
+                            // Older clients don't wait around for an answer,
+                            // so it's no surprise if this fails.
+                            let message = frame.message.clone();
+                            let _ = reply.reject(frame).await;
+                            Err(Error::new(std::io::ErrorKind::Other, message))
+                        }
+                    };
+                }
+            };
+
+            // If we're still here, the client is a displayer and we should keep
+            // it updated.
+
+            let hello = session.hello().clone();
+            recording::inbound(&peer, &ClientHelloMessage::Display(hello.clone()));
+            let (display_id, capabilities) = (hello.display_id, hello.capabilities);
+            let mut missed = hello.last_update.and_then(|t| send_updates.missed_since(t));
+            let overrides = display_id.as_ref().and_then(|id| config.displays.get(id));
+
+            if let Some(ref id) = display_id {
+                log!(
+                    "display {} identifies as `{}`{}",
+                    peer,
+                    id,
+                    if overrides.is_some() {
+                        ""
+                    } else {
+                        " (no settings configured)"
+                    }
+                );
+            }
+
+            let mut receive_updates = send_updates.subscribe();
+
+            // The subscription starts off with the current state.
+            let mut display_state = receive_updates.recv().await.unwrap_or_default();
+
+            let connected_at = chrono::Utc::now();
+            n_displays.fetch_add(1, Ordering::SeqCst);
+            connected_displays.add(displays::ConnectedDisplay {
+                peer: peer.clone(),
+                display_id: display_id.clone(),
+                connected_at,
+                capabilities: capabilities.clone(),
+            });
+            history.record(HistoryEvent::DisplayConnected {
+                timestamp: connected_at,
+                peer: peer.clone(),
+                capabilities: capabilities.clone(),
+                display_id: display_id.clone(),
+            });
+
+            if let Some(ref caps) = capabilities {
+                log!("display {} reports {}", peer, caps);
+            }
+
+            if let Some(ref m) = missed {
+                log!(
+                    "display {} missed {} update(s) since {}",
+                    peer,
+                    m.count,
+                    m.since.to_rfc3339()
+                );
+            }
+
+            // We'll make sure to send the client an update at least this often. The
+            // interval will fire immediately, which means that the client will get an
+            // update right off the bat, as desired.
+            let mut interval = time::interval(Duration::from_millis(1200_000));
+            let mut gate = maintenance::MaintenanceGate::default();
+
+            let result = loop {
+                let mut incoming = None;
+
+                select! {
+                    _ = interval.tick().fuse() => {},
+
+                    maybe_update = receive_updates.next().fuse() => {
+                        match maybe_update {
+                            Some(state) => {
+                                display_state = state;
+                            },
+
+                            None => {
+                                log!("client receive_updates ran out??");
+                            },
+                        }
+                    },
+
+                    frame = session.next_frame().fuse() => {
+                        incoming = Some(frame);
+                    },
+                }
+
+                // Something from a multiplexing panel. It doesn't change what
+                // the panel should show, so there's nothing to send after.
+
+                if let Some(frame) = incoming {
+                    match frame {
+                        Ok(Some(ClientFrame::Telemetry(hello))) => {
+                            recording::inbound(&peer, &hello);
+
+                            if let Err(frame) =
+                                handle_oneshot_hello(hello, &config, &send_updates, &history).await
+                            {
+                                log!("ignoring telemetry from display {}: {}", peer, frame);
+                            }
+                        }
+
+                        Ok(Some(ClientFrame::Control(request))) => {
+                            recording::inbound(&peer, &request.hello);
+                            let error = handle_oneshot_hello(
+                                request.hello,
+                                &config,
+                                &send_updates,
+                                &history,
+                            )
+                            .await
+                            .err();
+
+                            if let Err(e) = session
+                                .reply(ControlReply {
+                                    id: request.id,
+                                    error,
+                                })
+                                .await
+                            {
+                                log!("error communicating with client: {}", e);
+                                break Err(e);
+                            }
+                        }
+
+                        Ok(Some(ClientFrame::Unknown)) => {}
+
+                        Ok(None) => {
+                            log!("display {} hung up", peer);
+                            break Ok(());
+                        }
+
+                        Err(e) => {
+                            log!("error communicating with client: {}", e);
+                            break Err(e);
+                        }
+                    }
+
+                    continue;
+                }
+
+                let mut msg = match capabilities {
+                    Some(ref caps) => display_state.display.tailored_for(caps),
+                    None => display_state.display.clone(),
+                };
+
+                if let Some(o) = overrides {
+                    o.apply(&mut msg);
+                    o.show_preview(&mut msg, display_state.preview.as_ref());
+                }
+
+                let mut msg = match gate.filter(msg, display_state.maintenance) {
+                    Some(m) => m,
+                    None => continue,
+                };
+
+                msg.sent_at = Some(chrono::Utc::now());
+                msg.missed = missed.take();
+
+                recording::outbound(&peer, &msg);
+
+                if let Err(e) = session.send(msg).await {
+                    log!("error communicating with client: {}", e);
+                    log!("giving up on it");
+                    break Err(e);
+                }
+            };
+
+            n_displays.fetch_sub(1, Ordering::SeqCst);
+            connected_displays.remove(&peer);
+            history.record(HistoryEvent::DisplayDisconnected {
+                timestamp: chrono::Utc::now(),
+                peer,
+                connected_at,
+                display_id,
+            });
+
+            result
+        },
+    );
+
+    Ok(())
+}
+
+/// Handle a "hello" from a client that's just telling us something, rather
+/// than sticking around to receive display updates. If we won't act on it,
+/// the error says why, in a form that we can send back to the client.
+async fn handle_oneshot_hello(
+    hello: ClientHelloMessage,
+    config: &ServerConfiguration,
+    send_updates: &UpdateHub,
+    history: &History,
+) -> Result<(), ErrorFrame> {
+    match hello {
+        ClientHelloMessage::PersonIsUpdate(mut msg) => {
+            let token = msg.token.take();
+
+            match config.authorize(token.as_deref(), Role::Updater, None) {
+                Access::Granted(Some(name)) => msg.set_by = Some(name),
+                Access::Granted(None) => {}
+                Access::Denied => {
+                    return Err(ErrorFrame::new(
+                        ErrorCode::Unauthorized,
+                        "PersonIsUpdate message lacked a valid token; ignoring",
+                    ));
+                }
+            }
+
+            if let Err(reason) = config.check_signature(&msg) {
+                return Err(ErrorFrame::new(
+                    ErrorCode::BadSignature,
+                    format!("PersonIsUpdate message signature rejected: {}", reason),
+                ));
+            }
+
+            if let Some(ref sig) = msg.signature {
+                log!("PersonIsUpdate message signed by `{}`", sig.key_name);
+            }
+
+            // The signature covers the source, so this has to come after
+            // checking it.
+            msg.source = Some(config.stickyproto_source(msg.source.take(), token.as_deref()));
+
+            // Changing a signed status would break its signature, so those
+            // have to be valid as they are.
+
+            let original = msg.person_is.clone();
+
+            let fitted = if msg.signature.is_some() {
+                Some(msg.person_is.clone()).filter(|p| is_person_is_valid(p))
+            } else {
+                config.fit_status(&msg.person_is)
+            };
+
+            match fitted {
+                Some(person_is) => msg.person_is = person_is,
+                None => {
+                    return Err(ErrorFrame::new(
+                        ErrorCode::InvalidStatus,
+                        "PersonIsUpdate message didn't validate -- likely too long; ignoring",
+                    ));
+                }
+            }
+
+            // Just accept the update and we're done.
+            let person_is = msg.person_is.clone();
+            let source = msg.source.clone();
+
+            match config
+                .submit_update(msg, STICKYPROTO_SOURCE, send_updates)
+                .await
+            {
+                Ok(Submission::Rejected(reason)) => Err(ErrorFrame::new(
+                    ErrorCode::Filtered,
+                    format!("PersonIsUpdate message was filtered out: {}", reason),
+                )),
+                Ok(Submission::Locked(holder)) => Err(ErrorFrame::new(
+                    ErrorCode::Locked,
+                    format!("updates are locked by {}; ignoring", holder),
+                )),
+                Ok(_) => {
+                    config.record_shortening(&original, &person_is, source.as_deref());
+                    Ok(())
+                }
+                Err(e) => Err(ErrorFrame::new(ErrorCode::Internal, e.to_string())),
+            }
+        }
+
+        ClientHelloMessage::Doorbell(mut msg) => {
+            if let Access::Denied =
+                config.authorize(msg.token.take().as_deref(), Role::Updater, None)
+            {
+                return Err(ErrorFrame::new(
+                    ErrorCode::Unauthorized,
+                    "Doorbell message lacked a valid token; ignoring",
+                ));
+            }
+
+            send_updates.send(DisplayStateMutation::RingDoorbell(msg));
+            Ok(())
+        }
+
+        ClientHelloMessage::SensorReading(mut msg) => {
+            if let Access::Denied =
+                config.authorize(msg.token.take().as_deref(), Role::Updater, None)
+            {
+                return Err(ErrorFrame::new(
+                    ErrorCode::Unauthorized,
+                    "SensorReading message lacked a valid token; ignoring",
+                ));
+            }
+
+            history.record(HistoryEvent::SensorReading {
+                timestamp: msg.timestamp,
+                co2_ppm: msg.co2_ppm,
+                temperature_c: msg.temperature_c,
+                humidity_percent: msg.humidity_percent,
+                display_id: msg.display_id,
+            });
+            Ok(())
+        }
+
+        ClientHelloMessage::SystemHealth(mut msg) => {
+            if let Access::Denied =
+                config.authorize(msg.token.take().as_deref(), Role::Updater, None)
+            {
+                return Err(ErrorFrame::new(
+                    ErrorCode::Unauthorized,
+                    "SystemHealth message lacked a valid token; ignoring",
+                ));
+            }
+
+            if let Some(flags) = msg.throttled_flags.filter(|f| f & 0xF != 0) {
+                log!(
+                    "panel {} reports under-voltage or throttling (flags {:#x})",
+                    msg.display_id.as_deref().unwrap_or("(unidentified)"),
+                    flags
+                );
+            }
+
+            history.record(HistoryEvent::SystemHealth {
+                timestamp: msg.timestamp,
+                cpu_temperature_c: msg.cpu_temperature_c,
+                throttled_flags: msg.throttled_flags,
+                display_id: msg.display_id,
+            });
+            Ok(())
+        }
+
+        ClientHelloMessage::BookRoom(mut msg) => {
+            if let Access::Denied =
+                config.authorize(msg.token.take().as_deref(), Role::Updater, None)
+            {
+                return Err(ErrorFrame::new(
+                    ErrorCode::Unauthorized,
+                    "BookRoom message lacked a valid token; ignoring",
+                ));
+            }
+
+            let room_config = match config.room {
+                Some(ref r) => r,
+                None => {
+                    return Err(ErrorFrame::new(
+                        ErrorCode::Unavailable,
+                        "this hub isn't watching a room",
+                    ));
+                }
+            };
+
+            rooms::book(
+                room_config,
+                msg.minutes,
+                msg.display_id.as_deref(),
+                send_updates,
+            )
+            .map_err(|reason| ErrorFrame::new(ErrorCode::Unavailable, reason))
+        }
+
+        // Display clients stick around, so the caller takes care of them.
+        ClientHelloMessage::Display(_) => Ok(()),
+    }
+}
+
+async fn handle_http_request(
+    mut req: Request<Body>,
+    peer: SocketAddr,
+    config: ServerConfiguration,
+    send_updates: UpdateHub,
+    history: History,
+    schema: Option<graphql::HubSchema>,
+) -> Result<Response<Body>, GenericError> {
+    let client = config.http.client_addr(&req, peer);
+    req.extensions_mut().insert(proxy::ClientAddr(client));
+
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_owned());
+
+    let mut resp = if req.method() == Method::OPTIONS {
+        // A CORS preflight request; the headers are all that matter.
+        no_content()?
+    } else {
+        route_http_request(req, config.clone(), send_updates, history, schema).await?
+    };
+
+    config.http.add_cors_headers(origin.as_deref(), &mut resp);
+    Ok(resp)
+}
+
+async fn route_http_request(
+    req: Request<Body>,
+    config: ServerConfiguration,
+    send_updates: UpdateHub,
+    history: History,
+    schema: Option<graphql::HubSchema>,
+) -> Result<Response<Body>, GenericError> {
+    let path = config.http.route_path(req.uri().path());
+
+    match (req.method(), path.as_str()) {
+        (&Method::GET, "/healthz") => handle_healthz_get(),
+
+        (&Method::GET, "/metrics") => handle_metrics_get(),
+
+        (&Method::GET, "/stats") => handle_stats_get(req, &config, &history),
+
+        (&Method::POST, "/api/status") => handle_api_status_post(req, &config, send_updates).await,
+
+        (&Method::GET, "/api/status/fit") => handle_api_status_fit_get(req, &config),
+
+        (&Method::POST, "/api/status/undo") => {
+            handle_api_status_undo_post(req, &config, send_updates, &history).await
+        }
+
+        (&Method::GET, "/api/status/queue") => handle_api_status_queue_get(req, &config),
+
+        (&Method::POST, "/api/status/queue") => {
+            handle_api_status_queue_post(req, &config, send_updates).await
+        }
+
+        (&Method::DELETE, "/api/status/queue") => {
+            handle_api_status_queue_delete(req, &config, send_updates)
+        }
+
+        (&Method::POST, "/api/lock") => handle_api_lock_post(req, &config, send_updates).await,
+
+        (&Method::DELETE, "/api/lock") => handle_api_lock_delete(req, &config, send_updates),
+
+        (&Method::POST, "/api/maintenance") => {
+            handle_api_maintenance(req, &config, send_updates, true)
+        }
+
+        (&Method::DELETE, "/api/maintenance") => {
+            handle_api_maintenance(req, &config, send_updates, false)
+        }
+
+        (&Method::POST, "/api/command") => {
+            handle_api_command_post(req, &config, send_updates).await
+        }
+
+        (&Method::POST, "/api/counters/create") => {
+            handle_api_counters_post(req, &config, send_updates, CounterAction::Create).await
+        }
+
+        (&Method::POST, "/api/counters/increment") => {
+            handle_api_counters_post(req, &config, send_updates, CounterAction::Increment).await
+        }
+
+        (&Method::POST, "/api/counters/reset") => {
+            handle_api_counters_post(req, &config, send_updates, CounterAction::Reset).await
+        }
+
+        (&Method::POST, "/api/outbox/purge") => handle_api_outbox_purge_post(req, &config).await,
+
+        (&Method::POST, "/api/extras") => handle_api_extras_post(req, &config, send_updates).await,
+
+        (&Method::POST, "/api/room/book") => handle_api_room_book_post(req, &config, send_updates),
+
+        (&Method::GET, "/panels") => handle_panels_get(req, &config),
+
+        (&Method::GET, "/graphql") | (&Method::POST, "/graphql") => {
+            handle_graphql(req, &config, schema.as_ref()).await
+        }
+
+        (&Method::GET, "/pending") => handle_pending_get(req, &config),
+
+        (&Method::GET, "/render/current.png") => {
+            handle_render_current_get(req, &config, send_updates).await
+        }
+
+        (&Method::POST, "/pending/approve") => {
+            handle_pending_post(req, &config, send_updates, true).await
+        }
+
+        (&Method::POST, "/pending/reject") => {
+            handle_pending_post(req, &config, send_updates, false).await
+        }
+
+        (&Method::GET, "/preview") => handle_preview_get(req, &config),
+
+        (&Method::POST, "/preview/draft") => {
+            handle_preview_post(req, &config, send_updates, PreviewAction::Draft).await
+        }
+
+        (&Method::POST, "/preview/promote") => {
+            handle_preview_post(req, &config, send_updates, PreviewAction::Promote).await
+        }
+
+        (&Method::POST, "/preview/discard") => {
+            handle_preview_post(req, &config, send_updates, PreviewAction::Discard).await
+        }
+
+        (&Method::POST, "/doorbell") => handle_doorbell_post(req, &config, send_updates),
+
+        (&Method::GET, "/notes") => handle_notes_get(req, &config),
+
+        (&Method::GET, "/notes/new") => handle_notes_new_get(&config),
+
+        (&Method::POST, "/notes/new") => handle_notes_new_post(req, &config, send_updates).await,
+
+        (&Method::POST, "/notes/clear") => {
+            handle_notes_clear_post(req, &config, send_updates).await
+        }
+
+        (&Method::POST, "/webhooks/ci") => handle_ci_webhook_post(req, &config, send_updates).await,
+
+        (&Method::POST, "/webhooks/slack") => {
+            handle_slack_webhook_post(req, &config, send_updates, &history).await
+        }
+
+        (&Method::GET, "/webhooks/twitter") => handle_twitter_webhook_get(req, &config).await,
+
+        (&Method::POST, "/webhooks/twitter") => {
+            handle_twitter_webhook_post(req, &config, send_updates, &history).await
+        }
+
+        _ => Ok(Response::builder()
+            .status(hyper::StatusCode::NOT_FOUND)
+            .body((&b"not found"[..]).into())
+            .unwrap()),
+    }
+}
+
+/// A liveness check for container orchestrators. If we can answer at all,
+/// we're alive.
+fn handle_healthz_get() -> Result<Response<Body>, GenericError> {
+    Ok(Response::builder()
+        .status(hyper::StatusCode::OK)
+        .body((&b"ok"[..]).into())?)
+}
+
+/// Counters about the hub's own health, in the Prometheus text format.
+fn handle_metrics_get() -> Result<Response<Body>, GenericError> {
+    let text = format!(
+        "# HELP stickynote_hub_task_panics_total Background tasks that have panicked.\n\
+         # TYPE stickynote_hub_task_panics_total counter\n\
+         stickynote_hub_task_panics_total {}\n\
+         # HELP stickynote_hub_task_restarts_total Core tasks that have been restarted.\n\
+         # TYPE stickynote_hub_task_restarts_total counter\n\
+         stickynote_hub_task_restarts_total {}\n",
+        supervisor::n_panics(),
+        supervisor::n_restarts(),
+    );
+
+    Ok(Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(text))?)
+}
+
+fn forbidden() -> Result<Response<Body>, GenericError> {
+    Ok(Response::builder()
+        .status(hyper::StatusCode::FORBIDDEN)
+        .body((&b"forbidden"[..]).into())?)
+}
+
+fn no_content() -> Result<Response<Body>, GenericError> {
+    Ok(Response::builder()
+        .status(hyper::StatusCode::NO_CONTENT)
+        .body(Body::from(""))?)
+}
+
+fn bad_request(msg: &str) -> Result<Response<Body>, GenericError> {
+    Ok(Response::builder()
+        .status(hyper::StatusCode::BAD_REQUEST)
+        .body(Body::from(msg.to_owned()))?)
+}
+
+/// Explain why a status didn't validate.
+fn status_problem(person_is: &str) -> String {
+    if person_is.starts_with(sealed::SEALED_PREFIX) {
+        return "sealed status is too long".to_owned();
+    }
+
+    format!(
+        "status is too long: it would be {} pixels wide, but the panel only has room for {}",
+        width::status_width(person_is),
+        width::STATUS_BOX_WIDTH
+    )
+}
+
+/// Explain what a length of time given to the API has to be.
+fn minutes_problem() -> String {
+    format!(
+        "expected a number of minutes from 1 to {}",
+        phrases::MAX_MINUTES
+    )
+}
+
+/// Say whether a status would fit on the panel, without setting it, so that
+/// forms and bots can warn about one that's too long before it's sent. The
+/// status goes in the `status` query parameter. If the status doesn't fit
+/// but the hub would shorten it, `shortened` says what it would become.
+fn handle_api_status_fit_get(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+) -> Result<Response<Body>, GenericError> {
+    let token = auth::request_token(&req);
+
+    if let Access::Denied = config.authorize(token.as_deref(), Role::Updater, None) {
+        return forbidden();
+    }
+
+    let query = req.uri().query().unwrap_or("");
+    let person_is = form_field(query.as_bytes(), "status").unwrap_or_default();
+
+    let fits = is_person_is_valid(&person_is);
+    let shortened = match config.shortening {
+        Some(ref shortening) if !fits && !sealed::is_sealed(&person_is) => {
+            shortening.shorten(&person_is)
+        }
+        _ => None,
+    };
+
+    let resp_json = serde_json::to_string(&json!({
+        "width": width::status_width(&person_is),
+        "max_width": width::STATUS_BOX_WIDTH,
+        "fits": fits,
+        "shortened": shortened,
+    }))?;
+
+    Ok(Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(resp_json))?)
+}
+
+/// Set the status. The new status is given in the `status` form field. If
+/// the `expires_minutes` field is given, the status goes back to what it was
+/// after that many minutes, unless something else replaces it first.
+async fn handle_api_status_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+) -> Result<Response<Body>, GenericError> {
+    let token = auth::request_token(&req);
+    let client = proxy::describe_client(&req);
+
+    let who = match config.authorize(token.as_deref(), Role::Updater, None) {
+        Access::Granted(who) => who,
+        Access::Denied => return forbidden(),
+    };
+
+    let is_admin = config.authorize(token.as_deref(), Role::Admin, None) != Access::Denied;
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let original = form_field(&body, "status").unwrap_or_default();
+
+    let source = if is_admin && config.has_tokens() {
+        ADMIN_SOURCE
+    } else {
+        HTTP_API_SOURCE
+    };
+
+    let person_is = match config.fit_status(&original) {
+        Some(p) => p,
+        None => return bad_request(&status_problem(&original)),
+    };
+
+    let expires_minutes: Option<i64> = match form_field(&body, "expires_minutes").map(|m| m.parse())
+    {
+        None => None,
+        Some(Ok(m)) if m > 0 && m <= phrases::MAX_MINUTES => Some(m),
+        _ => return bad_request(&minutes_problem()),
+    };
+
+    log!(
+        "status update via HTTP API from {} ({}): {}",
+        who.as_deref().unwrap_or("anonymous"),
+        client,
+        person_is
+    );
+
+    let msg = PersonIsUpdateHelloMessage {
+        person_is: person_is.clone(),
+        timestamp: chrono::Utc::now(),
+        source: Some(source.to_owned()),
+        set_by: who,
+        token: None,
+        signature: None,
+    };
+
+    recording::inbound(&client, &ClientHelloMessage::PersonIsUpdate(msg.clone()));
+
+    let previous = send_updates.current().last_update;
+    let timestamp = msg.timestamp;
+    let submission = config.submit_update(msg, source, &send_updates).await?;
+
+    if submission.is_accepted() {
+        config.record_shortening(&original, &person_is, Some(source));
+    }
+
+    match submission {
+        Submission::Sent => {
+            if let Some(minutes) = expires_minutes {
+                revert_status_later(
+                    send_updates,
+                    config.default_status.clone(),
+                    previous,
+                    timestamp,
+                    minutes,
+                );
+            }
+
+            no_content()
+        }
+
+        Submission::Queued => Ok(Response::builder()
+            .status(hyper::StatusCode::ACCEPTED)
+            .body(Body::from("update is awaiting approval"))?),
+
+        Submission::Rejected(reason) => bad_request(&reason),
+
+        Submission::Locked(holder) => {
+            Ok(Response::builder()
+                .status(hyper::StatusCode::CONFLICT)
+                .body(Body::from(format!("updates are locked by {}", holder)))?)
+        }
+    }
+}
+
+/// Put the previous status back up, as the history log remembers it, with
+/// its original timestamp and source. Returns the restored status, or why it
+/// couldn't be restored.
+async fn undo_status(
+    config: &ServerConfiguration,
+    send_updates: &UpdateHub,
+    history: &History,
+    who: Option<String>,
+) -> Result<String, UndoFailure> {
+    if config.history_path.is_none() {
+        return Err(UndoFailure::Unavailable(
+            "undoing needs the hub to keep a history log".to_owned(),
+        ));
+    }
+
+    let (current, previous) = match history.undo_target() {
+        Ok(Some(t)) => t,
+        Ok(None) => {
+            return Err(UndoFailure::Unavailable(
+                "there's no earlier status to go back to".to_owned(),
+            ))
+        }
+        Err(e) => {
+            return Err(UndoFailure::Unavailable(format!(
+                "can't read the history log: {}",
+                e
+            )))
+        }
+    };
+
+    // The undo has to go into the log ahead of the restored status, but only
+    // if the restore actually happens, so the state loop records both.
+
+    let undo = HistoryEvent::Undo {
+        timestamp: chrono::Utc::now(),
+        undone: current.person_is.clone(),
+        set_by: who.clone(),
+    };
+
+    let restore = DisplayStateMutation::SetPersonIs(PersonIsUpdateHelloMessage {
+        person_is: previous.person_is.clone(),
+        timestamp: previous.timestamp,
+        source: previous.source,
+        set_by: previous.set_by,
+        token: None,
+        signature: None,
+    });
+
+    match send_updates.submit_after(undo, restore).await {
+        Ok(true) => {}
+
+        Ok(false) => {
+            let holder = send_updates
+                .current()
+                .lock
+                .map(|l| l.source)
+                .unwrap_or_else(|| "someone".to_owned());
+            return Err(UndoFailure::Locked(holder));
+        }
+
+        Err(e) => return Err(UndoFailure::Unavailable(e.to_string())),
+    }
+
+    log!(
+        "{} undid \"{}\"; going back to: {}",
+        who.as_deref().unwrap_or("anonymous"),
+        current.person_is,
+        previous.person_is
+    );
+
+    Ok(previous.person_is)
+}
+
+/// Why the latest status change couldn't be undone.
+#[derive(Clone, Debug)]
+enum UndoFailure {
+    /// There's nothing to go back to, or no way to find out what it is.
+    Unavailable(String),
+
+    /// Updates from the earlier status's source are locked out by the given
+    /// source.
+    Locked(String),
+}
+
+impl std::fmt::Display for UndoFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UndoFailure::Unavailable(reason) => write!(f, "{}", reason),
+            UndoFailure::Locked(holder) => write!(f, "updates are locked by {}", holder),
+        }
+    }
+}
+
+/// Undo the latest status change. The response is the restored status.
+async fn handle_api_status_undo_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+    history: &History,
+) -> Result<Response<Body>, GenericError> {
+    let token = auth::request_token(&req);
+
+    let who = match config.authorize(token.as_deref(), Role::Updater, None) {
+        Access::Granted(who) => who,
+        Access::Denied => return forbidden(),
+    };
+
+    match undo_status(config, &send_updates, history, who).await {
+        Ok(person_is) => Ok(Response::builder()
+            .status(hyper::StatusCode::OK)
+            .body(Body::from(person_is))?),
+
+        Err(UndoFailure::Unavailable(reason)) => bad_request(&reason),
+
+        Err(e @ UndoFailure::Locked(_)) => Ok(Response::builder()
+            .status(hyper::StatusCode::CONFLICT)
+            .body(Body::from(e.to_string()))?),
+    }
+}
+
+/// A queued status as it appears in the API.
+#[derive(Debug, Deserialize, Serialize)]
+struct ApiQueuedStatus {
+    /// When the status goes up.
+    at: chrono::DateTime<chrono::Utc>,
+
+    /// The status.
+    status: String,
+
+    /// Where the status was queued from, which the hub fills in.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+
+    /// Who queued the status, which the hub fills in.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    set_by: Option<String>,
+}
+
+/// List the queued statuses, soonest first.
+fn handle_api_status_queue_get(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+) -> Result<Response<Body>, GenericError> {
+    let status_queue = match config.status_queue() {
+        Ok(q) => q,
+        Err(_) => return not_found(),
+    };
+
+    let token = auth::request_token(&req);
+
+    if let Access::Denied = config.authorize(token.as_deref(), Role::Observer, None) {
+        return forbidden();
+    }
+
+    let queued: Vec<ApiQueuedStatus> = status_queue
+        .load()?
+        .into_iter()
+        .map(|u| ApiQueuedStatus {
+            at: u.timestamp,
+            status: u.person_is,
+            source: u.source,
+            set_by: u.set_by,
+        })
+        .collect();
+
+    Ok(Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&queued)?))?)
+}
+
+/// Queue up statuses to go up later. The body is a JSON array of objects
+/// with `at` and `status` fields, where `at` is an RFC 3339 timestamp.
+async fn handle_api_status_queue_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+) -> Result<Response<Body>, GenericError> {
+    let status_queue = match config.status_queue() {
+        Ok(q) => q,
+        Err(_) => return not_found(),
+    };
+
+    let token = auth::request_token(&req);
+
+    let who = match config.authorize(token.as_deref(), Role::Updater, None) {
+        Access::Granted(who) => who,
+        Access::Denied => return forbidden(),
+    };
+
+    let is_admin = config.authorize(token.as_deref(), Role::Admin, None) != Access::Denied;
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+
+    let requested: Vec<ApiQueuedStatus> = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(_) => return bad_request("expected a JSON array of objects with `at` and `status`"),
+    };
+
+    let source = if is_admin && config.has_tokens() {
+        ADMIN_SOURCE
+    } else {
+        HTTP_API_SOURCE
+    };
+
+    let now = chrono::Utc::now();
+    let mut updates = Vec::new();
+    let mut originals = Vec::new();
+
+    for r in requested {
+        if r.at <= now {
+            return bad_request(&format!(
+                "\"{}\" is queued for a time in the past",
+                r.status
+            ));
+        }
+
+        let person_is = match config.fit_status(&r.status) {
+            Some(p) => p,
+            None => return bad_request(&status_problem(&r.status)),
+        };
+
+        originals.push(r.status);
+
+        updates.push(PersonIsUpdateHelloMessage {
+            person_is,
+            timestamp: r.at,
+            source: Some(source.to_owned()),
+            set_by: who.clone(),
+            token: None,
+            signature: None,
+        });
+    }
+
+    for u in &updates {
+        log!(
+            "status queued by {} for {}: {}",
+            who.as_deref().unwrap_or("anonymous"),
+            u.timestamp
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M"),
+            u.person_is
+        );
+    }
+
+    let shortenings: Vec<_> = originals
+        .into_iter()
+        .zip(updates.iter().map(|u| u.person_is.clone()))
+        .collect();
+
+    let queued = match status_queue.add(updates) {
+        Ok(q) => q,
+        Err(e) => return bad_request(&e.to_string()),
+    };
+
+    for (original, person_is) in shortenings {
+        config.record_shortening(&original, &person_is, Some(source));
+    }
+
+    send_updates.send(DisplayStateMutation::SetNextStatus(queue::next_status(
+        &queued,
+    )));
+    no_content()
+}
+
+/// Throw away all of the queued statuses.
+fn handle_api_status_queue_delete(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+) -> Result<Response<Body>, GenericError> {
+    let status_queue = match config.status_queue() {
+        Ok(q) => q,
+        Err(_) => return not_found(),
+    };
+
+    let token = auth::request_token(&req);
+
+    if let Access::Denied = config.authorize(token.as_deref(), Role::Updater, None) {
+        return forbidden();
+    }
+
+    status_queue.clear()?;
+    log!("status queue cleared");
+    send_updates.send(DisplayStateMutation::SetNextStatus(None));
+    no_content()
+}
+
+/// Once a temporary status expires, put back the one that it replaced. The
+/// temporary status is identified by its timestamp, so that if anything else
+/// has set the status in the meantime, it's left alone. If there was no
+/// status before, or only a default one, the default for the time of expiry
+/// goes up instead. Expirations don't survive a restart of the hub.
+fn revert_status_later(
+    send_updates: UpdateHub,
+    defaults_config: Option<defaults::ServerDefaultStatusConfiguration>,
+    previous: Option<PersonIsUpdateHelloMessage>,
+    timestamp: chrono::DateTime<chrono::Utc>,
+    minutes: i64,
+) {
+    // Callers keep this reasonable, but tokio panics on a delay that runs
+    // past the end of time, so let's make sure.
+    let seconds = match minutes.min(phrases::MAX_MINUTES).checked_mul(60) {
+        Some(s) if s > 0 => s as u64,
+        _ => return,
+    };
+
+    supervisor::spawn("status expiration".to_owned(), async move {
+        time::delay_for(Duration::from_secs(seconds)).await;
+
+        let current = send_updates.current().last_update;
+
+        if current.map(|m| m.timestamp) != Some(timestamp) {
+            return;
+        }
+
+        let mut msg = match previous {
+            Some(m) if m.source.as_deref() != Some(defaults::SOURCE) => m,
+            _ => defaults::update_at(defaults_config.as_ref(), chrono::Utc::now()),
+        };
+
+        log!("temporary status expired; going back to: {}", msg.person_is);
+        msg.timestamp = chrono::Utc::now();
+        msg.signature = None;
+        send_updates.send(DisplayStateMutation::SetPersonIs(msg));
+    });
+}
+
+/// Lock out all but admin updates for the number of minutes given in the
+/// `minutes` form field.
+async fn handle_api_lock_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+) -> Result<Response<Body>, GenericError> {
+    let token = auth::request_token(&req);
+
+    if let Access::Denied = config.authorize(token.as_deref(), Role::Admin, None) {
+        return forbidden();
+    }
+
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+
+    let minutes: i64 = match form_field(&body, "minutes").map(|m| m.parse()) {
+        Some(Ok(m)) if m > 0 && m <= phrases::MAX_MINUTES => m,
+        _ => return bad_request(&minutes_problem()),
+    };
+
+    let lock = SourceLock {
+        source: ADMIN_SOURCE.to_owned(),
+        until: chrono::Utc::now() + chrono::Duration::minutes(minutes),
+    };
+
+    send_updates.send(DisplayStateMutation::SetLock(Some(lock)));
+
+    no_content()
+}
+
+/// Remove any lock.
+fn handle_api_lock_delete(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+) -> Result<Response<Body>, GenericError> {
+    let token = auth::request_token(&req);
+
+    if let Access::Denied = config.authorize(token.as_deref(), Role::Admin, None) {
+        return forbidden();
+    }
+
+    send_updates.send(DisplayStateMutation::SetLock(None));
+
+    no_content()
+}
+
+/// Start or end maintenance mode. While it's on, the panels show what they
+/// had when it started, with a banner, and get caught up when it ends.
+fn handle_api_maintenance(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+    on: bool,
+) -> Result<Response<Body>, GenericError> {
+    let token = auth::request_token(&req);
+
+    let who = match config.authorize(token.as_deref(), Role::Admin, None) {
+        Access::Granted(who) => who,
+        Access::Denied => return forbidden(),
+    };
+
+    log!(
+        "maintenance mode {} by {}",
+        if on { "started" } else { "ended" },
+        who.as_deref().unwrap_or("anonymous")
+    );
+
+    send_updates.send(DisplayStateMutation::SetMaintenance(on));
+
+    no_content()
+}
+
+/// Show the updates awaiting approval, if the requester is an admin.
+/// Send a command to the panels. The command is given in the `command` form
+/// field, e.g. "redraw" or "layout:status".
+async fn handle_api_command_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+) -> Result<Response<Body>, GenericError> {
+    let token = auth::request_token(&req);
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+
+    // The admin page sends the token as a form field.
+    let token = token.or_else(|| form_field(&body, "token"));
+
+    let who = match config.authorize(token.as_deref(), Role::Admin, None) {
+        Access::Granted(who) => who,
+        Access::Denied => return forbidden(),
+    };
+
+    let command: PanelCommand = match form_field(&body, "command").map(|c| c.parse()) {
+        Some(Ok(c)) => c,
+        Some(Err(e)) => return bad_request(&e),
+        None => return bad_request("expected a command"),
+    };
+
+    log!(
+        "panel command from {}: {}",
+        who.as_deref().unwrap_or("anonymous"),
+        command
+    );
+
+    let msg = PanelCommandMessage {
+        command,
+        issued: chrono::Utc::now(),
+    };
+
+    send_updates.send(DisplayStateMutation::SendCommand(msg));
+
+    no_content()
+}
+
+/// Merge data into the display state's extras, for panel widgets to show.
+/// The body is a JSON object whose keys must be namespaced, like
+/// "weather.temperature". A null value removes its key.
+async fn handle_api_extras_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+) -> Result<Response<Body>, GenericError> {
+    let token = auth::request_token(&req);
+
+    let who = match config.authorize(token.as_deref(), Role::Updater, None) {
+        Access::Granted(who) => who,
+        Access::Denied => return forbidden(),
+    };
+
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+
+    let patch: BTreeMap<String, serde_json::Value> = match serde_json::from_slice(&body) {
+        Ok(p) => p,
+        Err(_) => return bad_request("expected a JSON object"),
+    };
+
+    if let Some(key) = patch.keys().find(|k| !is_extras_key_valid(k)) {
+        return bad_request(&format!(
+            "invalid key \"{}\": keys look like \"namespace.name\"",
+            key
+        ));
+    }
+
+    let mut merged = send_updates.current().display.extras;
+    merge_extras(&mut merged, patch.clone());
+
+    if merged.len() > MAX_EXTRAS {
+        return bad_request("too many extras");
+    }
+
+    log!(
+        "extras from {}: {}",
+        who.as_deref().unwrap_or("anonymous"),
+        patch.keys().cloned().collect::<Vec<_>>().join(", ")
+    );
+
+    send_updates.send(DisplayStateMutation::MergeExtras(patch));
+    no_content()
+}
+
+/// Book the meeting room from now, for the number of minutes in the
+/// `minutes` query parameter, or half an hour.
+fn handle_api_room_book_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+) -> Result<Response<Body>, GenericError> {
+    let room_config = match config.room {
+        Some(ref r) => r,
+        None => return not_found(),
+    };
+
+    let token = auth::request_token(&req);
+
+    if let Access::Denied = config.authorize(token.as_deref(), Role::Updater, None) {
+        return forbidden();
+    }
+
+    let minutes = match url::form_urlencoded::parse(req.uri().query().unwrap_or("").as_bytes())
+        .find(|(name, _)| name == "minutes")
+    {
+        Some((_, value)) => match value.parse() {
+            Ok(m) => m,
+            Err(_) => return bad_request("`minutes` must be a whole number"),
+        },
+        None => 30,
+    };
+
+    match rooms::book(room_config, minutes, None, &send_updates) {
+        Ok(()) => Ok(Response::builder()
+            .status(hyper::StatusCode::ACCEPTED)
+            .body(Body::from(""))?),
+
+        Err(reason) => Ok(Response::builder()
+            .status(hyper::StatusCode::CONFLICT)
+            .body(Body::from(reason))?),
+    }
+}
+
+/// What to do to a counter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CounterAction {
+    /// Create it, with the label given in the `label` form field.
+    Create,
+
+    /// Add the amount given in the `by` form field, or one.
+    Increment,
+
+    /// Set it back to zero.
+    Reset,
+}
+
+/// Change a counter. The counter is named in the `name` form field, and the
+/// response is its new value.
+async fn handle_api_counters_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+    action: CounterAction,
+) -> Result<Response<Body>, GenericError> {
+    let store = match config.counter_store() {
+        Ok(s) => s,
+        Err(_) => return not_found(),
+    };
+
+    let token = auth::request_token(&req);
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let token = token.or_else(|| form_field(&body, "token"));
+
+    let who = match config.authorize(token.as_deref(), Role::Updater, None) {
+        Access::Granted(who) => who,
+        Access::Denied => return forbidden(),
+    };
+
+    let name = form_field(&body, "name").unwrap_or_default();
+
+    let result = match action {
+        CounterAction::Create => {
+            let label = form_field(&body, "label").unwrap_or_default();
+            store.create(&name, &label)
+        }
+
+        CounterAction::Increment => {
+            let by = match form_field(&body, "by").map(|b| b.parse()) {
+                Some(Ok(b)) => b,
+                Some(Err(_)) => return bad_request("expected an integer to increment by"),
+                None => 1,
+            };
+
+            store.increment(&name, by)
+        }
+
+        CounterAction::Reset => store.reset(&name),
+    };
+
+    let counter = match result {
+        Ok(c) => c,
+
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Response::builder()
+                .status(hyper::StatusCode::NOT_FOUND)
+                .body(Body::from(e.to_string()))?)
+        }
+
+        Err(e) => return bad_request(&e.to_string()),
+    };
+
+    log!(
+        "counter `{}` set to {} by {}",
+        counter.name,
+        counter.value,
+        who.as_deref().unwrap_or("anonymous")
+    );
+
+    let value = counter.value;
+    send_updates.send(DisplayStateMutation::SetCounter(counter, who));
+
+    Ok(Response::builder()
+        .status(hyper::StatusCode::OK)
+        .body(Body::from(value.to_string()))?)
+}
+
+/// Throw away deliveries waiting in the outbox: the one given by the `id`
+/// form field, or all of them if it's absent. The response is how many were
+/// thrown away.
+async fn handle_api_outbox_purge_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+) -> Result<Response<Body>, GenericError> {
+    let outbox = match config.outbox() {
+        Ok(o) => o,
+        Err(_) => return not_found(),
+    };
+
+    let token = auth::request_token(&req);
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let token = token.or_else(|| form_field(&body, "token"));
+
+    let who = match config.authorize(token.as_deref(), Role::Admin, None) {
+        Access::Granted(who) => who,
+        Access::Denied => return forbidden(),
+    };
+
+    let id = match form_field(&body, "id").map(|i| i.parse()) {
+        None => None,
+        Some(Ok(i)) => Some(i),
+        Some(Err(_)) => return bad_request("expected a delivery ID"),
+    };
+
+    let n = outbox.purge(id)?;
+
+    log!(
+        "{} outbox deliveries purged by {}",
+        n,
+        who.as_deref().unwrap_or("anonymous")
+    );
+
+    Ok(Response::builder()
+        .status(hyper::StatusCode::OK)
+        .body(Body::from(n.to_string()))?)
+}
+
+/// Answer a GraphQL query, if the endpoint is enabled. There's no point in
+/// offering it to everybody, so it needs a token even if access control is
+/// otherwise disabled.
+async fn handle_graphql(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    schema: Option<&graphql::HubSchema>,
+) -> Result<Response<Body>, GenericError> {
+    let (gql_config, schema) = match (config.graphql.as_ref(), schema) {
+        (Some(c), Some(s)) => (c, s),
+        _ => return not_found(),
+    };
+
+    let token = auth::request_token(&req);
+
+    if gql_config.token.is_none() && !config.has_tokens() {
+        return forbidden();
+    }
+
+    if let Access::Denied = config.authorize(
+        token.as_deref(),
+        Role::Observer,
+        gql_config.token.as_deref(),
+    ) {
+        return forbidden();
+    }
+
+    graphql::handle_request(req, schema).await
+}
+
+/// Draw the current state as a PNG image, for devices that can show a
+/// picture but can't run a panel client. The `width` and `height`
+/// parameters set the size of the image, and `display_id` applies that
+/// panel's settings.
+async fn handle_render_current_get(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+) -> Result<Response<Body>, GenericError> {
+    let render_config = match config.render {
+        Some(ref c) if render::AVAILABLE => c.clone(),
+        _ => return not_found(),
+    };
+
+    let token = auth::request_token(&req);
+
+    if let Access::Denied = config.authorize(token.as_deref(), Role::Observer, None) {
+        return forbidden();
+    }
+
+    let query = req.uri().query().unwrap_or("");
+    let param = |key: &str| {
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.into_owned())
+    };
+
+    let mut dimensions = [None, None];
+
+    for (i, key) in ["width", "height"].iter().enumerate() {
+        if let Some(text) = param(key) {
+            match text.parse::<u32>() {
+                Ok(n) => dimensions[i] = Some(n),
+                Err(_) => return bad_request(&format!("invalid `{}` parameter", key)),
+            }
+        }
+    }
+
+    let (width, height) = match render::image_size(dimensions[0], dimensions[1]) {
+        Some(s) => s,
+        None => {
+            return bad_request(&format!(
+                "images can be at most {} pixels on a side",
+                render::MAX_SIZE
+            ))
+        }
+    };
+
+    let state = send_updates.current();
+    let mut msg = state.display;
+
+    if let Some(o) = param("display_id").and_then(|id| config.displays.get(&id)) {
+        o.apply(&mut msg);
+        o.show_preview(&mut msg, state.preview.as_ref());
+    }
+
+    msg.maintenance = state.maintenance;
+    msg.sent_at = Some(chrono::Utc::now());
+
+    let png_data =
+        tokio::task::spawn_blocking(move || render::render_png(&render_config, msg, width, height))
+            .await??;
+
+    Ok(Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(header::CONTENT_TYPE, "image/png")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::from(png_data))?)
+}
+
+/// A page of buttons for sending commands to the panels.
+fn handle_panels_get(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+) -> Result<Response<Body>, GenericError> {
+    let token = auth::request_token(&req).unwrap_or_default();
+
+    if let Access::Denied = config.authorize(Some(&token), Role::Admin, None) {
+        return forbidden();
+    }
+
+    html_response(hyper::StatusCode::OK, panels::render_panels_page(&token))
+}
+
+fn handle_pending_get(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+) -> Result<Response<Body>, GenericError> {
+    let queue = match config.pending_queue() {
+        Ok(q) => q,
+        Err(_) => return not_found(),
+    };
+
+    let token = auth::request_token(&req).unwrap_or_default();
+
+    if let Access::Denied = config.authorize(Some(&token), Role::Admin, None) {
+        return forbidden();
+    }
+
+    html_response(
+        hyper::StatusCode::OK,
+        moderation::render_dashboard_page(&queue.load()?, &token),
+    )
+}
+
+/// Approve or reject the pending update whose ID is given in the `id` form
+/// field.
+async fn handle_pending_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+    approve: bool,
+) -> Result<Response<Body>, GenericError> {
+    let queue = match config.pending_queue() {
+        Ok(q) => q,
+        Err(_) => return not_found(),
+    };
+
+    let header_token = auth::request_token(&req);
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let token = form_field(&body, "token")
+        .or(header_token)
+        .unwrap_or_default();
+
+    if let Access::Denied = config.authorize(Some(&token), Role::Admin, None) {
+        return forbidden();
+    }
+
+    let id = match form_field(&body, "id").and_then(|s| s.parse().ok()) {
+        Some(id) => id,
+        None => return bad_request("expected the ID of a pending update"),
+    };
+
+    let pending = match queue.take(id)? {
+        Some(p) => p,
+        None => return not_found(),
+    };
+
+    if approve {
+        log!("approved update #{}: {}", id, pending.update.person_is);
+
+        send_updates.send(DisplayStateMutation::SetPersonIs(pending.update));
+    } else {
+        log!("rejected update #{}: {}", id, pending.update.person_is);
+    }
+
+    html_response(
+        hyper::StatusCode::OK,
+        moderation::render_dashboard_page(&queue.load()?, &token),
+    )
+}
+
+/// Summarize the history log as an HTML page.
+fn handle_preview_get(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+) -> Result<Response<Body>, GenericError> {
+    let draft = match config.preview_draft() {
+        Ok(d) => d,
+        Err(_) => return not_found(),
+    };
+
+    let token = auth::request_token(&req).unwrap_or_default();
+
+    if let Access::Denied = config.authorize(Some(&token), Role::Updater, None) {
+        return forbidden();
+    }
+
+    html_response(
+        hyper::StatusCode::OK,
+        preview::render_preview_page(draft.load()?.as_ref(), &token, None),
+    )
+}
+
+/// What to do with the draft status.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PreviewAction {
+    /// Replace it with the one given in the `status` form field.
+    Draft,
+
+    /// Make it the real status.
+    Promote,
+
+    /// Get rid of it.
+    Discard,
+}
+
+async fn handle_preview_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+    action: PreviewAction,
+) -> Result<Response<Body>, GenericError> {
+    let draft = match config.preview_draft() {
+        Ok(d) => d,
+        Err(_) => return not_found(),
+    };
+
+    let header_token = auth::request_token(&req);
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let token = form_field(&body, "token")
+        .or(header_token)
+        .unwrap_or_default();
+
+    let who = match config.authorize(Some(&token), Role::Updater, None) {
+        Access::Granted(who) => who,
+        Access::Denied => return forbidden(),
+    };
+
+    let is_admin = config.authorize(Some(&token), Role::Admin, None) != Access::Denied;
+    let mut notice = None;
+
+    match action {
+        PreviewAction::Draft => {
+            let person_is = form_field(&body, "status").unwrap_or_default();
+
+            if !is_person_is_valid(&person_is) {
+                return bad_request(&status_problem(&person_is));
+            }
+
+            log!(
+                "draft status from {}: {}",
+                who.as_deref().unwrap_or("anonymous"),
+                person_is
+            );
+
+            let source = if is_admin && config.has_tokens() {
+                ADMIN_SOURCE
+            } else {
+                HTTP_API_SOURCE
+            };
+
+            let msg = PersonIsUpdateHelloMessage {
+                person_is,
+                timestamp: chrono::Utc::now(),
+                source: Some(source.to_owned()),
+                set_by: who,
+                token: None,
+                signature: None,
+            };
+
+            draft.save(Some(&msg))?;
+            send_updates.send(DisplayStateMutation::SetPreview(Some(msg)));
+        }
+
+        PreviewAction::Promote => {
+            let mut msg = match draft.load()? {
+                Some(m) => m,
+                None => return bad_request("there is no draft status"),
+            };
+
+            log!(
+                "draft status promoted by {}: {}",
+                who.as_deref().unwrap_or("anonymous"),
+                msg.person_is
+            );
+
+            msg.timestamp = chrono::Utc::now();
+            let via = msg.source.clone().unwrap_or_default();
+
+            // If the content filter rejects it, keep the draft around so that
+            // it can be fixed up.
+            let text = match config.submit_update(msg, &via, &send_updates).await? {
+                Submission::Sent => "The draft is now the status.".to_owned(),
+                Submission::Queued => "The draft is awaiting approval.".to_owned(),
+                Submission::Locked(holder) => {
+                    format!("The draft wasn't put up: updates are locked by {}.", holder)
+                }
+                Submission::Rejected(reason) => {
+                    return html_response(
+                        hyper::StatusCode::BAD_REQUEST,
+                        preview::render_preview_page(
+                            draft.load()?.as_ref(),
+                            &token,
+                            Some(&format!("The draft was rejected: {}", reason)),
+                        ),
+                    );
+                }
+            };
+
+            notice = Some(text);
+            draft.save(None)?;
+            send_updates.send(DisplayStateMutation::SetPreview(None));
+        }
+
+        PreviewAction::Discard => {
+            log!(
+                "draft status discarded by {}",
+                who.as_deref().unwrap_or("anonymous")
+            );
+            draft.save(None)?;
+            send_updates.send(DisplayStateMutation::SetPreview(None));
+        }
+    }
+
+    html_response(
+        hyper::StatusCode::OK,
+        preview::render_preview_page(draft.load()?.as_ref(), &token, notice.as_deref()),
+    )
+}
+
+fn handle_stats_get(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    history: &History,
+) -> Result<Response<Body>, GenericError> {
+    let token = auth::request_token(&req);
+
+    if let Access::Denied = config.authorize(token.as_deref(), Role::Observer, None) {
+        return forbidden();
+    }
+
+    let events = history.load()?;
+    let html = stats::render_stats_page(&events);
+
+    let response = Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(Body::from(html))?;
+    Ok(response)
+}
+
+fn html_response(status: hyper::StatusCode, html: String) -> Result<Response<Body>, GenericError> {
+    Ok(Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(Body::from(html))?)
+}
+
+fn not_found() -> Result<Response<Body>, GenericError> {
+    Ok(Response::builder()
+        .status(hyper::StatusCode::NOT_FOUND)
+        .body((&b"not found"[..]).into())
+        .unwrap())
+}
+
+/// Get the value of the named field from URL-encoded form data.
+fn form_field(data: &[u8], field: &str) -> Option<String> {
+    url::form_urlencoded::parse(data)
+        .find(|(name, _)| name == field)
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Ring the doorbell, if the correct token is provided. This is meant to be
+/// called by things like smart doorbells, so the token goes in the query
+/// string.
+fn handle_doorbell_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+) -> Result<Response<Body>, GenericError> {
+    let feature_token = config.doorbell.as_ref().map(|c| c.token.as_str());
+
+    if feature_token.is_none() && !config.has_tokens() {
+        return not_found();
+    }
+
+    let token = auth::request_token(&req);
+
+    if let Access::Denied = config.authorize(token.as_deref(), Role::Updater, feature_token) {
+        return forbidden();
+    }
+
+    let msg = DoorbellHelloMessage {
+        timestamp: chrono::Utc::now(),
+        token: None,
+    };
+
+    recording::inbound(
+        &proxy::describe_client(&req),
+        &ClientHelloMessage::Doorbell(msg.clone()),
+    );
+
+    send_updates.send(DisplayStateMutation::RingDoorbell(msg));
+
+    Ok(Response::builder()
+        .status(hyper::StatusCode::NO_CONTENT)
+        .body(Body::from(""))?)
+}
+
+/// Show the visitor notes, if the correct admin token is provided.
+fn handle_notes_get(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+) -> Result<Response<Body>, GenericError> {
+    let (notes_config, notebox) = match (&config.notes, config.note_box()) {
+        (Some(c), Ok(nb)) => (c, nb),
+        _ => return not_found(),
+    };
+
+    let token = auth::request_token(&req).unwrap_or_default();
+    let feature_token = Some(notes_config.admin_token.as_str());
+
+    if let Access::Denied = config.authorize(Some(&token), Role::Admin, feature_token) {
+        return forbidden();
+    }
+
+    let html = notes::render_admin_page(&notebox.load()?, &token);
+    html_response(hyper::StatusCode::OK, html)
+}
+
+/// Show the form for visitors to leave a note.
+fn handle_notes_new_get(config: &ServerConfiguration) -> Result<Response<Body>, GenericError> {
+    if config.notes.is_none() {
+        return not_found();
+    }
+
+    html_response(hyper::StatusCode::OK, notes::render_form_page(None))
+}
+
+/// Accept a note from a visitor.
+async fn handle_notes_new_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+) -> Result<Response<Body>, GenericError> {
+    let notebox = match config.note_box() {
+        Ok(nb) => nb,
+        Err(_) => return not_found(),
+    };
+
+    let client = proxy::describe_client(&req);
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let text = form_field(&body, "text").unwrap_or_default();
+    let text = text.trim();
+    let from = form_field(&body, "from").unwrap_or_default();
+    let from = match from.trim() {
+        "" => "anonymous",
+        f => f,
+    };
+
+    if text.is_empty()
+        || text.chars().count() > notes::MAX_NOTE_LENGTH
+        || from.chars().count() > notes::MAX_FROM_LENGTH
+    {
+        return html_response(
+            hyper::StatusCode::BAD_REQUEST,
+            notes::render_form_page(Some("Sorry, that note is empty or too long.")),
+        );
+    }
+
+    let note = Note {
+        timestamp: chrono::Utc::now(),
+        from: from.to_owned(),
+        text: text.to_owned(),
+    };
+
+    let n = match notebox.add(&note) {
+        Ok(n) => n,
+        Err(e) => {
+            log!("error saving visitor note: {}", e);
+            return html_response(
+                hyper::StatusCode::SERVICE_UNAVAILABLE,
+                notes::render_form_page(Some("Sorry, the note could not be saved.")),
+            );
+        }
+    };
+
+    log!("received a visitor note from {}; {} now waiting", client, n);
+    config.notifications.notify(
+        "New visitor note",
+        &format!("{} left a note: {}", note.from, note.text),
+    );
+    send_updates.send(DisplayStateMutation::SetNotesWaiting(n));
+
+    html_response(
+        hyper::StatusCode::OK,
+        notes::render_form_page(Some("Thanks! Your note has been left.")),
+    )
+}
+
+/// Clear out the visitor notes, if the correct admin token is provided.
+async fn handle_notes_clear_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+) -> Result<Response<Body>, GenericError> {
+    let (notes_config, notebox) = match (&config.notes, config.note_box()) {
+        (Some(c), Ok(nb)) => (c, nb),
+        _ => return not_found(),
+    };
+
+    let header_token = auth::request_token(&req);
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let token = form_field(&body, "token")
+        .or(header_token)
+        .unwrap_or_default();
+    let feature_token = Some(notes_config.admin_token.as_str());
+
+    if let Access::Denied = config.authorize(Some(&token), Role::Admin, feature_token) {
+        return forbidden();
+    }
+
+    notebox.clear()?;
+    send_updates.send(DisplayStateMutation::SetNotesWaiting(0));
+
+    html_response(hyper::StatusCode::OK, notes::render_admin_page(&[], &token))
+}
+
+/// This function must perform Twitter's "challenge-response check" (CRC, but
+/// not the one you're used to.
+async fn handle_twitter_webhook_get(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+) -> Result<Response<Body>, GenericError> {
+    log!("handling Twitter challenge-response check");
+
+    // Get the crc_token argument.
+
+    let mut crc_token = None;
+
+    if let Some(qstring) = req.uri().query() {
+        for (name, value) in url::form_urlencoded::parse(qstring.as_bytes()) {
+            if name == "crc_token" {
+                crc_token = Some(value);
+            }
+        }
+    }
+
+    let crc_token = match crc_token {
+        Some(t) => t,
+
+        None => {
+            return Ok(Response::builder()
+                .status(hyper::StatusCode::BAD_REQUEST)
+                .body((&b"expected crc_token"[..]).into())
+                .unwrap());
+        }
+    };
+
+    // Do the computation.
+
+    let key = config.twitter.consumer_api_secret_key.as_bytes();
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("uhoh");
+    mac.input(crc_token.as_bytes());
+    let result = mac.result();
+    let enc = base64::encode(&result.code());
+
+    // Respond.
+
+    let resp_val = json!({ "response_token": format!("sha256={}", enc) });
+    let resp_json = serde_json::to_string(&resp_val)?;
+    let response = Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(resp_json))?;
+    Ok(response)
+}
+
+/// This function is called when something happens to the subscribed account(s).
+/// Handle a CI webhook event from GitHub or GitLab.
+async fn handle_ci_webhook_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+) -> Result<Response<Body>, GenericError> {
+    let ci_config = match config.ci {
+        Some(ref c) => c,
+        None => return not_found(),
+    };
+
+    let headers = req.headers().clone();
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+
+    match ci_config.status_from_webhook(&headers, &body) {
+        Ok(Some(status)) => {
+            log!(
+                "CI result for {}: {}",
+                status.label,
+                if status.passed { "passed" } else { "failed" }
+            );
+            send_updates
+                .submit(DisplayStateMutation::SetCiStatus(status))
+                .await?;
+        }
+
+        Ok(None) => {}
+
+        Err(e) => {
+            log!("rejecting CI webhook event: {}", e);
+            return forbidden();
+        }
+    }
+
+    no_content()
+}
+
+/// Carry out a command from one of the chat integrations, named by
+/// `source`, and say how it went. The reply is meant for whoever sent the
+/// command, who is named by `set_by`; `sent` is when they sent it.
+async fn run_chat_command(
+    command: chat::ChatCommand,
+    source: &str,
+    set_by: Option<String>,
+    sent: chrono::DateTime<chrono::Utc>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+    history: &History,
+) -> Result<String, GenericError> {
+    // A lock can only be taken or ended if nobody more important holds it.
+    let lock_holder = send_updates
+        .current()
+        .lock
+        .filter(|l| chrono::Utc::now() < l.until)
+        .filter(|l| source_priority(Some(&l.source)) > source_priority(Some(source)))
+        .map(|l| l.source);
+
+    let reply = match command {
+        chat::ChatCommand::Show => format!(
+            "The sign says: {}",
+            send_updates.current().display.person_is
+        ),
+
+        chat::ChatCommand::Help => chat::HELP.to_owned(),
+
+        chat::ChatCommand::Undo => {
+            match undo_status(config, &send_updates, history, set_by).await {
+                Ok(person_is) => format!("Went back to: {}", person_is),
+                Err(e) => format!("Couldn't undo: {}", e),
+            }
+        }
+
+        chat::ChatCommand::Set(status) => {
+            let status = phrases::parse(&status, chrono::Local::now());
+
+            let person_is = match config.fit_status(&status.person_is) {
+                Some(p) => p,
+                None => return Ok(status_problem(&status.person_is)),
+            };
+
+            log!("status update via {}: {}", source, person_is);
+
+            let msg = PersonIsUpdateHelloMessage {
+                person_is: person_is.clone(),
+                timestamp: sent,
+                source: Some(source.to_owned()),
+                set_by,
+                token: None,
+                signature: None,
+            };
+
+            let previous = send_updates.current().last_update;
+            let submission = config.submit_update(msg, source, &send_updates).await?;
+
+            if submission.is_accepted() {
+                config.record_shortening(&status.person_is, &person_is, Some(source));
+            }
+
+            match submission {
+                Submission::Sent => {
+                    if let Some(minutes) = status.expires_minutes(chrono::Utc::now()) {
+                        revert_status_later(
+                            send_updates,
+                            config.default_status.clone(),
+                            previous,
+                            sent,
+                            minutes,
+                        );
+                    }
+
+                    format!("Set the status to: {}", person_is)
+                }
+
+                Submission::Queued => "The status is awaiting approval.".to_owned(),
+                Submission::Rejected(reason) => format!("Status rejected: {}", reason),
+                Submission::Locked(holder) => {
+                    format!("Couldn't set the status: updates are locked by {}", holder)
+                }
+            }
+        }
+
+        chat::ChatCommand::Lock(minutes) => {
+            if let Some(holder) = lock_holder {
+                return Ok(format!("Couldn't lock: updates are locked by {}", holder));
+            }
+
+            // The parser keeps lengths of time reasonable, but let's not
+            // panic if one gets through.
+            let until =
+                match chrono::Utc::now().checked_add_signed(chrono::Duration::minutes(minutes)) {
+                    Some(t) => t,
+                    None => return Ok("That's too long to lock for.".to_owned()),
+                };
+
+            log!("updates locked via {} for {} minutes", source, minutes);
+
+            send_updates.send(DisplayStateMutation::SetLock(Some(SourceLock {
+                source: source.to_owned(),
+                until,
+            })));
+
+            format!(
+                "Ignoring less important updates until {}.",
+                until.with_timezone(&chrono::Local).format("%H:%M")
+            )
+        }
+
+        chat::ChatCommand::Unlock => {
+            if let Some(holder) = lock_holder {
+                return Ok(format!("Couldn't unlock: updates are locked by {}", holder));
+            }
+
+            if send_updates.current().lock.is_none() {
+                return Ok("Updates aren't locked.".to_owned());
+            }
+
+            log!("updates unlocked via {}", source);
+            send_updates.send(DisplayStateMutation::SetLock(None));
+            "Unlocked.".to_owned()
+        }
+
+        chat::ChatCommand::Schedule { at, status } => {
+            let status_queue = match config.status_queue() {
+                Ok(q) => q,
+                Err(_) => return Ok("This hub can't queue up statuses.".to_owned()),
+            };
+
+            let person_is = match config.fit_status(&status) {
+                Some(p) => p,
+                None => return Ok(status_problem(&status)),
+            };
+
+            let when = at.with_timezone(&chrono::Local).format("%a %H:%M");
+            log!("status queued via {} for {}: {}", source, when, person_is);
+
+            let update = PersonIsUpdateHelloMessage {
+                person_is: person_is.clone(),
+                timestamp: at,
+                source: Some(source.to_owned()),
+                set_by,
+                token: None,
+                signature: None,
+            };
+
+            match status_queue.add(vec![update]) {
+                Ok(queued) => {
+                    config.record_shortening(&status, &person_is, Some(source));
+                    send_updates.send(DisplayStateMutation::SetNextStatus(queue::next_status(
+                        &queued,
+                    )));
+                    format!("At {}, the status will be: {}", when, person_is)
+                }
+
+                Err(e) => format!("Couldn't queue the status: {}", e),
+            }
+        }
+
+        chat::ChatCommand::Notes => {
+            let notebox = match config.note_box() {
+                Ok(nb) => nb,
+                Err(_) => return Ok("This hub doesn't take visitor notes.".to_owned()),
+            };
+
+            let notes = notebox.load()?;
+
+            if notes.is_empty() {
+                return Ok("No notes waiting.".to_owned());
+            }
+
+            notes
+                .iter()
+                .map(|note| {
+                    format!(
+                        "{} from {}: {}",
+                        note.timestamp
+                            .with_timezone(&chrono::Local)
+                            .format("%a %H:%M"),
+                        note.from,
+                        note.text
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+
+        chat::ChatCommand::ClearNotes => {
+            let notebox = match config.note_box() {
+                Ok(nb) => nb,
+                Err(_) => return Ok("This hub doesn't take visitor notes.".to_owned()),
+            };
+
+            log!("visitor notes cleared via {}", source);
+            notebox.clear()?;
+            send_updates.send(DisplayStateMutation::SetNotesWaiting(0));
+            "Cleared the notes.".to_owned()
+        }
+    };
+
+    Ok(reply)
+}
+
+/// Handle a Slack slash command. Slack shows the reply to whoever typed the
+/// command, so problems with the status are reported that way too.
+async fn handle_slack_webhook_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+    history: &History,
+) -> Result<Response<Body>, GenericError> {
+    let slack_config = match config.slack {
+        Some(ref s) => s,
+        None => return not_found(),
+    };
+
+    let headers = req.headers().clone();
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let user_id = form_field(&body, "user_id").unwrap_or_default();
+    let user_name = form_field(&body, "user_name");
+
+    if let Err(e) = slack_config.verify(&headers, &body, &user_id) {
+        log!("rejecting Slack command: {}", e);
+        return forbidden();
+    }
+
+    let text = form_field(&body, "text").unwrap_or_default();
+
+    let reply = match chat::ChatCommand::parse(&text, chrono::Local::now()) {
+        Ok(command) => {
+            run_chat_command(
+                command,
+                slack::SOURCE,
+                user_name,
+                chrono::Utc::now(),
+                config,
+                send_updates,
+                history,
+            )
+            .await?
+        }
+        Err(e) => e,
+    };
+
+    Ok(Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(slack::reply(&reply)))?)
+}
+
+async fn handle_twitter_webhook_post(
+    req: Request<Body>,
+    config: &ServerConfiguration,
+    send_updates: UpdateHub,
+    history: &History,
+) -> Result<Response<Body>, GenericError> {
+    log!("handling Twitter webhook event");
+
+    enum EarlyExit {
+        Irrelevant(&'static str),
+        Error(GenericError),
+    }
+
+    impl<T: 'static + std::error::Error + Send + Sync> From<T> for EarlyExit {
+        fn from(e: T) -> Self {
+            EarlyExit::Error(Box::new(e))
+        }
+    }
+
+    async fn inner(
+        req: Request<Body>,
+        config: &ServerConfiguration,
+        send_updates: UpdateHub,
+        history: &History,
+    ) -> Result<(), EarlyExit> {
+        // Validate the request.
+
+        let signature = req
+            .headers()
+            .get("x-twitter-webhooks-signature")
+            .ok_or(EarlyExit::Error(
+                "no x-twitter-webhooks-signature header".into(),
+            ))?
+            .to_str()?
+            .to_owned();
+
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        let key = config.twitter.consumer_api_secret_key.as_bytes();
+        let mut mac = Hmac::<Sha256>::new_varkey(key).expect("uhoh");
+        mac.input(&body);
+        let result = mac.result();
+        let enc = format!("sha256={}", base64::encode(&result.code()));
+
+        // I believe that in principle, we ought to use a constant-time comparison
+        // function to avoid timing attacks (see `mac.result()` docs).
+
+        if enc != signature {
+            return Err(EarlyExit::Error("signature mismatch".into()));
+        }
+
+        // Now we can start parsing the event.
+
+        let body = String::from_utf8(body.to_vec())?;
+        let body: serde_json::Value = serde_json::from_str(&body)?;
+
+        let item = body
+            .get("direct_message_events")
+            .ok_or(EarlyExit::Irrelevant("not DM event"))?;
+
+        // The value can be a list, presumably to allow batching, but
+        // we're going to go ahead and assume that's not going to happen
+        // for us.
+        let item = item
+            .get(0)
+            .ok_or(EarlyExit::Irrelevant("empty DM Event list?"))?;
+
+        // The timestamp is a string giving a Unix time measured in
+        // *milliseconds* since the Epoch.
+        let timestamp: i64 = item
+            .get("created_timestamp")
+            .ok_or(EarlyExit::Error("no created_timestamp".into()))?
+            .as_str()
+            .ok_or(EarlyExit::Error("created_timestamp not stringlike".into()))?
+            .parse()?;
+        let timestamp = chrono::Utc.timestamp(timestamp / 1000, 0);
+
+        let item = item
+            .get("message_create")
+            .ok_or(EarlyExit::Irrelevant("not creation"))?;
+
+        let sender_id = item
+            .get("sender_id")
+            .ok_or(EarlyExit::Error("no sender_id".into()))?;
+
+        if sender_id != &json!(&config.twitter.allowed_sender_id) {
+            return Err(EarlyExit::Irrelevant("wrong sender"));
+        }
+
+        // The event comes with information about the users involved, which
+        // gives us a friendlier name for the sender than their ID.
+        let set_by = body
+            .get("users")
+            .and_then(|users| users.get(&config.twitter.allowed_sender_id))
+            .and_then(|user| user.get("screen_name"))
+            .and_then(|name| name.as_str())
+            .map(|name| format!("@{}", name));
+
+        let item = item
+            .get("message_data")
+            .ok_or(EarlyExit::Error("no message_data".into()))?;
+
+        let item = item
+            .get("text")
+            .ok_or(EarlyExit::Error("no message_data.text".into()))?;
+
+        let text = item
+            .as_str()
+            .ok_or(EarlyExit::Error("message text is not a string".into()))?
+            .to_owned();
+
+        // We finally have the text!
+        log!(" ... update text from Twitter DM: {}", text);
+
+        let reply = match chat::ChatCommand::parse(&text, chrono::Local::now()) {
+            Ok(command) => run_chat_command(
+                command,
+                TWITTER_SOURCE,
+                set_by,
+                timestamp,
+                config,
+                send_updates,
+                history,
+            )
+            .await
+            .map_err(EarlyExit::Error)?,
+            Err(e) => e,
+        };
+
+        log!("  => reply: {}", reply);
+
+        // The command has been carried out by now, so if the reply doesn't
+        // get through, we just say so, rather than have Twitter deliver the
+        // message again.
+        let recipient: u64 = config.twitter.allowed_sender_id.parse()?;
+
+        if let Err(e) = egg_mode::direct::DraftMessage::new(reply, recipient)
+            .send(&config.twitter.token())
+            .await
+        {
+            log!("error replying to Twitter DM: {}", e);
+        }
+
+        Ok(())
+    }
+
+    let rv = inner(req, config, send_updates, history).await;
+
+    let response = if let Err(ref e) = rv {
+        match e {
+            EarlyExit::Irrelevant(s) => {
+                log!("  => not relevant: {}", s);
+
+                Response::builder()
+                    .status(hyper::StatusCode::NO_CONTENT)
+                    .body(Body::from(""))?
+            }
+
+            EarlyExit::Error(e) => {
+                log!("  => ERROR: {}", e);
+
+                Response::builder()
+                    .status(hyper::StatusCode::BAD_REQUEST)
+                    .body(Body::from(e.to_string()))?
+            }
+        }
+    } else {
+        log!("  => success!");
+
+        Response::builder()
+            .status(hyper::StatusCode::NO_CONTENT)
+            .body(Body::from(""))?
+    };
+
+    Ok(response)
+}
+
+// "set-status" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct SetStatusCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(help = "The new status; may be omitted if statuses are queued with --at")]
+    status: Option<String>,
+
+    #[structopt(
+        long = "expires",
+        help = "Go back to the previous status after this long, like \"90m\" or \"2h\"; \
+                otherwise a status like \"back at 3\" or \"lunch, 45 min\" lasts as long as it says",
+        parse(try_from_str = parse_minutes)
+    )]
+    expires_minutes: Option<i64>,
+
+    #[structopt(
+        long = "at",
+        number_of_values = 2,
+        value_names = &["TIME", "STATUS"],
+        help = "Queue up a status to go up at a time like \"13:00\"; may be repeated"
+    )]
+    queued: Vec<String>,
+}
+
+/// Parse a length of time like "45m", "2h", or "1d" into minutes. A bare
+/// number is taken to be minutes.
+fn parse_minutes(text: &str) -> Result<i64, String> {
+    let (number, scale) = match text.chars().last() {
+        Some('m') => (&text[..text.len() - 1], 1),
+        Some('h') => (&text[..text.len() - 1], 60),
+        Some('d') => (&text[..text.len() - 1], 24 * 60),
+        _ => (text, 1),
+    };
+
+    match number.parse::<i64>() {
+        Ok(n) if n > 0 => n
+            .checked_mul(scale)
+            .filter(|m| *m <= phrases::MAX_MINUTES)
+            .ok_or_else(|| {
+                format!(
+                    "`{}` is too long; the limit is {} days",
+                    text,
+                    phrases::MAX_MINUTES / (24 * 60)
+                )
+            }),
+        _ => Err(format!("can't understand `{}` as a length of time", text)),
+    }
+}
+
+impl SetStatusCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        let now = chrono::Local::now();
+        let mut queued = Vec::new();
+
+        for pair in self.queued.chunks(2) {
+            queued.push(ApiQueuedStatus {
+                at: queue::next_time_of_day(&pair[0], now)?,
+                status: pair[1].clone(),
+                source: None,
+                set_by: None,
+            });
+        }
+
+        if self.status.is_none() && queued.is_empty() {
+            return Err("give a status to set, or some to queue up with --at".into());
+        }
+
+        let status = self.status.as_deref().map(|s| phrases::parse(s, now));
+
+        // If the hub shortens statuses that are too long, leave it to decide.
+        let statuses = status
+            .iter()
+            .map(|s| &s.person_is)
+            .chain(queued.iter().map(|q| &q.status));
+
+        for status in statuses {
+            if !is_person_is_valid(status) && config.shortening.is_none() {
+                return Err(format!("status \"{}\" invalid -- likely too long", status).into());
+            }
+        }
+
+        // Going through the running hub's API, with an admin token if there
+        // is one, means that the update takes precedence like any other
+        // admin update.
+        let token = config
+            .tokens
+            .iter()
+            .find(|t| t.role == Role::Admin)
+            .map(|t| t.token.clone());
+
+        if let Some(status) = status {
+            let mut form = url::form_urlencoded::Serializer::new(String::new());
+            form.append_pair("status", &status.person_is);
+
+            let expires_minutes = self
+                .expires_minutes
+                .or_else(|| status.expires_minutes(now.with_timezone(&chrono::Utc)));
+
+            if let Some(minutes) = expires_minutes {
+                form.append_pair("expires_minutes", &minutes.to_string());
+            }
+
+            if let Some(ref t) = token {
+                form.append_pair("token", t);
+            }
+
+            let req = Request::builder()
+                .method(Method::POST)
+                .uri(format!("http://127.0.0.1:{}/api/status", config.http_port))
+                .header(
+                    hyper::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                )
+                .body(Body::from(form.finish()))?;
+
+            match send_to_hub(req).await? {
+                hyper::StatusCode::ACCEPTED => println!("the status is awaiting approval"),
+                _ => println!("set the status to \"{}\"", status.person_is),
+            }
+        }
+
+        if !queued.is_empty() {
+            let mut req = Request::builder()
+                .method(Method::POST)
+                .uri(format!(
+                    "http://127.0.0.1:{}/api/status/queue",
+                    config.http_port
+                ))
+                .header(hyper::header::CONTENT_TYPE, "application/json");
+
+            if let Some(ref t) = token {
+                req = req.header(hyper::header::AUTHORIZATION, format!("Bearer {}", t));
+            }
+
+            send_to_hub(req.body(Body::from(serde_json::to_string(&queued)?))?).await?;
+
+            for q in &queued {
+                println!(
+                    "queued \"{}\" for {}",
+                    q.status,
+                    q.at.with_timezone(&chrono::Local).format("%a %H:%M")
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Make a request of the running hub, turning a refusal into an error.
+async fn send_to_hub(req: Request<Body>) -> Result<hyper::StatusCode, GenericError> {
+    let resp = http_client::https_client().request(req).await?;
+    let status = resp.status();
+    let body = hyper::body::to_bytes(resp.into_body()).await?;
+    let body = String::from_utf8_lossy(&body);
+
+    if status.is_success() {
+        Ok(status)
+    } else if body.is_empty() {
+        Err(format!("the hub refused the status: {}", status).into())
+    } else {
+        Err(format!("the hub refused the status: {}", body).into())
+    }
+}
+
+// "undo" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct UndoCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+}
+
+impl UndoCommand {
+    /// Ask the running hub to put the previous status back up.
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+
+        let mut req = Request::builder().method(Method::POST).uri(format!(
+            "http://127.0.0.1:{}/api/status/undo",
+            config.http_port
+        ));
+
+        if let Some(t) = config.tokens.iter().find(|t| t.role == Role::Admin) {
+            req = req.header(hyper::header::AUTHORIZATION, format!("Bearer {}", t.token));
+        }
+
+        let resp = http_client::https_client()
+            .request(req.body(Body::empty())?)
+            .await?;
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body()).await?;
+        let body = String::from_utf8_lossy(&body);
+
+        if status.is_success() {
+            println!("went back to \"{}\"", body);
+            Ok(())
+        } else {
+            Err(format!("the hub couldn't undo: {}", body).into())
+        }
+    }
+}
+
+// "install-service" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct InstallServiceCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(long = "user", help = "The user to run the hub as")]
+    user: Option<String>,
+
+    #[structopt(
+        long = "sockets",
+        help = "Have systemd listen on the hub's ports, so that restarts don't refuse connections"
+    )]
+    sockets: bool,
+
+    #[structopt(
+        long = "dir",
+        default_value = "/etc/systemd/system",
+        help = "Where to write the unit files"
+    )]
+    dir: PathBuf,
+
+    #[structopt(long = "print", help = "Print the unit files rather than writing them")]
+    print: bool,
+}
+
+impl InstallServiceCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config_path = self.config_path.canonicalize()?;
+        let config = ServerConfiguration::load(&config_path)?;
+
+        let settings = service::UnitSettings {
+            exe: std::env::current_exe()?,
+            working_directory: config_path
+                .parent()
+                .map(|p| p.to_owned())
+                .unwrap_or_else(|| "/".into()),
+            config_path,
+            user: self.user,
+            sockets: if self.sockets {
+                Some((
+                    config.bind_address,
+                    config.stickyproto_port,
+                    config.http_port,
+                ))
+            } else {
+                None
+            },
+        };
+
+        for (name, contents) in settings.unit_files() {
+            if self.print {
+                println!("### {}\n{}", name, contents);
+            } else {
+                let path = self.dir.join(&name);
+                std::fs::write(&path, contents)?;
+                println!("wrote {}", path.display());
+            }
+        }
+
+        if !self.print {
+            let units: Vec<_> = settings.unit_files().into_iter().map(|(n, _)| n).collect();
+
+            println!(
+                "to start it: systemctl daemon-reload && systemctl enable --now {}",
+                units.join(" ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+// "stdio" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct StdioCommand {
+    #[structopt(
+        help = "The path to the server configuration file; if omitted, settings come from STICKYNOTE_HUB_* environment variables"
+    )]
+    config_path: Option<PathBuf>,
+}
+
+impl StdioCommand {
+    /// Pass a stickyproto connection between standard input and output and
+    /// the running hub server. This way the hub can be reached through
+    /// anything that can run a command and hook up its I/O, like inetd, a
+    /// systemd socket with `Accept=yes`, or `ssh hub rc_stickynote_hub stdio`,
+    /// without the hub needing to know about the transport.
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load_layered(self.config_path.as_deref())?;
+
+        // If the server listens on all interfaces, loopback will do.
+        let host = if config.bind_address.is_unspecified() {
+            Ipv4Addr::LOCALHOST
+        } else {
+            config.bind_address
+        };
+
+        let socket = std::net::TcpStream::connect((host, config.stickyproto_port))?;
+        let mut from_hub = socket.try_clone()?;
+        let mut to_hub = socket;
+
+        // These are blocking copies, so they get their own threads. The
+        // connection is over once the hub hangs up.
+
+        std::thread::spawn(move || {
+            let _ignored = std::io::copy(&mut stdin(), &mut to_hub);
+            let _ignored = to_hub.shutdown(std::net::Shutdown::Write);
+        });
+
+        tokio::task::spawn_blocking(move || {
+            let mut out = stdout();
+            std::io::copy(&mut from_hub, &mut out)?;
+            out.flush()
+        })
+        .await??;
+
+        Ok(())
+    }
+}
+
+// "token create" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct TokenCreateCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(help = "A name for the token holder, e.g. \"alice\"")]
+    name: String,
+
+    #[structopt(
+        long = "role",
+        default_value = "updater",
+        help = "What the token allows: observer, updater, or admin"
+    )]
+    role: Role,
+
+    #[structopt(
+        long = "source",
+        help = "A source that the holder may claim for their updates, e.g. \"hotkey\"; can be repeated"
+    )]
+    sources: Vec<String>,
+}
+
+impl TokenCreateCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        let token = config
+            .token_store()?
+            .create(&self.name, self.role, &self.sources)?;
+        println!("issued {} token for {}:", self.role, self.name);
+        println!("{}", token);
+        println!("(it won't be shown again)");
+        Ok(())
+    }
+}
+
+// "token list" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct TokenListCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+}
+
+impl TokenListCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        let tokens = config.token_store()?.load()?;
+
+        if tokens.is_empty() {
+            println!("No tokens issued.");
+        }
+
+        for t in &tokens {
+            println!(
+                "{} ({}), issued {}",
+                t.name,
+                t.role,
+                t.created
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M"),
+            );
+
+            if !t.sources.is_empty() {
+                println!("    may claim: {}", t.sources.join(", "));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// "token revoke" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct TokenRevokeCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(help = "The name of the token holder")]
+    name: String,
+}
+
+impl TokenRevokeCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+
+        if !config.token_store()?.revoke(&self.name)? {
+            return Err(format!("no token issued to `{}`", self.name).into());
+        }
+
+        println!("revoked the token issued to {}", self.name);
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum TokenCommand {
+    #[structopt(name = "create")]
+    /// Issue a new access token
+    Create(TokenCreateCommand),
+
+    #[structopt(name = "list")]
+    /// List the access tokens that have been issued
+    List(TokenListCommand),
+
+    #[structopt(name = "revoke")]
+    /// Revoke an access token
+    Revoke(TokenRevokeCommand),
+}
+
+impl TokenCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        match self {
+            TokenCommand::Create(opts) => opts.cli().await,
+            TokenCommand::List(opts) => opts.cli().await,
+            TokenCommand::Revoke(opts) => opts.cli().await,
+        }
+    }
+}
+
+// "twitter-login" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct TwitterLoginCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(help = "The path to the server state file (need not exist)")]
+    state_path: PathBuf,
+}
+
+impl TwitterLoginCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        let mut state = ServerState::try_load(&self.state_path)?;
+
+        println!("Beginning authentication flow ...");
+        let con_token = egg_mode::KeyPair::new(
+            config.twitter.consumer_api_key,
+            config.twitter.consumer_api_secret_key,
+        );
+        let req_token = egg_mode::request_token(&con_token, "oob").await?;
+        let auth_url = egg_mode::authorize_url(&req_token);
+        print!(
+            "Visit the following URL and obtain a verification PIN:\n\n\
+             {}\n\n\
+             Then enter the PIN here: ",
+            auth_url
+        );
+        stdout().flush()?;
+
+        let mut pin: String = String::new();
+        stdin().read_line(&mut pin)?;
+
+        let (token, user_id, screen_name) =
+            egg_mode::access_token(con_token, &req_token, pin).await?;
+        println!("Authenticated as @{} (user ID {})", screen_name, user_id);
+
+        match token {
+            egg_mode::Token::Access {
+                access: ref access_token,
+                ..
+            } => {
+                state.twitter.access_token = access_token.key.to_string();
+                state.twitter.access_token_secret = access_token.secret.to_string();
+            }
+
+            _ => panic!("expected Access-type token"),
+        }
+
+        state.save(&self.state_path)?;
+
+        Ok(())
+    }
+}
+
+// "twitter-register-webhook" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct TwitterRegisterWebhookCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(help = "The path to the server state file")]
+    state_path: PathBuf,
+}
+
+impl TwitterRegisterWebhookCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        let state = ServerState::load(&self.state_path)?;
+        let token = state.twitter.get_token(&config);
+        let hookspec = egg_mode::activity::WebhookSpec::new(&config.twitter.webhook_url);
+        let result = hookspec.register(&config.twitter.env_name, &token).await?;
+        println!("registered webhook: {:?}", result);
+        Ok(())
+    }
+}
+
+// "twitter-subscribe" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct TwitterSubscribeCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(help = "The path to the server state file")]
+    state_path: PathBuf,
+}
+
+impl TwitterSubscribeCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        let state = ServerState::load(&self.state_path)?;
+        let token = state.twitter.get_token(&config);
+        egg_mode::activity::subscribe_current_user(&config.twitter.env_name, &token).await?;
+        println!("subscribed to activity from logged-in user");
+        Ok(())
+    }
+}
+
+// "twitter-unregister-webhook" subcommand
+
+#[derive(Debug, StructOpt)]
+pub struct TwitterUnregisterWebhookCommand {
+    #[structopt(help = "The path to the server configuration file")]
+    config_path: PathBuf,
+
+    #[structopt(help = "The path to the server state file")]
+    state_path: PathBuf,
+
+    /// TODO: if we really want this workflow to be reliable, we should save
+    /// this ID in the state file.
+    #[structopt(long = "id", help = "The ID of the webhook")]
+    hook_id: String,
+}
+
+impl TwitterUnregisterWebhookCommand {
+    async fn cli(self) -> Result<(), GenericError> {
+        let config = ServerConfiguration::load(&self.config_path)?;
+        let state = ServerState::load(&self.state_path)?;
+        let token = state.twitter.get_token(&config);
+        egg_mode::activity::delete_webhook(&config.twitter.env_name, &self.hook_id, &token).await?;
+        println!("deregistered webhook");
+        Ok(())
+    }
+}
+
+// CLI root interface
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "hub", about = "RC Stickynote dispatch hub")]
+pub enum RootCli {
+    #[structopt(name = "counter")]
+    /// Create, increment, reset, and list counters
+    Counter(CounterCommand),
+
+    #[structopt(name = "history")]
+    /// Work with the hub's history log
+    History(HistoryCommand),
+
+    #[structopt(name = "install-service")]
+    /// Write systemd unit files for running the hub as a service
+    InstallService(InstallServiceCommand),
+
+    #[structopt(name = "notes")]
+    /// Read and clear notes left by visitors
+    Notes(NotesCommand),
+
+    #[structopt(name = "outbox")]
+    /// List and purge notifications waiting to be tried again
+    Outbox(OutboxCommand),
+
+    #[structopt(name = "panel-command")]
+    /// Send a command to the panels
+    PanelCommand(PanelCommandCommand),
+
+    #[structopt(name = "pending")]
+    /// List, approve, and reject updates awaiting approval
+    Pending(PendingCommand),
+
+    #[structopt(name = "replay")]
+    /// Feed traffic recorded with `serve --record` to a test hub
+    Replay(ReplayCommand),
+
+    #[structopt(name = "serve")]
+    /// Launch the dispatch hub server.
+    Serve(ServeCommand),
+
+    #[structopt(name = "set-status")]
+    /// Set the status through the running hub server
+    SetStatus(SetStatusCommand),
+
+    #[structopt(name = "stdio")]
+    /// Connect standard input and output to the running hub server
+    Stdio(StdioCommand),
+
+    #[structopt(name = "token")]
+    /// Issue, list, and revoke access tokens
+    Token(TokenCommand),
+
+    #[structopt(name = "twitter-login")]
+    /// Login to the connected Twitter account
+    TwitterLogin(TwitterLoginCommand),
+
+    #[structopt(name = "twitter-register-webhook")]
+    /// Register the activity webhook with Twitter
+    TwitterRegisterWebhook(TwitterRegisterWebhookCommand),
+
+    #[structopt(name = "twitter-subscribe")]
+    /// Subscribe to Twitter events from the logged-in user
+    TwitterSubscribe(TwitterSubscribeCommand),
+
+    #[structopt(name = "twitter-unregister-webhook")]
+    /// Un-register the activity webhook with Twitter
+    TwitterUnregisterWebhook(TwitterUnregisterWebhookCommand),
+
+    #[structopt(name = "undo")]
+    /// Go back to the previous status through the running hub server
+    Undo(UndoCommand),
+}
+
+impl RootCli {
+    pub async fn cli(self) -> Result<(), GenericError> {
+        match self {
+            RootCli::Counter(opts) => opts.cli().await,
+            RootCli::History(opts) => opts.cli().await,
+            RootCli::InstallService(opts) => opts.cli().await,
+            RootCli::Notes(opts) => opts.cli().await,
+            RootCli::Outbox(opts) => opts.cli().await,
+            RootCli::PanelCommand(opts) => opts.cli().await,
+            RootCli::Pending(opts) => opts.cli().await,
+            RootCli::Replay(opts) => opts.cli().await,
+            RootCli::Serve(opts) => opts.cli().await,
+            RootCli::SetStatus(opts) => opts.cli().await,
+            RootCli::Stdio(opts) => opts.cli().await,
+            RootCli::Token(opts) => opts.cli().await,
+            RootCli::TwitterLogin(opts) => opts.cli().await,
+            RootCli::TwitterRegisterWebhook(opts) => opts.cli().await,
+            RootCli::TwitterSubscribe(opts) => opts.cli().await,
+            RootCli::TwitterUnregisterWebhook(opts) => opts.cli().await,
+            RootCli::Undo(opts) => opts.cli().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal configuration, plus the given extra settings.
+    fn test_config(extra: &str) -> ServerConfiguration {
+        toml::from_str(&format!(
+            "stickyproto_port = 0\n\
+             http_port = 0\n\
+             {}\n\
+             [twitter]\n\
+             env_name = \"\"\n\
+             webhook_url = \"\"\n\
+             allowed_sender_id = \"\"\n\
+             consumer_api_key = \"\"\n\
+             consumer_api_secret_key = \"\"\n\
+             access_token = \"\"\n\
+             access_token_secret = \"\"\n",
+            extra
+        ))
+        .unwrap()
+    }
+
+    /// A configuration that holds updates sent over the stickynote protocol
+    /// for approval, with a fresh queue file named after the test.
+    fn moderated_config(test: &str) -> ServerConfiguration {
+        let path = std::env::temp_dir().join(format!(
+            "rc-stickynote-{}-{}.jsonl",
+            test,
+            std::process::id()
+        ));
+        let _ignored = std::fs::remove_file(&path);
+
+        test_config(&format!(
+            "[moderation]\n\
+             path = \"{}\"\n\
+             sources = [\"{}\"]",
+            path.display(),
+            STICKYPROTO_SOURCE
+        ))
+    }
+
+    #[tokio::test]
+    async fn submitted_changes_wait_for_the_state_loop() {
+        let config = test_config("");
+        let send_updates = UpdateHub::new(HubDisplayState::default(), &config, History::new(None));
+
+        let submitted = {
+            let send_updates = send_updates.clone();
+            tokio::spawn(async move {
+                send_updates
+                    .submit(DisplayStateMutation::SetNotesWaiting(3))
+                    .await
+                    .unwrap()
+            })
+        };
+
+        time::delay_for(Duration::from_millis(10)).await;
+        assert_eq!(send_updates.current().display.notes_waiting, 0);
+
+        tokio::spawn(send_updates.clone().run());
+        assert!(submitted.await.unwrap());
+        assert_eq!(send_updates.current().display.notes_waiting, 3);
+    }
+
+    #[tokio::test]
+    async fn renamed_stickyproto_update_is_moderated() {
+        let config = moderated_config("renamed-stickyproto");
+        let history = History::new(None);
+        let send_updates = UpdateHub::new(HubDisplayState::default(), &config, history.clone());
+
+        for claimed in &[
+            None,
+            Some("my script"),
+            Some(HOTKEY_SOURCE),
+            Some(ADMIN_SOURCE),
+        ] {
+            let msg = PersonIsUpdateHelloMessage {
+                person_is: "in the lab".to_owned(),
+                timestamp: chrono::Utc::now(),
+                source: claimed.map(str::to_owned),
+                set_by: None,
+                token: None,
+                signature: None,
+            };
+
+            handle_oneshot_hello(
+                ClientHelloMessage::PersonIsUpdate(msg),
+                &config,
+                &send_updates,
+                &history,
+            )
+            .await
+            .unwrap();
+        }
+
+        let pending = config.pending_queue().unwrap().load().unwrap();
+        assert_eq!(pending.len(), 4);
+        assert!(send_updates.current().last_update.is_none());
+        std::fs::remove_file(&config.moderation.unwrap().path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn locked_undo_is_not_recorded() {
+        let path = std::env::temp_dir().join(format!(
+            "rc-stickynote-locked-undo-{}.jsonl",
+            std::process::id()
+        ));
+        let _ignored = std::fs::remove_file(&path);
+
+        let config = test_config(&format!("history_path = \"{}\"", path.display()));
+        let history = History::new(Some(path.clone()));
+        let send_updates = UpdateHub::new(HubDisplayState::default(), &config, history.clone());
+        tokio::spawn(send_updates.clone().run());
+
+        for person_is in &["at lunch", "in a meeting"] {
+            send_updates.send(DisplayStateMutation::SetPersonIs(
+                PersonIsUpdateHelloMessage {
+                    person_is: (*person_is).to_owned(),
+                    timestamp: chrono::Utc::now(),
+                    source: Some(HTTP_API_SOURCE.to_owned()),
+                    set_by: None,
+                    token: None,
+                    signature: None,
+                },
+            ));
+        }
+
+        send_updates.send(DisplayStateMutation::SetLock(Some(SourceLock {
+            source: ADMIN_SOURCE.to_owned(),
+            until: chrono::Utc::now() + chrono::Duration::hours(1),
+        })));
+
+        match undo_status(&config, &send_updates, &history, None).await {
+            Err(UndoFailure::Locked(holder)) => assert_eq!(holder, ADMIN_SOURCE),
+            other => panic!("unexpected undo outcome: {:?}", other),
+        }
+
+        let events = history.load().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(send_updates.current().display.person_is, "in a meeting");
+
+        send_updates.send(DisplayStateMutation::SetLock(None));
+        let restored = undo_status(&config, &send_updates, &history, None).await;
+        assert_eq!(restored.unwrap(), "at lunch");
+
+        let events = history.load().unwrap();
+        assert_eq!(events.len(), 4);
+        assert!(matches!(events[2], HistoryEvent::Undo { .. }));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
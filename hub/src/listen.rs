@@ -0,0 +1,110 @@
+//! Setting up the sockets that the hub's servers listen on.
+//!
+//! If the hub was started by systemd with socket activation, we use the
+//! sockets that it passes in, so that connections made while the hub is
+//! restarting are queued up rather than refused. The sockets are matched by
+//! their names, as set with `FileDescriptorName=` in the socket unit, falling
+//! back to the order in which they are passed.
+//!
+//! Otherwise we bind the sockets ourselves. The standard library sets
+//! `SO_REUSEADDR` on Unix, but the port can still be in use briefly if the
+//! previous instance of the hub hasn't quite exited yet, so we retry a few
+//! times before giving up.
+
+use std::{
+    io::{Error, ErrorKind},
+    net::{Ipv4Addr, TcpListener},
+};
+use tokio::time::{self, Duration};
+
+/// How many times to try binding a port before giving up.
+const BIND_ATTEMPTS: u32 = 6;
+
+/// The first file descriptor passed by systemd.
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: i32 = 3;
+
+/// Get a socket passed in by systemd, if there is one. `name` is the socket's
+/// name and `index` is its position if the sockets don't have names.
+#[cfg(unix)]
+fn activated_listener(name: &str, index: usize) -> Option<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+
+    let pid: u32 = std::env::var("LISTEN_PID").ok()?.parse().ok()?;
+
+    if pid != std::process::id() {
+        return None;
+    }
+
+    let n_fds: usize = std::env::var("LISTEN_FDS").ok()?.parse().ok()?;
+
+    let index = match std::env::var("LISTEN_FDNAMES") {
+        Ok(names) => names.split(':').position(|n| n == name)?,
+        Err(_) => index,
+    };
+
+    if index >= n_fds {
+        return None;
+    }
+
+    // Safety: systemd guarantees that these file descriptors are open and
+    // belong to us, and we only take each one once.
+    Some(unsafe { TcpListener::from_raw_fd(SD_LISTEN_FDS_START + index as i32) })
+}
+
+#[cfg(not(unix))]
+fn activated_listener(_name: &str, _index: usize) -> Option<TcpListener> {
+    None
+}
+
+/// Get a listening socket for one of the hub's servers, ready to be handed
+/// over to tokio.
+pub async fn listen(
+    name: &str,
+    index: usize,
+    host: Ipv4Addr,
+    port: u16,
+) -> Result<TcpListener, Error> {
+    let listener = match activated_listener(name, index) {
+        Some(l) => {
+            log!("{} server using socket from systemd", name);
+            l
+        }
+
+        None => bind_with_retry(name, host, port).await?,
+    };
+
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+async fn bind_with_retry(name: &str, host: Ipv4Addr, port: u16) -> Result<TcpListener, Error> {
+    let mut delay = Duration::from_secs(1);
+    let mut attempt = 1;
+
+    loop {
+        match TcpListener::bind((host, port)) {
+            Ok(l) => return Ok(l),
+
+            Err(e) if e.kind() == ErrorKind::AddrInUse && attempt < BIND_ATTEMPTS => {
+                log!(
+                    "{} server: {}:{} is in use; retrying in {} s",
+                    name,
+                    host,
+                    port,
+                    delay.as_secs()
+                );
+                time::delay_for(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!("cannot listen on {}:{}: {}", host, port, e),
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Logging what the hub server is up to.
+//!
+//! Log messages go to standard output, either as plain text or, for log
+//! collectors like the ones used with containers, as one JSON object per
+//! line. When the hub isn't run by something that collects its output, they
+//! can go to a file instead, which starts over when it gets too big or too
+//! old, keeping a few of the old files, in the same way as the display
+//! client's log.
+
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{Error, ErrorKind, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+pub fn default_log_format() -> LogFormat {
+    LogFormat::Text
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerLogConfiguration {
+    /// Where to write the log.
+    pub path: PathBuf,
+
+    /// Start a new log file once the current one reaches this many
+    /// kilobytes. Zero means never.
+    #[serde(default = "default_max_kilobytes")]
+    max_kilobytes: u64,
+
+    /// Start a new log file once the current one is this many hours old.
+    /// Zero means never.
+    #[serde(default)]
+    max_age_hours: u64,
+
+    /// How many old log files to keep, as `<path>.1`, `<path>.2`, and so on,
+    /// newest first.
+    #[serde(default = "default_keep")]
+    keep: usize,
+}
+
+fn default_max_kilobytes() -> u64 {
+    1024
+}
+
+fn default_keep() -> usize {
+    3
+}
+
+/// The log file, which starts over now and then.
+struct LogFile {
+    config: ServerLogConfiguration,
+    file: File,
+    size: u64,
+    opened: SystemTime,
+}
+
+impl LogFile {
+    fn open(config: &ServerLogConfiguration) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+        let meta = file.metadata()?;
+
+        Ok(LogFile {
+            config: config.clone(),
+            file,
+            size: meta.len(),
+            opened: meta.created().unwrap_or_else(|_| SystemTime::now()),
+        })
+    }
+
+    fn is_due(&self) -> bool {
+        let too_big =
+            self.config.max_kilobytes > 0 && self.size >= self.config.max_kilobytes * 1024;
+        let max_age = Duration::from_secs(self.config.max_age_hours * 3600);
+        let too_old = self.config.max_age_hours > 0
+            && self.opened.elapsed().map(|a| a >= max_age).unwrap_or(false);
+        too_big || too_old
+    }
+
+    fn old_path(&self, n: usize) -> PathBuf {
+        let mut path = self.config.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    /// Move the current file out of the way, shuffling along the old ones and
+    /// deleting the oldest, and start a new one.
+    fn rotate(&mut self) -> Result<(), Error> {
+        let remove = |path: PathBuf| match fs::remove_file(path) {
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            r => r,
+        };
+
+        if self.config.keep == 0 {
+            remove(self.config.path.clone())?;
+        } else {
+            remove(self.old_path(self.config.keep))?;
+
+            for n in (1..self.config.keep).rev() {
+                let from = self.old_path(n);
+
+                if from.exists() {
+                    fs::rename(from, self.old_path(n + 1))?;
+                }
+            }
+
+            fs::rename(&self.config.path, self.old_path(1))?;
+        }
+
+        self.file = File::create(&self.config.path)?;
+        self.size = 0;
+        self.opened = SystemTime::now();
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), Error> {
+        // If we can't start a new file, it's better to keep writing to the old
+        // one than to lose the line.
+        if self.is_due() {
+            let _ = self.rotate();
+        }
+
+        writeln!(self.file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+static FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+
+/// Choose how log messages are formatted.
+pub fn set_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::SeqCst);
+}
+
+/// Send log messages to a file rather than standard output.
+pub fn set_file(config: &ServerLogConfiguration) -> Result<(), Error> {
+    *FILE.lock().unwrap() = Some(LogFile::open(config)?);
+    Ok(())
+}
+
+/// Emit a log message. Use the `log!` macro rather than calling this
+/// directly.
+pub fn emit(args: fmt::Arguments) {
+    let json = JSON.load(Ordering::SeqCst);
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let line = if json {
+        json!({
+            "timestamp": now,
+            "message": args.to_string(),
+        })
+        .to_string()
+    } else {
+        args.to_string()
+    };
+
+    // Nothing adds timestamps to the lines in the file, so we do that
+    // ourselves. If the file can't be written, the message might as well go
+    // somewhere.
+    if let Some(ref mut f) = *FILE.lock().unwrap() {
+        let written = if json {
+            f.write_line(&line)
+        } else {
+            f.write_line(&format!("{} {}", now, line))
+        };
+
+        if written.is_ok() {
+            return;
+        }
+    }
+
+    println!("{}", line);
+}
+
+/// Log a message, with the same syntax as `println!`.
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::logging::emit(format_args!($($arg)*))
+    };
+}
@@ -0,0 +1,41 @@
+//! Maintenance mode.
+//!
+//! While an admin is redeploying or testing things, the status might flip
+//! through all sorts of intermediate states, and every panel would dutifully
+//! redraw for each of them. In maintenance mode, the hub instead sends each
+//! panel one last copy of whatever it was showing, marked so that the panel
+//! puts up a banner, and then leaves it alone. When maintenance ends, every
+//! panel is sent the current state, so they all catch up at once.
+
+use rc_stickynote_protocol::DisplayMessage;
+
+/// Decides what to send one panel, or one group of panels, given the state.
+#[derive(Debug, Default)]
+pub struct MaintenanceGate {
+    /// The last message that went out before maintenance started.
+    last_sent: Option<DisplayMessage>,
+
+    /// Whether the panel has already been told about maintenance.
+    paused: bool,
+}
+
+impl MaintenanceGate {
+    /// Given the message that would be sent if not for maintenance, return
+    /// what should actually be sent, if anything.
+    pub fn filter(&mut self, msg: DisplayMessage, maintenance: bool) -> Option<DisplayMessage> {
+        if !maintenance {
+            self.paused = false;
+            self.last_sent = Some(msg.clone());
+            return Some(msg);
+        }
+
+        if self.paused {
+            return None;
+        }
+
+        self.paused = true;
+        let mut frozen = self.last_sent.clone().unwrap_or(msg);
+        frozen.maintenance = true;
+        Some(frozen)
+    }
+}
@@ -0,0 +1,190 @@
+//! Holding status updates from untrusted sources for approval.
+//!
+//! If moderation is configured, updates that come in a listed way, or with a
+//! listed token, don't go straight to the panel. Instead they're queued up in a file, one JSON record
+//! per line, until an admin approves or rejects them, either through the web
+//! dashboard or with the `pending` CLI commands.
+
+use chrono::{DateTime, Local, Utc};
+use rc_stickynote_protocol::{sealed, PersonIsUpdateHelloMessage};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Write as FmtWrite,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Error, ErrorKind, Write},
+    path::PathBuf,
+};
+
+use crate::html::{escape_html, page};
+
+/// The most updates that we'll keep waiting for approval.
+pub const MAX_PENDING_UPDATES: usize = 50;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerModerationConfiguration {
+    /// Where to store the updates awaiting approval.
+    pub path: PathBuf,
+
+    /// The ways of reaching the hub whose updates must be approved, e.g.
+    /// "HTTP API" or "stickynote protocol". This goes by how the update
+    /// actually came in, not the source that it claims, which a client can
+    /// set to anything.
+    #[serde(default)]
+    pub sources: Vec<String>,
+
+    /// The holders of tokens whose updates must be approved, by name.
+    #[serde(default)]
+    pub tokens: Vec<String>,
+}
+
+impl ServerModerationConfiguration {
+    /// Whether an update that came in by way of `via`, with a token held by
+    /// `set_by`, if any, needs to be approved.
+    pub fn is_moderated(&self, via: &str, set_by: Option<&str>) -> bool {
+        self.sources.iter().any(|m| m == via)
+            || matches!(set_by, Some(h) if self.tokens.iter().any(|m| m == h))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PendingUpdate {
+    /// An identifier for approving or rejecting this update.
+    pub id: u64,
+
+    /// When the hub received the update.
+    pub received: DateTime<Utc>,
+
+    pub update: PersonIsUpdateHelloMessage,
+}
+
+/// A handle to the queue of updates awaiting approval.
+#[derive(Clone, Debug)]
+pub struct PendingQueue {
+    path: PathBuf,
+}
+
+impl PendingQueue {
+    pub fn new(config: &ServerModerationConfiguration) -> Self {
+        PendingQueue {
+            path: config.path.clone(),
+        }
+    }
+
+    /// Read all of the pending updates, oldest first.
+    pub fn load(&self) -> Result<Vec<PendingUpdate>, Error> {
+        let f = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut pending = Vec::new();
+
+        for line in BufReader::new(f).lines() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str(&line) {
+                Ok(p) => pending.push(p),
+                Err(e) => eprintln!("skipping unparseable pending update: {}", e),
+            }
+        }
+
+        Ok(pending)
+    }
+
+    /// Add an update to the queue, returning its ID.
+    pub fn add(&self, update: PersonIsUpdateHelloMessage) -> Result<u64, Error> {
+        let pending = self.load()?;
+
+        if pending.len() >= MAX_PENDING_UPDATES {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "too many updates are awaiting approval",
+            ));
+        }
+
+        let id = pending.iter().map(|p| p.id).max().unwrap_or(0) + 1;
+
+        let mut line = serde_json::to_string(&PendingUpdate {
+            id,
+            received: Utc::now(),
+            update,
+        })?;
+        line.push('\n');
+
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        f.write_all(line.as_bytes())?;
+        Ok(id)
+    }
+
+    /// Remove the update with the given ID from the queue, returning it if it
+    /// was there.
+    pub fn take(&self, id: u64) -> Result<Option<PendingUpdate>, Error> {
+        let (taken, kept): (Vec<_>, Vec<_>) = self.load()?.into_iter().partition(|p| p.id == id);
+
+        let mut text = String::new();
+
+        for p in &kept {
+            text.push_str(&serde_json::to_string(p)?);
+            text.push('\n');
+        }
+
+        std::fs::write(&self.path, text)?;
+        Ok(taken.into_iter().next())
+    }
+}
+
+/// The page listing the pending updates, with buttons to approve or reject
+/// each of them.
+pub fn render_dashboard_page(pending: &[PendingUpdate], token: &str) -> String {
+    let mut html = String::new();
+
+    if pending.is_empty() {
+        html.push_str("<p>No updates awaiting approval.</p>\n");
+    } else {
+        html.push_str("<table>\n");
+
+        for p in pending {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>",
+                p.received.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                escape_html(p.update.source.as_deref().unwrap_or("unknown")),
+                escape_html(p.update.set_by.as_deref().unwrap_or("")),
+                if sealed::is_sealed(&p.update.person_is) {
+                    "<i>(sealed)</i>".to_owned()
+                } else {
+                    escape_html(&p.update.person_is)
+                },
+            );
+
+            for (action, label) in &[("approve", "Approve"), ("reject", "Reject")] {
+                let _ = writeln!(
+                    html,
+                    "<form method=\"post\" action=\"pending/{}\" style=\"display: inline\">\n\
+                     <input type=\"hidden\" name=\"token\" value=\"{}\">\n\
+                     <input type=\"hidden\" name=\"id\" value=\"{}\">\n\
+                     <input type=\"submit\" value=\"{}\">\n\
+                     </form>",
+                    action,
+                    escape_html(token),
+                    p.id,
+                    label,
+                );
+            }
+
+            html.push_str("</td></tr>\n");
+        }
+
+        html.push_str("</table>\n");
+    }
+
+    page("Pending Updates", &html)
+}
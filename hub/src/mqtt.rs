@@ -0,0 +1,143 @@
+//! Talking to panels and clients through an MQTT broker.
+//!
+//! This is for installations where the panels can't reach the hub directly.
+//! The hub publishes the display state to the broker, and handles the "hello"
+//! messages that clients publish there just like ones that arrive over the
+//! stickyproto server. See `rc_stickynote_protocol::mqtt` for the topic
+//! layout.
+//!
+//! The MQTT client library is synchronous, so it runs in its own thread.
+
+use futures::{prelude::*, select};
+pub use rc_stickynote_protocol::mqtt::MqttConfiguration;
+use rc_stickynote_protocol::{ClientHelloMessage, DisplayMessage};
+use rumqttc::{Client, Connection, Event, Packet, QoS};
+use std::{thread, time::Duration};
+use tokio::sync::mpsc;
+
+use crate::{
+    handle_oneshot_hello, history::History, maintenance::MaintenanceGate, recording,
+    updates::UpdateHub, ServerConfiguration,
+};
+
+/// Bridge the hub to the MQTT broker forever.
+pub async fn run(
+    mqtt: MqttConfiguration,
+    config: ServerConfiguration,
+    send_updates: UpdateHub,
+    history: History,
+) {
+    let (mut client, connection) = Client::new(mqtt.options("hub"), 10);
+    let (send_hellos, mut receive_hellos) = mpsc::unbounded_channel();
+
+    {
+        let client = client.clone();
+        let topic = mqtt.hello_topic();
+        thread::spawn(move || receive_thread(connection, client, topic, send_hellos));
+    }
+
+    let mut receive_updates = send_updates.subscribe();
+    let mut state = send_updates.current();
+    let mut gate = MaintenanceGate::default();
+
+    if let Some(msg) = gate.filter(state.display.clone(), state.maintenance) {
+        publish_display(&mut client, &mqtt, &msg);
+    }
+
+    loop {
+        select! {
+            maybe_hello = receive_hellos.recv().fuse() => {
+                match maybe_hello {
+                    Some(hello) => {
+                        recording::inbound("mqtt", &hello);
+
+                        if let Err(e) = handle_oneshot_hello(hello, &config, &send_updates, &history).await {
+                            log!("mqtt: error handling message: {}", e);
+                        }
+                    },
+
+                    None => {
+                        log!("mqtt: receiver thread died?");
+                        return;
+                    },
+                }
+            },
+
+            maybe_update = receive_updates.next().fuse() => {
+                match maybe_update {
+                    Some(new_state) => {
+                        let previous = (state.display.clone(), state.maintenance);
+                        state = new_state;
+
+                        if (state.display.clone(), state.maintenance) != previous {
+                            if let Some(msg) = gate.filter(state.display.clone(), state.maintenance) {
+                                publish_display(&mut client, &mqtt, &msg);
+                            }
+                        }
+                    },
+
+                    None => {
+                        log!("mqtt receive_updates ran out??");
+                    },
+                }
+            },
+        }
+    }
+}
+
+/// Publish the display state as a retained message, so that panels get it as
+/// soon as they subscribe.
+fn publish_display(client: &mut Client, mqtt: &MqttConfiguration, display: &DisplayMessage) {
+    recording::outbound("mqtt", display);
+
+    let payload = match serde_json::to_vec(display) {
+        Ok(p) => p,
+        Err(e) => {
+            log!("mqtt: cannot serialize display state: {}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = client.try_publish(mqtt.display_topic(), QoS::AtLeastOnce, true, payload) {
+        log!("mqtt: cannot publish display state: {}", e);
+    }
+}
+
+/// Drive the MQTT connection, passing along the hellos that clients publish.
+fn receive_thread(
+    mut connection: Connection,
+    mut client: Client,
+    topic: String,
+    hellos: mpsc::UnboundedSender<ClientHelloMessage>,
+) {
+    for event in connection.iter() {
+        match event {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                log!("mqtt: connected to broker");
+
+                if let Err(e) = client.try_subscribe(topic.as_str(), QoS::AtLeastOnce) {
+                    log!("mqtt: cannot subscribe to {}: {}", topic, e);
+                }
+            }
+
+            Ok(Event::Incoming(Packet::Publish(p))) => match serde_json::from_slice(&p.payload) {
+                Ok(hello) => {
+                    if hellos.send(hello).is_err() {
+                        return;
+                    }
+                }
+
+                Err(e) => log!("mqtt: ignoring unparseable message: {}", e),
+            },
+
+            Ok(_) => {}
+
+            Err(e) => {
+                // The connection will be retried the next time around the
+                // loop; don't hammer the broker.
+                log!("mqtt: connection error: {}", e);
+                thread::sleep(Duration::from_secs(10));
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Polling RSS and Atom feeds for headlines to show on the panel.
+//!
+//! As with the calendar integration, the parsing here is deliberately
+//! minimal: we just pull the titles out of the `<item>` (RSS) or `<entry>`
+//! (Atom) elements, in the order that they appear in the feed.
+
+use serde::Deserialize;
+use tokio::time::{self, Duration};
+
+use crate::{http_client, updates::UpdateHub, DisplayStateMutation};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerNewsConfiguration {
+    /// The URLs of the feeds to poll.
+    feeds: Vec<String>,
+
+    /// How often to poll the feeds.
+    #[serde(default = "default_poll_minutes")]
+    poll_minutes: u64,
+
+    /// How many of the latest headlines to take from each feed.
+    #[serde(default = "default_headlines_per_feed")]
+    headlines_per_feed: usize,
+}
+
+fn default_poll_minutes() -> u64 {
+    30
+}
+
+fn default_headlines_per_feed() -> usize {
+    3
+}
+
+/// Replace the XML character entities that are likely to show up in a
+/// headline.
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Get the text content of the first `<tag>` element in the XML fragment.
+fn element_text<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+
+    let mut search_from = 0;
+
+    // Skip over tags that merely start with the one we want, e.g. `<titles>`.
+    let start = loop {
+        let i = search_from + xml[search_from..].find(&open)?;
+        let after = &xml[i + open.len()..];
+
+        if after.starts_with('>') || after.starts_with(char::is_whitespace) {
+            break i + open.len() + after.find('>')? + 1;
+        }
+
+        search_from = i + open.len();
+    };
+
+    let end = start + xml[start..].find(&close)?;
+    Some(&xml[start..end])
+}
+
+/// Extract the headlines from the text of an RSS or Atom feed.
+fn parse_headlines(feed: &str, limit: usize) -> Vec<String> {
+    let (item_open, item_close) = if feed.contains("<item") {
+        ("<item", "</item>")
+    } else {
+        ("<entry", "</entry>")
+    };
+
+    let mut headlines = Vec::new();
+
+    for chunk in feed.split(item_open).skip(1) {
+        if headlines.len() >= limit {
+            break;
+        }
+
+        let item = match chunk.find(item_close) {
+            Some(i) => &chunk[..i],
+            None => chunk,
+        };
+
+        let title = match element_text(item, "title") {
+            Some(t) => t.trim(),
+            None => continue,
+        };
+
+        let title = if title.starts_with("<![CDATA[") && title.ends_with("]]>") {
+            title[9..title.len() - 3].to_owned()
+        } else {
+            decode_entities(title)
+        };
+
+        let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if !title.is_empty() {
+            headlines.push(title);
+        }
+    }
+
+    headlines
+}
+
+/// Poll the feeds forever, sending the hub the latest headlines.
+pub async fn run(config: ServerNewsConfiguration, send_updates: UpdateHub) {
+    let mut interval = time::interval(Duration::from_secs(config.poll_minutes.max(1) * 60));
+    let mut last_headlines = Vec::new();
+
+    loop {
+        interval.tick().await;
+
+        let mut headlines = Vec::new();
+
+        for url in &config.feeds {
+            match http_client::fetch_text(url).await {
+                Ok(text) => headlines.extend(parse_headlines(&text, config.headlines_per_feed)),
+                Err(e) => log!("error fetching news feed {}: {}", url, e),
+            }
+        }
+
+        if headlines != last_headlines {
+            last_headlines = headlines.clone();
+
+            send_updates.send(DisplayStateMutation::SetHeadlines(headlines));
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! The visitor message box.
+//!
+//! Visitors can leave short notes through a form hosted by the hub. The notes
+//! are queued up in a file, one JSON record per line, until they're read and
+//! cleared.
+
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Write as FmtWrite,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Error, ErrorKind, Write},
+    path::PathBuf,
+};
+
+use crate::html::{escape_html, page};
+
+/// The longest note that we'll accept, in characters.
+pub const MAX_NOTE_LENGTH: usize = 280;
+
+/// The longest visitor name that we'll accept, in characters.
+pub const MAX_FROM_LENGTH: usize = 40;
+
+/// The most notes that we'll keep queued up. This keeps a misbehaving visitor
+/// from filling up the disk.
+pub const MAX_QUEUED_NOTES: usize = 50;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerNotesConfiguration {
+    /// Where to store the queued notes.
+    pub path: PathBuf,
+
+    /// The public URL of the note form, shown on the display as a QR code.
+    pub form_url: String,
+
+    /// A secret that must be supplied to read or clear the notes over the web.
+    pub admin_token: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Note {
+    pub timestamp: DateTime<Utc>,
+    pub from: String,
+    pub text: String,
+}
+
+/// A handle to the queue of notes.
+#[derive(Clone, Debug)]
+pub struct NoteBox {
+    path: PathBuf,
+}
+
+impl NoteBox {
+    pub fn new(config: &ServerNotesConfiguration) -> Self {
+        NoteBox {
+            path: config.path.clone(),
+        }
+    }
+
+    /// Read all of the queued notes, oldest first.
+    pub fn load(&self) -> Result<Vec<Note>, Error> {
+        let f = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut notes = Vec::new();
+
+        for line in BufReader::new(f).lines() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str(&line) {
+                Ok(note) => notes.push(note),
+                Err(e) => eprintln!("skipping unparseable note: {}", e),
+            }
+        }
+
+        Ok(notes)
+    }
+
+    /// Add a note to the queue, returning the new number of queued notes.
+    pub fn add(&self, note: &Note) -> Result<usize, Error> {
+        let n = self.load()?.len();
+
+        if n >= MAX_QUEUED_NOTES {
+            return Err(Error::new(ErrorKind::Other, "the note box is full"));
+        }
+
+        let mut line = serde_json::to_string(note)?;
+        line.push('\n');
+
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        f.write_all(line.as_bytes())?;
+        Ok(n + 1)
+    }
+
+    /// Throw away all of the queued notes.
+    pub fn clear(&self) -> Result<(), Error> {
+        File::create(&self.path)?;
+        Ok(())
+    }
+}
+
+/// The form that visitors use to leave a note. If `message` is given, it's
+/// shown above the form, e.g. to confirm that a note was received.
+pub fn render_form_page(message: Option<&str>) -> String {
+    let mut html = String::new();
+
+    if let Some(m) = message {
+        let _ = writeln!(html, "<p><strong>{}</strong></p>", escape_html(m));
+    }
+
+    let _ = writeln!(
+        html,
+        "<form method=\"post\" action=\"notes/new\">\n\
+         <p><label>Your name: <input name=\"from\" maxlength=\"{}\"></label></p>\n\
+         <p><textarea name=\"text\" rows=\"5\" cols=\"40\" maxlength=\"{}\" required></textarea></p>\n\
+         <p><input type=\"submit\" value=\"Leave note\"></p>\n\
+         </form>",
+        MAX_FROM_LENGTH, MAX_NOTE_LENGTH,
+    );
+
+    page("Leave a Note", &html)
+}
+
+/// The page listing the queued notes, with a button to clear them.
+pub fn render_admin_page(notes: &[Note], token: &str) -> String {
+    let mut html = String::new();
+
+    if notes.is_empty() {
+        html.push_str("<p>No notes waiting.</p>\n");
+    } else {
+        html.push_str("<table>\n");
+
+        for note in notes {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                note.timestamp
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M"),
+                escape_html(&note.from),
+                escape_html(&note.text),
+            );
+        }
+
+        html.push_str("</table>\n");
+        let _ = writeln!(
+            html,
+            "<form method=\"post\" action=\"notes/clear\">\n\
+             <input type=\"hidden\" name=\"token\" value=\"{}\">\n\
+             <p><input type=\"submit\" value=\"Clear all notes\"></p>\n\
+             </form>",
+            escape_html(token),
+        );
+    }
+
+    page("Visitor Notes", &html)
+}
@@ -0,0 +1,264 @@
+//! Notifying the sticky note's owner when something happens.
+//!
+//! Notifications can go out through several kinds of providers at once:
+//! [ntfy](https://ntfy.sh/), [Pushover](https://pushover.net/), email via
+//! SMTP, and a generic webhook that receives JSON of the form
+//! `{"text": "..."}` (the format used by Slack's "incoming webhooks"). Each
+//! provider is enabled by adding its subsection to the `[notifications]`
+//! section of the server configuration. With a `[notifications.outbox]`
+//! subsection too, notifications that can't be sent are tried again later;
+//! see the `outbox` module.
+
+use hyper::{header, Body, Method, Request};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    http_client,
+    outbox::{self, Delivery, ServerOutboxConfiguration},
+    supervisor, GenericError,
+};
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ServerNotificationsConfiguration {
+    #[serde(default)]
+    ntfy: Option<NtfyConfiguration>,
+
+    #[serde(default)]
+    pushover: Option<PushoverConfiguration>,
+
+    #[serde(default)]
+    email: Option<EmailConfiguration>,
+
+    #[serde(default)]
+    webhook: Option<WebhookConfiguration>,
+
+    /// If set, keep notifications and webhook calls that fail, and try them
+    /// again later.
+    #[serde(default)]
+    pub outbox: Option<ServerOutboxConfiguration>,
+
+    /// Send a notification if no panel has been connected for this many
+    /// minutes. Zero disables this notification.
+    #[serde(default = "default_panel_offline_minutes")]
+    pub panel_offline_minutes: u64,
+}
+
+fn default_panel_offline_minutes() -> u64 {
+    30
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct NtfyConfiguration {
+    #[serde(default = "default_ntfy_server")]
+    server: String,
+    topic: String,
+
+    /// An access token, if the topic is protected.
+    #[serde(default)]
+    token: Option<String>,
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".to_owned()
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct PushoverConfiguration {
+    app_token: String,
+    user_key: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct EmailConfiguration {
+    smtp_host: String,
+
+    #[serde(default = "default_smtp_port")]
+    smtp_port: u16,
+
+    #[serde(default)]
+    username: Option<String>,
+
+    #[serde(default)]
+    password: Option<String>,
+
+    from: String,
+    to: String,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct WebhookConfiguration {
+    url: String,
+}
+
+/// The ways that we can send a notification.
+#[derive(Clone, Debug)]
+enum Provider {
+    Ntfy(NtfyConfiguration),
+    Pushover(PushoverConfiguration),
+    Email(EmailConfiguration),
+    Webhook(WebhookConfiguration),
+}
+
+impl Provider {
+    fn name(&self) -> &'static str {
+        match self {
+            Provider::Ntfy(_) => "ntfy",
+            Provider::Pushover(_) => "Pushover",
+            Provider::Email(_) => "email",
+            Provider::Webhook(_) => "webhook",
+        }
+    }
+
+    async fn send(&self, title: &str, message: &str) -> Result<(), GenericError> {
+        let req = match self {
+            Provider::Ntfy(c) => {
+                let url = format!("{}/{}", c.server.trim_end_matches('/'), c.topic);
+                let mut builder = Request::builder()
+                    .method(Method::POST)
+                    .uri(url)
+                    .header("title", title);
+
+                if let Some(ref token) = c.token {
+                    builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+                }
+
+                builder.body(Body::from(message.to_owned()))?
+            }
+
+            Provider::Pushover(c) => {
+                let form = url::form_urlencoded::Serializer::new(String::new())
+                    .append_pair("token", &c.app_token)
+                    .append_pair("user", &c.user_key)
+                    .append_pair("title", title)
+                    .append_pair("message", message)
+                    .finish();
+
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("https://api.pushover.net/1/messages.json")
+                    .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from(form))?
+            }
+
+            Provider::Email(c) => {
+                let c = c.clone();
+                let title = title.to_owned();
+                let message = message.to_owned();
+
+                // The SMTP client is synchronous, so run it off of the async
+                // threads.
+                return tokio::task::spawn_blocking(move || send_email(&c, &title, &message))
+                    .await?;
+            }
+
+            Provider::Webhook(c) => Request::builder()
+                .method(Method::POST)
+                .uri(&c.url)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    json!({ "text": format!("{}: {}", title, message) }).to_string(),
+                ))?,
+        };
+
+        let resp = http_client::send(req).await?;
+
+        if !resp.status().is_success() {
+            return Err(format!("request failed with status {}", resp.status()).into());
+        }
+
+        Ok(())
+    }
+}
+
+fn send_email(c: &EmailConfiguration, title: &str, message: &str) -> Result<(), GenericError> {
+    use lettre::{transport::smtp::authentication::Credentials, SmtpTransport, Transport};
+
+    let email = lettre::Message::builder()
+        .from(c.from.parse()?)
+        .to(c.to.parse()?)
+        .subject(title)
+        .body(message.to_owned())?;
+
+    let mut builder = SmtpTransport::starttls_relay(&c.smtp_host)?.port(c.smtp_port);
+
+    if let (Some(u), Some(p)) = (&c.username, &c.password) {
+        builder = builder.credentials(Credentials::new(u.clone(), p.clone()));
+    }
+
+    builder.build().send(&email)?;
+    Ok(())
+}
+
+impl ServerNotificationsConfiguration {
+    fn providers(&self) -> Vec<Provider> {
+        let mut providers = Vec::new();
+
+        if let Some(ref c) = self.ntfy {
+            providers.push(Provider::Ntfy(c.clone()));
+        }
+
+        if let Some(ref c) = self.pushover {
+            providers.push(Provider::Pushover(c.clone()));
+        }
+
+        if let Some(ref c) = self.email {
+            providers.push(Provider::Email(c.clone()));
+        }
+
+        if let Some(ref c) = self.webhook {
+            providers.push(Provider::Webhook(c.clone()));
+        }
+
+        providers
+    }
+
+    /// Send a notification through all of the configured providers, in the
+    /// background. Failures are logged, and kept in the outbox if there is
+    /// one.
+    pub fn notify(&self, title: &str, message: &str) {
+        for provider in self.providers() {
+            let title = title.to_owned();
+            let message = message.to_owned();
+            let outbox = self.outbox.clone();
+
+            supervisor::spawn(format!("{} notification", provider.name()), async move {
+                let delivery = Delivery::Notification {
+                    provider: provider.name().to_owned(),
+                    title: title.clone(),
+                    message: message.clone(),
+                };
+                let held = outbox::hold(outbox.as_ref(), &delivery);
+                let outcome = provider
+                    .send(&title, &message)
+                    .await
+                    .map_err(|e| e.to_string());
+
+                if let Err(ref e) = outcome {
+                    log!("error sending {} notification: {}", provider.name(), e);
+                }
+
+                if let Some(h) = held {
+                    h.settle(outcome);
+                }
+            });
+        }
+    }
+
+    /// Send a notification through just the provider with the given name.
+    pub async fn send_via(
+        &self,
+        provider: &str,
+        title: &str,
+        message: &str,
+    ) -> Result<(), GenericError> {
+        match self.providers().into_iter().find(|p| p.name() == provider) {
+            Some(p) => p.send(title, message).await,
+            None => Err(format!("no {} notifications are configured", provider).into()),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Who's on call, from PagerDuty and Opsgenie.
+//!
+//! Some teams hang the panel up as an on-call sign. The hub polls the
+//! configured schedules and tells the panels who's on call for each one. If
+//! the configuration names a person, the sign is taken to be theirs, and the
+//! panels just get an "on call" badge for each schedule that they're on call
+//! for.
+
+use rc_stickynote_protocol::OnCall;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::time::{self, Duration};
+
+use crate::{http_client, updates::UpdateHub, DisplayStateMutation, GenericError};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerOnCallConfiguration {
+    /// The schedules to watch.
+    schedules: Vec<OnCallSchedule>,
+
+    /// If set, only show badges for the schedules that this person is on
+    /// call for. The name must match what the service reports: the user's
+    /// name for PagerDuty, and their username for Opsgenie.
+    #[serde(default)]
+    person: Option<String>,
+
+    /// How often to poll the schedules.
+    #[serde(default = "default_poll_minutes")]
+    poll_minutes: u64,
+}
+
+fn default_poll_minutes() -> u64 {
+    5
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OnCallService {
+    PagerDuty,
+    Opsgenie,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct OnCallSchedule {
+    /// The service that has the schedule: "pagerduty" or "opsgenie".
+    service: OnCallService,
+
+    /// A read-only API key for the service.
+    api_key: String,
+
+    /// The ID of the schedule.
+    schedule: String,
+
+    /// What to call the schedule on the panel, like "Infra".
+    label: String,
+
+    /// The service's API, if not the usual one; for instance,
+    /// "https://api.eu.opsgenie.com" for Opsgenie's EU region.
+    #[serde(default)]
+    api_url: Option<String>,
+}
+
+impl OnCallSchedule {
+    /// Get the names of the people who are on call right now.
+    async fn on_call(&self) -> Result<Vec<String>, GenericError> {
+        match self.service {
+            OnCallService::PagerDuty => {
+                let url = format!(
+                    "{}/oncalls?schedule_ids%5B%5D={}",
+                    self.api_url
+                        .as_deref()
+                        .unwrap_or("https://api.pagerduty.com"),
+                    self.schedule
+                );
+                let auth = format!("Token token={}", self.api_key);
+                let text = http_client::fetch_text_with_headers(
+                    &url,
+                    &[
+                        ("authorization", &auth),
+                        ("accept", "application/vnd.pagerduty+json;version=2"),
+                    ],
+                )
+                .await?;
+                let resp: Value = serde_json::from_str(&text)?;
+
+                // Everyone on the schedule's escalation policy comes back,
+                // but only the first level is really on call.
+                let oncalls = resp["oncalls"].as_array().cloned().unwrap_or_default();
+                let first_level = oncalls
+                    .iter()
+                    .filter_map(|o| o["escalation_level"].as_u64())
+                    .min();
+
+                Ok(oncalls
+                    .iter()
+                    .filter(|o| o["escalation_level"].as_u64() == first_level)
+                    .filter_map(|o| o["user"]["summary"].as_str())
+                    .map(|s| s.to_owned())
+                    .collect())
+            }
+
+            OnCallService::Opsgenie => {
+                let url = format!(
+                    "{}/v2/schedules/{}/on-calls",
+                    self.api_url
+                        .as_deref()
+                        .unwrap_or("https://api.opsgenie.com"),
+                    self.schedule
+                );
+                let auth = format!("GenieKey {}", self.api_key);
+                let text =
+                    http_client::fetch_text_with_headers(&url, &[("authorization", &auth)]).await?;
+                let resp: Value = serde_json::from_str(&text)?;
+
+                Ok(resp["data"]["onCallParticipants"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter(|p| p["type"] == "user")
+                    .filter_map(|p| p["name"].as_str())
+                    .map(|s| s.to_owned())
+                    .collect())
+            }
+        }
+    }
+}
+
+impl ServerOnCallConfiguration {
+    /// What to show on the panel for a schedule, given who's on call for it.
+    fn entry(&self, schedule: &OnCallSchedule, people: Vec<String>) -> Option<OnCall> {
+        match self.person {
+            Some(ref me) => {
+                if people.contains(me) {
+                    Some(OnCall {
+                        label: schedule.label.clone(),
+                        person: None,
+                    })
+                } else {
+                    None
+                }
+            }
+
+            None => {
+                if people.is_empty() {
+                    None
+                } else {
+                    Some(OnCall {
+                        label: schedule.label.clone(),
+                        person: Some(people.join(", ")),
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// Poll the schedules forever, sending the hub the latest on-call
+/// information. If a schedule can't be fetched, what we last knew about it
+/// stays up.
+pub async fn run(config: ServerOnCallConfiguration, send_updates: UpdateHub) {
+    let mut interval = time::interval(Duration::from_secs(config.poll_minutes.max(1) * 60));
+    let mut entries = vec![None; config.schedules.len()];
+    let mut last_on_call = None;
+
+    loop {
+        interval.tick().await;
+
+        for (i, schedule) in config.schedules.iter().enumerate() {
+            match schedule.on_call().await {
+                Ok(people) => entries[i] = config.entry(schedule, people),
+                Err(e) => log!("error fetching on-call schedule {}: {}", schedule.label, e),
+            }
+        }
+
+        let on_call: Vec<OnCall> = entries.iter().flatten().cloned().collect();
+
+        if last_on_call.as_ref() != Some(&on_call) {
+            last_on_call = Some(on_call.clone());
+
+            send_updates.send(DisplayStateMutation::SetOnCall(on_call));
+        }
+    }
+}
@@ -0,0 +1,451 @@
+//! Trying failed notifications and webhook calls again.
+//!
+//! Notifications and webhook calls go out as soon as there's something to
+//! send. If an outbox is configured, each one is written to a file before
+//! it's first tried, and taken out again once it goes through. One that fails
+//! stays in the file, and is tried again later, waiting longer after each
+//! failure, until it goes through or has failed too many times. The file
+//! outlasts the hub, so a restart doesn't lose anything that's waiting, even
+//! a delivery that was in the middle of being tried.
+//!
+//! A delivery isn't tried again while an attempt at it is still under way,
+//! however long that attempt takes, so that it doesn't go out twice.
+//!
+//! A webhook is told the whole display state each time, so once a newer call
+//! to a webhook is on its way, an older one waiting to be tried again would
+//! only send out stale state, and is dropped.
+//!
+//! Only the running hub changes the file. The `outbox list` CLI command reads
+//! it to show what's waiting, and `outbox purge` asks the hub to throw
+//! deliveries away, so that the two can't trip over each other.
+//!
+//! A delivery names the notification provider or webhook URL that it's for,
+//! rather than keeping a copy of its settings, so that secrets stay out of the
+//! file and a change to the settings applies to retries too. If the provider
+//! or webhook has been taken out of the configuration in the meantime, the
+//! retries fail, and the delivery is eventually given up on.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    io::{Error, ErrorKind},
+    path::PathBuf,
+    sync::Mutex,
+};
+use tokio::time::{self, Duration as TokioDuration};
+
+use crate::{webhooks, GenericError, ServerConfiguration};
+
+/// Held while changing the outbox, so that simultaneous changes don't
+/// overwrite each other.
+static CHANGING: Mutex<()> = Mutex::new(());
+
+/// The deliveries being tried right now, by outbox file and ID.
+static IN_FLIGHT: Mutex<BTreeSet<(PathBuf, u64)>> = Mutex::new(BTreeSet::new());
+
+/// How long to wait before the first retry. The wait doubles after each
+/// failure after that.
+const FIRST_RETRY_SECONDS: i64 = 30;
+
+/// The longest to wait between retries.
+const MAX_RETRY_SECONDS: i64 = 3600;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerOutboxConfiguration {
+    /// Where to keep the deliveries waiting to be tried again.
+    pub path: PathBuf,
+
+    /// How many times to try each delivery, including the first, before
+    /// giving up on it.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+}
+
+fn default_max_attempts() -> u32 {
+    10
+}
+
+/// Something to send.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Delivery {
+    /// A notification through the provider with the given name.
+    Notification {
+        provider: String,
+        title: String,
+        message: String,
+    },
+
+    /// A call to the webhook with the given URL.
+    Webhook { url: String, body: String },
+}
+
+impl Delivery {
+    /// A short description, for logs and listings.
+    pub fn describe(&self) -> String {
+        match self {
+            Delivery::Notification {
+                provider, title, ..
+            } => format!("{} notification \"{}\"", provider, title),
+            Delivery::Webhook { url, .. } => format!("call to webhook {}", url),
+        }
+    }
+
+    async fn send(&self, config: &ServerConfiguration) -> Result<(), GenericError> {
+        match self {
+            Delivery::Notification {
+                provider,
+                title,
+                message,
+            } => {
+                config
+                    .notifications
+                    .send_via(provider, title, message)
+                    .await
+            }
+
+            Delivery::Webhook { url, body } => {
+                webhooks::send_to(&config.webhooks, url, body.clone()).await
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OutboxEntry {
+    /// An identifier for purging this delivery.
+    pub id: u64,
+
+    pub delivery: Delivery,
+
+    /// When the delivery was first tried.
+    pub created: DateTime<Utc>,
+
+    /// How many times the delivery has been tried and failed.
+    pub attempts: u32,
+
+    /// When to try it again.
+    pub next_attempt: DateTime<Utc>,
+
+    /// Why the latest attempt failed, or empty if none has yet.
+    pub last_error: String,
+}
+
+/// A handle to the deliveries waiting to be tried again.
+#[derive(Clone, Debug)]
+pub struct Outbox {
+    path: PathBuf,
+    max_attempts: u32,
+}
+
+impl Outbox {
+    pub fn new(config: &ServerOutboxConfiguration) -> Self {
+        Outbox {
+            path: config.path.clone(),
+            max_attempts: config.max_attempts,
+        }
+    }
+
+    /// Read all of the waiting deliveries, oldest first.
+    pub fn load(&self) -> Result<Vec<OutboxEntry>, Error> {
+        match std::fs::read(&self.path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write out the deliveries. The new contents go into a file alongside
+    /// and are then moved into place, so that the hub stopping partway
+    /// through can't leave half of a file behind.
+    fn save(&self, entries: &[OutboxEntry]) -> Result<(), Error> {
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        std::fs::write(&temp, serde_json::to_string_pretty(entries)?)?;
+        std::fs::rename(&temp, &self.path)
+    }
+
+    /// Mark a delivery as being tried, or not. Returns false if it already
+    /// was.
+    fn set_in_flight(&self, id: u64, in_flight: bool) -> bool {
+        let mut set = IN_FLIGHT.lock().unwrap();
+        let key = (self.path.clone(), id);
+
+        if in_flight {
+            set.insert(key)
+        } else {
+            set.remove(&key)
+        }
+    }
+
+    /// Keep a delivery that's about to be tried for the first time, returning
+    /// its ID. It's marked as being tried, so that the retries leave it alone
+    /// until the first attempt is over.
+    fn add(&self, delivery: Delivery) -> Result<u64, Error> {
+        let _guard = CHANGING.lock().unwrap();
+        let mut entries = self.load()?;
+        let id = entries.iter().map(|e| e.id).max().unwrap_or(0) + 1;
+        let now = Utc::now();
+
+        if let Delivery::Webhook { ref url, .. } = delivery {
+            entries.retain(|e| match e.delivery {
+                Delivery::Webhook { url: ref u, .. } if u == url => {
+                    log!(
+                        "outbox #{}: dropping, since a newer call to {} is on its way",
+                        e.id,
+                        url
+                    );
+                    false
+                }
+                _ => true,
+            });
+        }
+
+        entries.push(OutboxEntry {
+            id,
+            delivery,
+            created: now,
+            attempts: 0,
+            next_attempt: now + retry_wait(1),
+            last_error: String::new(),
+        });
+
+        self.save(&entries)?;
+        self.set_in_flight(id, true);
+        Ok(id)
+    }
+
+    /// Whether the given delivery is still waiting. It won't be if it's been
+    /// purged, or dropped for a newer call to the same webhook.
+    fn contains(&self, entry: &OutboxEntry) -> Result<bool, Error> {
+        Ok(self
+            .load()?
+            .iter()
+            .any(|e| e.id == entry.id && e.created == entry.created))
+    }
+
+    /// Throw away the delivery with the given ID, or all of them if None.
+    /// Returns how many were thrown away.
+    pub fn purge(&self, id: Option<u64>) -> Result<usize, Error> {
+        let _guard = CHANGING.lock().unwrap();
+        let entries = self.load()?;
+        let n = entries.len();
+        let kept: Vec<_> = entries
+            .into_iter()
+            .filter(|e| id.map(|i| i != e.id).unwrap_or(false))
+            .collect();
+        let purged = n - kept.len();
+
+        if purged > 0 {
+            self.save(&kept)?;
+        }
+
+        Ok(purged)
+    }
+
+    /// Record how attempts at some deliveries went. The ones that went
+    /// through, or that have now failed too many times, are removed, and the
+    /// latter are returned.
+    fn settle(&self, outcomes: Vec<(u64, Result<(), String>)>) -> Result<Vec<OutboxEntry>, Error> {
+        let _guard = CHANGING.lock().unwrap();
+        let now = Utc::now();
+        let mut kept = Vec::new();
+        let mut given_up = Vec::new();
+
+        // Deliveries might have been added or purged in the meantime, so we
+        // go through what's in the file now.
+        for mut entry in self.load()? {
+            match outcomes.iter().find(|(id, _)| *id == entry.id) {
+                None => kept.push(entry),
+                Some((_, Ok(()))) => {}
+
+                Some((_, Err(e))) => {
+                    entry.attempts += 1;
+                    entry.last_error = e.clone();
+                    entry.next_attempt = now + retry_wait(entry.attempts);
+
+                    if entry.attempts >= self.max_attempts {
+                        given_up.push(entry);
+                    } else {
+                        kept.push(entry);
+                    }
+                }
+            }
+        }
+
+        self.save(&kept)?;
+        Ok(given_up)
+    }
+}
+
+/// How long to wait after a delivery has been tried `attempts` times.
+fn retry_wait(attempts: u32) -> Duration {
+    let doublings = attempts.saturating_sub(1).min(16);
+    Duration::seconds((FIRST_RETRY_SECONDS << doublings).min(MAX_RETRY_SECONDS))
+}
+
+/// A delivery kept in the outbox while it's tried for the first time. It
+/// stops being marked as being tried when this is dropped.
+pub struct Held {
+    outbox: Outbox,
+    id: u64,
+    desc: String,
+}
+
+/// Keep a delivery in the outbox, if one is configured, before it's tried
+/// for the first time. Returns None if it won't be tried again should that
+/// fail.
+pub fn hold(config: Option<&ServerOutboxConfiguration>, delivery: &Delivery) -> Option<Held> {
+    let outbox = Outbox::new(config?);
+
+    if outbox.max_attempts <= 1 {
+        return None;
+    }
+
+    let desc = delivery.describe();
+
+    match outbox.add(delivery.clone()) {
+        Ok(id) => Some(Held { outbox, id, desc }),
+        Err(e) => {
+            log!("error keeping the {} in the outbox: {}", desc, e);
+            None
+        }
+    }
+}
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        self.outbox.set_in_flight(self.id, false);
+    }
+}
+
+impl Held {
+    /// Record how the first attempt went. Returns whether the delivery will
+    /// be tried again.
+    pub fn settle(self, outcome: Result<(), String>) -> bool {
+        let failed = outcome.is_err();
+
+        match self.outbox.settle(vec![(self.id, outcome)]) {
+            Ok(given_up) if failed && given_up.is_empty() => {
+                log!(
+                    "will try the {} again later, as outbox #{}",
+                    self.desc,
+                    self.id
+                );
+                true
+            }
+
+            Ok(_) => false,
+
+            Err(e) => {
+                log!("error updating the outbox: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Try the waiting deliveries again as their times come.
+pub async fn run(outbox: Outbox, config: ServerConfiguration) {
+    let mut interval = time::interval(TokioDuration::from_secs(15));
+
+    loop {
+        interval.tick().await;
+
+        let now = Utc::now();
+
+        let due: Vec<_> = match outbox.load() {
+            Ok(entries) => entries
+                .into_iter()
+                .filter(|e| e.next_attempt <= now)
+                .collect(),
+            Err(e) => {
+                log!("error reading the outbox: {}", e);
+                continue;
+            }
+        };
+
+        if due.is_empty() {
+            continue;
+        }
+
+        let mut outcomes = Vec::new();
+
+        for entry in &due {
+            // The first attempt at a delivery might still be under way.
+            if !outbox.set_in_flight(entry.id, true) {
+                continue;
+            }
+
+            // While the ones before it were being tried, a delivery might
+            // have been purged, or dropped for a newer call to its webhook.
+            match outbox.contains(entry) {
+                Ok(true) => {}
+                Ok(false) => {
+                    outbox.set_in_flight(entry.id, false);
+                    continue;
+                }
+                Err(e) => {
+                    outbox.set_in_flight(entry.id, false);
+                    log!("error reading the outbox: {}", e);
+                    break;
+                }
+            }
+
+            let outcome = entry
+                .delivery
+                .send(&config)
+                .await
+                .map_err(|e| e.to_string());
+
+            match outcome {
+                Ok(()) => log!(
+                    "outbox #{}: {} went through on attempt {}",
+                    entry.id,
+                    entry.delivery.describe(),
+                    entry.attempts + 1
+                ),
+                Err(ref e) => log!(
+                    "outbox #{}: {} failed again: {}",
+                    entry.id,
+                    entry.delivery.describe(),
+                    e
+                ),
+            }
+
+            outcomes.push((entry.id, outcome));
+        }
+
+        let ids: Vec<_> = outcomes.iter().map(|(id, _)| *id).collect();
+        let settled = outbox.settle(outcomes);
+
+        for id in ids {
+            outbox.set_in_flight(id, false);
+        }
+
+        let given_up = match settled {
+            Ok(g) => g,
+            Err(e) => {
+                log!("error updating the outbox: {}", e);
+                continue;
+            }
+        };
+
+        for entry in given_up {
+            let msg = format!(
+                "gave up on the {} after {} attempts: {}",
+                entry.delivery.describe(),
+                entry.attempts,
+                entry.last_error
+            );
+            log!("outbox #{}: {}", entry.id, msg);
+
+            // Telling the owner that a notification couldn't be sent would
+            // likely just fail the same way.
+            if let Delivery::Webhook { .. } = entry.delivery {
+                config.notifications.notify("Webhook failed", &msg);
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Sending commands to the panels.
+//!
+//! Admins can ask the panels to redraw, clear themselves, restart, switch
+//! layouts, or blank their displays. Each panel only carries out the commands
+//! that its own configuration allows.
+
+use std::fmt::Write as FmtWrite;
+
+use crate::html::{escape_html, page};
+
+/// The commands offered on the admin page, with their button labels.
+const COMMANDS: &[(&str, &str)] = &[
+    ("redraw", "Redraw"),
+    ("clear", "Clear and redraw"),
+    ("screensaver", "Screensaver"),
+    ("layout:standard", "Standard layout"),
+    ("layout:status", "Status-only layout"),
+    ("layout:week", "Who's-around layout"),
+    ("layout:room", "Meeting-room layout"),
+    ("restart", "Restart client"),
+];
+
+pub fn render_panels_page(token: &str) -> String {
+    let mut html = String::new();
+
+    html.push_str(
+        "<p>Panels only carry out the commands allowed by their configuration. \
+         Sending a command leaves you on this page.</p>\n",
+    );
+
+    for (command, label) in COMMANDS {
+        let _ = writeln!(
+            html,
+            "<form method=\"post\" action=\"api/command\" style=\"display: inline\">\n\
+             <input type=\"hidden\" name=\"token\" value=\"{}\">\n\
+             <input type=\"hidden\" name=\"command\" value=\"{}\">\n\
+             <input type=\"submit\" value=\"{}\">\n\
+             </form>",
+            escape_html(token),
+            command,
+            label,
+        );
+    }
+
+    page("Panel Commands", &html)
+}
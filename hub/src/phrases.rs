@@ -0,0 +1,498 @@
+//! Times in the wording of a status.
+//!
+//! People write statuses like "back at 3", "gone until Monday", or "lunch,
+//! 45 min", which say when they stop being true. We pick out that time, so
+//! that the previous status can go back up once it passes, and rewrite the
+//! phrase so that it still makes sense later on: "45 min" means nothing an
+//! hour from now, so it becomes "back at 13:15", and "back at 3" says which
+//! three o'clock. Only a phrase at the very end of a status counts, since
+//! "seminar at 3, then around" isn't saying when the status expires.
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, TimeZone, Utc, Weekday};
+
+/// A status, and when it says that it stops being true.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimedStatus {
+    /// The status, with any phrase about time rewritten.
+    pub person_is: String,
+
+    /// When the status stops being true, if it says.
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl TimedStatus {
+    /// How many minutes after `now` the status stops being true, rounded up,
+    /// if it says.
+    pub fn expires_minutes(&self, now: DateTime<Utc>) -> Option<i64> {
+        let seconds = (self.until? - now).num_seconds();
+        Some(((seconds + 59) / 60).max(1))
+    }
+}
+
+/// The longest length of time that we'll take a status to mention, in
+/// minutes. Anything longer is more likely a typo than a plan, and is left
+/// alone. It's also the longest that a status or lock can be set to last.
+pub const MAX_MINUTES: i64 = 30 * 24 * 60;
+
+/// The words that can come before a return time or day, like "until 3" or
+/// "back on Monday". Longer ones come first, so that they win.
+const INTRODUCTIONS: &[&[&str]] = &[
+    &["back", "at"],
+    &["back", "by"],
+    &["back", "around"],
+    &["back", "on"],
+    &["back"],
+    &["until"],
+    &["till"],
+    &["til"],
+    &["'til"],
+];
+
+/// Find the time, if any, at the end of a status, and rewrite it.
+pub fn parse(text: &str, now: DateTime<Local>) -> TimedStatus {
+    let text = text.trim();
+    let words = split_words(text);
+
+    parse_return_time(text, &words, now)
+        .or_else(|| parse_return_day(text, &words, now))
+        .or_else(|| parse_duration(text, &words, now))
+        .unwrap_or_else(|| TimedStatus {
+            person_is: text.to_owned(),
+            until: None,
+        })
+}
+
+/// The next time after `now` that the clock reads `text`, like "9am" or
+/// "13:30". A time like "9" is whichever of 9:00 and 21:00 comes first.
+pub fn next_time(text: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    next_of(&clock_candidates(&text.to_lowercase())?, now)
+}
+
+/// The number of minutes in a length of time like "2h", "90 minutes", or
+/// "half an hour".
+pub fn duration_minutes(text: &str) -> Option<i64> {
+    let words: Vec<String> = text.split_whitespace().map(str::to_lowercase).collect();
+
+    match words.as_slice() {
+        [word] => minutes_of(word, ""),
+        [number, unit] => minutes_of(number, unit),
+        [half, a, hour] if half == "half" && (a == "a" || a == "an") && hour == "hour" => Some(30),
+        _ => None,
+    }
+}
+
+/// A word of the status: where it starts, and its text in lowercase, without
+/// any punctuation around it.
+struct Word {
+    start: usize,
+    text: String,
+}
+
+fn split_words(text: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut start = None;
+
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(i),
+
+            (Some(s), true) => {
+                let text = text[s..i]
+                    .trim_matches(|c| matches!(c, '(' | ')' | ',' | '.' | '!' | ';'))
+                    .to_lowercase();
+                words.push(Word { start: s, text });
+                start = None;
+            }
+
+            _ => {}
+        }
+    }
+
+    words
+}
+
+/// If the words before the last `tail` of them are one of the
+/// introductions, where it starts.
+fn introduction(words: &[Word], tail: usize) -> Option<usize> {
+    let end = words.len().checked_sub(tail)?;
+
+    INTRODUCTIONS.iter().find_map(|intro| {
+        let begin = end.checked_sub(intro.len())?;
+
+        if words[begin..end]
+            .iter()
+            .zip(intro.iter())
+            .all(|(w, i)| w.text == *i)
+        {
+            Some(words[begin].start)
+        } else {
+            None
+        }
+    })
+}
+
+/// "back at 3", "until 4:30pm", and so on.
+fn parse_return_time(text: &str, words: &[Word], now: DateTime<Local>) -> Option<TimedStatus> {
+    // The time can be split in two, like "3 pm".
+    for tail in &[2, 1] {
+        let time_words = words.get(words.len().checked_sub(*tail)?..)?;
+        let joined: String = time_words.iter().map(|w| w.text.as_str()).collect();
+
+        let candidates = match clock_candidates(&joined) {
+            Some(c) => c,
+            None => continue,
+        };
+
+        if introduction(words, *tail).is_none() {
+            continue;
+        }
+
+        let until = next_of(&candidates, now)?;
+        let time_start = time_words[0].start;
+
+        return Some(TimedStatus {
+            person_is: format!(
+                "{} {}",
+                text[..time_start].trim_end(),
+                until.format("%H:%M")
+            ),
+            until: Some(until.with_timezone(&Utc)),
+        });
+    }
+
+    None
+}
+
+/// The times of day that `text` might mean. A time like "3" could be in the
+/// morning or the afternoon, so it has two.
+fn clock_candidates(text: &str) -> Option<Vec<NaiveTime>> {
+    match text {
+        "noon" => return Some(vec![NaiveTime::from_hms_opt(12, 0, 0)?]),
+        "midnight" => return Some(vec![NaiveTime::from_hms_opt(0, 0, 0)?]),
+        _ => {}
+    }
+
+    let (clock, pm) = if let Some(c) = text.strip_suffix("am").or_else(|| text.strip_suffix("a.m"))
+    {
+        (c, Some(false))
+    } else if let Some(c) = text.strip_suffix("pm").or_else(|| text.strip_suffix("p.m")) {
+        (c, Some(true))
+    } else {
+        (text, None)
+    };
+
+    let (hour_text, minute) = match clock.split_once(':') {
+        Some((h, m)) if m.len() == 2 => (h, m.parse::<u32>().ok()?),
+        Some(_) => return None,
+        None => (clock, 0),
+    };
+
+    if hour_text.is_empty() || hour_text.len() > 2 || !hour_text.chars().all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let hour: u32 = hour_text.parse().ok()?;
+    let time = |h| NaiveTime::from_hms_opt(h, minute, 0);
+
+    match pm {
+        Some(_) if hour == 0 || hour > 12 => None,
+        Some(false) => Some(vec![time(hour % 12)?]),
+        Some(true) => Some(vec![time(hour % 12 + 12)?]),
+
+        // A leading zero, like "09:00", means the 24-hour clock.
+        None if hour == 0 || hour > 12 || hour_text.starts_with('0') => Some(vec![time(hour)?]),
+        None => Some(vec![time(hour % 12)?, time(hour % 12 + 12)?]),
+    }
+}
+
+/// The soonest time after `now` that the clock reads one of `candidates`.
+fn next_of(candidates: &[NaiveTime], now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let today = now.date_naive();
+
+    [today, today.succ_opt()?]
+        .iter()
+        .flat_map(|date| candidates.iter().map(move |time| date.and_time(*time)))
+        .filter_map(|t| Local.from_local_datetime(&t).earliest())
+        .filter(|t| *t > now)
+        .min()
+}
+
+/// "gone until Monday", "back tomorrow", and so on. The status lasts until
+/// that day starts.
+fn parse_return_day(text: &str, words: &[Word], now: DateTime<Local>) -> Option<TimedStatus> {
+    let last = words.last()?;
+    introduction(words, 1)?;
+
+    let today = now.date_naive();
+
+    let (date, name) = if last.text == "tomorrow" {
+        (today.succ_opt()?, "tomorrow")
+    } else {
+        let day = weekday(&last.text)?;
+
+        let mut ahead =
+            (7 + day.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+
+        // The same day of the week as today means next week.
+        if ahead == 0 {
+            ahead = 7;
+        }
+
+        (today + Duration::days(ahead as i64), day_name(day))
+    };
+
+    let until = Local
+        .from_local_datetime(&date.and_time(NaiveTime::MIN))
+        .earliest()?;
+
+    Some(TimedStatus {
+        person_is: format!("{} {}", text[..last.start].trim_end(), name),
+        until: Some(until.with_timezone(&Utc)),
+    })
+}
+
+fn weekday(text: &str) -> Option<Weekday> {
+    let day = match text {
+        "mon" | "monday" => Weekday::Mon,
+        "tue" | "tues" | "tuesday" => Weekday::Tue,
+        "wed" | "weds" | "wednesday" => Weekday::Wed,
+        "thu" | "thur" | "thurs" | "thursday" => Weekday::Thu,
+        "fri" | "friday" => Weekday::Fri,
+        "sat" | "saturday" => Weekday::Sat,
+        "sun" | "sunday" => Weekday::Sun,
+        _ => return None,
+    };
+
+    Some(day)
+}
+
+fn day_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+/// "lunch, 45 min", "in a meeting for 2 hours", "errand (30m)", and so on.
+/// The length of time becomes the time that it ends.
+fn parse_duration(text: &str, words: &[Word], now: DateTime<Local>) -> Option<TimedStatus> {
+    let n = words.len();
+
+    let (minutes, tail) = if let Some(m) = words.last().and_then(|w| minutes_of(&w.text, "")) {
+        (m, 1)
+    } else if n >= 2 && words[n - 2].text == "half" && words[n - 1].text == "hour" {
+        (30, 2)
+    } else if n >= 3
+        && words[n - 3].text == "half"
+        && matches!(words[n - 2].text.as_str(), "a" | "an")
+        && words[n - 1].text == "hour"
+    {
+        (30, 3)
+    } else if n >= 2 {
+        (minutes_of(&words[n - 2].text, &words[n - 1].text)?, 2)
+    } else {
+        return None;
+    };
+
+    let mut start = n - tail;
+    let is_separator = |c| matches!(c, ',' | '-' | ':' | '(');
+
+    // The length of time has to be set apart from the rest of the status,
+    // so that something like "room 3h" is left alone.
+    if start > 0 && words[start - 1].text == "for" {
+        start -= 1;
+    } else if start > 0 && !text[words[start].start..].starts_with('(') {
+        let before = text[..words[start].start].trim_end();
+
+        if !before.ends_with(is_separator) {
+            return None;
+        }
+    }
+
+    let prefix =
+        text[..words[start].start].trim_end_matches(|c: char| c.is_whitespace() || is_separator(c));
+    let until = now.checked_add_signed(Duration::minutes(minutes))?;
+    let back = format!("back at {}", until.format("%H:%M"));
+
+    Some(TimedStatus {
+        person_is: if prefix.is_empty() {
+            back
+        } else {
+            format!("{}, {}", prefix, back)
+        },
+        until: Some(until.with_timezone(&Utc)),
+    })
+}
+
+/// The number of minutes in a length of time like "45 min", given as a
+/// number and a unit, or "45min", given as one word with an empty `unit`.
+/// Lengths longer than `MAX_MINUTES` don't count.
+fn minutes_of(number: &str, unit: &str) -> Option<i64> {
+    let (number, unit) = if unit.is_empty() {
+        let split = number.find(|c: char| !c.is_ascii_digit())?;
+        number.split_at(split)
+    } else {
+        (number, unit)
+    };
+
+    let count = match number {
+        "a" | "an" | "one" => 1,
+        _ => number.parse::<i64>().ok().filter(|n| *n > 0)?,
+    };
+
+    let scale = match unit {
+        "m" | "min" | "mins" | "minute" | "minutes" => 1,
+        "h" | "hr" | "hrs" | "hour" | "hours" => 60,
+        _ => return None,
+    };
+
+    count.checked_mul(scale).filter(|m| *m <= MAX_MINUTES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Monday, 12 October 2026, at the given time.
+    fn monday_at(hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2026, 10, 12, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn local(date: (i32, u32, u32), hour: u32, minute: u32) -> DateTime<Utc> {
+        Local
+            .with_ymd_and_hms(date.0, date.1, date.2, hour, minute, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn unchanged(text: &str) -> TimedStatus {
+        TimedStatus {
+            person_is: text.to_owned(),
+            until: None,
+        }
+    }
+
+    #[test]
+    fn return_time() {
+        assert_eq!(
+            parse("back at 3", monday_at(10, 0)),
+            TimedStatus {
+                person_is: "back at 15:00".to_owned(),
+                until: Some(local((2026, 10, 12), 15, 0)),
+            }
+        );
+
+        assert_eq!(
+            parse("in a meeting until 4:30pm", monday_at(10, 0)),
+            TimedStatus {
+                person_is: "in a meeting until 16:30".to_owned(),
+                until: Some(local((2026, 10, 12), 16, 30)),
+            }
+        );
+    }
+
+    #[test]
+    fn twelve_or_twenty_four_hours() {
+        // "3" is whichever of 3:00 and 15:00 comes next.
+        assert_eq!(
+            parse("back at 3", monday_at(2, 0)).until,
+            Some(local((2026, 10, 12), 3, 0))
+        );
+        assert_eq!(
+            parse("back at 3", monday_at(16, 0)).until,
+            Some(local((2026, 10, 13), 3, 0))
+        );
+
+        // A leading zero or an "am" rules out the afternoon.
+        assert_eq!(
+            parse("back at 09:00", monday_at(10, 0)).until,
+            Some(local((2026, 10, 13), 9, 0))
+        );
+        assert_eq!(
+            parse("back at 9 am", monday_at(10, 0)).until,
+            Some(local((2026, 10, 13), 9, 0))
+        );
+
+        // And an hour past 12 can only be on the 24-hour clock.
+        assert_eq!(
+            parse("back at 13pm", monday_at(10, 0)),
+            unchanged("back at 13pm")
+        );
+    }
+
+    #[test]
+    fn return_day() {
+        assert_eq!(
+            parse("gone until Friday", monday_at(10, 0)),
+            TimedStatus {
+                person_is: "gone until Friday".to_owned(),
+                until: Some(local((2026, 10, 16), 0, 0)),
+            }
+        );
+
+        // Today's day of the week means next week.
+        assert_eq!(
+            parse("away until mon", monday_at(10, 0)),
+            TimedStatus {
+                person_is: "away until Monday".to_owned(),
+                until: Some(local((2026, 10, 19), 0, 0)),
+            }
+        );
+    }
+
+    #[test]
+    fn duration() {
+        assert_eq!(
+            parse("lunch, 45 min", monday_at(12, 30)),
+            TimedStatus {
+                person_is: "lunch, back at 13:15".to_owned(),
+                until: Some(local((2026, 10, 12), 13, 15)),
+            }
+        );
+
+        assert_eq!(
+            parse("in a meeting for half an hour", monday_at(10, 0)).person_is,
+            "in a meeting, back at 10:30"
+        );
+
+        assert_eq!(
+            parse("errand (2h)", monday_at(10, 0)).person_is,
+            "errand, back at 12:00"
+        );
+        assert_eq!(duration_minutes("90 minutes"), Some(90));
+    }
+
+    #[test]
+    fn not_a_time() {
+        assert_eq!(parse("room 3h", monday_at(10, 0)), unchanged("room 3h"));
+        assert_eq!(
+            parse("seminar at 3, then around", monday_at(10, 0)),
+            unchanged("seminar at 3, then around")
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        for text in &[
+            "lunch, 9999999999 h",
+            "lunch, 99999999999999999999 min",
+            "lunch, 721 hours",
+        ] {
+            assert_eq!(parse(text, monday_at(10, 0)), unchanged(text));
+        }
+
+        assert_eq!(duration_minutes("9999999999 h"), None);
+        assert_eq!(duration_minutes("720h"), Some(MAX_MINUTES));
+    }
+}
@@ -0,0 +1,124 @@
+//! Trying out a status before it goes live.
+//!
+//! A long announcement might not fit the panel the way one hopes. So, if
+//! previews are configured, a draft status can be set from the preview page.
+//! It's only shown there and on test panels, the ones whose `[displays.<ID>]`
+//! settings have `preview = true`, until it's promoted to be the real status.
+//! The draft is kept in a file, so that it survives a restart of the hub.
+
+use rc_stickynote_protocol::{sealed, PersonIsUpdateHelloMessage};
+use serde::Deserialize;
+use std::{
+    fmt::Write as FmtWrite,
+    io::{Error, ErrorKind},
+    path::PathBuf,
+};
+
+use crate::html::{escape_html, page};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerPreviewConfiguration {
+    /// Where to store the draft status.
+    pub path: PathBuf,
+}
+
+/// A handle to the draft status.
+#[derive(Clone, Debug)]
+pub struct PreviewDraft {
+    path: PathBuf,
+}
+
+impl PreviewDraft {
+    pub fn new(config: &ServerPreviewConfiguration) -> Self {
+        PreviewDraft {
+            path: config.path.clone(),
+        }
+    }
+
+    /// Read the draft, if there is one.
+    pub fn load(&self) -> Result<Option<PersonIsUpdateHelloMessage>, Error> {
+        match std::fs::read(&self.path) {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replace the draft, or get rid of it.
+    pub fn save(&self, draft: Option<&PersonIsUpdateHelloMessage>) -> Result<(), Error> {
+        match draft {
+            Some(d) => std::fs::write(&self.path, serde_json::to_string(d)?),
+
+            None => match std::fs::remove_file(&self.path) {
+                Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+                r => r,
+            },
+        }
+    }
+}
+
+/// The preview page: the draft, roughly as the panel would show it, with
+/// buttons to promote or discard it, and a form for setting a new one.
+pub fn render_preview_page(
+    draft: Option<&PersonIsUpdateHelloMessage>,
+    token: &str,
+    notice: Option<&str>,
+) -> String {
+    let mut html = String::new();
+
+    if let Some(n) = notice {
+        let _ = writeln!(html, "<p><b>{}</b></p>", escape_html(n));
+    }
+
+    match draft {
+        None => html.push_str("<p>There is no draft status.</p>\n"),
+
+        Some(d) => {
+            let text = if sealed::is_sealed(&d.person_is) {
+                "<i>(sealed)</i>".to_owned()
+            } else {
+                escape_html(&d.person_is)
+            };
+
+            let _ = writeln!(
+                html,
+                "<div style=\"width: 384px; border: 2px solid black; padding: 1em 0; \
+                 font-family: serif; text-align: center\">\n\
+                 <div style=\"font-size: 2em\">The Innovation Scientist is:</div>\n\
+                 <div style=\"font-family: sans-serif; font-size: 1.5em; margin-top: 0.5em; \
+                 white-space: pre-wrap\">{}</div>\n\
+                 </div>\n\
+                 <p>Drafted by {} from {}.</p>",
+                text,
+                escape_html(d.set_by.as_deref().unwrap_or("unknown")),
+                escape_html(d.source.as_deref().unwrap_or("unknown")),
+            );
+
+            for (action, label) in &[("promote", "Make it live"), ("discard", "Discard")] {
+                let _ = writeln!(
+                    html,
+                    "<form method=\"post\" action=\"preview/{}\" style=\"display: inline\">\n\
+                     <input type=\"hidden\" name=\"token\" value=\"{}\">\n\
+                     <input type=\"submit\" value=\"{}\">\n\
+                     </form>",
+                    action,
+                    escape_html(token),
+                    label,
+                );
+            }
+        }
+    }
+
+    let _ = writeln!(
+        html,
+        "<h2>New draft</h2>\n\
+         <form method=\"post\" action=\"preview/draft\">\n\
+         <input type=\"hidden\" name=\"token\" value=\"{}\">\n\
+         <input type=\"text\" name=\"status\" size=\"30\">\n\
+         <input type=\"submit\" value=\"Preview\">\n\
+         </form>",
+        escape_html(token),
+    );
+
+    page("Status Preview", &html)
+}
@@ -0,0 +1,139 @@
+//! Serving the HTTP API from behind a reverse proxy and to other origins.
+//!
+//! The hub might be served by nginx under a path like `/stickynote/`, in which
+//! case the proxy may or may not strip that prefix before passing requests
+//! along, and the pages' links need to include it. The proxy also hides the
+//! real client address, which it passes along in `X-Forwarded-For`, a header
+//! that we only believe if it comes from a proxy that we trust. Finally, a
+//! dashboard served from a different origin can only call the API if we send
+//! CORS headers allowing it.
+
+use hyper::{
+    header::{self, HeaderValue},
+    Body, Request, Response,
+};
+use serde::Deserialize;
+use std::net::{IpAddr, SocketAddr};
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ServerHttpConfiguration {
+    /// If the hub is served under a path prefix, like "/stickynote", that
+    /// prefix.
+    pub base_path: String,
+
+    /// Origins whose web pages may call the API, like
+    /// "https://dashboard.example.com", or "*" for any origin.
+    pub cors_origins: Vec<String>,
+
+    /// The addresses of reverse proxies whose `X-Forwarded-For` headers we
+    /// believe.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+/// The address of the client making an HTTP request, as best we can tell.
+/// It's stored in the request's extensions.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientAddr(pub IpAddr);
+
+/// Describe the client making a request, for logging.
+pub fn describe_client(req: &Request<Body>) -> String {
+    match req.extensions().get::<ClientAddr>() {
+        Some(a) => a.0.to_string(),
+        None => "unknown address".to_owned(),
+    }
+}
+
+impl ServerHttpConfiguration {
+    /// The base path, normalized to either be empty or to start but not end
+    /// with a slash.
+    pub fn base_path(&self) -> String {
+        let trimmed = self.base_path.trim_matches('/');
+
+        if trimmed.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", trimmed)
+        }
+    }
+
+    /// The path of a request, relative to the base path. If the request's
+    /// path doesn't start with the base path, the proxy has presumably
+    /// stripped it already.
+    pub fn route_path(&self, path: &str) -> String {
+        let base = self.base_path();
+
+        match path.strip_prefix(base.as_str()) {
+            Some(rest) if !base.is_empty() && rest.is_empty() => "/".to_owned(),
+            Some(rest) if !base.is_empty() && rest.starts_with('/') => rest.to_owned(),
+            _ => path.to_owned(),
+        }
+    }
+
+    /// Figure out who's really making a request. If it comes through a
+    /// trusted proxy, the client is the last address in `X-Forwarded-For`
+    /// that isn't another trusted proxy.
+    pub fn client_addr(&self, req: &Request<Body>, peer: SocketAddr) -> IpAddr {
+        let mut addr = peer.ip();
+
+        if !self.trusted_proxies.contains(&addr) {
+            return addr;
+        }
+
+        let forwarded = req
+            .headers()
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter_map(|a| a.trim().parse::<IpAddr>().ok())
+            .collect::<Vec<_>>();
+
+        for a in forwarded.into_iter().rev() {
+            addr = a;
+
+            if !self.trusted_proxies.contains(&a) {
+                break;
+            }
+        }
+
+        addr
+    }
+
+    /// The value for `Access-Control-Allow-Origin` for a request from the
+    /// given origin, if it's allowed.
+    fn allowed_origin(&self, origin: &str) -> Option<String> {
+        if self.cors_origins.iter().any(|o| o == "*") {
+            Some("*".to_owned())
+        } else if self.cors_origins.iter().any(|o| o == origin) {
+            Some(origin.to_owned())
+        } else {
+            None
+        }
+    }
+
+    /// Add CORS headers to a response, if the request came from an allowed
+    /// origin.
+    pub fn add_cors_headers(&self, origin: Option<&str>, resp: &mut Response<Body>) {
+        let allowed = match origin.and_then(|o| self.allowed_origin(o)) {
+            Some(a) => a,
+            None => return,
+        };
+
+        let headers = resp.headers_mut();
+
+        if let Ok(v) = HeaderValue::from_str(&allowed) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, v);
+        }
+
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("GET, POST, DELETE, OPTIONS"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("Authorization, Content-Type"),
+        );
+        headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+    }
+}
@@ -0,0 +1,171 @@
+//! Statuses queued up for later.
+//!
+//! A day's plans can be set in one go, like "in seminar" at 1:00 and "office
+//! hours" at 3:00, rather than remembering to update the status as each thing
+//! starts. The queued statuses are kept in a file, in the order that they go
+//! up, so that they survive a restart of the hub. When each one's time comes,
+//! it's submitted as if it had just arrived from wherever it was queued from,
+//! so the content filter and moderation apply as usual. The panels are told
+//! about the next one, so that they can mention it in the footer.
+
+use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone, Utc};
+use rc_stickynote_protocol::{PersonIsUpdateHelloMessage, QueuedStatus};
+use serde::Deserialize;
+use std::{
+    io::{Error, ErrorKind},
+    path::PathBuf,
+    sync::Mutex,
+};
+use tokio::time::{self, Duration as TokioDuration};
+
+use crate::{updates::UpdateHub, DisplayStateMutation, ServerConfiguration};
+
+/// The most statuses that we'll keep queued up.
+pub const MAX_QUEUED_STATUSES: usize = 20;
+
+/// Held while changing the queue, so that simultaneous changes don't
+/// overwrite each other.
+static CHANGING: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerStatusQueueConfiguration {
+    /// Where to store the queued statuses.
+    pub path: PathBuf,
+}
+
+/// A handle to the queued statuses. Each is stored as the update that will
+/// be submitted, with its timestamp saying when.
+#[derive(Clone, Debug)]
+pub struct StatusQueue {
+    path: PathBuf,
+}
+
+impl StatusQueue {
+    pub fn new(config: &ServerStatusQueueConfiguration) -> Self {
+        StatusQueue {
+            path: config.path.clone(),
+        }
+    }
+
+    /// Read all of the queued statuses, soonest first.
+    pub fn load(&self) -> Result<Vec<PersonIsUpdateHelloMessage>, Error> {
+        match std::fs::read(&self.path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&self, queue: &[PersonIsUpdateHelloMessage]) -> Result<(), Error> {
+        std::fs::write(&self.path, serde_json::to_string_pretty(queue)?)
+    }
+
+    /// Add statuses to the queue, returning the new queue.
+    pub fn add(
+        &self,
+        updates: Vec<PersonIsUpdateHelloMessage>,
+    ) -> Result<Vec<PersonIsUpdateHelloMessage>, Error> {
+        let _guard = CHANGING.lock().unwrap();
+        let mut queue = self.load()?;
+
+        if queue.len() + updates.len() > MAX_QUEUED_STATUSES {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("the queue can only hold {} statuses", MAX_QUEUED_STATUSES),
+            ));
+        }
+
+        queue.extend(updates);
+        queue.sort_by_key(|u| u.timestamp);
+        self.save(&queue)?;
+        Ok(queue)
+    }
+
+    /// Throw away all of the queued statuses.
+    pub fn clear(&self) -> Result<(), Error> {
+        let _guard = CHANGING.lock().unwrap();
+        self.save(&[])
+    }
+
+    /// Take the statuses whose time has come off of the queue.
+    fn take_due(&self, now: DateTime<Utc>) -> Result<Vec<PersonIsUpdateHelloMessage>, Error> {
+        let _guard = CHANGING.lock().unwrap();
+        let (due, rest): (Vec<_>, Vec<_>) =
+            self.load()?.into_iter().partition(|u| u.timestamp <= now);
+
+        if !due.is_empty() {
+            self.save(&rest)?;
+        }
+
+        Ok(due)
+    }
+}
+
+/// What to tell the panels about the queue: its first entry, if any.
+pub fn next_status(queue: &[PersonIsUpdateHelloMessage]) -> Option<QueuedStatus> {
+    queue.first().map(|u| QueuedStatus {
+        person_is: u.person_is.clone(),
+        at: u.timestamp,
+    })
+}
+
+/// The next time that the local clock reads `text`, like "13:00". A time
+/// that has already passed today means tomorrow.
+pub fn next_time_of_day(text: &str, now: DateTime<Local>) -> Result<DateTime<Utc>, String> {
+    let time = NaiveTime::parse_from_str(text, "%H:%M")
+        .map_err(|_| format!("expected a time like \"13:00\", got \"{}\"", text))?;
+
+    let mut date = now.date_naive();
+
+    if time <= now.time() {
+        date += Duration::days(1);
+    }
+
+    Local
+        .from_local_datetime(&date.and_time(time))
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .ok_or_else(|| format!("{} doesn't happen on {}", text, date))
+}
+
+/// Put up the queued statuses as their times come.
+pub async fn run(queue: StatusQueue, config: ServerConfiguration, send_updates: UpdateHub) {
+    let mut interval = time::interval(TokioDuration::from_secs(15));
+
+    loop {
+        interval.tick().await;
+
+        let due = match queue.take_due(Utc::now()) {
+            Ok(d) => d,
+            Err(e) => {
+                log!("error reading the status queue: {}", e);
+                continue;
+            }
+        };
+
+        // If the hub was down for a while, several might be due at once, and
+        // only the latest matters.
+
+        let update = match due.into_iter().last() {
+            Some(u) => u,
+            None => continue,
+        };
+
+        log!("putting up queued status: {}", update.person_is);
+
+        // The hub attached the source when it queued the update, so it says
+        // how the update came in.
+        let via = update.source.clone().unwrap_or_default();
+
+        if let Err(e) = config.submit_update(update, &via, &send_updates).await {
+            log!("error submitting queued status: {}", e);
+        }
+
+        match queue.load() {
+            Ok(rest) => {
+                send_updates.send(DisplayStateMutation::SetNextStatus(next_status(&rest)));
+            }
+            Err(e) => log!("error reading the status queue: {}", e),
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Recording the hub's traffic, and playing it back.
+//!
+//! Bugs reported from the field tend to depend on the exact sequence of
+//! updates that a hub received. If the hub is run with `serve --record`, every
+//! hello that it receives and every frame that it sends to a panel is appended
+//! to a file, one JSON record per line. The `replay` command then sends the
+//! recorded hellos to a test hub with the same timing, keeping panel
+//! connections open as the panels did, so that the bug can be watched
+//! happening again; the test hub can itself be recorded, to compare the frames
+//! that it sends with the original ones. Tokens are left out of the recording,
+//! since it may well get passed around.
+
+use chrono::{DateTime, Utc};
+use rc_stickynote_protocol::{session::client::AwaitingHello, ClientHelloMessage, DisplayMessage};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Error, Write},
+    net::Ipv4Addr,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::{
+    net::TcpStream,
+    time::{self, Duration},
+};
+
+use crate::GenericError;
+
+/// One record in a recording.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "direction", rename_all = "kebab-case")]
+pub enum TrafficRecord {
+    /// A hello from a client or panel.
+    Inbound {
+        timestamp: DateTime<Utc>,
+        peer: String,
+        hello: ClientHelloMessage,
+    },
+
+    /// A frame sent to a panel.
+    Outbound {
+        timestamp: DateTime<Utc>,
+        peer: String,
+        frame: Box<DisplayMessage>,
+    },
+}
+
+/// The file that we're recording to, if any.
+static RECORDING: Mutex<Option<File>> = Mutex::new(None);
+
+/// Start recording traffic to the given file, appending to it if it already
+/// exists.
+pub fn start(path: &Path) -> Result<(), Error> {
+    let f = OpenOptions::new().create(true).append(true).open(path)?;
+    *RECORDING.lock().unwrap() = Some(f);
+    Ok(())
+}
+
+fn is_recording() -> bool {
+    RECORDING.lock().unwrap().is_some()
+}
+
+fn record(rec: &TrafficRecord) {
+    let mut guard = RECORDING.lock().unwrap();
+
+    let f = match *guard {
+        Some(ref mut f) => f,
+        None => return,
+    };
+
+    let result = serde_json::to_string(rec)
+        .map_err(Error::from)
+        .and_then(|line| writeln!(f, "{}", line));
+
+    if let Err(e) = result {
+        log!("error writing to traffic recording: {}", e);
+    }
+}
+
+/// Record a hello received from the given peer.
+pub fn inbound(peer: &str, hello: &ClientHelloMessage) {
+    if !is_recording() {
+        return;
+    }
+
+    let mut hello = hello.clone();
+
+    match hello {
+        ClientHelloMessage::PersonIsUpdate(ref mut m) => m.token = None,
+        ClientHelloMessage::Doorbell(ref mut m) => m.token = None,
+        ClientHelloMessage::BookRoom(ref mut m) => m.token = None,
+        _ => {}
+    }
+
+    record(&TrafficRecord::Inbound {
+        timestamp: Utc::now(),
+        peer: peer.to_owned(),
+        hello,
+    });
+}
+
+/// Record a frame sent to the given peer.
+pub fn outbound(peer: &str, frame: &DisplayMessage) {
+    if !is_recording() {
+        return;
+    }
+
+    record(&TrafficRecord::Outbound {
+        timestamp: Utc::now(),
+        peer: peer.to_owned(),
+        frame: Box::new(frame.clone()),
+    });
+}
+
+/// Read a recording.
+pub fn load(path: &Path) -> Result<Vec<TrafficRecord>, Error> {
+    let mut records = Vec::new();
+
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str(&line) {
+            Ok(r) => records.push(r),
+            Err(e) => eprintln!("skipping unparseable traffic record: {}", e),
+        }
+    }
+
+    Ok(records)
+}
+
+fn describe(hello: &ClientHelloMessage) -> &'static str {
+    match hello {
+        ClientHelloMessage::Display(_) => "panel connection",
+        ClientHelloMessage::PersonIsUpdate(_) => "status update",
+        ClientHelloMessage::Doorbell(_) => "doorbell ring",
+        ClientHelloMessage::SensorReading(_) => "sensor reading",
+        ClientHelloMessage::SystemHealth(_) => "health report",
+        ClientHelloMessage::BookRoom(_) => "room booking",
+    }
+}
+
+/// Send the hellos in a recording to the hub listening on the given address,
+/// `speed` times faster than they originally arrived. Panel connections are
+/// kept open until the end. If a token is given, it's attached to the
+/// updates.
+pub async fn replay(
+    records: Vec<TrafficRecord>,
+    host: Ipv4Addr,
+    port: u16,
+    token: Option<String>,
+    speed: f64,
+) -> Result<(), GenericError> {
+    let n_frames = Arc::new(AtomicUsize::new(0));
+    let mut n_recorded_frames = 0;
+    let mut n_hellos = 0;
+    let mut previous = None;
+
+    for rec in records {
+        let (timestamp, peer, mut hello) = match rec {
+            TrafficRecord::Inbound {
+                timestamp,
+                peer,
+                hello,
+            } => (timestamp, peer, hello),
+
+            TrafficRecord::Outbound { .. } => {
+                n_recorded_frames += 1;
+                continue;
+            }
+        };
+
+        if let Some(p) = previous {
+            if let Ok(gap) = timestamp.signed_duration_since(p).to_std() {
+                time::delay_for(gap.div_f64(speed)).await;
+            }
+        }
+
+        previous = Some(timestamp);
+
+        match hello {
+            ClientHelloMessage::PersonIsUpdate(ref mut m) => m.token = token.clone(),
+            ClientHelloMessage::Doorbell(ref mut m) => m.token = token.clone(),
+            ClientHelloMessage::BookRoom(ref mut m) => m.token = token.clone(),
+            _ => {}
+        }
+
+        log!("replaying {} from {}", describe(&hello), peer);
+
+        let conn = AwaitingHello::new(TcpStream::connect((host, port)).await?);
+        let n_frames = n_frames.clone();
+
+        // The connection is handled in its own task, so that panels can keep
+        // receiving frames while we carry on.
+        tokio::spawn(async move {
+            let result = match hello {
+                ClientHelloMessage::Display(h) => match conn.start_display(h).await {
+                    Ok(mut session) => {
+                        while let Ok(Some(_)) = session.next_message().await {
+                            n_frames.fetch_add(1, Ordering::SeqCst);
+                        }
+
+                        Ok(())
+                    }
+
+                    Err(e) => Err(e),
+                },
+
+                ClientHelloMessage::PersonIsUpdate(m) => conn.send_update(m).await,
+                ClientHelloMessage::Doorbell(m) => conn.send_update(m).await,
+                ClientHelloMessage::SensorReading(m) => conn.send_update(m).await,
+                ClientHelloMessage::SystemHealth(m) => conn.send_update(m).await,
+                ClientHelloMessage::BookRoom(m) => conn.send_update(m).await,
+            };
+
+            if let Err(e) = result {
+                log!("error sending hello from {}: {}", peer, e);
+            }
+        });
+
+        n_hellos += 1;
+    }
+
+    // Give the last frames a moment to arrive.
+    time::delay_for(Duration::from_secs(1)).await;
+
+    log!(
+        "replayed {} hellos; the panels received {} frames, versus {} in the recording",
+        n_hellos,
+        n_frames.load(Ordering::SeqCst),
+        n_recorded_frames
+    );
+    Ok(())
+}
@@ -0,0 +1,165 @@
+//! Mirroring the status between two hubs.
+//!
+//! A hub with a `[relay]` section connects to another hub's stickyproto
+//! server, just like a display panel does, and also forwards its own status
+//! updates there. So both hubs, and all of their panels, show the same status
+//! even if only one of them can reach the other.
+//!
+//! To keep updates from bouncing back and forth forever, we only pass along
+//! updates that are newer than the latest status that we know about.
+
+use futures::{prelude::*, select};
+use rc_stickynote_protocol::{
+    session::client::AwaitingHello, DisplayHelloMessage, PersonIsUpdateHelloMessage,
+};
+use serde::Deserialize;
+use tokio::{
+    net::TcpStream,
+    sync::watch::Receiver,
+    time::{self, Duration},
+};
+
+use crate::{updates::UpdateHub, DisplayStateMutation, GenericError, HubDisplayState};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerRelayConfiguration {
+    /// The host running the other hub.
+    host: String,
+
+    /// The port of the other hub's stickyproto server.
+    port: u16,
+
+    /// An updater token for the other hub, if it requires one.
+    #[serde(default)]
+    token: Option<String>,
+
+    /// How long to wait before reconnecting if the connection is lost.
+    #[serde(default = "default_retry_seconds")]
+    retry_seconds: u64,
+}
+
+fn default_retry_seconds() -> u64 {
+    60
+}
+
+/// Whether an update is newer than the latest status we know about.
+fn is_newer(
+    latest: &Option<PersonIsUpdateHelloMessage>,
+    update: &PersonIsUpdateHelloMessage,
+) -> bool {
+    match latest {
+        Some(l) => update.timestamp > l.timestamp,
+        None => true,
+    }
+}
+
+/// Relay updates to and from the other hub forever.
+pub async fn run(config: ServerRelayConfiguration, send_updates: UpdateHub) {
+    let mut receive_updates = send_updates.subscribe();
+    let mut latest = None;
+
+    loop {
+        if let Err(e) = session(&config, &send_updates, &mut receive_updates, &mut latest).await {
+            log!(
+                "relay: lost connection to {}:{}: {}",
+                config.host,
+                config.port,
+                e
+            );
+        }
+
+        time::delay_for(Duration::from_secs(config.retry_seconds)).await;
+    }
+}
+
+/// Relay updates over one connection to the other hub, until it fails.
+async fn session(
+    config: &ServerRelayConfiguration,
+    send_updates: &UpdateHub,
+    receive_updates: &mut Receiver<HubDisplayState>,
+    latest: &mut Option<PersonIsUpdateHelloMessage>,
+) -> Result<(), GenericError> {
+    let socket = TcpStream::connect((config.host.as_str(), config.port)).await?;
+    let mut session = AwaitingHello::new(socket)
+        .start_display(DisplayHelloMessage {
+            display_id: None,
+            capabilities: None,
+            multiplex: false,
+            last_update: None,
+        })
+        .await?;
+
+    log!("relay: connected to {}:{}", config.host, config.port);
+
+    // The other hub sends its state as soon as we connect. If ours is newer,
+    // it must have changed while we were disconnected, so send it over.
+    let mut first = true;
+
+    loop {
+        select! {
+            maybe_remote = session.next_message().fuse() => {
+                let remote = match maybe_remote? {
+                    Some(m) => m,
+                    None => return Err("the other hub hung up".into()),
+                };
+
+                let update = PersonIsUpdateHelloMessage {
+                    person_is: remote.person_is,
+                    timestamp: remote.person_is_timestamp,
+                    source: Some(remote.person_is_source).filter(|s| !s.is_empty()),
+                    set_by: Some(remote.person_is_set_by).filter(|s| !s.is_empty()),
+                    token: None,
+                    signature: None,
+                };
+
+                if is_newer(latest, &update) {
+                    log!("relay: received update: {}", update.person_is);
+                    *latest = Some(update.clone());
+
+                    send_updates.send(DisplayStateMutation::SetPersonIs(update));
+                } else if first {
+                    if let Some(ref l) = latest {
+                        if l.timestamp > update.timestamp {
+                            forward(config, l.clone()).await?;
+                        }
+                    }
+                }
+
+                first = false;
+            },
+
+            maybe_update = receive_updates.next().fuse() => {
+                match maybe_update {
+                    Some(state) => {
+                        // Don't forward updates that we received from the
+                        // other hub in the first place. Ones that the hub
+                        // rejected never show up here at all.
+                        if let Some(update) = state.last_update {
+                            if is_newer(latest, &update) {
+                                *latest = Some(update.clone());
+                                forward(config, update).await?;
+                            }
+                        }
+                    },
+
+                    None => {
+                        log!("relay receive_updates ran out??");
+                    },
+                }
+            },
+        }
+    }
+}
+
+/// Send a status update to the other hub.
+async fn forward(
+    config: &ServerRelayConfiguration,
+    mut update: PersonIsUpdateHelloMessage,
+) -> Result<(), GenericError> {
+    log!("relay: forwarding update: {}", update.person_is);
+    update.token = config.token.clone();
+
+    let socket = TcpStream::connect((config.host.as_str(), config.port)).await?;
+    AwaitingHello::new(socket).send_update(update).await?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! Pictures of the sign.
+//!
+//! Old Kindles and Android home-screen widgets can't run a panel client, but
+//! they can show an image from a URL. So, if configured, the hub draws the
+//! current state with the panels' own layouts and serves it from
+//! `/render/current.png`, scaled to whatever size the device asks for.
+//!
+//! The drawing is done by the displayer library, which is a hefty thing for
+//! the hub to carry around, so it's only built in with the `render` feature.
+
+use serde::Deserialize;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, Deserialize)]
+#[cfg_attr(not(feature = "render"), allow(dead_code))]
+pub struct ServerRenderConfiguration {
+    /// The font used for most text, as on the panels.
+    pub sans_path: PathBuf,
+
+    /// The font used for the heading.
+    pub serif_path: PathBuf,
+}
+
+/// Whether this hub was built with the `render` feature.
+pub const AVAILABLE: bool = cfg!(feature = "render");
+
+/// The largest image that we'll draw, in either dimension.
+pub const MAX_SIZE: u32 = 2048;
+
+/// Work out the size of the image to draw, given the requested width and
+/// height. If only one is given, the other follows from the shape of the
+/// panel.
+pub fn image_size(width: Option<u32>, height: Option<u32>) -> Option<(u32, u32)> {
+    let (w, h) = (PANEL_WIDTH, PANEL_HEIGHT);
+
+    let (width, height) = match (width, height) {
+        (None, None) => (w, h),
+        (Some(width), None) => (width, (width * h + w / 2) / w),
+        (None, Some(height)) => ((height * w + h / 2) / h, height),
+        (Some(width), Some(height)) => (width, height),
+    };
+
+    if width == 0 || height == 0 || width > MAX_SIZE || height > MAX_SIZE {
+        None
+    } else {
+        Some((width, height))
+    }
+}
+
+/// The size of the panels' layouts.
+const PANEL_WIDTH: u32 = 384;
+const PANEL_HEIGHT: u32 = 640;
+
+/// Draw the message as a grayscale PNG of the given size. This is slow-ish,
+/// so it should be run off of the async threads.
+#[cfg(feature = "render")]
+pub fn render_png(
+    config: &ServerRenderConfiguration,
+    msg: rc_stickynote_protocol::DisplayMessage,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, std::io::Error> {
+    use rc_stickynote_displayer::{memory::MemoryBackend, DisplayBackend, FrameRenderer};
+    use std::io::{Error, ErrorKind};
+
+    let renderer = FrameRenderer::new(&config.sans_path, &config.serif_path)?;
+    let mut backend = MemoryBackend::open()?;
+    renderer.draw(msg, &mut backend)?;
+    let gray = backend.frame().to_gray(width, height);
+
+    let mut png_data = Vec::new();
+
+    {
+        let mut encoder = png::Encoder::new(&mut png_data, width, height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut w| w.write_image_data(&gray))
+            .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
+    }
+
+    Ok(png_data)
+}
+
+#[cfg(not(feature = "render"))]
+pub fn render_png(
+    _config: &ServerRenderConfiguration,
+    _msg: rc_stickynote_protocol::DisplayMessage,
+    _width: u32,
+    _height: u32,
+) -> Result<Vec<u8>, std::io::Error> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "this hub was built without the `render` feature",
+    ))
+}
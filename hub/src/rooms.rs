@@ -0,0 +1,267 @@
+//! Conference-room mode: showing a meeting room's bookings, and booking it
+//! from the door.
+//!
+//! We periodically fetch the room's iCalendar feed and send the panels the
+//! rest of today's bookings, so that a panel by the door can say whether the
+//! room is free, and until when. If a CalDAV calendar is configured, the
+//! room can also be booked from now until the next booking, up to a limit,
+//! with a button on the panel or over the HTTP API. New bookings are
+//! written straight into the calendar, so the feed picks them up as well.
+
+use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+use rc_stickynote_protocol::{Booking, RoomSchedule};
+use serde::Deserialize;
+use tokio::time::{self, Duration as TokioDuration};
+
+use crate::{calendar, http_client, updates::UpdateHub, DisplayStateMutation, GenericError};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerRoomConfiguration {
+    /// The room's name, to show on the panels.
+    name: String,
+
+    /// The URL of the room's iCalendar (.ics) feed.
+    ics_url: String,
+
+    /// How often to check the feed.
+    #[serde(default = "default_poll_minutes")]
+    poll_minutes: u64,
+
+    /// If set, allow the room to be booked from the door.
+    #[serde(default)]
+    booking: Option<RoomBookingConfiguration>,
+}
+
+fn default_poll_minutes() -> u64 {
+    5
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct RoomBookingConfiguration {
+    /// The URL of the room's CalDAV calendar collection. New bookings are
+    /// PUT into it.
+    caldav_url: String,
+
+    /// The username for the CalDAV server, if it needs one.
+    #[serde(default)]
+    username: Option<String>,
+
+    /// The password for the CalDAV server.
+    #[serde(default)]
+    password: Option<String>,
+
+    /// The summary of bookings made from the door.
+    #[serde(default = "default_booking_summary")]
+    summary: String,
+
+    /// The longest booking that can be made from the door.
+    #[serde(default = "default_max_minutes")]
+    max_minutes: u32,
+}
+
+fn default_booking_summary() -> String {
+    "Booked at the door".to_owned()
+}
+
+fn default_max_minutes() -> u32 {
+    120
+}
+
+/// Bookings shorter than this aren't worth making.
+const MIN_BOOKING_MINUTES: i64 = 5;
+
+impl ServerRoomConfiguration {
+    /// Pick out the bookings that matter to the panels: the ones that haven't
+    /// ended yet and start before the end of today.
+    fn schedule(&self, events: Vec<calendar::TimedEvent>, now: DateTime<Utc>) -> RoomSchedule {
+        let tomorrow = (now.with_timezone(&Local).naive_local().date() + Duration::days(1))
+            .and_hms_opt(0, 0, 0)
+            .expect("midnight is a valid time");
+        let end_of_day = match Local.from_local_datetime(&tomorrow).earliest() {
+            Some(t) => t.with_timezone(&Utc),
+            None => Utc.from_utc_datetime(&tomorrow),
+        };
+
+        let mut bookings: Vec<Booking> = events
+            .into_iter()
+            .filter(|e| e.end > now && e.start < end_of_day)
+            .map(|e| Booking {
+                summary: e.summary,
+                start: e.start,
+                end: e.end,
+            })
+            .collect();
+
+        bookings.sort_by_key(|b| b.start);
+
+        RoomSchedule {
+            name: self.name.clone(),
+            bookings,
+            can_book: self.booking.is_some(),
+        }
+    }
+}
+
+/// Work out the booking to make for someone who wants the room for the next
+/// `minutes`, given its current bookings. The booking is cut short if the
+/// room is booked before then. If the room can't be booked, the error says
+/// why.
+fn plan_booking(
+    config: &ServerRoomConfiguration,
+    schedule: Option<&RoomSchedule>,
+    minutes: u32,
+    now: DateTime<Utc>,
+) -> Result<Booking, String> {
+    let booking_config = match config.booking {
+        Some(ref b) => b,
+        None => return Err("this room can't be booked from the door".to_owned()),
+    };
+
+    let bookings = match schedule {
+        Some(s) => &s.bookings,
+        None => return Err("the room's calendar hasn't been read yet".to_owned()),
+    };
+
+    if let Some(current) = bookings.iter().find(|b| b.start <= now && now < b.end) {
+        return Err(format!(
+            "the room is booked until {}",
+            current.end.with_timezone(&Local).format("%H:%M")
+        ));
+    }
+
+    let minutes = minutes.min(booking_config.max_minutes);
+    let mut end = now + Duration::minutes(minutes as i64);
+
+    if let Some(next) = bookings.iter().find(|b| b.start > now && b.start < end) {
+        end = next.start;
+    }
+
+    if end - now < Duration::minutes(MIN_BOOKING_MINUTES) {
+        return Err(format!(
+            "the room is booked at {}",
+            end.with_timezone(&Local).format("%H:%M")
+        ));
+    }
+
+    Ok(Booking {
+        summary: booking_config.summary.clone(),
+        start: now,
+        end,
+    })
+}
+
+/// Write a booking into the room's CalDAV calendar.
+async fn create_booking(
+    config: &RoomBookingConfiguration,
+    booking: &Booking,
+) -> Result<(), GenericError> {
+    let fmt = "%Y%m%dT%H%M%SZ";
+    let uid = format!(
+        "{}-{}@rc-stickynote",
+        booking.start.timestamp_millis(),
+        std::process::id()
+    );
+
+    let ics = format!(
+        "BEGIN:VCALENDAR\r\n\
+         VERSION:2.0\r\n\
+         PRODID:-//rc-stickynote//hub//EN\r\n\
+         BEGIN:VEVENT\r\n\
+         UID:{}\r\n\
+         DTSTAMP:{}\r\n\
+         DTSTART:{}\r\n\
+         DTEND:{}\r\n\
+         SUMMARY:{}\r\n\
+         END:VEVENT\r\n\
+         END:VCALENDAR\r\n",
+        uid,
+        Utc::now().format(fmt),
+        booking.start.format(fmt),
+        booking.end.format(fmt),
+        booking
+            .summary
+            .replace('\\', "\\\\")
+            .replace(',', "\\,")
+            .replace(';', "\\;"),
+    );
+
+    let url = format!("{}/{}.ics", config.caldav_url.trim_end_matches('/'), uid);
+    let auth = config.username.as_ref().map(|user| {
+        let pair = format!("{}:{}", user, config.password.as_deref().unwrap_or(""));
+        format!("Basic {}", base64::encode(&pair))
+    });
+
+    let mut headers = vec![
+        ("Content-Type", "text/calendar; charset=utf-8"),
+        ("If-None-Match", "*"),
+    ];
+
+    if let Some(ref auth) = auth {
+        headers.push(("Authorization", auth));
+    }
+
+    http_client::put_text_with_headers(&url, &headers, ics).await
+}
+
+/// Book the room for the next `minutes`, if it's free. The booking shows up
+/// on the panels right away, so that nobody else can take the room, and is
+/// written to the calendar in the background. If that fails, it's taken
+/// down again. If the room can't be booked, the error says why.
+pub fn book(
+    config: &ServerRoomConfiguration,
+    minutes: u32,
+    display_id: Option<&str>,
+    send_updates: &UpdateHub,
+) -> Result<(), String> {
+    let state = send_updates.current();
+    let booking = plan_booking(config, state.display.room.as_ref(), minutes, Utc::now())?;
+    let booking_config = config.booking.clone().expect("booking was planned");
+    let send_updates = send_updates.clone();
+
+    log!(
+        "booking {} until {}{}",
+        config.name,
+        booking.end.with_timezone(&Local).format("%H:%M"),
+        display_id
+            .map(|id| format!(" from panel {}", id))
+            .unwrap_or_default()
+    );
+
+    send_updates.send(DisplayStateMutation::AddRoomBooking(booking.clone()));
+
+    tokio::spawn(async move {
+        if let Err(e) = create_booking(&booking_config, &booking).await {
+            log!("error booking room: {}", e);
+            send_updates.send(DisplayStateMutation::RemoveRoomBooking(booking));
+        }
+    });
+
+    Ok(())
+}
+
+/// Poll the room's calendar forever, sending the hub the room's bookings.
+/// If the feed can't be fetched, what we last knew stays up.
+pub async fn run(config: ServerRoomConfiguration, send_updates: UpdateHub) {
+    let mut interval = time::interval(TokioDuration::from_secs(config.poll_minutes.max(1) * 60));
+    let mut last_schedule = None;
+
+    loop {
+        interval.tick().await;
+
+        let events = match http_client::fetch_text(&config.ics_url).await {
+            Ok(text) => calendar::parse_timed_events(&text),
+            Err(e) => {
+                log!("error fetching room calendar: {}", e);
+                continue;
+            }
+        };
+
+        let schedule = config.schedule(events, Utc::now());
+
+        if last_schedule.as_ref() != Some(&schedule) {
+            last_schedule = Some(schedule.clone());
+
+            send_updates.send(DisplayStateMutation::SetRoom(schedule));
+        }
+    }
+}
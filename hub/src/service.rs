@@ -0,0 +1,139 @@
+//! Running the hub as a systemd service.
+//!
+//! Rather than detaching itself from the terminal, the hub leaves that to
+//! systemd. It tells systemd when it's ready to take connections, and, if the
+//! service has `WatchdogSec=` set, keeps telling it that the main loop is
+//! still turning over, so that a hung hub gets restarted. The
+//! `install-service` command writes out the unit files for all of this, with
+//! the sockets as separate units if socket activation is wanted.
+
+use std::{env, net::Ipv4Addr, os::unix::net::UnixDatagram, path::PathBuf};
+
+/// The name of the service unit, and the prefix of the socket units.
+pub const UNIT_NAME: &str = "rc-stickynote-hub";
+
+/// The first line of each unit file.
+const HEADER: &str = "# Generated by `rc_stickynote_hub install-service`.";
+
+/// Tell systemd something about how the service is doing, like "READY=1".
+/// This does nothing if we weren't started by systemd.
+pub fn notify(state: &str) {
+    let path = match env::var_os("NOTIFY_SOCKET") {
+        Some(p) => PathBuf::from(p),
+        None => return,
+    };
+
+    let result = UnixDatagram::unbound().and_then(|s| s.send_to(state.as_bytes(), &path));
+
+    if let Err(e) = result {
+        log!("cannot notify systemd ({}): {}", state, e);
+    }
+}
+
+/// Whether systemd wants to hear that we're still alive.
+pub fn watchdog_enabled() -> bool {
+    env::var_os("WATCHDOG_USEC").is_some()
+}
+
+/// What goes into the unit files.
+#[derive(Clone, Debug)]
+pub struct UnitSettings {
+    /// The hub program.
+    pub exe: PathBuf,
+
+    /// The hub's configuration file.
+    pub config_path: PathBuf,
+
+    /// The directory to run in, which relative paths in the configuration
+    /// are taken from.
+    pub working_directory: PathBuf,
+
+    /// If set, the user to run as.
+    pub user: Option<String>,
+
+    /// If set, have systemd listen on the hub's ports.
+    pub sockets: Option<(Ipv4Addr, u16, u16)>,
+}
+
+impl UnitSettings {
+    /// The unit files to install, as pairs of names and contents.
+    pub fn unit_files(&self) -> Vec<(String, String)> {
+        let mut files = Vec::new();
+        let mut socket_units = Vec::new();
+
+        if let Some((host, stickyproto_port, http_port)) = self.sockets {
+            for (name, port) in &[("stickyproto", stickyproto_port), ("http", http_port)] {
+                let unit = format!("{}-{}.socket", UNIT_NAME, name);
+
+                files.push((
+                    unit.clone(),
+                    format!(
+                        "{header}\n\n\
+                         [Unit]\n\
+                         Description=RC-stickynote hub {name} socket\n\
+                         \n\
+                         [Socket]\n\
+                         ListenStream={host}:{port}\n\
+                         FileDescriptorName={name}\n\
+                         Service={unit_name}.service\n\
+                         \n\
+                         [Install]\n\
+                         WantedBy=sockets.target\n",
+                        header = HEADER,
+                        name = name,
+                        host = host,
+                        port = port,
+                        unit_name = UNIT_NAME,
+                    ),
+                ));
+
+                socket_units.push(unit);
+            }
+        }
+
+        let mut extra = String::new();
+
+        if let Some(ref user) = self.user {
+            extra.push_str(&format!("User={}\n", user));
+        }
+
+        if !socket_units.is_empty() {
+            extra.push_str(&format!("Sockets={}\n", socket_units.join(" ")));
+        }
+
+        // The watchdog is fed from the main loop, which comes around at least
+        // once a minute.
+        files.insert(
+            0,
+            (
+                format!("{}.service", UNIT_NAME),
+                format!(
+                    "{header}\n\n\
+                     [Unit]\n\
+                     Description=RC-stickynote hub\n\
+                     Wants=network-online.target\n\
+                     After=network-online.target\n\
+                     \n\
+                     [Service]\n\
+                     Type=notify\n\
+                     {extra}\
+                     WorkingDirectory={dir}\n\
+                     ExecStart={exe} serve {config}\n\
+                     Restart=on-failure\n\
+                     RestartSec=10\n\
+                     WatchdogSec=180\n\
+                     \n\
+                     [Install]\n\
+                     WantedBy=multi-user.target\n",
+                    header = HEADER,
+                    extra = extra,
+                    dir = self.working_directory.display(),
+                    exe = self.exe.display(),
+                    config = self.config_path.display(),
+                ),
+            ),
+        );
+
+        files
+    }
+}
@@ -0,0 +1,145 @@
+//! Shortening statuses that are a bit too long.
+//!
+//! A status that's only a little too wide for the panel is usually still
+//! worth showing, and it's annoying to have it turned away over a few
+//! pixels. If shortening is configured, such statuses are squeezed down
+//! instead: first by swapping words for their abbreviations, like "mtg" for
+//! "meeting", and then, if that isn't enough, by hyphenating the longest
+//! words and keeping only their first parts, as in "confer." for
+//! "conference". Statuses that are much too long are still rejected, since
+//! there'd be little left of them.
+
+use rc_stickynote_protocol::{is_person_is_valid, width};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerShorteningConfiguration {
+    /// Words and their abbreviations, matched case-insensitively. If given,
+    /// these replace the built-in list.
+    #[serde(default = "default_abbreviations")]
+    abbreviations: BTreeMap<String, String>,
+
+    /// How many pixels too wide a status can be and still be shortened.
+    #[serde(default = "default_max_overflow_pixels")]
+    max_overflow_pixels: u32,
+
+    /// Whether to cut long words short if the abbreviations aren't enough.
+    #[serde(default = "default_hyphenate")]
+    hyphenate: bool,
+}
+
+fn default_abbreviations() -> BTreeMap<String, String> {
+    [
+        ("about", "abt"),
+        ("appointment", "appt"),
+        ("approximately", "approx"),
+        ("building", "bldg"),
+        ("conference", "conf"),
+        ("meeting", "mtg"),
+        ("minutes", "min"),
+        ("tomorrow", "tmrw"),
+        ("until", "til"),
+        ("working", "wkg"),
+    ]
+    .iter()
+    .map(|(w, a)| (w.to_string(), a.to_string()))
+    .collect()
+}
+
+fn default_max_overflow_pixels() -> u32 {
+    96
+}
+
+fn default_hyphenate() -> bool {
+    true
+}
+
+impl ServerShorteningConfiguration {
+    /// Shorten a status that's too long to be valid, returning the shortened
+    /// version, or None if it can't be done.
+    pub fn shorten(&self, person_is: &str) -> Option<String> {
+        if width::status_width(person_is) > width::STATUS_BOX_WIDTH + self.max_overflow_pixels {
+            return None;
+        }
+
+        let mut words: Vec<String> = person_is.split_whitespace().map(str::to_owned).collect();
+        let fits = |words: &[String]| is_person_is_valid(&words.join(" "));
+
+        for i in 0..words.len() {
+            if let Some(abbrev) = self.abbreviate(&words[i]) {
+                words[i] = abbrev;
+
+                if fits(&words) {
+                    return Some(words.join(" "));
+                }
+            }
+        }
+
+        if !self.hyphenate {
+            return None;
+        }
+
+        // Cut down the longest words first, as little as will do.
+
+        let mut order: Vec<usize> = (0..words.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(words[i].chars().count()));
+
+        for i in order {
+            let original = words[i].clone();
+            let cuts = hyphenation_points(&original);
+
+            for &n in cuts.iter().rev() {
+                words[i] = format!("{}.", original.chars().take(n).collect::<String>());
+
+                if fits(&words) {
+                    return Some(words.join(" "));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// The abbreviation of a word, keeping any punctuation around it and
+    /// following its capitalization.
+    fn abbreviate(&self, word: &str) -> Option<String> {
+        let core = word.trim_matches(|c: char| !c.is_alphanumeric());
+        let abbrev = self.abbreviations.get(&core.to_lowercase())?;
+
+        let abbrev = if core.len() > 1 && core.chars().all(|c| !c.is_lowercase()) {
+            abbrev.to_uppercase()
+        } else if matches!(core.chars().next(), Some(c) if c.is_uppercase()) {
+            let mut chars = abbrev.chars();
+            chars
+                .next()
+                .map(|c| c.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        } else {
+            abbrev.clone()
+        };
+
+        Some(word.replacen(core, &abbrev, 1))
+    }
+}
+
+fn is_vowel(c: char) -> bool {
+    "aeiouyAEIOUY".contains(c)
+}
+
+/// Where a word could be hyphenated, as numbers of characters to keep,
+/// shortest first. This goes by a rough rule that suits abbreviations: break
+/// after a consonant that follows a vowel, as in "con-fer-en-ce", keeping at
+/// least three letters and cutting off at least two. Words with anything but
+/// letters in them are left alone.
+fn hyphenation_points(word: &str) -> Vec<usize> {
+    let chars: Vec<char> = word.chars().collect();
+
+    if !chars.iter().all(|c| c.is_alphabetic()) {
+        return Vec::new();
+    }
+
+    (3..chars.len().saturating_sub(1))
+        .filter(|&n| !is_vowel(chars[n - 1]) && is_vowel(chars[n - 2]))
+        .collect()
+}
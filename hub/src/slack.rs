@@ -0,0 +1,82 @@
+//! Setting the status from Slack.
+//!
+//! A Slack app with a slash command, like `/sticky`, can be pointed at
+//! `/webhooks/slack`. The text after the command is one of the commands
+//! that all of the chat integrations understand, like `/sticky lunch, 45 min`
+//! or `/sticky undo`, and `/sticky help` lists them. Replies go only to
+//! whoever typed the command.
+//!
+//! Slack signs its requests with the app's signing secret, in an
+//! `X-Slack-Signature` header of the form `v0=<hex>`, computed over the
+//! request's timestamp and body. Requests more than five minutes old are
+//! turned away, so that a captured one can't be replayed later.
+
+use hmac::{Hmac, Mac};
+use hyper::HeaderMap;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+
+use crate::ci::decode_hex;
+
+/// The source name attached to statuses set from Slack.
+pub const SOURCE: &str = "Slack";
+
+/// How old a request can be, in seconds, before we refuse it.
+const MAX_REQUEST_AGE_SECONDS: i64 = 300;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerSlackConfiguration {
+    /// The Slack app's signing secret.
+    signing_secret: String,
+
+    /// The Slack user IDs, like "U012AB3CD", that may use the command. If
+    /// empty, anyone in the workspace may.
+    #[serde(default)]
+    allowed_users: Vec<String>,
+}
+
+impl ServerSlackConfiguration {
+    /// Check that a request came from Slack, and from somebody who's allowed
+    /// to use the command, whose ID is given.
+    pub fn verify(&self, headers: &HeaderMap, body: &[u8], user_id: &str) -> Result<(), String> {
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+
+        let timestamp = header("x-slack-request-timestamp").ok_or("no timestamp header")?;
+        let signature = header("x-slack-signature").ok_or("no signature header")?;
+
+        let sent: i64 = timestamp.parse().map_err(|_| "malformed timestamp")?;
+
+        if (chrono::Utc::now().timestamp() - sent).abs() > MAX_REQUEST_AGE_SECONDS {
+            return Err("request is too old".to_owned());
+        }
+
+        let code = signature
+            .strip_prefix("v0=")
+            .and_then(decode_hex)
+            .ok_or("malformed signature")?;
+
+        let mut mac = Hmac::<Sha256>::new_varkey(self.signing_secret.as_bytes()).expect("uhoh");
+        mac.input(b"v0:");
+        mac.input(timestamp.as_bytes());
+        mac.input(b":");
+        mac.input(body);
+        mac.verify(&code)
+            .map_err(|_| "signature mismatch".to_owned())?;
+
+        if !self.allowed_users.is_empty() && !self.allowed_users.iter().any(|u| u == user_id) {
+            return Err(format!("user {} isn't allowed", user_id));
+        }
+
+        Ok(())
+    }
+}
+
+/// The body of a reply that only the person who typed the command sees.
+pub fn reply(text: &str) -> String {
+    json!({
+        "response_type": "ephemeral",
+        "text": text,
+    })
+    .to_string()
+}
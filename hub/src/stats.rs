@@ -0,0 +1,374 @@
+//! The hub's statistics page, summarizing the history log.
+
+use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
+use rc_stickynote_protocol::sealed;
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{
+    history::HistoryEvent,
+    html::{escape_html, page},
+};
+
+/// How many days of update counts to chart.
+const N_DAYS: i64 = 30;
+
+/// How many of the most common statuses to list.
+const N_TOP_STATUSES: usize = 10;
+
+/// Numbers derived from the history log.
+#[derive(Debug)]
+struct Stats<'a> {
+    n_updates: usize,
+    updates_per_day: Vec<(NaiveDate, usize)>,
+    top_statuses: Vec<(String, usize)>,
+    sources: Vec<(String, usize)>,
+    n_sessions: usize,
+    sessions_per_display: Vec<(String, usize)>,
+    mean_uptime: Option<Duration>,
+    latest_reading: Option<RoomReading>,
+    room_per_day: Vec<(NaiveDate, RoomReading)>,
+    latest_health: Option<HealthReport<'a>>,
+    n_health_warnings: usize,
+}
+
+/// A panel's report on its own health.
+#[derive(Clone, Copy, Debug)]
+struct HealthReport<'a> {
+    timestamp: DateTime<Utc>,
+    display_id: Option<&'a str>,
+    cpu_temperature_c: Option<f32>,
+    throttled_flags: Option<u32>,
+}
+
+impl<'a> HealthReport<'a> {
+    /// Whether the panel was under-voltage or throttled when it reported.
+    fn is_warning(&self) -> bool {
+        self.throttled_flags.map(|f| f & 0xF != 0) == Some(true)
+    }
+}
+
+/// Room conditions, either a single reading or an average.
+#[derive(Clone, Copy, Debug, Default)]
+struct RoomReading {
+    timestamp: Option<DateTime<Utc>>,
+    co2_ppm: f32,
+    temperature_c: f32,
+    humidity_percent: f32,
+}
+
+impl<'a> Stats<'a> {
+    fn compute(events: &'a [HistoryEvent]) -> Self {
+        let today = Local::now().naive_local().date();
+        let first_day = today - Duration::days(N_DAYS - 1);
+
+        let mut per_day = HashMap::new();
+        let mut statuses = HashMap::new();
+        let mut sources = HashMap::new();
+        let mut n_updates = 0;
+        let mut n_sessions = 0;
+        let mut display_sessions = HashMap::new();
+        let mut total_uptime_seconds = 0;
+        let mut latest_reading = None;
+        let mut room_sums: HashMap<NaiveDate, (RoomReading, usize)> = HashMap::new();
+        let mut latest_health = None;
+        let mut n_health_warnings = 0;
+
+        for event in events {
+            match event {
+                HistoryEvent::StatusUpdate {
+                    timestamp,
+                    person_is,
+                    source,
+                    ..
+                } => {
+                    n_updates += 1;
+
+                    let day = timestamp.with_timezone(&Local).naive_local().date();
+                    *per_day.entry(day).or_insert(0) += 1;
+
+                    // Every sealed status looks different, so lump them together.
+                    let status = if sealed::is_sealed(person_is) {
+                        "(sealed)".to_owned()
+                    } else {
+                        person_is.clone()
+                    };
+                    *statuses.entry(status).or_insert(0) += 1;
+
+                    let source = source.clone().unwrap_or_else(|| "unknown".to_owned());
+                    *sources.entry(source).or_insert(0) += 1;
+                }
+
+                HistoryEvent::DisplayConnected { .. } => {}
+                HistoryEvent::CounterChange { .. } => {}
+                HistoryEvent::AutoShortened { .. } => {}
+                HistoryEvent::Undo { .. } => {}
+
+                HistoryEvent::DisplayDisconnected {
+                    timestamp,
+                    connected_at,
+                    display_id,
+                    ..
+                } => {
+                    n_sessions += 1;
+
+                    let id = display_id
+                        .clone()
+                        .unwrap_or_else(|| "unidentified".to_owned());
+                    *display_sessions.entry(id).or_insert(0) += 1;
+                    total_uptime_seconds +=
+                        timestamp.signed_duration_since(*connected_at).num_seconds();
+                }
+
+                HistoryEvent::SensorReading {
+                    timestamp,
+                    co2_ppm,
+                    temperature_c,
+                    humidity_percent,
+                    ..
+                } => {
+                    latest_reading = Some(RoomReading {
+                        timestamp: Some(*timestamp),
+                        co2_ppm: *co2_ppm,
+                        temperature_c: *temperature_c,
+                        humidity_percent: *humidity_percent,
+                    });
+
+                    let day = timestamp.with_timezone(&Local).naive_local().date();
+                    let entry = room_sums.entry(day).or_default();
+                    entry.0.co2_ppm += co2_ppm;
+                    entry.0.temperature_c += temperature_c;
+                    entry.0.humidity_percent += humidity_percent;
+                    entry.1 += 1;
+                }
+
+                HistoryEvent::SystemHealth {
+                    timestamp,
+                    cpu_temperature_c,
+                    throttled_flags,
+                    display_id,
+                } => {
+                    let report = HealthReport {
+                        timestamp: *timestamp,
+                        display_id: display_id.as_deref(),
+                        cpu_temperature_c: *cpu_temperature_c,
+                        throttled_flags: *throttled_flags,
+                    };
+
+                    if report.is_warning() {
+                        n_health_warnings += 1;
+                    }
+
+                    latest_health = Some(report);
+                }
+            }
+        }
+
+        let updates_per_day = (0..N_DAYS)
+            .map(|i| {
+                let day = first_day + Duration::days(i);
+                (day, per_day.get(&day).cloned().unwrap_or(0))
+            })
+            .collect();
+
+        let room_per_day = (0..N_DAYS)
+            .filter_map(|i| {
+                let day = first_day + Duration::days(i);
+                let (sums, n) = room_sums.get(&day)?;
+                let n = *n as f32;
+
+                Some((
+                    day,
+                    RoomReading {
+                        timestamp: None,
+                        co2_ppm: sums.co2_ppm / n,
+                        temperature_c: sums.temperature_c / n,
+                        humidity_percent: sums.humidity_percent / n,
+                    },
+                ))
+            })
+            .collect();
+
+        let mean_uptime = if n_sessions > 0 {
+            Some(Duration::seconds(total_uptime_seconds / n_sessions as i64))
+        } else {
+            None
+        };
+
+        Stats {
+            n_updates,
+            updates_per_day,
+            top_statuses: sorted_counts(statuses, N_TOP_STATUSES),
+            sources: sorted_counts(sources, usize::MAX),
+            n_sessions,
+            sessions_per_display: sorted_counts(display_sessions, usize::MAX),
+            mean_uptime,
+            latest_reading,
+            room_per_day,
+            latest_health,
+            n_health_warnings,
+        }
+    }
+}
+
+/// Sort tallies from most to least common, breaking ties alphabetically, and
+/// keep at most `limit` of them.
+fn sorted_counts(counts: HashMap<String, usize>, limit: usize) -> Vec<(String, usize)> {
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(limit);
+    counts
+}
+
+fn format_duration(d: Duration) -> String {
+    let minutes = d.num_minutes();
+
+    if minutes < 60 {
+        format!("{} min", minutes)
+    } else if minutes < 48 * 60 {
+        format!("{:.1} hours", minutes as f64 / 60.)
+    } else {
+        format!("{:.1} days", minutes as f64 / 1440.)
+    }
+}
+
+/// Write a table of labeled counts with a horizontal bar for each one.
+fn write_bar_table<I>(html: &mut String, rows: I)
+where
+    I: IntoIterator<Item = (String, usize)>,
+{
+    let rows: Vec<_> = rows.into_iter().collect();
+    let max = rows.iter().map(|r| r.1).max().unwrap_or(0).max(1);
+
+    html.push_str("<table>\n");
+
+    for (label, count) in &rows {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td class=\"n\">{}</td>\
+             <td><div class=\"bar\" style=\"width: {}px\"></div></td></tr>",
+            escape_html(label),
+            count,
+            count * 300 / max,
+        );
+    }
+
+    html.push_str("</table>\n");
+}
+
+/// Render the statistics page for the given history log events.
+pub fn render_stats_page(events: &[HistoryEvent]) -> String {
+    let stats = Stats::compute(events);
+    let mut html = String::new();
+
+    let _ = writeln!(
+        html,
+        "<p>{} status updates recorded in total. Generated {}.</p>",
+        stats.n_updates,
+        Utc::now().with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+    );
+
+    let _ = writeln!(html, "<h2>Updates per day (last {} days)</h2>", N_DAYS);
+    write_bar_table(
+        &mut html,
+        stats
+            .updates_per_day
+            .iter()
+            .map(|(day, n)| (day.format("%a %Y-%m-%d").to_string(), *n)),
+    );
+
+    html.push_str("<h2>Most common statuses</h2>\n");
+    write_bar_table(&mut html, stats.top_statuses);
+
+    html.push_str("<h2>Update sources</h2>\n");
+    write_bar_table(&mut html, stats.sources);
+
+    html.push_str("<h2>Panel connections</h2>\n");
+
+    match stats.mean_uptime {
+        Some(uptime) => {
+            let _ = writeln!(
+                html,
+                "<p>{} completed connections, lasting {} on average.</p>",
+                stats.n_sessions,
+                format_duration(uptime),
+            );
+        }
+
+        None => html.push_str("<p>No completed connections recorded.</p>\n"),
+    }
+
+    if !stats.sessions_per_display.is_empty() {
+        html.push_str("<p>Completed connections by panel:</p>\n");
+        write_bar_table(&mut html, stats.sessions_per_display);
+    }
+
+    html.push_str("<h2>Room conditions</h2>\n");
+
+    match stats.latest_reading {
+        Some(r) => {
+            let _ = writeln!(
+                html,
+                "<p>Latest reading: {:.0} ppm CO<sub>2</sub>, {:.1}&nbsp;&deg;C, \
+                 {:.0}% humidity ({}).</p>",
+                r.co2_ppm,
+                r.temperature_c,
+                r.humidity_percent,
+                r.timestamp
+                    .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default(),
+            );
+
+            html.push_str("<p>Daily averages; bars show CO<sub>2</sub> in ppm.</p>\n");
+            write_bar_table(
+                &mut html,
+                stats.room_per_day.iter().map(|(day, r)| {
+                    (
+                        format!(
+                            "{} ({:.1} \u{b0}C, {:.0}%)",
+                            day.format("%a %Y-%m-%d"),
+                            r.temperature_c,
+                            r.humidity_percent
+                        ),
+                        r.co2_ppm.round().max(0.) as usize,
+                    )
+                }),
+            );
+        }
+
+        None => html.push_str("<p>No sensor readings recorded.</p>\n"),
+    }
+
+    html.push_str("<h2>Panel health</h2>\n");
+
+    match stats.latest_health {
+        Some(h) => {
+            let _ = writeln!(
+                html,
+                "<p>Latest report{}: CPU at {}, {} ({}).</p>",
+                h.display_id
+                    .map(|id| format!(" from <code>{}</code>", escape_html(id)))
+                    .unwrap_or_default(),
+                h.cpu_temperature_c
+                    .map(|t| format!("{:.0}&nbsp;&deg;C", t))
+                    .unwrap_or_else(|| "unknown temperature".to_owned()),
+                match h.throttled_flags {
+                    Some(f) if h.is_warning() =>
+                        format!("under-voltage or throttled (flags {:#x})", f),
+                    Some(_) => "not throttled".to_owned(),
+                    None => "throttling unknown".to_owned(),
+                },
+                h.timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+            );
+
+            let _ = writeln!(
+                html,
+                "<p>{} reports of under-voltage or throttling.</p>",
+                stats.n_health_warnings,
+            );
+        }
+
+        None => html.push_str("<p>No health reports recorded.</p>\n"),
+    }
+
+    page("Sticky Note Statistics", &html)
+}
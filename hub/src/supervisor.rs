@@ -0,0 +1,125 @@
+//! Keeping an eye on the hub's background tasks.
+//!
+//! A panic inside a task started with a plain `tokio::spawn` takes down just
+//! that task, and nobody hears about it: a panel quietly stops getting
+//! updates, or the HTTP server goes away while the rest of the hub carries on.
+//! Tasks started through this module are watched instead. If one panics or
+//! fails, that's logged along with a description of the task and counted in
+//! the hub's metrics. The core tasks, which are supposed to run forever, are
+//! started again after a short pause.
+
+use futures::prelude::*;
+use std::{
+    any::Any,
+    fmt::Display,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tokio::time::{self, Duration};
+
+/// How long to wait before restarting a core task, so that one that dies
+/// right away doesn't spin.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+static N_PANICS: AtomicUsize = AtomicUsize::new(0);
+static N_RESTARTS: AtomicUsize = AtomicUsize::new(0);
+
+/// How many supervised tasks have panicked since the hub started.
+pub fn n_panics() -> usize {
+    N_PANICS.load(Ordering::SeqCst)
+}
+
+/// How many times core tasks have been restarted since the hub started.
+pub fn n_restarts() -> usize {
+    N_RESTARTS.load(Ordering::SeqCst)
+}
+
+/// Something that a supervised task can finish with.
+pub trait TaskOutcome {
+    /// A description of the failure, if the task failed.
+    fn failure(self) -> Option<String>;
+}
+
+impl TaskOutcome for () {
+    fn failure(self) -> Option<String> {
+        None
+    }
+}
+
+impl<E: Display> TaskOutcome for Result<(), E> {
+    fn failure(self) -> Option<String> {
+        self.err().map(|e| e.to_string())
+    }
+}
+
+fn describe_panic(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "(no message)"
+    }
+}
+
+/// Run a task to completion, logging it if it panics or fails. Returns
+/// whether the task got to the end, rather than panicking.
+async fn watch<F>(what: &str, task: F) -> bool
+where
+    F: Future + Send + 'static,
+    F::Output: TaskOutcome + Send + 'static,
+{
+    match tokio::spawn(task).await {
+        Ok(outcome) => {
+            if let Some(e) = outcome.failure() {
+                log!("{} failed: {}", what, e);
+            }
+
+            true
+        }
+
+        Err(e) if e.is_panic() => {
+            N_PANICS.fetch_add(1, Ordering::SeqCst);
+            log!("{} panicked: {}", what, describe_panic(&*e.into_panic()));
+            false
+        }
+
+        Err(e) => {
+            log!("{} was cancelled: {}", what, e);
+            false
+        }
+    }
+}
+
+/// Spawn a task that runs once, such as the handling of a connection.
+pub fn spawn<F>(what: String, task: F)
+where
+    F: Future + Send + 'static,
+    F::Output: TaskOutcome + Send + 'static,
+{
+    tokio::spawn(async move {
+        watch(&what, task).await;
+    });
+}
+
+/// Spawn a core task that's supposed to run for as long as the hub does. If
+/// it ever stops, for whatever reason, `start` is called to start it again.
+pub fn spawn_restarting<S, F>(what: &str, mut start: S)
+where
+    S: FnMut() -> F + Send + 'static,
+    F: Future + Send + 'static,
+    F::Output: TaskOutcome + Send + 'static,
+{
+    let what = what.to_owned();
+
+    tokio::spawn(async move {
+        loop {
+            if watch(&what, start()).await {
+                log!("{} stopped unexpectedly", what);
+            }
+
+            time::delay_for(RESTART_DELAY).await;
+            N_RESTARTS.fetch_add(1, Ordering::SeqCst);
+            log!("restarting {}", what);
+        }
+    });
+}
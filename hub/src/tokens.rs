@@ -0,0 +1,136 @@
+//! Access tokens issued from the command line.
+//!
+//! Tokens listed in the configuration file are fine for the hub's owner, but
+//! handing one to a colleague means editing the file and restarting the hub,
+//! and taking it back means the same again. Tokens issued with the `token`
+//! commands live in their own file instead, which the hub rereads whenever it
+//! checks a token, so revoking one takes effect right away. Only a hash of
+//! each token is stored, so the file doesn't need to be kept secret.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Error, ErrorKind, Read},
+    path::PathBuf,
+};
+
+use crate::auth::Role;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct IssuedToken {
+    /// A name for the token holder, for logging.
+    pub name: String,
+
+    pub role: Role,
+
+    /// The sources that the holder may claim for their updates; see
+    /// `ServerTokenConfiguration`.
+    #[serde(default)]
+    pub sources: Vec<String>,
+
+    /// When the token was issued.
+    pub created: DateTime<Utc>,
+
+    /// The SHA-256 hash of the token, in hex.
+    pub hash: String,
+}
+
+/// Hash a token for storage or comparison.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// A handle to the file of issued tokens.
+#[derive(Clone, Debug)]
+pub struct TokenStore {
+    path: PathBuf,
+}
+
+impl TokenStore {
+    pub fn new(path: PathBuf) -> Self {
+        TokenStore { path }
+    }
+
+    /// Read all of the issued tokens.
+    pub fn load(&self) -> Result<Vec<IssuedToken>, Error> {
+        let f = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut tokens = Vec::new();
+
+        for line in BufReader::new(f).lines() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str(&line) {
+                Ok(t) => tokens.push(t),
+                Err(e) => eprintln!("skipping unparseable issued token: {}", e),
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    fn save(&self, tokens: &[IssuedToken]) -> Result<(), Error> {
+        let mut text = String::new();
+
+        for t in tokens {
+            text.push_str(&serde_json::to_string(t)?);
+            text.push('\n');
+        }
+
+        std::fs::write(&self.path, text)
+    }
+
+    /// Issue a new token, returning the token itself. This is the only time
+    /// that it's available.
+    pub fn create(&self, name: &str, role: Role, sources: &[String]) -> Result<String, Error> {
+        let mut tokens = self.load()?;
+
+        if tokens.iter().any(|t| t.name == name) {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("there is already a token named `{}`", name),
+            ));
+        }
+
+        let mut bytes = [0u8; 24];
+        File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+        let token = base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD);
+
+        tokens.push(IssuedToken {
+            name: name.to_owned(),
+            role,
+            sources: sources.to_vec(),
+            created: Utc::now(),
+            hash: hash_token(&token),
+        });
+
+        self.save(&tokens)?;
+        Ok(token)
+    }
+
+    /// Revoke the token with the given name, returning whether there was one.
+    pub fn revoke(&self, name: &str) -> Result<bool, Error> {
+        let (revoked, kept): (Vec<_>, Vec<_>) =
+            self.load()?.into_iter().partition(|t| t.name == name);
+
+        if revoked.is_empty() {
+            return Ok(false);
+        }
+
+        self.save(&kept)?;
+        Ok(true)
+    }
+}
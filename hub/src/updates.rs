@@ -0,0 +1,276 @@
+//! Getting changes to the display state out to everyone who cares.
+//!
+//! The state is kept in one place. Mutations are applied as soon as they're
+//! sent, under a lock, and the new state is published to the subscribers
+//! through a watch channel. Each subscriber, in effect, has its own mailbox
+//! holding just the latest state, plus a notification that it has changed. A
+//! subscriber that falls behind, like the task for a panel on a slow link,
+//! simply sees the latest state when it gets around to looking, rather than
+//! missing changes; and nothing queues up in memory however fast the updates
+//! come in.
+//!
+//! Status updates, and the other changes that come in from outside, like
+//! webhook calls, are handed to the state through a queue with `submit`.
+//! The state loop, started with `run`, takes them off of the queue and
+//! applies them in order. The queue holds them if the loop isn't running
+//! yet, or is being restarted. If applying a change panics, the loop is
+//! restarted by the supervisor and tries the same change again, a few times,
+//! before giving up on it and telling whoever submitted it. Meanwhile the
+//! state isn't left half-changed, since each change is applied to a copy
+//! that only replaces the state once it's done. The hub's own tasks make
+//! changes directly with `send`, which has no queue.
+//!
+//! A panel that loses its connection altogether does miss changes, though,
+//! and by the time it's back, the state only shows how things ended up. So
+//! the hub remembers when the last few changes happened, and tells a panel
+//! that reconnects how many of them it missed.
+
+use rc_stickynote_protocol::{MissedUpdates, Timestamp};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+use tokio::sync::{mpsc, oneshot, watch, Mutex as AsyncMutex};
+
+use crate::{
+    history::{History, HistoryEvent},
+    notifications::ServerNotificationsConfiguration,
+    webhooks, DisplayStateMutation, GenericError, HubDisplayState, ServerConfiguration,
+};
+
+/// How many submitted changes can wait to be applied before whoever submits
+/// another has to wait too.
+const QUEUE_LENGTH: usize = 256;
+
+/// How many times to try applying a submitted change before giving up on it.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// A handle for changing the display state and hearing about the changes.
+#[derive(Clone)]
+pub struct UpdateHub {
+    inner: Arc<Mutex<Inner>>,
+
+    /// New subscribers are cloned from this receiver.
+    receiver: watch::Receiver<HubDisplayState>,
+
+    /// Where submitted changes go to wait for the state loop.
+    queue: mpsc::Sender<Queued>,
+
+    /// The state loop's end of the queue. It's kept here, rather than in the
+    /// loop, so that a restarted loop carries on where the last one stopped.
+    queued: Arc<AsyncMutex<Queue>>,
+}
+
+/// A submitted change, and where to say how it went.
+struct Queued {
+    mutation: DisplayStateMutation,
+
+    /// An event to record in the history ahead of the mutation's own, if
+    /// the mutation is accepted.
+    preface: Option<HistoryEvent>,
+
+    /// How many times applying it has panicked.
+    failures: u32,
+
+    reply: oneshot::Sender<bool>,
+}
+
+struct Queue {
+    receiver: mpsc::Receiver<Queued>,
+
+    /// The change being applied, if any.
+    current: Option<Queued>,
+}
+
+struct Inner {
+    state: HubDisplayState,
+    sender: watch::Sender<HubDisplayState>,
+    history: History,
+    webhooks: Vec<webhooks::ServerWebhookConfiguration>,
+    notifications: ServerNotificationsConfiguration,
+
+    /// When the latest changes to the display happened, oldest first.
+    recent: VecDeque<Timestamp>,
+    recent_limit: usize,
+}
+
+impl UpdateHub {
+    pub fn new(state: HubDisplayState, config: &ServerConfiguration, history: History) -> Self {
+        let (sender, receiver) = watch::channel(state.clone());
+        let (queue, queue_receiver) = mpsc::channel(QUEUE_LENGTH);
+
+        UpdateHub {
+            inner: Arc::new(Mutex::new(Inner {
+                state,
+                sender,
+                history,
+                webhooks: config.webhooks.clone(),
+                notifications: config.notifications.clone(),
+                recent: VecDeque::new(),
+                recent_limit: config.recent_frames,
+            })),
+            receiver,
+            queue,
+            queued: Arc::new(AsyncMutex::new(Queue {
+                receiver: queue_receiver,
+                current: None,
+            })),
+        }
+    }
+
+    /// Lock the state. A change that panicked partway through never got to
+    /// replace the state, so it's fine to carry on after one.
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The current state.
+    pub fn current(&self) -> HubDisplayState {
+        self.lock().state.clone()
+    }
+
+    /// Subscribe to the state. The subscription yields the current state
+    /// right away, and then the latest state whenever it changes.
+    pub fn subscribe(&self) -> watch::Receiver<HubDisplayState> {
+        self.receiver.clone()
+    }
+
+    /// The changes that a panel missed, if the last message that it got was
+    /// sent at `since`.
+    pub fn missed_since(&self, since: Timestamp) -> Option<MissedUpdates> {
+        let inner = self.lock();
+        let mut missed = inner.recent.iter().filter(|t| **t > since);
+        let first = *missed.next()?;
+
+        Some(MissedUpdates {
+            count: 1 + missed.count(),
+            since: first,
+        })
+    }
+
+    /// Apply a mutation to the state and let everyone know. Returns false if
+    /// the mutation was rejected because of a lock.
+    pub fn send(&self, mutation: DisplayStateMutation) -> bool {
+        self.apply(mutation, None)
+    }
+
+    fn apply(&self, mutation: DisplayStateMutation, preface: Option<HistoryEvent>) -> bool {
+        let mut inner = self.lock();
+
+        if let DisplayStateMutation::RingDoorbell(_) = mutation {
+            log!("ding dong!");
+            inner
+                .notifications
+                .notify("Doorbell", "Someone's at the door!");
+        }
+
+        let event = mutation.to_history_event();
+        let previous = inner.state.display.clone();
+        let mut state = inner.state.clone();
+
+        if !mutation.consume_into(&mut state) {
+            log!("ignoring update from locked-out source");
+            return false;
+        }
+
+        inner.state = state;
+
+        if let Some(event) = preface {
+            inner.history.record(event);
+        }
+
+        if let Some(event) = event {
+            inner.history.record(event);
+        }
+
+        if inner.state.display != previous {
+            webhooks::notify_all(&inner.webhooks, &inner.state.display, &inner.notifications);
+
+            inner.recent.push_back(chrono::Utc::now());
+
+            while inner.recent.len() > inner.recent_limit {
+                inner.recent.pop_front();
+            }
+        }
+
+        // We hold a receiver ourselves, so this can't fail.
+        let _ = inner.sender.broadcast(inner.state.clone());
+        true
+    }
+
+    /// Hand a mutation to the state loop and wait for it to be applied.
+    /// Returns false if it was rejected because of a lock, and an error if
+    /// it couldn't be applied at all.
+    pub async fn submit(&self, mutation: DisplayStateMutation) -> Result<bool, GenericError> {
+        self.enqueue(mutation, None).await
+    }
+
+    /// Like `submit`, but if the mutation is accepted, `event` goes into the
+    /// history log just ahead of whatever the mutation itself records.
+    pub async fn submit_after(
+        &self,
+        event: HistoryEvent,
+        mutation: DisplayStateMutation,
+    ) -> Result<bool, GenericError> {
+        self.enqueue(mutation, Some(event)).await
+    }
+
+    async fn enqueue(
+        &self,
+        mutation: DisplayStateMutation,
+        preface: Option<HistoryEvent>,
+    ) -> Result<bool, GenericError> {
+        let (reply, outcome) = oneshot::channel();
+
+        let queued = Queued {
+            mutation,
+            preface,
+            failures: 0,
+            reply,
+        };
+
+        // We hold the other end of the queue ourselves, so this can't fail.
+        let _ = self.queue.clone().send(queued).await;
+
+        outcome
+            .await
+            .map_err(|_| "the change couldn't be applied to the display state".into())
+    }
+
+    /// Apply the submitted mutations, in order, for as long as the hub runs.
+    pub async fn run(self) {
+        let mut queue = self.queued.lock().await;
+
+        loop {
+            // If the last loop panicked, it was partway through this one.
+            let queued = match queue.current.take() {
+                Some(mut q) => {
+                    q.failures += 1;
+
+                    if q.failures >= MAX_ATTEMPTS {
+                        log!("giving up on applying a change that keeps failing");
+                        continue;
+                    }
+
+                    log!("trying a change again after it failed");
+                    q
+                }
+
+                None => match queue.receiver.recv().await {
+                    Some(q) => q,
+                    None => return,
+                },
+            };
+
+            let mutation = queued.mutation.clone();
+            let preface = queued.preface.clone();
+            queue.current = Some(queued);
+            let accepted = self.apply(mutation, preface);
+
+            if let Some(q) = queue.current.take() {
+                // Whoever submitted it might have given up waiting.
+                let _ = q.reply.send(accepted);
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Notifying other services when the display state changes.
+//!
+//! Whenever the `DisplayMessage` changes, it is POSTed as JSON to each
+//! configured URL. If a webhook has a secret, the request carries an
+//! `X-Stickynote-Signature` header of the form `sha256=<base64>`, the
+//! HMAC-SHA256 of the body keyed with the secret, so that the receiver can
+//! check that the request really came from the hub. This is the same scheme
+//! that Twitter uses for its webhooks. Calls that fail are tried again later
+//! if there's an outbox; see the `outbox` module.
+
+use hmac::{Hmac, Mac};
+use hyper::{header, Body, Method, Request};
+use rc_stickynote_protocol::DisplayMessage;
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::{
+    http_client,
+    notifications::ServerNotificationsConfiguration,
+    outbox::{self, Delivery},
+    supervisor, GenericError,
+};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerWebhookConfiguration {
+    /// Where to POST the new state.
+    url: String,
+
+    /// The key used to sign requests, if any.
+    #[serde(default)]
+    secret: Option<String>,
+}
+
+impl ServerWebhookConfiguration {
+    async fn try_send(&self, body: String) -> Result<(), GenericError> {
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri(&self.url)
+            .header(header::CONTENT_TYPE, "application/json");
+
+        if let Some(ref secret) = self.secret {
+            let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("uhoh");
+            mac.input(body.as_bytes());
+            let sig = format!("sha256={}", base64::encode(&mac.result().code()));
+            builder = builder.header("x-stickynote-signature", sig);
+        }
+
+        let resp = http_client::send(builder.body(Body::from(body))?).await?;
+
+        if !resp.status().is_success() {
+            return Err(format!("webhook failed with status {}", resp.status()).into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Send a body to the webhook with the given URL.
+pub async fn send_to(
+    webhooks: &[ServerWebhookConfiguration],
+    url: &str,
+    body: String,
+) -> Result<(), GenericError> {
+    match webhooks.iter().find(|w| w.url == url) {
+        Some(hook) => hook.try_send(body).await,
+        None => Err(format!("webhook {} isn't configured any more", url).into()),
+    }
+}
+
+/// Send the new display state to all of the webhooks in the background.
+/// Failures are logged, and either kept in the outbox to try again or
+/// passed along as notifications.
+pub fn notify_all(
+    webhooks: &[ServerWebhookConfiguration],
+    state: &DisplayMessage,
+    notifications: &ServerNotificationsConfiguration,
+) {
+    if webhooks.is_empty() {
+        return;
+    }
+
+    let body = match serde_json::to_string(state) {
+        Ok(b) => b,
+        Err(e) => {
+            log!("error serializing state for webhooks: {}", e);
+            return;
+        }
+    };
+
+    for hook in webhooks {
+        let hook = hook.clone();
+        let body = body.clone();
+        let notifications = notifications.clone();
+
+        supervisor::spawn(format!("call to webhook {}", hook.url), async move {
+            let delivery = Delivery::Webhook {
+                url: hook.url.clone(),
+                body: body.clone(),
+            };
+            let held = outbox::hold(notifications.outbox.as_ref(), &delivery);
+            let outcome = hook.try_send(body).await.map_err(|e| e.to_string());
+
+            if let Err(ref e) = outcome {
+                log!("error calling webhook {}: {}", hook.url, e);
+            }
+
+            // If it's going to be tried again, the owner only hears about it
+            // if that doesn't work out.
+            let retrying = held.map(|h| h.settle(outcome.clone())).unwrap_or(false);
+
+            if let (Err(e), false) = (outcome, retrying) {
+                let msg = format!("error calling webhook {}: {}", hook.url, e);
+                notifications.notify("Webhook failed", &msg);
+            }
+        });
+    }
+}
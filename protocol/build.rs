@@ -0,0 +1,219 @@
+//! Generate the table of font metrics used to estimate how wide text will be
+//! on the panel.
+//!
+//! The panels draw statuses with DejaVu Sans, a copy of which the display
+//! client has built in, so we read the advance widths and kerning pairs out
+//! of that file. Only the characters that people are likely to put in a
+//! status go into the table, to keep it small.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{Error, ErrorKind},
+    path::PathBuf,
+};
+
+const FONT_PATH: &str = "../displayer/fonts/DejaVuSans.ttf";
+
+/// The characters to include: Latin, general punctuation, currency, letter-
+/// like symbols, and arrows.
+const RANGES: &[(u32, u32)] = &[
+    (0x20, 0x7e),
+    (0xa0, 0x24f),
+    (0x2000, 0x206f),
+    (0x20a0, 0x20bf),
+    (0x2100, 0x21ff),
+];
+
+fn main() -> Result<(), Error> {
+    println!("cargo:rerun-if-changed={}", FONT_PATH);
+
+    let data = fs::read(FONT_PATH)?;
+    let font = Font { data: &data };
+
+    let hhea = font.table(b"hhea")?;
+    let ascent = font.u16_at(hhea + 4)? as i16;
+    let descent = font.u16_at(hhea + 6)? as i16;
+
+    let mut glyphs = BTreeMap::new();
+
+    for &(start, end) in RANGES {
+        for code in start..=end {
+            if let Some(c) = char::from_u32(code) {
+                let glyph = font.glyph_index(code)?;
+
+                if glyph != 0 {
+                    glyphs.insert(c, glyph);
+                }
+            }
+        }
+    }
+
+    let mut out = String::new();
+    out.push_str("// Generated by build.rs from DejaVu Sans. Do not edit.\n\n");
+    out.push_str(&format!(
+        "/// The font's height, from the top of the ascenders to the bottom of\n\
+         /// the descenders, in font units. This is what a font size is measured\n\
+         /// against.\n\
+         const HEIGHT_UNITS: u16 = {};\n\
+         /// The advance of characters that aren't in the table.\n\
+         const MISSING_ADVANCE: u16 = {};\n\n",
+        ascent as i32 - descent as i32,
+        font.advance(0)?,
+    ));
+
+    out.push_str("const ADVANCES: &[(char, u16)] = &[\n");
+
+    for (c, &glyph) in &glyphs {
+        out.push_str(&format!("    ({:?}, {}),\n", c, font.advance(glyph)?));
+    }
+
+    out.push_str("];\n\nconst KERNING: &[((char, char), i16)] = &[\n");
+
+    let kerning = font.kerning()?;
+
+    for (left, &lg) in &glyphs {
+        for (right, &rg) in &glyphs {
+            if let Some(value) = kerning.get(&(lg, rg)) {
+                out.push_str(&format!("    (({:?}, {:?}), {}),\n", left, right, value));
+            }
+        }
+    }
+
+    out.push_str("];\n");
+
+    let path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("font_metrics.rs");
+    fs::write(path, out)
+}
+
+/// Just enough of a TrueType parser to get at the metrics.
+struct Font<'a> {
+    data: &'a [u8],
+}
+
+fn malformed() -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("{} is malformed", FONT_PATH),
+    )
+}
+
+impl<'a> Font<'a> {
+    fn u16_at(&self, offset: usize) -> Result<u16, Error> {
+        let b = self.data.get(offset..offset + 2).ok_or_else(malformed)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32_at(&self, offset: usize) -> Result<u32, Error> {
+        let b = self.data.get(offset..offset + 4).ok_or_else(malformed)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// The offset of the named table.
+    fn table(&self, tag: &[u8; 4]) -> Result<usize, Error> {
+        for i in 0..self.u16_at(4)? as usize {
+            let record = 12 + 16 * i;
+
+            if self.data.get(record..record + 4) == Some(&tag[..]) {
+                return Ok(self.u32_at(record + 8)? as usize);
+            }
+        }
+
+        Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} has no {:?} table", FONT_PATH, tag),
+        ))
+    }
+
+    /// The advance width of a glyph, in font units.
+    fn advance(&self, glyph: u16) -> Result<u16, Error> {
+        let n_metrics = self.u16_at(self.table(b"hhea")? + 34)?;
+        let index = glyph.min(n_metrics - 1) as usize;
+        self.u16_at(self.table(b"hmtx")? + 4 * index)
+    }
+
+    /// Look up a character in the Unicode BMP cmap subtable, which is format
+    /// 4.
+    fn glyph_index(&self, code: u32) -> Result<u16, Error> {
+        let cmap = self.table(b"cmap")?;
+        let mut subtable = None;
+
+        for i in 0..self.u16_at(cmap + 2)? as usize {
+            let record = cmap + 4 + 8 * i;
+
+            if self.u16_at(record)? == 3 && self.u16_at(record + 2)? == 1 {
+                subtable = Some(cmap + self.u32_at(record + 4)? as usize);
+            }
+        }
+
+        let st = subtable.ok_or_else(malformed)?;
+
+        if self.u16_at(st)? != 4 || code > 0xffff {
+            return Ok(0);
+        }
+
+        let code = code as u16;
+        let n_segs = self.u16_at(st + 6)? as usize / 2;
+        let ends = st + 14;
+        let starts = ends + 2 * n_segs + 2;
+        let deltas = starts + 2 * n_segs;
+        let range_offsets = deltas + 2 * n_segs;
+
+        for i in 0..n_segs {
+            if code > self.u16_at(ends + 2 * i)? {
+                continue;
+            }
+
+            let start = self.u16_at(starts + 2 * i)?;
+
+            if code < start {
+                return Ok(0);
+            }
+
+            let delta = self.u16_at(deltas + 2 * i)?;
+            let range_offset = self.u16_at(range_offsets + 2 * i)?;
+
+            if range_offset == 0 {
+                return Ok(code.wrapping_add(delta));
+            }
+
+            let at = range_offsets + 2 * i + range_offset as usize + 2 * (code - start) as usize;
+
+            return match self.u16_at(at)? {
+                0 => Ok(0),
+                g => Ok(g.wrapping_add(delta)),
+            };
+        }
+
+        Ok(0)
+    }
+
+    /// The pairs in the first format-0 subtable of the old-style kern table,
+    /// which is the only kerning that the display client applies.
+    fn kerning(&self) -> Result<BTreeMap<(u16, u16), i16>, Error> {
+        let kern = self.table(b"kern")?;
+        let mut pairs = BTreeMap::new();
+
+        if self.u16_at(kern)? != 0 || self.u16_at(kern + 2)? < 1 {
+            return Ok(pairs);
+        }
+
+        let st = kern + 4;
+
+        // The coverage must say that it's horizontal kerning in format 0.
+
+        if self.u16_at(st + 4)? != 1 {
+            return Ok(pairs);
+        }
+
+        for i in 0..self.u16_at(st + 6)? as usize {
+            let pair = st + 14 + 6 * i;
+            pairs.insert(
+                (self.u16_at(pair)?, self.u16_at(pair + 2)?),
+                self.u16_at(pair + 4)? as i16,
+            );
+        }
+
+        Ok(pairs)
+    }
+}
@@ -1,30 +1,463 @@
-use serde::{Deserialize, Serialize};
+//! The messages that the hub, panels, and updaters exchange.
+//!
+//! Hubs and panels get upgraded at different times, so any version of one
+//! has to get along with any version of the other. The rules that keep that
+//! working:
+//!
+//! - Fields are only ever added, never renamed, removed, or changed in type.
+//! - Every field added after the first release has `#[serde(default)]`, so
+//!   that messages from older versions, which lack it, still parse.
+//! - Nothing uses `#[serde(deny_unknown_fields)]`, so that messages from
+//!   newer versions, with fields that we don't know about, still parse.
+//! - Optional fields holding enums use `deserialize_with = "lenient"`, so
+//!   that a variant added in a newer version reads as if the field were
+//!   missing, rather than making the whole message unreadable.
+//!
+//! The tests in `tests/compat.rs` check these rules against messages saved
+//! from earlier versions, in `tests/fixtures`. When a message changes, save
+//! an example of the new version there too.
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use std::{collections::BTreeMap, convert::TryFrom};
+
+pub mod mqtt;
+pub mod sealed;
+#[cfg(feature = "session")]
+pub mod session;
+pub mod signing;
+pub mod width;
 
 pub type Timestamp = chrono::DateTime<chrono::Utc>;
 
+/// The "person is:" message before anybody has set one.
+pub const UNKNOWN_PERSON_IS: &str = "whereabouts unknown";
+
+/// The source of statuses set automatically during video calls. The hub
+/// gives them the same precedence as statuses set from the command line.
+pub const VIDEO_CALL_SOURCE: &str = "video call";
+
+/// The source of statuses set with desktop hotkeys, which take the same
+/// precedence as statuses set from the command line too.
+pub const HOTKEY_SOURCE: &str = "hotkey";
+
 /// A message sent to the panel giving all of the information it needs to
 /// populate the display.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct DisplayMessage {
     /// The "person is:" message.
     pub person_is: String,
 
     /// When the "person is:" message was last updated.
     pub person_is_timestamp: Timestamp,
+
+    /// A short description of where the "person is:" message came from, e.g.
+    /// "Twitter". Empty if unknown.
+    #[serde(default)]
+    pub person_is_source: String,
+
+    /// Who set the "person is:" message, e.g. "Alice". Empty if unknown.
+    #[serde(default)]
+    pub person_is_set_by: String,
+
+    /// How many notes left by visitors are waiting to be read.
+    #[serde(default)]
+    pub notes_waiting: usize,
+
+    /// If visitors can leave notes, the URL of the form for doing so.
+    #[serde(default)]
+    pub note_form_url: Option<String>,
+
+    /// If someone has rung the doorbell recently, when the panel should stop
+    /// saying so.
+    #[serde(default)]
+    pub doorbell_until: Option<Timestamp>,
+
+    /// News headlines for the panel to cycle through.
+    #[serde(default)]
+    pub headlines: Vec<String>,
+
+    /// The latest command that an admin has sent to the panels. The hub
+    /// keeps sending it along with the rest of the state, so panels should
+    /// only act on each command once, and not on stale ones.
+    #[serde(default, deserialize_with = "lenient")]
+    pub command: Option<PanelCommandMessage>,
+
+    /// Settings for this particular panel from the hub's configuration, if
+    /// there are any.
+    #[serde(default)]
+    pub settings: Option<DisplaySettings>,
+
+    /// Whether the hub is down for maintenance. If so, the hub stops sending
+    /// updates until it's over, so the panel should say that what it shows
+    /// might be out of date.
+    #[serde(default)]
+    pub maintenance: bool,
+
+    /// When the hub sent this message, by its own clock, so that the panel
+    /// can tell how far off its clock is. Unset if the message might be
+    /// delivered long after it was sent, like a retained MQTT message.
+    #[serde(default)]
+    pub sent_at: Option<Timestamp>,
+
+    /// Whether the person is generally available right now, according to
+    /// the office hours in the hub's configuration. Unset if the hub doesn't
+    /// have any.
+    #[serde(default, deserialize_with = "lenient")]
+    pub availability: Option<Availability>,
+
+    /// The latest CI results for the branches that the hub is watching, in
+    /// the order that they were first heard about.
+    #[serde(default)]
+    pub ci: Vec<CiStatus>,
+
+    /// Who's on call for the schedules that the hub is watching.
+    #[serde(default)]
+    pub on_call: Vec<OnCall>,
+
+    /// The hub's counters, in the order that they were created.
+    #[serde(default)]
+    pub counters: Vec<Counter>,
+
+    /// Who plans to be in the office on which days this week, for panels
+    /// used as hybrid-work boards.
+    #[serde(default)]
+    pub office_days: Vec<OfficeDays>,
+
+    /// If the hub is watching a meeting room's calendar, the room's bookings,
+    /// for panels used as room displays.
+    #[serde(default)]
+    pub room: Option<RoomSchedule>,
+
+    /// The next status queued up on the hub, if there is one.
+    #[serde(default)]
+    pub next_status: Option<QueuedStatus>,
+
+    /// If the panel has just reconnected, what changed while it was away.
+    /// Only the first message on the new connection says.
+    #[serde(default)]
+    pub missed: Option<MissedUpdates>,
+
+    /// Free-form data for panel widgets that don't need their own field,
+    /// keyed by names like "weather.temperature" whose first part says who
+    /// set them. Panels just ignore the keys that they don't know about.
+    #[serde(default)]
+    pub extras: BTreeMap<String, serde_json::Value>,
+}
+
+/// Deserialize an optional field, treating a value that we can't make sense
+/// of, like an enum variant from a newer version, as if it were missing.
+fn lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(T::deserialize(value).ok())
+}
+
+/// Whether visitors can expect to find the person in, going by their usual
+/// office hours rather than the latest status.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Availability {
+    /// During office hours.
+    Available,
+
+    /// During hours set aside for something else, like appointments only.
+    Limited,
+
+    /// Outside office hours.
+    Unavailable,
+}
+
+/// The outcome of the latest CI run on a branch that the hub is watching.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct CiStatus {
+    /// A short name for the branch, like "main".
+    pub label: String,
+
+    /// Whether the run passed.
+    pub passed: bool,
+
+    /// When the run finished.
+    pub finished: Timestamp,
+}
+
+/// Who's on call for one of the schedules that the hub is watching.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct OnCall {
+    /// A short name for the schedule, like "Infra".
+    pub label: String,
+
+    /// Who's on call. If unset, it's the person whose panel this is, and the
+    /// panel should show a badge rather than a name.
+    #[serde(default)]
+    pub person: Option<String>,
+}
+
+/// A counter kept by the hub, for signs like "12 days since the last
+/// incident".
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Counter {
+    /// The name that the counter goes by in the hub's API.
+    pub name: String,
+
+    /// What to show next to the value, like "days since the last incident".
+    pub label: String,
+
+    /// The current value.
+    pub value: i64,
+}
+
+/// A status that the hub will put up at a given time.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct QueuedStatus {
+    /// The status.
+    pub person_is: String,
+
+    /// When it goes up.
+    pub at: Timestamp,
+}
+
+/// The changes to the display state that a panel missed while it was
+/// disconnected.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct MissedUpdates {
+    /// How many changes there were. The hub only remembers so many, so after
+    /// a long outage, there might have been more.
+    pub count: usize,
+
+    /// When the first of them happened.
+    pub since: Timestamp,
+}
+
+/// The days that somebody plans to be in the office.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct OfficeDays {
+    /// Who, like "Alice".
+    pub name: String,
+
+    /// The days in the office, by the hub's calendar, in order.
+    pub days: Vec<chrono::NaiveDate>,
+}
+
+/// The bookings of a meeting room.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct RoomSchedule {
+    /// The room's name, like "Galileo Room".
+    pub name: String,
+
+    /// The bookings that haven't ended yet, for the rest of today, in order
+    /// of their start. The panel works out which one is going on from its
+    /// own clock, so that it stays right between updates.
+    pub bookings: Vec<Booking>,
+
+    /// Whether the hub can make new bookings with a `RoomBookingHelloMessage`.
+    pub can_book: bool,
+}
+
+/// A booking of a meeting room.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Booking {
+    /// What the booking is for. Empty if it's private.
+    pub summary: String,
+
+    /// When it starts.
+    pub start: Timestamp,
+
+    /// When it ends.
+    pub end: Timestamp,
+}
+
+/// Settings for a particular panel that are kept in the hub's configuration.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct DisplaySettings {
+    /// The strftime-style format for the clock, e.g. "%H:%M" for a 24-hour
+    /// clock.
+    #[serde(default)]
+    pub clock_format: Option<String>,
+
+    /// The language for text like "5 minutes ago", as a two-letter code
+    /// such as "de".
+    #[serde(default)]
+    pub locale: Option<String>,
+
+    /// The layout to show, unless an admin asks for a different one.
+    #[serde(default)]
+    pub layout: Option<String>,
 }
 
 impl Default for DisplayMessage {
     fn default() -> Self {
         DisplayMessage {
-            person_is: "whereabouts unknown".to_owned(),
+            person_is: UNKNOWN_PERSON_IS.to_owned(),
             person_is_timestamp: chrono::Utc::now(),
+            person_is_source: String::new(),
+            person_is_set_by: String::new(),
+            notes_waiting: 0,
+            note_form_url: None,
+            doorbell_until: None,
+            headlines: Vec::new(),
+            command: None,
+            settings: None,
+            maintenance: false,
+            sent_at: None,
+            availability: None,
+            ci: Vec::new(),
+            on_call: Vec::new(),
+            counters: Vec::new(),
+            office_days: Vec::new(),
+            room: None,
+            next_status: None,
+            missed: None,
+            extras: BTreeMap::new(),
+        }
+    }
+}
+
+impl DisplayMessage {
+    /// Get a copy of this message without anything that a panel with the
+    /// given capabilities couldn't show.
+    pub fn tailored_for(&self, caps: &DisplayCapabilities) -> DisplayMessage {
+        let mut msg = self.clone();
+
+        // The note form is advertised with a QR code.
+        if !caps.images {
+            msg.note_form_url = None;
         }
+
+        msg
     }
 }
 
+/// Something that an admin can ask the panels to do.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PanelCommand {
+    /// Redraw the display.
+    Redraw,
+
+    /// Fully clear the display before redrawing it, to get rid of ghosting.
+    Clear,
+
+    /// Restart the client program.
+    Restart,
+
+    /// Switch to the named layout.
+    Layout(String),
+
+    /// Blank the display until the status changes.
+    Screensaver,
+}
+
+impl PanelCommand {
+    /// The name of the command, as used in panels' allowlists.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PanelCommand::Redraw => "redraw",
+            PanelCommand::Clear => "clear",
+            PanelCommand::Restart => "restart",
+            PanelCommand::Layout(_) => "layout",
+            PanelCommand::Screensaver => "screensaver",
+        }
+    }
+}
+
+impl std::str::FromStr for PanelCommand {
+    type Err = String;
+
+    /// Parse a command as typed by an admin, e.g. "redraw" or "layout:status".
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut pieces = text.splitn(2, ':');
+        let name = pieces.next().unwrap_or_default().trim();
+        let arg = pieces.next().map(|a| a.trim());
+
+        match (name, arg) {
+            ("redraw", None) => Ok(PanelCommand::Redraw),
+            ("clear", None) => Ok(PanelCommand::Clear),
+            ("restart", None) => Ok(PanelCommand::Restart),
+            ("layout", Some(l)) if !l.is_empty() => Ok(PanelCommand::Layout(l.to_owned())),
+            ("screensaver", None) => Ok(PanelCommand::Screensaver),
+            _ => Err(format!("unrecognized panel command `{}`", text)),
+        }
+    }
+}
+
+impl std::fmt::Display for PanelCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PanelCommand::Layout(l) => write!(f, "layout:{}", l),
+            c => f.write_str(c.name()),
+        }
+    }
+}
+
+/// A command for the panels, with when it was issued.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PanelCommandMessage {
+    pub command: PanelCommand,
+    pub issued: Timestamp,
+}
+
 /// A "hello" from a displayer client.
 #[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct DisplayHelloMessage {}
+pub struct DisplayHelloMessage {
+    /// Which panel this is, so that the hub can apply settings specific to
+    /// it. Older clients don't say.
+    #[serde(default)]
+    pub display_id: Option<String>,
+
+    /// What the client's panel can do. Older clients don't say.
+    #[serde(default)]
+    pub capabilities: Option<DisplayCapabilities>,
+
+    /// Whether the client can share the connection between the display
+    /// updates and other traffic, with each message wrapped in a
+    /// `HubFrame` or `ClientFrame`. Older clients can't.
+    #[serde(default)]
+    pub multiplex: bool,
+
+    /// If the client is reconnecting, the `sent_at` of the last message that
+    /// it got, so that the hub can tell it what it missed.
+    #[serde(default)]
+    pub last_update: Option<Timestamp>,
+}
+
+/// What a display panel is able to show, so that the hub can avoid sending
+/// it things that it can't.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct DisplayCapabilities {
+    /// The width of the panel, in pixels.
+    pub width: u32,
+
+    /// The height of the panel, in pixels.
+    pub height: u32,
+
+    /// How many colors the panel can show; 2 for black and white.
+    pub colors: u32,
+
+    /// Whether the panel can quickly refresh just part of its area.
+    pub partial_refresh: bool,
+
+    /// Whether the panel can show images, such as QR codes.
+    pub images: bool,
+}
+
+impl std::fmt::Display for DisplayCapabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}, {} colors", self.width, self.height, self.colors)?;
+
+        if self.partial_refresh {
+            write!(f, ", partial refresh")?;
+        }
+
+        if self.images {
+            write!(f, ", images")?;
+        }
+
+        Ok(())
+    }
+}
 
 /// A "hello" from a "person is"-update client.
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -34,6 +467,105 @@ pub struct PersonIsUpdateHelloMessage {
 
     /// The message timestamp.
     pub timestamp: Timestamp,
+
+    /// A short description of where the update came from, if known.
+    #[serde(default)]
+    pub source: Option<String>,
+
+    /// Who made the update, if known. The hub overwrites this with the name
+    /// associated with the access token, if it has one, so clients can't
+    /// impersonate each other.
+    #[serde(default)]
+    pub set_by: Option<String>,
+
+    /// An access token, if the hub requires one for updates.
+    #[serde(default)]
+    pub token: Option<String>,
+
+    /// The updater's signature on the update, if it has a signing key.
+    #[serde(default)]
+    pub signature: Option<signing::UpdateSignature>,
+}
+
+/// A "hello" from a client reporting that someone rang the doorbell.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DoorbellHelloMessage {
+    /// When the doorbell was rung.
+    pub timestamp: Timestamp,
+
+    /// An access token, if the hub requires one for updates.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// A "hello" from a client asking to book the meeting room that the hub is
+/// watching, starting now, as with a button on a room display.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RoomBookingHelloMessage {
+    /// When the booking was asked for.
+    pub timestamp: Timestamp,
+
+    /// How long to book the room for. The booking is cut short if the room
+    /// is booked by someone else before then.
+    pub minutes: u32,
+
+    /// The ID of the panel asking, if it has one.
+    #[serde(default)]
+    pub display_id: Option<String>,
+
+    /// An access token, if the hub requires one for updates.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// A "hello" from a client reporting room conditions measured by a sensor.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SensorReadingHelloMessage {
+    /// When the measurement was made.
+    pub timestamp: Timestamp,
+
+    /// The CO2 concentration, in parts per million.
+    pub co2_ppm: f32,
+
+    /// The temperature, in degrees Celsius.
+    pub temperature_c: f32,
+
+    /// The relative humidity, in percent.
+    pub humidity_percent: f32,
+
+    /// The ID of the panel that made the measurement, if it has one.
+    #[serde(default)]
+    pub display_id: Option<String>,
+
+    /// An access token, if the hub requires one for updates.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// A "hello" from a client reporting on the health of the machine it runs on.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SystemHealthHelloMessage {
+    /// When the check was made.
+    pub timestamp: Timestamp,
+
+    /// The CPU temperature, in degrees Celsius, if known.
+    #[serde(default)]
+    pub cpu_temperature_c: Option<f32>,
+
+    /// On a Raspberry Pi, the flags reported by `vcgencmd get_throttled`.
+    /// The low four bits mean under-voltage, frequency capping, throttling,
+    /// and the soft temperature limit, respectively; the same bits shifted
+    /// up by 16 mean that each has happened since boot.
+    #[serde(default)]
+    pub throttled_flags: Option<u32>,
+
+    /// The ID of the panel reporting, if it has one.
+    #[serde(default)]
+    pub display_id: Option<String>,
+
+    /// An access token, if the hub requires one for updates.
+    #[serde(default)]
+    pub token: Option<String>,
 }
 
 /// A message sent to hub from a client introducing itself.
@@ -45,13 +577,225 @@ pub enum ClientHelloMessage {
 
     /// This client wants to update the "person is:" message.
     PersonIsUpdate(PersonIsUpdateHelloMessage),
+
+    /// This client is reporting that someone rang the doorbell.
+    Doorbell(DoorbellHelloMessage),
+
+    /// This client is reporting room conditions.
+    SensorReading(SensorReadingHelloMessage),
+
+    /// This client is reporting on its own health.
+    SystemHealth(SystemHealthHelloMessage),
+
+    /// This client wants to book the meeting room.
+    BookRoom(RoomBookingHelloMessage),
+}
+
+/// Sent by the hub to an updater whose hello it won't act on, just before
+/// hanging up. Hubs that predate this just hang up, so an updater that
+/// doesn't get one can't tell a success from a rejection by an old hub.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ErrorFrame {
+    /// What went wrong, for programs to act on.
+    pub code: ErrorCode,
+
+    /// What went wrong, for people to read.
+    pub message: String,
+}
+
+/// The reasons that the hub might reject a hello.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    /// The status isn't one that can be displayed; most likely, it's too
+    /// long.
+    InvalidStatus,
+
+    /// The hello lacked a token that allows it.
+    Unauthorized,
+
+    /// The hub requires signed updates, and the signature was missing or
+    /// didn't check out.
+    BadSignature,
+
+    /// The hub's content filter turned the status down.
+    Filtered,
+
+    /// The hub ran into trouble of its own.
+    Internal,
+
+    /// The hub can't do that right now, like booking a room that's already
+    /// booked.
+    Unavailable,
+
+    /// Updates from the client's source are locked out by a more important
+    /// source.
+    Locked,
+
+    /// A code from a newer hub than this.
+    #[serde(other)]
+    Unknown,
+}
+
+impl ErrorFrame {
+    pub fn new<S: Into<String>>(code: ErrorCode, message: S) -> Self {
+        ErrorFrame {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Get the frame that the hub sent, if it's the reason for an error
+    /// returned by `session::client::AwaitingHello::send_update()`.
+    pub fn from_error(e: &std::io::Error) -> Option<&ErrorFrame> {
+        e.get_ref()?.downcast_ref()
+    }
+}
+
+impl std::fmt::Display for ErrorFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ErrorFrame {}
+
+/// A message from the hub on a multiplexed connection. On the wire, it's
+/// tagged with its channel, like `{"channel": "display", "body": {...}}`.
+/// A hub only sends these to clients that ask for them in their hello, and
+/// only after the hello; an older hub keeps sending bare `DisplayMessage`s.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(
+    tag = "channel",
+    content = "body",
+    rename_all = "kebab-case",
+    try_from = "RawFrame"
+)]
+pub enum HubFrame {
+    /// The latest display state, as on a connection that isn't multiplexed.
+    Display(Box<DisplayMessage>),
+
+    /// The answer to a `ClientFrame::Control`.
+    Control(ControlReply),
+
+    /// A channel from a newer hub than this.
+    Unknown,
+}
+
+/// A message from a client on a multiplexed connection, tagged with its
+/// channel in the same way as a `HubFrame`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(
+    tag = "channel",
+    content = "body",
+    rename_all = "kebab-case",
+    try_from = "RawFrame"
+)]
+pub enum ClientFrame {
+    /// A report that doesn't need an answer, like a sensor reading. The hub
+    /// handles it as it would the same hello on a connection of its own.
+    Telemetry(ClientHelloMessage),
+
+    /// A request that the hub answers with a `HubFrame::Control`.
+    Control(ControlRequest),
+
+    /// A channel from a newer client than this.
+    Unknown,
+}
+
+/// A frame with its body not yet read. Unlike on other enums,
+/// `#[serde(other)]` can't be used for the unknown channels, since it only
+/// matches variants without content, so frames are read through this.
+#[derive(Deserialize)]
+struct RawFrame {
+    channel: String,
+
+    #[serde(default)]
+    body: serde_json::Value,
+}
+
+impl TryFrom<RawFrame> for HubFrame {
+    type Error = serde_json::Error;
+
+    fn try_from(raw: RawFrame) -> Result<Self, Self::Error> {
+        Ok(match raw.channel.as_str() {
+            "display" => HubFrame::Display(serde_json::from_value(raw.body)?),
+            "control" => HubFrame::Control(serde_json::from_value(raw.body)?),
+            _ => HubFrame::Unknown,
+        })
+    }
+}
+
+impl TryFrom<RawFrame> for ClientFrame {
+    type Error = serde_json::Error;
+
+    fn try_from(raw: RawFrame) -> Result<Self, Self::Error> {
+        Ok(match raw.channel.as_str() {
+            "telemetry" => ClientFrame::Telemetry(serde_json::from_value(raw.body)?),
+            "control" => ClientFrame::Control(serde_json::from_value(raw.body)?),
+            _ => ClientFrame::Unknown,
+        })
+    }
+}
+
+/// A request on the control channel. The hub handles the hello as it would
+/// on a connection of its own, but answers rather than hanging up.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ControlRequest {
+    /// Picked by the client, to match up the answer.
+    pub id: u64,
+
+    pub hello: ClientHelloMessage,
+}
+
+/// The hub's answer to a `ControlRequest`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ControlReply {
+    /// The `id` of the request.
+    pub id: u64,
+
+    /// Why the hub won't act on the request, if it won't.
+    #[serde(default)]
+    pub error: Option<ErrorFrame>,
 }
 
 /// Validate a "person_is" message.
 ///
-/// We just check length against an empirical limit based on the current
-/// display size and font setup. The font used is variable-width so there's
-/// some slop but we don't need to be exactly perfect.
+/// A message is valid if it fits in the status box on the panel, going by
+/// the estimate in the `width` module. There's a limit on its length in
+/// bytes too, since some characters take up no room at all.
+///
+/// We can't see inside sealed messages, so for them we just check that the
+/// sealed box isn't bigger than a valid message would make it.
 pub fn is_person_is_valid(person_is: &str) -> bool {
-    person_is.len() < 23
+    const MAX_LEN: usize = 64;
+
+    match person_is.strip_prefix(sealed::SEALED_PREFIX) {
+        Some(body) => {
+            let n_bytes = body.trim_end_matches('=').len() * 3 / 4;
+            n_bytes <= sealed::SEAL_OVERHEAD + MAX_LEN
+        }
+        None => person_is.len() <= MAX_LEN && width::does_status_fit(person_is),
+    }
+}
+
+/// Check that a key for `DisplayMessage.extras` is namespaced, like
+/// "weather.temperature": a namespace and a name, separated by a dot, made
+/// of letters, digits, dashes, and underscores.
+pub fn is_extras_key_valid(key: &str) -> bool {
+    const MAX_LEN: usize = 64;
+
+    let is_part_valid = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    };
+
+    match key.split_once('.') {
+        Some((namespace, name)) => {
+            key.len() <= MAX_LEN && is_part_valid(namespace) && is_part_valid(name)
+        }
+        None => false,
+    }
 }
@@ -0,0 +1,80 @@
+//! Exchanging protocol messages through an MQTT broker.
+//!
+//! Instead of the panel connecting to the hub, both can connect out to a
+//! shared broker. The hub publishes the current `DisplayMessage` to the
+//! display topic, as a retained message so that panels get it as soon as
+//! they subscribe, and clients publish `ClientHelloMessage`s to the hello
+//! topic, all encoded as JSON.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MqttConfiguration {
+    /// The hostname of the broker.
+    pub host: String,
+
+    /// The broker's port.
+    #[serde(default = "default_port")]
+    pub port: u16,
+
+    /// Whether to connect to the broker using TLS.
+    #[serde(default = "default_tls")]
+    pub tls: bool,
+
+    #[serde(default)]
+    pub username: Option<String>,
+
+    #[serde(default)]
+    pub password: Option<String>,
+
+    /// The prefix of the topics used by this installation, e.g.
+    /// "stickynote/alice". Anyone who can publish to these topics can talk to
+    /// the hub, so the broker should restrict access to them.
+    pub topic_prefix: String,
+}
+
+fn default_port() -> u16 {
+    8883
+}
+
+fn default_tls() -> bool {
+    true
+}
+
+impl MqttConfiguration {
+    /// The topic where the hub publishes the display state.
+    pub fn display_topic(&self) -> String {
+        format!("{}/display", self.topic_prefix.trim_end_matches('/'))
+    }
+
+    /// The topic where clients publish their "hello" messages.
+    pub fn hello_topic(&self) -> String {
+        format!("{}/hello", self.topic_prefix.trim_end_matches('/'))
+    }
+
+    /// Get options for connecting to the broker. The `role` distinguishes the
+    /// different programs connecting, since the broker requires each client
+    /// to have a unique ID.
+    #[cfg(feature = "mqtt")]
+    pub fn options(&self, role: &str) -> rumqttc::MqttOptions {
+        let client_id = format!(
+            "{}-{}-{}",
+            self.topic_prefix.replace('/', "-"),
+            role,
+            std::process::id()
+        );
+
+        let mut options = rumqttc::MqttOptions::new(client_id, &self.host, self.port);
+        options.set_keep_alive(std::time::Duration::from_secs(60));
+
+        if let (Some(u), Some(p)) = (&self.username, &self.password) {
+            options.set_credentials(u, p);
+        }
+
+        if self.tls {
+            options.set_transport(rumqttc::Transport::tls_with_default_config());
+        }
+
+        options
+    }
+}
@@ -0,0 +1,120 @@
+//! Statuses that only the panel can read.
+//!
+//! In a shared space, the hub might be run by somebody else. So an updater can
+//! seal the status with the panel's public key (a NaCl sealed box), and the
+//! hub passes it along without being able to read it. Sealed statuses are
+//! marked with `SEALED_PREFIX` and carried in the usual `person_is` fields;
+//! the panel opens them with its secret key before drawing them.
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "sealed")]
+use std::io::{Error, ErrorKind};
+
+/// Sealed statuses start with this, followed by the sealed box in base64.
+pub const SEALED_PREFIX: &str = "sealed:";
+
+/// The number of bytes a sealed box adds to the status: an ephemeral public
+/// key and an authentication tag.
+pub const SEAL_OVERHEAD: usize = 48;
+
+/// Whether a "person is:" message is sealed.
+pub fn is_sealed(person_is: &str) -> bool {
+    person_is.starts_with(SEALED_PREFIX)
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SealingConfiguration {
+    /// The panel's secret key, in base64. Only the panel should have this.
+    #[serde(default)]
+    pub secret_key: Option<String>,
+
+    /// The panel's public key, in base64, for updaters that don't have the
+    /// secret key. If unset, it's derived from the secret key.
+    #[serde(default)]
+    pub public_key: Option<String>,
+}
+
+#[cfg(feature = "sealed")]
+fn decode_key(text: &str, what: &str) -> Result<[u8; crypto_box::KEY_SIZE], Error> {
+    let bytes = base64::decode(text.trim())
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("bad {}: {}", what, e)))?;
+
+    if bytes.len() != crypto_box::KEY_SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("bad {}: expected {} bytes", what, crypto_box::KEY_SIZE),
+        ));
+    }
+
+    let mut key = [0u8; crypto_box::KEY_SIZE];
+    key.copy_from_slice(&bytes);
+    Ok(key)
+}
+
+#[cfg(feature = "sealed")]
+impl SealingConfiguration {
+    /// Make up a new key pair, returning the secret and public keys in
+    /// base64.
+    pub fn generate_keys() -> (String, String) {
+        let secret = crypto_box::SecretKey::generate(&mut crypto_box::aead::OsRng);
+        (
+            base64::encode(&secret.to_bytes()),
+            base64::encode(secret.public_key().as_bytes()),
+        )
+    }
+
+    fn secret_key(&self) -> Result<crypto_box::SecretKey, Error> {
+        let text = self.secret_key.as_ref().ok_or_else(|| {
+            Error::new(
+                ErrorKind::Other,
+                "the [sealing] configuration does not have a secret_key",
+            )
+        })?;
+
+        Ok(crypto_box::SecretKey::from(decode_key(
+            text,
+            "sealing secret key",
+        )?))
+    }
+
+    fn public_key(&self) -> Result<crypto_box::PublicKey, Error> {
+        match self.public_key {
+            Some(ref text) => Ok(crypto_box::PublicKey::from(decode_key(
+                text,
+                "sealing public key",
+            )?)),
+            None => Ok(self.secret_key()?.public_key()),
+        }
+    }
+
+    /// Seal a status so that only the panel can read it.
+    pub fn seal(&self, person_is: &str) -> Result<String, Error> {
+        let sealed = self
+            .public_key()?
+            .seal(&mut crypto_box::aead::OsRng, person_is.as_bytes())
+            .map_err(|_| Error::new(ErrorKind::Other, "failed to seal status"))?;
+
+        Ok(format!("{}{}", SEALED_PREFIX, base64::encode(&sealed)))
+    }
+
+    /// Open a sealed status. Statuses that aren't sealed are returned as-is.
+    pub fn unseal(&self, person_is: &str) -> Result<String, Error> {
+        let body = match person_is.strip_prefix(SEALED_PREFIX) {
+            Some(b) => b,
+            None => return Ok(person_is.to_owned()),
+        };
+
+        let sealed = base64::decode(body)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("bad sealed status: {}", e)))?;
+
+        let opened = self.secret_key()?.unseal(&sealed).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                "failed to open sealed status (wrong key?)",
+            )
+        })?;
+
+        String::from_utf8(opened).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
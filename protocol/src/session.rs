@@ -0,0 +1,368 @@
+//! Typed stickyproto sessions.
+//!
+//! A stickyproto connection starts with the client sending a hello. If it's a
+//! `DisplayHelloMessage`, the connection becomes a display session, in which
+//! the hub sends `DisplayMessage`s for as long as the panel stays connected.
+//! Any other hello is a one-off from an updater. If the hub won't act on it,
+//! it answers with an `ErrorFrame` saying why, and either way, that's the end
+//! of the conversation. The types here follow those states, for both ends of the
+//! connection, so that the compiler catches things like the hub trying to
+//! send display messages to an updater, or a client sending a second hello.
+//!
+//! A panel can also ask, in its hello, to share the connection with other
+//! traffic, such as telemetry that would otherwise need connections of its
+//! own. If the hub agrees, every message after the hello is wrapped in a
+//! `HubFrame` or `ClientFrame`, tagged with its channel. Older hubs don't
+//! know to agree, and keep sending bare display messages, so the client
+//! works out which it's dealing with from the first message that arrives.
+//!
+//! Messages are JSON, framed with a length-delimited codec.
+
+use futures::prelude::*;
+use std::io::{Error, ErrorKind};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_serde::{formats::Json, Framed as SerdeFramed};
+use tokio_util::codec::{Framed as CodecFramed, LengthDelimitedCodec};
+
+use crate::{
+    ClientFrame, ClientHelloMessage, ControlReply, DisplayHelloMessage, DisplayMessage,
+    DoorbellHelloMessage, ErrorFrame, HubFrame, PersonIsUpdateHelloMessage,
+    RoomBookingHelloMessage, SensorReadingHelloMessage, SystemHealthHelloMessage,
+};
+
+type Transport<T, Item, SinkItem> =
+    SerdeFramed<CodecFramed<T, LengthDelimitedCodec>, Item, SinkItem, Json<Item, SinkItem>>;
+
+fn transport<T, Item, SinkItem>(io: T) -> Transport<T, Item, SinkItem>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    SerdeFramed::new(
+        CodecFramed::new(io, LengthDelimitedCodec::new()),
+        Json::default(),
+    )
+}
+
+/// Switch a transport over to different message types, as when an updater
+/// connection turns out to need an `ErrorFrame` rather than a
+/// `DisplayMessage`.
+fn retype<T, Item, SinkItem, NewItem, NewSinkItem>(
+    transport: Transport<T, Item, SinkItem>,
+) -> Transport<T, NewItem, NewSinkItem> {
+    SerdeFramed::new(transport.into_inner(), Json::default())
+}
+
+/// A hello that an updater can send: anything but a display hello.
+pub trait UpdaterHello: Into<ClientHelloMessage> {}
+
+impl UpdaterHello for PersonIsUpdateHelloMessage {}
+impl UpdaterHello for DoorbellHelloMessage {}
+impl UpdaterHello for SensorReadingHelloMessage {}
+impl UpdaterHello for SystemHealthHelloMessage {}
+impl UpdaterHello for RoomBookingHelloMessage {}
+
+impl From<PersonIsUpdateHelloMessage> for ClientHelloMessage {
+    fn from(m: PersonIsUpdateHelloMessage) -> Self {
+        ClientHelloMessage::PersonIsUpdate(m)
+    }
+}
+
+impl From<DoorbellHelloMessage> for ClientHelloMessage {
+    fn from(m: DoorbellHelloMessage) -> Self {
+        ClientHelloMessage::Doorbell(m)
+    }
+}
+
+impl From<SensorReadingHelloMessage> for ClientHelloMessage {
+    fn from(m: SensorReadingHelloMessage) -> Self {
+        ClientHelloMessage::SensorReading(m)
+    }
+}
+
+impl From<SystemHealthHelloMessage> for ClientHelloMessage {
+    fn from(m: SystemHealthHelloMessage) -> Self {
+        ClientHelloMessage::SystemHealth(m)
+    }
+}
+
+impl From<RoomBookingHelloMessage> for ClientHelloMessage {
+    fn from(m: RoomBookingHelloMessage) -> Self {
+        ClientHelloMessage::BookRoom(m)
+    }
+}
+
+/// The hub's end of a connection.
+pub mod hub {
+    use super::*;
+
+    /// A new connection, whose client hasn't said hello yet.
+    pub struct AwaitingHello<T> {
+        transport: Transport<T, ClientHelloMessage, DisplayMessage>,
+    }
+
+    /// What a connection turns into once the client has said hello.
+    pub enum Session<T> {
+        Display(DisplaySession<T>),
+        Updater(UpdaterSession<T>),
+    }
+
+    impl<T: AsyncRead + AsyncWrite + Unpin> AwaitingHello<T> {
+        pub fn new(io: T) -> Self {
+            AwaitingHello {
+                transport: transport(io),
+            }
+        }
+
+        /// Wait for the client's hello.
+        pub async fn receive_hello(mut self) -> Result<Session<T>, Error> {
+            match self.transport.try_next().await? {
+                Some(ClientHelloMessage::Display(hello)) => {
+                    let transport = if hello.multiplex {
+                        DisplayTransport::Multiplexed(retype(self.transport))
+                    } else {
+                        DisplayTransport::Plain(self.transport)
+                    };
+
+                    Ok(Session::Display(DisplaySession { hello, transport }))
+                }
+
+                Some(hello) => Ok(Session::Updater(UpdaterSession {
+                    hello,
+                    transport: retype(self.transport),
+                })),
+
+                None => Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "connection dropped before hello?",
+                )),
+            }
+        }
+    }
+
+    /// How the messages to a panel are framed.
+    enum DisplayTransport<T> {
+        Plain(Transport<T, ClientHelloMessage, DisplayMessage>),
+        Multiplexed(Transport<T, ClientFrame, HubFrame>),
+    }
+
+    /// A panel that wants to be kept up to date.
+    pub struct DisplaySession<T> {
+        hello: DisplayHelloMessage,
+        transport: DisplayTransport<T>,
+    }
+
+    impl<T: AsyncRead + AsyncWrite + Unpin> DisplaySession<T> {
+        /// How the panel introduced itself.
+        pub fn hello(&self) -> &DisplayHelloMessage {
+            &self.hello
+        }
+
+        /// Whether the panel shares the connection with other traffic.
+        pub fn is_multiplexed(&self) -> bool {
+            match self.transport {
+                DisplayTransport::Plain(_) => false,
+                DisplayTransport::Multiplexed(_) => true,
+            }
+        }
+
+        /// Send the panel the latest display state.
+        pub async fn send(&mut self, msg: DisplayMessage) -> Result<(), Error> {
+            match self.transport {
+                DisplayTransport::Plain(ref mut t) => t.send(msg).await,
+                DisplayTransport::Multiplexed(ref mut t) => {
+                    t.send(HubFrame::Display(Box::new(msg))).await
+                }
+            }
+        }
+
+        /// Answer a request that the panel sent on the control channel.
+        pub async fn reply(&mut self, reply: ControlReply) -> Result<(), Error> {
+            match self.transport {
+                DisplayTransport::Plain(_) => Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "the panel's connection isn't multiplexed",
+                )),
+                DisplayTransport::Multiplexed(ref mut t) => t.send(HubFrame::Control(reply)).await,
+            }
+        }
+
+        /// Wait for the panel to send something. Returns `None` if it hangs
+        /// up. A panel that isn't multiplexing has nothing to say after its
+        /// hello, so this waits forever.
+        pub async fn next_frame(&mut self) -> Result<Option<ClientFrame>, Error> {
+            match self.transport {
+                DisplayTransport::Plain(_) => future::pending().await,
+                DisplayTransport::Multiplexed(ref mut t) => t.try_next().await,
+            }
+        }
+    }
+
+    /// A client that just had something to tell us. All that's left to do
+    /// is handle its hello, which is never a display hello, and maybe tell
+    /// the client that we won't.
+    pub struct UpdaterSession<T> {
+        hello: ClientHelloMessage,
+        transport: Transport<T, ClientHelloMessage, ErrorFrame>,
+    }
+
+    impl<T: AsyncRead + AsyncWrite + Unpin> UpdaterSession<T> {
+        pub fn hello(&self) -> &ClientHelloMessage {
+            &self.hello
+        }
+
+        /// Take the hello, keeping the connection open in case we need to
+        /// reject it.
+        pub fn into_parts(self) -> (ClientHelloMessage, UpdaterReply<T>) {
+            (
+                self.hello,
+                UpdaterReply {
+                    transport: self.transport,
+                },
+            )
+        }
+    }
+
+    /// The connection to an updater, after we've taken its hello. Dropping
+    /// it hangs up, which tells the client that all went well.
+    pub struct UpdaterReply<T> {
+        transport: Transport<T, ClientHelloMessage, ErrorFrame>,
+    }
+
+    impl<T: AsyncRead + AsyncWrite + Unpin> UpdaterReply<T> {
+        /// Tell the client why we won't act on its hello, and hang up.
+        pub async fn reject(mut self, frame: ErrorFrame) -> Result<(), Error> {
+            self.transport.send(frame).await
+        }
+    }
+}
+
+/// The client's end of a connection.
+pub mod client {
+    use super::*;
+
+    /// A new connection to the hub, before we've said hello.
+    pub struct AwaitingHello<T> {
+        transport: Transport<T, DisplayMessage, ClientHelloMessage>,
+    }
+
+    impl<T: AsyncRead + AsyncWrite + Unpin> AwaitingHello<T> {
+        pub fn new(io: T) -> Self {
+            AwaitingHello {
+                transport: transport(io),
+            }
+        }
+
+        /// Introduce ourselves as a panel, to start receiving display
+        /// updates.
+        pub async fn start_display(
+            mut self,
+            hello: DisplayHelloMessage,
+        ) -> Result<DisplaySession<T>, Error> {
+            self.transport
+                .send(ClientHelloMessage::Display(hello))
+                .await?;
+
+            Ok(DisplaySession {
+                transport: retype(self.transport),
+                multiplexed: false,
+            })
+        }
+
+        /// Tell the hub something, which is all that an updater connection
+        /// is good for, and wait for it to hang up. If it rejects the hello,
+        /// the error wraps the `ErrorFrame` that it sent, which
+        /// `ErrorFrame::from_error()` can get back out.
+        pub async fn send_update<H: UpdaterHello>(self, hello: H) -> Result<(), Error> {
+            self.send_any_update(hello.into()).await
+        }
+
+        /// Pass along a hello that some other client sent us, as a relay
+        /// does, with the same handling of rejections as `send_update()`.
+        /// Display hellos can't be passed along, since there'd be nobody to
+        /// give the updates to.
+        pub async fn forward_update(self, hello: ClientHelloMessage) -> Result<(), Error> {
+            if let ClientHelloMessage::Display(_) = hello {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "display hellos can't be forwarded",
+                ));
+            }
+
+            self.send_any_update(hello).await
+        }
+
+        async fn send_any_update(mut self, hello: ClientHelloMessage) -> Result<(), Error> {
+            self.transport.send(hello).await?;
+
+            let mut transport: Transport<T, ErrorFrame, ClientHelloMessage> =
+                retype(self.transport);
+
+            match transport.try_next().await {
+                Ok(Some(frame)) => Err(Error::new(ErrorKind::Other, frame)),
+
+                // Older hubs hang up no matter what, and might do it rudely,
+                // so there's nothing to learn from how the connection ends.
+                _ => Ok(()),
+            }
+        }
+    }
+
+    /// A connection on which the hub sends us display updates. Until the
+    /// first message arrives, we don't know whether the hub is
+    /// multiplexing, so messages are read loosely and sorted out by hand.
+    pub struct DisplaySession<T> {
+        transport: Transport<T, serde_json::Value, ClientFrame>,
+        multiplexed: bool,
+    }
+
+    impl<T: AsyncRead + AsyncWrite + Unpin> DisplaySession<T> {
+        /// Wait for the next message from the hub. A bare display message,
+        /// from a hub that isn't multiplexing, comes back as a
+        /// `HubFrame::Display`. Returns `None` if the hub hangs up.
+        pub async fn next_frame(&mut self) -> Result<Option<HubFrame>, Error> {
+            let value = match self.transport.try_next().await? {
+                Some(v) => v,
+                None => return Ok(None),
+            };
+
+            // Display messages don't have a `channel` field, so a message
+            // that does must be a frame.
+            if value.get("channel").is_some() {
+                self.multiplexed = true;
+                Ok(Some(serde_json::from_value(value)?))
+            } else {
+                Ok(Some(HubFrame::Display(serde_json::from_value(value)?)))
+            }
+        }
+
+        /// Wait for the next display update, passing over anything else
+        /// that the hub sends. Returns `None` if the hub hangs up.
+        pub async fn next_message(&mut self) -> Result<Option<DisplayMessage>, Error> {
+            loop {
+                match self.next_frame().await? {
+                    Some(HubFrame::Display(m)) => return Ok(Some(*m)),
+                    Some(_) => continue,
+                    None => return Ok(None),
+                }
+            }
+        }
+
+        /// Whether the hub has shown that it's sharing the connection with
+        /// other traffic. We can only tell once a message has arrived.
+        pub fn is_multiplexed(&self) -> bool {
+            self.multiplexed
+        }
+
+        /// Send the hub something on the shared connection, if it is one.
+        pub async fn send(&mut self, frame: ClientFrame) -> Result<(), Error> {
+            if !self.multiplexed {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "the hub connection isn't multiplexed",
+                ));
+            }
+
+            self.transport.send(frame).await
+        }
+    }
+}
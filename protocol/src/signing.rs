@@ -0,0 +1,122 @@
+//! Signed status updates.
+//!
+//! Tokens say that an updater was allowed to connect, but not that the update
+//! wasn't tampered with along the way, e.g. by a compromised MQTT broker. So an
+//! updater can sign its updates with an Ed25519 key, and the hub can check
+//! them against the public keys listed in its configuration. The signature
+//! covers the timestamp, source, and status, so that it can't be reattached
+//! to a different update; the hub also refuses signatures that are too old,
+//! so that old updates can't be replayed.
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "signing")]
+use std::io::{Error, ErrorKind};
+
+#[cfg(feature = "signing")]
+use crate::PersonIsUpdateHelloMessage;
+
+/// An Ed25519 signature on a status update.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UpdateSignature {
+    /// The name of the key that made the signature, as listed in the hub's
+    /// configuration.
+    pub key_name: String,
+
+    /// The signature itself, in base64.
+    pub signature: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SigningConfiguration {
+    /// The name of the key, as listed in the hub's configuration.
+    pub key_name: String,
+
+    /// The secret key, in base64.
+    pub secret_key: String,
+}
+
+#[cfg(feature = "signing")]
+fn decode_key(text: &str, what: &str) -> Result<[u8; 32], Error> {
+    let bytes = base64::decode(text.trim())
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("bad {}: {}", what, e)))?;
+
+    if bytes.len() != 32 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("bad {}: expected 32 bytes", what),
+        ));
+    }
+
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&bytes);
+    Ok(key)
+}
+
+#[cfg(feature = "signing")]
+impl PersonIsUpdateHelloMessage {
+    /// The bytes covered by the update's signature.
+    fn signed_bytes(&self) -> Vec<u8> {
+        format!(
+            "{}\n{}\n{}",
+            self.timestamp.to_rfc3339(),
+            self.source.as_deref().unwrap_or(""),
+            self.person_is
+        )
+        .into_bytes()
+    }
+
+    /// Check the update's signature against the given public key, in base64.
+    pub fn verify_signature(&self, public_key: &str) -> Result<(), Error> {
+        let sig = self
+            .signature
+            .as_ref()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "update is not signed"))?;
+
+        let key =
+            ed25519_dalek::VerifyingKey::from_bytes(&decode_key(public_key, "updater public key")?)
+                .map_err(|e| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("bad updater public key: {}", e),
+                    )
+                })?;
+
+        let bytes = base64::decode(&sig.signature)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("bad signature: {}", e)))?;
+
+        let signature = ed25519_dalek::Signature::from_slice(&bytes)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("bad signature: {}", e)))?;
+
+        key.verify_strict(&self.signed_bytes(), &signature)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "signature does not match"))
+    }
+}
+
+#[cfg(feature = "signing")]
+impl SigningConfiguration {
+    /// Make up a new key, returning the secret and public keys in base64.
+    pub fn generate_key() -> (String, String) {
+        let key = ed25519_dalek::SigningKey::generate(&mut rand_core::OsRng);
+        (
+            base64::encode(&key.to_bytes()),
+            base64::encode(key.verifying_key().as_bytes()),
+        )
+    }
+
+    /// Sign an update, once it's otherwise ready to send.
+    pub fn sign(&self, msg: &mut PersonIsUpdateHelloMessage) -> Result<(), Error> {
+        use ed25519_dalek::Signer;
+
+        let key =
+            ed25519_dalek::SigningKey::from_bytes(&decode_key(&self.secret_key, "signing key")?);
+        let signature = key.sign(&msg.signed_bytes());
+
+        msg.signature = Some(UpdateSignature {
+            key_name: self.key_name.clone(),
+            signature: base64::encode(&signature.to_bytes()[..]),
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+//! Estimating how wide text will be on the panel.
+//!
+//! Panels draw the status in DejaVu Sans, so with that font's advance widths
+//! and kerning pairs, which `build.rs` pulls out of the copy of it that the
+//! display client has built in, we can lay out a status the same way that a
+//! panel would and see whether it fits in the status box. Characters that
+//! aren't in the table are counted as the font's missing-glyph box, which is
+//! what a panel would draw for most of them.
+
+include!(concat!(env!("OUT_DIR"), "/font_metrics.rs"));
+
+/// The height of the status text on the panel, in pixels.
+pub const STATUS_TEXT_HEIGHT: f32 = 32.0;
+
+/// The width of the status box on the panel, in pixels. Wider statuses get
+/// cut off with an ellipsis.
+pub const STATUS_BOX_WIDTH: u32 = 384;
+
+fn advance(c: char) -> u16 {
+    ADVANCES
+        .binary_search_by_key(&c, |&(k, _)| k)
+        .map(|i| ADVANCES[i].1)
+        .unwrap_or(MISSING_ADVANCE)
+}
+
+fn kerning(left: char, right: char) -> i16 {
+    KERNING
+        .binary_search_by_key(&(left, right), |&(k, _)| k)
+        .map(|i| KERNING[i].1)
+        .unwrap_or(0)
+}
+
+/// How wide the text would be on one line, in pixels, with a font size of
+/// `height` pixels.
+pub fn text_width(text: &str, height: f32) -> u32 {
+    let scale = height / HEIGHT_UNITS as f32;
+    let mut caret = 0.0;
+    let mut last = None;
+
+    for c in text.chars() {
+        if let Some(last) = last {
+            caret += kerning(last, c) as f32 * scale;
+        }
+
+        caret += advance(c) as f32 * scale;
+        last = Some(c);
+    }
+
+    caret.ceil() as u32
+}
+
+/// How wide a status would be on the panel, in pixels.
+pub fn status_width(person_is: &str) -> u32 {
+    text_width(person_is, STATUS_TEXT_HEIGHT)
+}
+
+/// Whether a status fits in the status box without being cut off.
+pub fn does_status_fit(person_is: &str) -> bool {
+    status_width(person_is) <= STATUS_BOX_WIDTH
+}
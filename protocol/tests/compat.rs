@@ -0,0 +1,181 @@
+//! Checks that messages from other versions of the protocol still parse.
+//!
+//! The fixtures are messages as serialized by earlier versions: "original"
+//! ones from the first release, "current" ones with every field that exists
+//! today, and "newer" ones with things that this version doesn't know about.
+
+use rc_stickynote_protocol::{
+    Availability, ClientFrame, ClientHelloMessage, DisplayMessage, ErrorCode, ErrorFrame, HubFrame,
+    PanelCommand, UNKNOWN_PERSON_IS,
+};
+use serde::Deserialize;
+
+const ORIGINAL_DISPLAY_MESSAGE: &str = include_str!("fixtures/original-display-message.json");
+const ORIGINAL_DISPLAY_HELLO: &str = include_str!("fixtures/original-display-hello.json");
+const ORIGINAL_UPDATE_HELLO: &str = include_str!("fixtures/original-person-is-update-hello.json");
+const CURRENT_DISPLAY_MESSAGE: &str = include_str!("fixtures/current-display-message.json");
+const CURRENT_HELLOS: &str = include_str!("fixtures/current-hellos.json");
+const NEWER_DISPLAY_MESSAGE: &str = include_str!("fixtures/newer-display-message.json");
+const CURRENT_ERROR_FRAMES: &str = include_str!("fixtures/current-error-frames.json");
+const NEWER_ERROR_FRAME: &str = include_str!("fixtures/newer-error-frame.json");
+const CURRENT_FRAMES: &str = include_str!("fixtures/current-frames.json");
+const NEWER_FRAME: &str = include_str!("fixtures/newer-frame.json");
+
+#[test]
+fn original_display_message() {
+    let msg: DisplayMessage = serde_json::from_str(ORIGINAL_DISPLAY_MESSAGE).unwrap();
+    assert_eq!(msg.person_is, "in a meeting");
+    assert_eq!(msg.notes_waiting, 0);
+    assert_eq!(msg.command, None);
+    assert_eq!(msg.availability, None);
+    assert_eq!(msg.missed, None);
+    assert!(msg.extras.is_empty());
+}
+
+#[test]
+fn original_hellos() {
+    match serde_json::from_str(ORIGINAL_DISPLAY_HELLO).unwrap() {
+        ClientHelloMessage::Display(h) => {
+            assert_eq!(h.display_id, None);
+            assert_eq!(h.capabilities, None);
+            assert!(!h.multiplex);
+            assert_eq!(h.last_update, None);
+        }
+        other => panic!("expected a display hello, got {:?}", other),
+    }
+
+    match serde_json::from_str(ORIGINAL_UPDATE_HELLO).unwrap() {
+        ClientHelloMessage::PersonIsUpdate(m) => {
+            assert_eq!(m.person_is, "at lunch");
+            assert_eq!(m.source, None);
+            assert!(m.signature.is_none());
+        }
+        other => panic!("expected a status update, got {:?}", other),
+    }
+}
+
+#[test]
+fn current_display_message_round_trips() {
+    let msg: DisplayMessage = serde_json::from_str(CURRENT_DISPLAY_MESSAGE).unwrap();
+    assert_eq!(
+        msg.command.as_ref().map(|c| &c.command),
+        Some(&PanelCommand::Layout("status".to_owned()))
+    );
+    assert_eq!(msg.availability, Some(Availability::Limited));
+    assert_eq!(msg.on_call[1].person, None);
+    assert_eq!(msg.counters[0].value, 12);
+    assert_eq!(msg.office_days[0].days.len(), 2);
+    assert!(msg.room.as_ref().unwrap().can_book);
+    assert_eq!(msg.missed.as_ref().map(|m| m.count), Some(3));
+
+    let text = serde_json::to_string(&msg).unwrap();
+    let again: DisplayMessage = serde_json::from_str(&text).unwrap();
+    assert_eq!(again, msg);
+}
+
+#[test]
+fn current_hellos_round_trip() {
+    let fixtures: Vec<serde_json::Value> = serde_json::from_str(CURRENT_HELLOS).unwrap();
+
+    for fixture in fixtures {
+        let hello: ClientHelloMessage = serde_json::from_value(fixture.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&hello).unwrap(), fixture);
+    }
+}
+
+#[test]
+fn newer_display_message() {
+    let msg: DisplayMessage = serde_json::from_str(NEWER_DISPLAY_MESSAGE).unwrap();
+    assert_eq!(msg.person_is, "in the lab");
+    assert_eq!(msg.command, None);
+    assert_eq!(msg.availability, None);
+    assert_eq!(msg.counters[0].label, "days since the last incident");
+}
+
+#[test]
+fn current_error_frames_round_trip() {
+    let fixtures: Vec<serde_json::Value> = serde_json::from_str(CURRENT_ERROR_FRAMES).unwrap();
+
+    for fixture in fixtures {
+        let frame: ErrorFrame = serde_json::from_value(fixture.clone()).unwrap();
+        assert_ne!(frame.code, ErrorCode::Unknown);
+        assert_eq!(serde_json::to_value(&frame).unwrap(), fixture);
+    }
+}
+
+#[test]
+fn newer_error_frame() {
+    let frame: ErrorFrame = serde_json::from_str(NEWER_ERROR_FRAME).unwrap();
+    assert_eq!(frame.code, ErrorCode::Unknown);
+    assert_eq!(frame.message, "slow down");
+}
+
+#[test]
+fn current_frames() {
+    let fixtures: Vec<serde_json::Value> = serde_json::from_str(CURRENT_FRAMES).unwrap();
+
+    // The hub's frames come first, then the client's.
+
+    for fixture in &fixtures[..3] {
+        let frame: HubFrame = serde_json::from_value(fixture.clone()).unwrap();
+        assert_ne!(frame, HubFrame::Unknown);
+    }
+
+    match serde_json::from_value(fixtures[2].clone()).unwrap() {
+        HubFrame::Control(r) => assert_eq!(r.error.unwrap().code, ErrorCode::Unavailable),
+        other => panic!("expected a control reply, got {:?}", other),
+    }
+
+    for fixture in &fixtures[3..] {
+        let frame: ClientFrame = serde_json::from_value(fixture.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&frame).unwrap(), *fixture);
+    }
+}
+
+#[test]
+fn newer_frame() {
+    let frame: HubFrame = serde_json::from_str(NEWER_FRAME).unwrap();
+    assert_eq!(frame, HubFrame::Unknown);
+
+    match serde_json::from_str(NEWER_FRAME).unwrap() {
+        ClientFrame::Unknown => {}
+        other => panic!("expected an unknown frame, got {:?}", other),
+    }
+}
+
+/// The display message as the first release defined it.
+#[derive(Deserialize)]
+struct OriginalDisplayMessage {
+    person_is: String,
+}
+
+/// The status update as the first release defined it.
+#[derive(Deserialize)]
+struct OriginalPersonIsUpdateHelloMessage {
+    person_is: String,
+}
+
+#[derive(Deserialize)]
+enum OriginalClientHelloMessage {
+    Display {},
+    PersonIsUpdate(OriginalPersonIsUpdateHelloMessage),
+}
+
+#[test]
+fn original_versions_read_current_messages() {
+    let msg = serde_json::to_string(&DisplayMessage::default()).unwrap();
+    let old: OriginalDisplayMessage = serde_json::from_str(&msg).unwrap();
+    assert_eq!(old.person_is, UNKNOWN_PERSON_IS);
+
+    let fixtures: Vec<serde_json::Value> = serde_json::from_str(CURRENT_HELLOS).unwrap();
+
+    match serde_json::from_value(fixtures[0].clone()).unwrap() {
+        OriginalClientHelloMessage::Display {} => {}
+        _ => panic!("expected a display hello"),
+    }
+
+    match serde_json::from_value(fixtures[1].clone()).unwrap() {
+        OriginalClientHelloMessage::PersonIsUpdate(m) => assert_eq!(m.person_is, "at lunch"),
+        _ => panic!("expected a status update"),
+    }
+}
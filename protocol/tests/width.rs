@@ -0,0 +1,41 @@
+//! Checks of the status width estimates.
+//!
+//! The expected widths are what the display client's rusttype layout gives
+//! for the same text in DejaVu Sans at the status size.
+
+use rc_stickynote_protocol::{
+    is_person_is_valid,
+    width::{does_status_fit, status_width, text_width},
+    UNKNOWN_PERSON_IS,
+};
+
+#[test]
+fn known_widths() {
+    assert_eq!(status_width(""), 0);
+    assert_eq!(status_width("in the lab"), 130);
+    assert_eq!(status_width(UNKNOWN_PERSON_IS), 312);
+    assert_eq!(status_width("café — naïve “quotes” 25€"), 371);
+}
+
+#[test]
+fn kerning_is_applied() {
+    let pair = text_width("AV", 100.0);
+    let apart = text_width("A", 100.0) + text_width("V", 100.0);
+    assert!(pair < apart);
+}
+
+#[test]
+fn fit_goes_by_width() {
+    // Both are 22 characters, but capital Ws are much wider than is.
+    assert!(does_status_fit("iiiiiiiiiiiiiiiiiiiiii"));
+    assert!(!does_status_fit("WWWWWWWWWWWWWWWWWWWWWW"));
+
+    assert!(is_person_is_valid("Lunch; back at 1:30pm"));
+    assert!(!is_person_is_valid("WWWWWWWWWWWWWWWWWWWWWW"));
+}
+
+#[test]
+fn zero_width_characters_are_limited() {
+    let combining = "a\u{301}".repeat(40);
+    assert!(!is_person_is_valid(&combining));
+}
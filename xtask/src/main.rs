@@ -0,0 +1,249 @@
+//! Development chores for the workspace, run with `cargo xtask <command>`.
+//!
+//! So far there's just `build-pi`, which cross-compiles the display client
+//! for a single-board computer and packs it up with systemd units, ready to
+//! unpack on the device. Building it by hand means remembering the target
+//! triple, which features go with which backend, and where everything goes,
+//! and it's easy to get one of them wrong.
+
+use std::{
+    fs,
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+use structopt::StructOpt;
+
+/// The CPUs of the boards that we build for.
+#[derive(Clone, Copy, Debug)]
+enum Arch {
+    /// 32-bit ARM, for Raspberry Pi OS and most other Pi-like boards.
+    Armv7,
+
+    /// 64-bit ARM, for 64-bit Raspberry Pi OS and the like.
+    Aarch64,
+}
+
+impl FromStr for Arch {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "armv7" => Ok(Arch::Armv7),
+            "aarch64" => Ok(Arch::Aarch64),
+            _ => Err(format!(
+                "expected \"armv7\" or \"aarch64\", got \"{}\"",
+                text
+            )),
+        }
+    }
+}
+
+impl Arch {
+    fn target(self) -> &'static str {
+        match self {
+            Arch::Armv7 => "armv7-unknown-linux-gnueabihf",
+            Arch::Aarch64 => "aarch64-unknown-linux-gnu",
+        }
+    }
+}
+
+/// The systemd units that go in the package, from `displayer/systemd/`.
+const UNITS: &[&str] = &[
+    "rc-stickynote-displayer@.service",
+    "rc-stickynote-housekeeping@.service",
+];
+
+/// Instructions to go in the package.
+const INSTALL_TEXT: &str = "\
+To install on the device, as root:
+
+    cp bin/rc_stickynote_displayer /usr/local/bin/
+    cp systemd/*.service /etc/systemd/system/
+    systemctl daemon-reload
+    systemctl enable --now rc-stickynote-housekeeping@USER rc-stickynote-displayer@USER
+
+where USER is the account to run the display client as. Its configuration
+goes in ~USER/.config/rc-stickynote-client/rc-stickynote-client.toml; see
+client-config.example.toml for the settings. Run `rc_stickynote_displayer
+self-test` as that user to check the wiring and the connection to the hub.
+";
+
+// build-pi subcommand
+
+#[derive(Debug, StructOpt)]
+struct BuildPiCommand {
+    #[structopt(
+        long = "arch",
+        help = "The board's CPU: \"armv7\" (the default) or \"aarch64\""
+    )]
+    arch: Option<Arch>,
+
+    #[structopt(
+        long = "simulator",
+        help = "Build the SDL2 simulator for this machine instead, to try out the package"
+    )]
+    simulator: bool,
+
+    #[structopt(
+        long = "cargo",
+        help = "Build with plain cargo rather than cross, if a cross-linker is set up"
+    )]
+    plain_cargo: bool,
+}
+
+impl BuildPiCommand {
+    fn cli(self) -> Result<(), Error> {
+        let root = workspace_root();
+
+        // The Waveshare backend only makes sense on the board, and the
+        // simulator only makes sense on a desktop, so don't let them get
+        // mixed up.
+
+        let (label, target) = match (self.simulator, self.arch) {
+            (true, Some(_)) => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "the simulator is built for this machine; leave out --arch",
+                ))
+            }
+
+            (true, None) => ("simulator".to_owned(), None),
+
+            (false, arch) => {
+                let target = arch.unwrap_or(Arch::Armv7).target();
+                (target.to_owned(), Some(target))
+            }
+        };
+
+        let tool = if self.plain_cargo || target.is_none() {
+            "cargo"
+        } else {
+            "cross"
+        };
+
+        let mut cmd = Command::new(tool);
+        cmd.current_dir(&root)
+            .args(["build", "--release", "-p", "rc_stickynote_displayer"])
+            .args(["--config", "profile.release.strip=true"]);
+
+        match target {
+            Some(t) => {
+                cmd.args(["--target", t]);
+            }
+
+            None => {
+                cmd.args(["--no-default-features", "--features", "simulator"]);
+            }
+        }
+
+        run(&mut cmd)?;
+
+        let mut binary = root.join("target");
+
+        if let Some(t) = target {
+            binary.push(t);
+        }
+
+        binary.push("release");
+        binary.push("rc_stickynote_displayer");
+
+        // Lay out the package, then tar it up.
+
+        let dist = root.join("target").join("dist");
+        let name = format!("rc-stickynote-displayer-{}", label);
+        let stage = dist.join(&name);
+
+        if stage.exists() {
+            fs::remove_dir_all(&stage)?;
+        }
+
+        fs::create_dir_all(stage.join("bin"))?;
+        fs::create_dir_all(stage.join("systemd"))?;
+
+        copy(&binary, &stage.join("bin").join("rc_stickynote_displayer"))?;
+
+        for unit in UNITS {
+            copy(
+                &root.join("displayer").join("systemd").join(unit),
+                &stage.join("systemd").join(unit),
+            )?;
+        }
+
+        copy(
+            &root.join("local").join("client-config.example.toml"),
+            &stage.join("client-config.example.toml"),
+        )?;
+        fs::write(stage.join("INSTALL.txt"), INSTALL_TEXT)?;
+
+        let tarball = dist.join(format!("{}.tar.gz", name));
+        run(Command::new("tar")
+            .arg("-czf")
+            .arg(&tarball)
+            .arg("-C")
+            .arg(&dist)
+            .arg(&name))?;
+
+        println!("wrote {}", tarball.display());
+        Ok(())
+    }
+}
+
+// CLI root interface
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "xtask", about = "Development chores for rc-stickynote")]
+enum RootCli {
+    #[structopt(name = "build-pi")]
+    /// Cross-compile the display client and pack it up with systemd units
+    BuildPi(BuildPiCommand),
+}
+
+impl RootCli {
+    fn cli(self) -> Result<(), Error> {
+        match self {
+            RootCli::BuildPi(opts) => opts.cli(),
+        }
+    }
+}
+
+fn main() -> Result<(), Error> {
+    RootCli::from_args().cli()
+}
+
+/// The top of the workspace, which this crate is one level down from.
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask is inside the workspace")
+        .to_owned()
+}
+
+/// Run a command, failing if it does.
+fn run(cmd: &mut Command) -> Result<(), Error> {
+    println!("running: {:?}", cmd);
+
+    let status = cmd.status().map_err(|e| {
+        Error::new(
+            e.kind(),
+            format!("cannot run `{:?}`: {}", cmd.get_program(), e),
+        )
+    })?;
+
+    if !status.success() {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("`{:?}` failed: {}", cmd.get_program(), status),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Copy a file, saying which one if it can't be.
+fn copy(from: &Path, to: &Path) -> Result<(), Error> {
+    fs::copy(from, to)
+        .map(|_| ())
+        .map_err(|e| Error::new(e.kind(), format!("cannot copy {}: {}", from.display(), e)))
+}